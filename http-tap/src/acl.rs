@@ -0,0 +1,91 @@
+//! `--allow` / `--deny`: which request paths the tap forwards.
+
+use crate::glob;
+
+/// Path globs from `--allow` and `--deny`.
+#[derive(Clone, Debug, Default)]
+pub struct Acl {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+/// Why a path was let through or refused; `pattern` is the glob that
+/// decided it, absent when a path is refused for matching no `--allow`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allowed { pattern: String },
+    Denied { pattern: Option<String> },
+}
+
+impl Acl {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        Self { allow, deny }
+    }
+
+    /// None when no rule has anything to say about `path`: no lists are
+    /// set, or only `--deny` is and none of it matches. `--deny` wins over
+    /// `--allow`.
+    pub fn check(&self, path: &str) -> Option<Decision> {
+        if let Some(pattern) = first_match(&self.deny, path) {
+            return Some(Decision::Denied {
+                pattern: Some(pattern),
+            });
+        }
+        if self.allow.is_empty() {
+            return None;
+        }
+        Some(match first_match(&self.allow, path) {
+            Some(pattern) => Decision::Allowed { pattern },
+            None => Decision::Denied { pattern: None },
+        })
+    }
+}
+
+fn first_match(patterns: &[String], path: &str) -> Option<String> {
+    patterns.iter().find(|p| glob::matches(p, path)).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acl_deny_wins_and_allow_limits_the_rest() {
+        let acl = Acl::new(
+            vec!["/api/**".into(), "/health".into()],
+            vec!["/api/admin/*".into()],
+        );
+        assert_eq!(
+            acl.check("/api/users/7/posts"),
+            Some(Decision::Allowed {
+                pattern: "/api/**".into()
+            })
+        );
+        assert_eq!(
+            acl.check("/api/admin/keys"),
+            Some(Decision::Denied {
+                pattern: Some("/api/admin/*".into())
+            })
+        );
+        // `*` stops at a slash, so deeper admin paths fall through to the allow list
+        assert_eq!(
+            acl.check("/api/admin/keys/1"),
+            Some(Decision::Allowed {
+                pattern: "/api/**".into()
+            })
+        );
+        assert_eq!(
+            acl.check("/healthz"),
+            Some(Decision::Denied { pattern: None })
+        );
+
+        let deny_only = Acl::new(vec![], vec!["/v?/internal".into()]);
+        assert_eq!(
+            deny_only.check("/v1/internal"),
+            Some(Decision::Denied {
+                pattern: Some("/v?/internal".into())
+            })
+        );
+        assert_eq!(deny_only.check("/v1/public"), None);
+    }
+}
@@ -1,25 +1,58 @@
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{Parser, ValueHint};
+use clap::{Parser, ValueEnum, ValueHint};
+
+use crate::delay::PathDelay;
 
 #[derive(Debug, Clone, Parser)]
 #[command(
     name = "http-tap",
     about = "Listen on a port and proxy to a target, printing HTTP requests/responses.",
     version,
-    propagate_version = true
+    propagate_version = true,
+    after_help = "Upstream connections use HTTP/1.1 only: HTTP/2 (hyper's http2 feature and the h2 crate) is not vendored for offline builds."
 )]
 pub struct Cli {
-    /// Address to listen on (e.g., 127.0.0.1:8888)
+    /// Address or host name to listen on (e.g., 127.0.0.1:8888 or localhost:8888)
     #[arg(long, value_hint = ValueHint::Other, default_value = "127.0.0.1:8888")]
     pub listen: String,
 
+    /// When --listen names a host with both IPv4 and IPv6 addresses, bind the IPv6 one
+    #[arg(long, default_value_t = false)]
+    pub prefer_ipv6: bool,
+
     /// Target HTTP endpoint to forward to (host:port or full URL base)
     #[arg(long, value_hint = ValueHint::Url, required = true)]
     pub target: String,
 
+    /// Log output format: human-readable text or one JSON object per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Timestamp format for log lines: rfc3339, epoch, or a strftime-like pattern (e.g. "%H:%M:%S.%L")
+    #[arg(long, default_value = "rfc3339")]
+    pub time_format: String,
+
+    /// Render log timestamps in the local timezone instead of UTC
+    #[arg(long, default_value_t = false)]
+    pub local_time: bool,
+
+    /// Write the request/response log to this file instead of stdout
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate --log-file once it would grow beyond this many bytes (at least 1)
+    #[arg(long, default_value_t = 10 * 1024 * 1024, value_parser = clap::value_parser!(u64).range(1..))]
+    pub log_max_size: u64,
+
+    /// Also write each forwarded request to this directory as a replayable .http file
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    pub dump_http: Option<PathBuf>,
+
     /// Print request/response bodies (truncated by --max-body-bytes)
     #[arg(long, default_value_t = false)]
     pub include_bodies: bool,
@@ -28,6 +61,10 @@ pub struct Cli {
     #[arg(long, default_value_t = 2048)]
     pub max_body_bytes: usize,
 
+    /// Print bodies as a hex dump instead of decoding text and form submissions
+    #[arg(long, default_value_t = false)]
+    pub force_hex: bool,
+
     /// Header names to redact in logs (repeatable)
     #[arg(long, value_delimiter = ',', num_args = 0.., default_values_t = vec![
         String::from("authorization"),
@@ -36,6 +73,15 @@ pub struct Cli {
     ])]
     pub redact_header: Vec<String>,
 
+    /// Expand Cookie/Set-Cookie headers into one line per cookie and attribute
+    /// (overrides header-level redaction for those two headers; see --redact-cookie)
+    #[arg(long, default_value_t = false)]
+    pub show_cookies: bool,
+
+    /// Cookie names whose values are redacted when --show-cookies is on (repeatable)
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    pub redact_cookie: Vec<String>,
+
     /// Enable TLS on the listening port using the provided cert (PEM) and key (PEM)
     #[arg(long, value_hint = ValueHint::FilePath)]
     pub listen_tls_cert: Option<PathBuf>,
@@ -56,6 +102,26 @@ pub struct Cli {
     #[arg(long, default_value_t = false)]
     pub tui: bool,
 
+    /// Print a summary (totals, methods, top paths, error rate, bytes) on exit; always on with --tui
+    #[arg(long, default_value_t = false)]
+    pub summary: bool,
+
+    /// Load per-path stats from this JSON file at startup and write them back on exit (q in the TUI, or Ctrl-C)
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub stats_db: Option<PathBuf>,
+
+    /// Without --tui, print the summary table to stderr at this interval (e.g. 30s, 5m)
+    #[arg(long, value_parser = parse_interval)]
+    pub periodic_summary: Option<Duration>,
+
+    /// Also break stats down by client IP: a TUI view toggled with 'i' and top clients in the summary
+    #[arg(long, default_value_t = false)]
+    pub by_ip: bool,
+
+    /// Latency samples kept per path for the p50/p95/p99 columns and summary
+    #[arg(long, default_value_t = crate::stats::DEFAULT_LATENCY_WINDOW)]
+    pub latency_window: usize,
+
     /// Extra CA bundle(s) for upstream TLS verification (PEM). Comma-separated or repeatable.
     #[arg(long, value_hint = ValueHint::FilePath, value_delimiter = ',', num_args = 0..)]
     pub upstream_ca: Vec<PathBuf>,
@@ -68,6 +134,11 @@ pub struct Cli {
     #[arg(long, value_hint = ValueHint::FilePath)]
     pub upstream_client_key: Option<PathBuf>,
 
+    /// Append TLS session secrets to this file (NSS key log format, for Wireshark).
+    /// Defaults to $SSLKEYLOGFILE when set
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub keylog: Option<PathBuf>,
+
     /// Override SNI/hostname for upstream TLS (useful when targeting an IP)
     #[arg(long)]
     pub upstream_server_name: Option<String>,
@@ -75,11 +146,131 @@ pub struct Cli {
     /// Override the Host header sent to the upstream (virtual host routing)
     #[arg(long)]
     pub upstream_host: Option<String>,
+
+    /// Forward the client's own Host header instead of rewriting it to the target
+    #[arg(long, default_value_t = false, conflicts_with = "upstream_host")]
+    pub preserve_host: bool,
+
+    /// Forward this hop-by-hop header (e.g. te, upgrade) instead of stripping it (repeatable)
+    #[arg(long, value_delimiter = ',')]
+    pub keep_header: Vec<String>,
+
+    /// Send HTTP Basic credentials (user:pass) to the upstream, replacing any client Authorization header
+    #[arg(long)]
+    pub upstream_basic_auth: Option<String>,
+
+    /// Retry idempotent requests (GET/HEAD/PUT/DELETE/OPTIONS/TRACE) this many times on upstream connection failure
+    #[arg(long, default_value_t = 0)]
+    pub upstream_retries: u32,
+
+    /// Pace response bodies to the client at this many bytes per second (simulate slow links)
+    #[arg(long)]
+    pub upstream_bandwidth: Option<u64>,
+
+    /// Wait this long before forwarding requests whose path matches the glob, e.g. 'path=/search,ms=300' (repeatable)
+    #[arg(long, value_name = "path=GLOB,ms=N", value_parser = PathDelay::parse)]
+    pub delay: Vec<PathDelay>,
+
+    /// Reach the upstream through this HTTP proxy (http://[user:pass@]host:port); HTTPS
+    /// targets are tunnelled with CONNECT. Skipped when the target host is listed in NO_PROXY
+    #[arg(long, value_name = "URL")]
+    pub upstream_proxy: Option<String>,
+
+    /// Also act as a forward proxy: tunnel CONNECT host:port requests to the requested host
+    #[arg(long, default_value_t = false)]
+    pub forward_proxy: bool,
+
+    /// Serve at most this many client connections at once; further connections wait to be accepted
+    #[arg(long)]
+    pub max_connections: Option<NonZeroUsize>,
+
+    /// Pending connections the kernel queues before they are accepted
+    #[arg(long, default_value_t = crate::proxy::DEFAULT_LISTEN_BACKLOG, value_parser = clap::value_parser!(i32).range(1..))]
+    pub listen_backlog: i32,
+
+    /// Disable Nagle's algorithm on accepted client connections
+    #[arg(long, default_value_t = false)]
+    pub tcp_nodelay: bool,
+
+    /// Answer requests for this exact path (e.g. /_tap/health) with 200 OK instead of proxying them
+    #[arg(long)]
+    pub health_path: Option<String>,
+
+    /// Remove this path prefix (e.g. /proxy) before forwarding; other paths get 404
+    #[arg(long)]
+    pub strip_prefix: Option<String>,
+
+    /// Answer paths matching this glob (e.g. /admin/**) with 403 instead of proxying them (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub deny: Vec<String>,
+
+    /// Only proxy paths matching one of these globs (e.g. /api/*); everything else gets 403 (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub allow: Vec<String>,
+
+    /// Prepend this path prefix (e.g. /v1) to every forwarded path
+    #[arg(long)]
+    pub add_prefix: Option<String>,
+
+    /// Re-encode responses to match the client's Accept-Encoding: gzip plain bodies, decode unrequested gzip/deflate
+    #[arg(long, default_value_t = false)]
+    pub recompress: bool,
+
+    /// Replace text in request bodies before forwarding, as 'from=>to' (repeatable)
+    #[arg(long, value_name = "FROM=>TO")]
+    pub body_replace: Vec<String>,
+
+    /// Like --body-replace with a regular expression; the replacement may use $1..$9 (repeatable)
+    #[arg(long, value_name = "PATTERN=>TO")]
+    pub body_replace_regex: Vec<String>,
+
+    /// Apply --body-replace rules to every body, not only textual content types
+    #[arg(long, default_value_t = false)]
+    pub body_replace_binary: bool,
+
+    /// Export a trace span per proxied request to this OTLP/HTTP collector (e.g. http://localhost:4318)
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Multi-line request/response dumps
+    Text,
+    /// One JSON object per request/response line (for jq or log collectors)
+    Json,
 }
 
 impl Cli {
-    pub fn listen_addr(&self) -> anyhow::Result<SocketAddr> {
-        SocketAddr::from_str(&self.listen)
-            .map_err(|e| anyhow::anyhow!("invalid --listen address '{}': {}", self.listen, e))
+    /// Resolve --listen, which may name a host (e.g. localhost:8888). IPv4
+    /// addresses are preferred unless --prefer-ipv6 is set.
+    pub async fn listen_addr(&self) -> anyhow::Result<SocketAddr> {
+        if let Ok(addr) = SocketAddr::from_str(&self.listen) {
+            return Ok(addr);
+        }
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host(&self.listen)
+            .await
+            .map_err(|e| anyhow::anyhow!("invalid --listen address '{}': {}", self.listen, e))?
+            .collect();
+        addrs
+            .iter()
+            .find(|a| a.is_ipv6() == self.prefer_ipv6)
+            .or_else(|| addrs.first())
+            .copied()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "--listen host '{}' did not resolve to any address",
+                    self.listen
+                )
+            })
+    }
+}
+
+/// A non-zero humantime duration such as `30s` or `5m`.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(d) if d.is_zero() => Err("interval must be greater than zero".into()),
+        Ok(d) => Ok(d),
+        Err(e) => Err(e.to_string()),
     }
 }
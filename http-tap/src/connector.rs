@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::http::Uri;
+use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+use tower_service::Service;
+
+use crate::logfile::LogSink;
+use crate::upstream_proxy::{ProxyConnector, ProxyStream};
+
+type Inner = HttpsConnector<ProxyConnector>;
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Upstream connector that logs the negotiated TLS parameters of each new
+/// upstream connection. Connections are pooled, so this fires once per
+/// connection rather than once per request.
+#[derive(Clone)]
+pub struct TlsInfoConnector {
+    inner: Inner,
+    log: LogSink,
+}
+
+impl TlsInfoConnector {
+    pub fn new(inner: Inner, log: LogSink) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl Service<Uri> for TlsInfoConnector {
+    type Response = MaybeHttpsStream<ProxyStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let connecting = self.inner.call(dst.clone());
+        let log = self.log.clone();
+        Box::pin(async move {
+            let stream = connecting.await?;
+            if let MaybeHttpsStream::Https(tls) = &stream {
+                let (_, conn) = tls.inner().get_ref();
+                let authority = dst.authority().map(|a| a.as_str()).unwrap_or("?");
+                log.write_entry(&format!(
+                    "[upstream] TLS to {authority}: {}",
+                    describe_tls(
+                        conn.protocol_version(),
+                        conn.negotiated_cipher_suite(),
+                        conn.alpn_protocol()
+                    )
+                ));
+            }
+            Ok(stream)
+        })
+    }
+}
+
+/// One-line description like `TLSv1_3 TLS13_AES_128_GCM_SHA256 alpn=h2`.
+pub fn describe_tls(
+    version: Option<rustls::ProtocolVersion>,
+    suite: Option<rustls::SupportedCipherSuite>,
+    alpn: Option<&[u8]>,
+) -> String {
+    let mut out = match version {
+        Some(v) => format!("{v:?}"),
+        None => "unknown version".to_string(),
+    };
+    if let Some(suite) = suite {
+        out.push_str(&format!(" {:?}", suite.suite()));
+    }
+    if let Some(alpn) = alpn {
+        out.push_str(&format!(" alpn={}", String::from_utf8_lossy(alpn)));
+    }
+    out
+}
@@ -0,0 +1,41 @@
+/// A single `Set-Cookie` value split into its pair and attributes.
+pub struct SetCookie<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    /// Attributes in header order, e.g. ("Path", Some("/")) or ("HttpOnly", None).
+    pub attributes: Vec<(&'a str, Option<&'a str>)>,
+}
+
+/// Parse a request `Cookie` header (`a=1; b=2`) into name/value pairs.
+pub fn parse_cookie_header(value: &str) -> Vec<(&str, &str)> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (pair, ""),
+        })
+        .collect()
+}
+
+/// Parse a response `Set-Cookie` header (`sid=abc; Path=/; HttpOnly`).
+pub fn parse_set_cookie(value: &str) -> SetCookie<'_> {
+    let mut parts = value.split(';').map(str::trim);
+    let (name, value) = match parts.next().and_then(|pair| pair.split_once('=')) {
+        Some((name, value)) => (name.trim(), value.trim()),
+        None => (value.trim(), ""),
+    };
+    let attributes = parts
+        .filter(|attr| !attr.is_empty())
+        .map(|attr| match attr.split_once('=') {
+            Some((k, v)) => (k.trim(), Some(v.trim())),
+            None => (attr, None),
+        })
+        .collect();
+    SetCookie {
+        name,
+        value,
+        attributes,
+    }
+}
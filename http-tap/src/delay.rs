@@ -0,0 +1,38 @@
+//! `--delay 'path=/search,ms=300'`: synthetic latency for chosen paths.
+
+use std::time::Duration;
+
+use crate::glob;
+
+#[derive(Clone, Debug)]
+pub struct PathDelay {
+    pub pattern: String,
+    pub delay: Duration,
+}
+
+impl PathDelay {
+    /// Parse `path=<glob>,ms=<n>`; both keys are required.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut pattern = None;
+        let mut ms = None;
+        for field in spec.split(',') {
+            match field.split_once('=') {
+                Some(("path", glob)) if !glob.is_empty() => pattern = Some(glob.to_string()),
+                Some(("ms", n)) => ms = Some(n.parse::<u64>().map_err(|e| format!("ms={n}: {e}"))?),
+                _ => return Err(format!("unexpected '{field}', expected path=<glob>,ms=<n>")),
+            }
+        }
+        match (pattern, ms) {
+            (Some(pattern), Some(ms)) => Ok(Self {
+                pattern,
+                delay: Duration::from_millis(ms),
+            }),
+            _ => Err("expected path=<glob>,ms=<n>".into()),
+        }
+    }
+}
+
+/// The first `--delay` whose glob matches `path`.
+pub fn for_path<'a>(delays: &'a [PathDelay], path: &str) -> Option<&'a PathDelay> {
+    delays.iter().find(|d| glob::matches(&d.pattern, path))
+}
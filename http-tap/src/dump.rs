@@ -0,0 +1,150 @@
+//! `--dump-http`: each forwarded request saved as a replayable `.http` file.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Context;
+use hyper::http::Request;
+use hyper::http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+
+use crate::proxy::redact_headers;
+
+/// `--dump-http`: one `.http` file per forwarded request, in the format
+/// VS Code's REST Client and JetBrains' HTTP client open and re-send.
+#[derive(Debug)]
+pub struct HttpDump {
+    dir: PathBuf,
+    seq: AtomicU64,
+}
+
+impl HttpDump {
+    pub fn create(dir: &Path) -> anyhow::Result<Arc<Self>> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("create --dump-http {}", dir.display()))?;
+        Ok(Arc::new(Self {
+            dir: dir.to_path_buf(),
+            seq: AtomicU64::new(1),
+        }))
+    }
+
+    /// Write `req` and its body, with headers named in `redact` replaced by
+    /// `<redacted>` and listed in a comment so a replay knows to fill them in.
+    pub async fn write<B>(
+        &self,
+        req: &Request<B>,
+        body: &[u8],
+        redact: &[String],
+    ) -> anyhow::Result<PathBuf> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!(
+            "{seq:05}-{}-{}.http",
+            req.method(),
+            slug(req.uri().path())
+        ));
+
+        let mut head = String::new();
+        let mut redacted: Vec<_> = req
+            .headers()
+            .keys()
+            .filter(|name| redact.iter().any(|r| r.eq_ignore_ascii_case(name.as_str())))
+            .map(|name| name.as_str())
+            .collect();
+        if !redacted.is_empty() {
+            redacted.sort_unstable();
+            let _ = writeln!(head, "# redacted: {}", redacted.join(", "));
+        }
+        let _ = writeln!(head, "{} {} {:?}", req.method(), req.uri(), req.version());
+        // The replaying client frames the body itself
+        let mut headers = redact_headers(req.headers(), redact);
+        headers.remove(CONTENT_LENGTH);
+        headers.remove(TRANSFER_ENCODING);
+        let mut names: Vec<_> = headers.keys().collect();
+        names.sort_unstable_by_key(|name| name.as_str());
+        for name in names {
+            for val in headers.get_all(name) {
+                let _ = writeln!(head, "{name}: {}", String::from_utf8_lossy(val.as_bytes()));
+            }
+        }
+
+        let mut contents = head.into_bytes();
+        if !body.is_empty() {
+            contents.push(b'\n');
+            contents.extend_from_slice(body);
+            if !body.ends_with(b"\n") {
+                contents.push(b'\n');
+            }
+        }
+        // Off the runtime's worker threads, so a slow disk stalls no connection
+        let target = path.clone();
+        tokio::task::spawn_blocking(move || std::fs::write(target, contents))
+            .await
+            .context("--dump-http writer")?
+            .with_context(|| format!("write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// File-name-safe form of a request path, e.g. `/api/users/7` → `api_users_7`.
+fn slug(path: &str) -> String {
+    let slug: String = path
+        .trim_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(60)
+        .collect();
+    if slug.is_empty() { "root".into() } else { slug }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_slug_keeps_safe_characters_only() {
+        assert_eq!(slug("/api/users/7"), "api_users_7");
+        assert_eq!(slug("/v1.2/files/a-b c?"), "v1.2_files_a-b_c_");
+        assert_eq!(slug("/"), "root");
+        assert_eq!(slug(""), "root");
+        assert_eq!(slug(&format!("/{}", "x".repeat(100))).len(), 60);
+    }
+
+    #[tokio::test]
+    async fn dump_lists_redacted_headers_and_drops_framing() {
+        let dir = std::env::temp_dir().join(format!("http-tap-dump-{}", std::process::id()));
+        let dump = HttpDump::create(&dir).unwrap();
+        let req = Request::post("http://api.example/v1/items?x=1")
+            .header("x-api-key", "k3y")
+            .header("authorization", "Bearer t0ken")
+            .header("content-type", "application/json")
+            .header("content-length", "7")
+            .body(())
+            .unwrap();
+        let redact = vec!["Authorization".to_string(), "X-Api-Key".to_string()];
+
+        let path = dump.write(&req, b"{\"a\":1}", &redact).await.unwrap();
+        let second = dump.write(&req, b"", &[]).await.unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(path.ends_with("00001-POST-v1_items.http"), "{path:?}");
+        assert!(second.ends_with("00002-POST-v1_items.http"), "{second:?}");
+        assert_eq!(
+            text,
+            "# redacted: authorization, x-api-key\n\
+             POST http://api.example/v1/items?x=1 HTTP/1.1\n\
+             authorization: <redacted>\n\
+             content-type: application/json\n\
+             x-api-key: <redacted>\n\
+             \n\
+             {\"a\":1}\n"
+        );
+    }
+}
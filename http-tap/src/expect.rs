@@ -0,0 +1,118 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::Error as HyperError;
+use hyper::body::{Body, Frame, Incoming};
+use hyper::http::HeaderMap;
+use tokio::sync::{oneshot, watch};
+
+/// How long to wait for the upstream's `100 Continue` before sending the body
+/// anyway, matching curl's default.
+pub const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// True when the client will wait for `100 Continue` before sending its body.
+pub fn expects_continue(headers: &HeaderMap) -> bool {
+    headers
+        .get(hyper::http::header::EXPECT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+/// Signal that may be fired from several places (the upstream's
+/// informational callback and the timeout); only the first call counts.
+pub struct Gate(watch::Sender<bool>);
+
+impl Gate {
+    pub fn new() -> Self {
+        Self(watch::Sender::new(false))
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub fn open(&self) {
+        self.0.send_replace(true);
+    }
+
+    async fn opened(&self) {
+        let _ = self.0.subscribe().wait_for(|open| *open).await;
+    }
+}
+
+/// The client's body, shared by every attempt at the upstream so that a retry
+/// after a failed connect can still send it. Whichever attempt reads it hands
+/// the collected bytes to `tee` for logging.
+pub struct HeldBody {
+    body: Mutex<Option<Incoming>>,
+    tee: Mutex<Option<oneshot::Sender<Bytes>>>,
+}
+
+impl HeldBody {
+    pub fn new(body: Incoming, tee: oneshot::Sender<Bytes>) -> Arc<Self> {
+        Arc::new(Self {
+            body: Mutex::new(Some(body)),
+            tee: Mutex::new(Some(tee)),
+        })
+    }
+}
+
+type Collecting = Pin<Box<dyn Future<Output = Result<Bytes, HyperError>> + Send + Sync>>;
+
+/// Request body that only starts reading from the client once the gate opens.
+///
+/// hyper sends the client its `100 Continue` on the first read of the body, so
+/// holding the read back gives the upstream a chance to reject the request
+/// (e.g. 417 or 401) before the client uploads anything.
+pub struct DeferredBody {
+    collecting: Option<Collecting>,
+}
+
+impl DeferredBody {
+    pub fn new(held: Arc<HeldBody>, gate: Arc<Gate>) -> Self {
+        let collecting = async move {
+            gate.opened().await;
+            let Some(body) = held.body.lock().ok().and_then(|mut body| body.take()) else {
+                return Ok(Bytes::new());
+            };
+            let bytes = body.collect().await?.to_bytes();
+            if let Some(tee) = held.tee.lock().ok().and_then(|mut tee| tee.take()) {
+                let _ = tee.send(bytes.clone());
+            }
+            Ok(bytes)
+        };
+        Self {
+            collecting: Some(Box::pin(collecting)),
+        }
+    }
+}
+impl Body for DeferredBody {
+    type Data = Bytes;
+    type Error = HyperError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, HyperError>>> {
+        let this = self.get_mut();
+        let Some(collecting) = &mut this.collecting else {
+            return Poll::Ready(None);
+        };
+        let result = ready!(collecting.as_mut().poll(cx));
+        this.collecting = None;
+        match result {
+            Ok(bytes) if bytes.is_empty() => Poll::Ready(None),
+            Ok(bytes) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.collecting.is_none()
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Write as _;
+
+/// One part of a multipart/form-data body.
+pub struct Part {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub size: usize,
+}
+
+/// Recognised structured body types, detected from the content-type header.
+pub enum FormKind {
+    UrlEncoded,
+    Multipart { boundary: String },
+}
+
+pub fn detect(content_type: &str) -> Option<FormKind> {
+    let mut params = content_type.split(';').map(str::trim);
+    let mime = params.next()?.to_ascii_lowercase();
+    match mime.as_str() {
+        "application/x-www-form-urlencoded" => Some(FormKind::UrlEncoded),
+        "multipart/form-data" => params
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+            .map(|(_, v)| FormKind::Multipart {
+                boundary: v.trim().trim_matches('"').to_string(),
+            }),
+        _ => None,
+    }
+}
+
+/// Render a decoded summary of `body`, one line per field or part, cut
+/// off once the field lines pass `max` bytes (`--max-body-bytes`).
+pub fn summarize(kind: &FormKind, body: &[u8], prefix: &str, max: usize) -> String {
+    let mut out = String::new();
+    match kind {
+        FormKind::UrlEncoded => {
+            let pairs: Vec<_> = form_urlencoded::parse(body).collect();
+            let _ = write!(out, "{prefix} form ({} fields):", pairs.len());
+            for (key, value) in pairs {
+                let _ = write!(out, "\n{prefix}     {key} = {value}");
+            }
+        }
+        FormKind::Multipart { boundary } => {
+            let parts = parse_multipart(body, boundary);
+            let _ = write!(out, "{prefix} multipart ({} parts):", parts.len());
+            for part in parts {
+                let _ = write!(
+                    out,
+                    "\n{prefix}     name={} filename={} content-type={} ({} bytes)",
+                    part.name.as_deref().unwrap_or("-"),
+                    part.filename.as_deref().unwrap_or("-"),
+                    part.content_type.as_deref().unwrap_or("-"),
+                    part.size
+                );
+            }
+        }
+    }
+    truncate_summary(&mut out, prefix, max);
+    out
+}
+
+/// Cut the field lines after the header line off at `max` bytes, the way
+/// plain bodies are cut, leaving a marker behind.
+fn truncate_summary(out: &mut String, prefix: &str, max: usize) {
+    let Some(header_end) = out.find('\n') else {
+        return;
+    };
+    let mut cut = header_end + max;
+    if cut >= out.len() {
+        return;
+    }
+    while !out.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let total = out.len() - header_end;
+    out.truncate(cut);
+    let _ = write!(
+        out,
+        "\n{prefix} … (summary truncated, {max} / {total} bytes)"
+    );
+}
+
+fn parse_multipart(body: &[u8], boundary: &str) -> Vec<Part> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    for section in split_on(body, delimiter.as_bytes()).into_iter().skip(1) {
+        // The closing delimiter is "--boundary--"
+        if section.starts_with(b"--") {
+            break;
+        }
+        let section = section.strip_prefix(b"\r\n").unwrap_or(section);
+        let Some(header_end) = find(section, b"\r\n\r\n") else {
+            continue;
+        };
+        let headers = String::from_utf8_lossy(&section[..header_end]);
+        let content = &section[header_end + 4..];
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+
+        let mut part = Part {
+            name: None,
+            filename: None,
+            content_type: None,
+            size: content.len(),
+        };
+        for line in headers.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if key.trim().eq_ignore_ascii_case("content-disposition") {
+                for param in value.split(';').skip(1) {
+                    if let Some((k, v)) = param.split_once('=') {
+                        let v = v.trim().trim_matches('"').to_string();
+                        match k.trim().to_ascii_lowercase().as_str() {
+                            "name" => part.name = Some(v),
+                            "filename" => part.filename = Some(v),
+                            _ => {}
+                        }
+                    }
+                }
+            } else if key.trim().eq_ignore_ascii_case("content-type") {
+                part.content_type = Some(value.trim().to_string());
+            }
+        }
+        parts.push(part);
+    }
+    parts
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn split_on<'a>(mut haystack: &'a [u8], needle: &[u8]) -> Vec<&'a [u8]> {
+    let mut out = Vec::new();
+    while let Some(pos) = find(haystack, needle) {
+        out.push(&haystack[..pos]);
+        haystack = &haystack[pos + needle.len()..];
+    }
+    out.push(haystack);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forms_summary_respects_max_body_bytes() {
+        let body = format!("short=1&big={}", "x".repeat(500));
+        let full = summarize(&FormKind::UrlEncoded, body.as_bytes(), "→", 4096);
+        assert!(full.contains(&"x".repeat(500)));
+        assert!(!full.contains("truncated"));
+
+        let cut = summarize(&FormKind::UrlEncoded, body.as_bytes(), "→", 64);
+        assert!(cut.starts_with("→ form (2 fields):\n→     short = 1"));
+        assert!(!cut.contains(&"x".repeat(100)));
+        assert!(cut.contains("→ … (summary truncated, 64 / "));
+    }
+}
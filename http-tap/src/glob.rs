@@ -0,0 +1,22 @@
+//! Path globs shared by `--allow`, `--deny` and `--delay`.
+
+/// True when `path` matches `pattern`, where `*` matches within one path
+/// segment, `**` across segments, and `?` any single character but `/`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    match_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn match_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| match_bytes(rest, &path[i..])),
+        [b'*', rest @ ..] => {
+            let segment = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+            (0..=segment).any(|i| match_bytes(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => {
+            matches!(path, [c, tail @ ..] if *c != b'/' && match_bytes(rest, tail))
+        }
+        [c, rest @ ..] => matches!(path, [p, tail @ ..] if p == c && match_bytes(rest, tail)),
+    }
+}
@@ -0,0 +1,201 @@
+use std::io;
+use std::os::raw::c_int;
+use std::ptr;
+
+use hyper::http::HeaderValue;
+use libz_sys as z;
+
+/// Window bits for zlib: 15 is the maximum window; +16 writes a gzip wrapper,
+/// +32 auto-detects a gzip or zlib wrapper when inflating.
+const GZIP_WINDOW_BITS: c_int = 15 + 16;
+const AUTO_WINDOW_BITS: c_int = 15 + 32;
+const INFLATE_CHUNK: usize = 64 * 1024;
+
+/// Whether an Accept-Encoding value allows `coding` (gzip, deflate, ...).
+pub fn accepts(accept_encoding: Option<&HeaderValue>, coding: &str) -> bool {
+    let Some(value) = accept_encoding.and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value.split(',').any(|item| {
+        let mut params = item.split(';').map(str::trim);
+        let name = params.next().unwrap_or("").to_ascii_lowercase();
+        let q = params
+            .filter_map(|p| p.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let matches = name == coding || name == "*" || (coding == "gzip" && name == "x-gzip");
+        matches && q > 0.0
+    })
+}
+
+/// Gzip-compress `data` in one pass.
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut strm = new_stream();
+    check(unsafe {
+        z::deflateInit2_(
+            &mut strm,
+            z::Z_DEFAULT_COMPRESSION,
+            z::Z_DEFLATED,
+            GZIP_WINDOW_BITS,
+            8,
+            z::Z_DEFAULT_STRATEGY,
+            z::zlibVersion(),
+            size_of::<z::z_stream>() as c_int,
+        )
+    })?;
+
+    let bound = unsafe { z::deflateBound(&mut strm, data.len() as z::uLong) } as usize;
+    let mut out = vec![0u8; bound];
+    strm.next_in = data.as_ptr() as *mut u8;
+    strm.avail_in = len_u32(data.len())?;
+    strm.next_out = out.as_mut_ptr();
+    strm.avail_out = len_u32(out.len())?;
+    let rc = unsafe { z::deflate(&mut strm, z::Z_FINISH) };
+    let written = strm.total_out as usize;
+    unsafe { z::deflateEnd(&mut strm) };
+
+    if rc != z::Z_STREAM_END {
+        return Err(zlib_error("deflate", rc));
+    }
+    out.truncate(written);
+    Ok(out)
+}
+
+/// Decode a gzip (or zlib-wrapped deflate) body.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut strm = new_stream();
+    check(unsafe {
+        z::inflateInit2_(
+            &mut strm,
+            AUTO_WINDOW_BITS,
+            z::zlibVersion(),
+            size_of::<z::z_stream>() as c_int,
+        )
+    })?;
+
+    strm.next_in = data.as_ptr() as *mut u8;
+    strm.avail_in = len_u32(data.len())?;
+    let mut out: Vec<u8> = Vec::new();
+    let result = loop {
+        out.reserve(INFLATE_CHUNK);
+        let spare = out.spare_capacity_mut();
+        let room = spare.len().min(u32::MAX as usize);
+        strm.next_out = spare.as_mut_ptr().cast();
+        strm.avail_out = room as u32;
+        let rc = unsafe { z::inflate(&mut strm, z::Z_NO_FLUSH) };
+        let produced = room - strm.avail_out as usize;
+        // SAFETY: zlib initialised `produced` bytes of the spare capacity
+        unsafe { out.set_len(out.len() + produced) };
+        match rc {
+            z::Z_STREAM_END => break Ok(()),
+            z::Z_OK => continue,
+            z::Z_BUF_ERROR if strm.avail_in == 0 => {
+                break Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated gzip body",
+                ));
+            }
+            rc => break Err(zlib_error("inflate", rc)),
+        }
+    };
+    unsafe { z::inflateEnd(&mut strm) };
+    result.map(|()| out)
+}
+
+fn new_stream() -> z::z_stream {
+    z::z_stream {
+        next_in: ptr::null_mut(),
+        avail_in: 0,
+        total_in: 0,
+        next_out: ptr::null_mut(),
+        avail_out: 0,
+        total_out: 0,
+        msg: ptr::null_mut(),
+        state: ptr::null_mut(),
+        zalloc,
+        zfree,
+        opaque: ptr::null_mut(),
+        data_type: 0,
+        adler: 0,
+        reserved: 0,
+    }
+}
+
+unsafe extern "C" fn zalloc(_opaque: z::voidpf, items: z::uInt, size: z::uInt) -> z::voidpf {
+    unsafe { libc::calloc(items as usize, size as usize) }
+}
+
+unsafe extern "C" fn zfree(_opaque: z::voidpf, address: z::voidpf) {
+    unsafe { libc::free(address) }
+}
+
+fn len_u32(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| io::Error::other("body too large for gzip"))
+}
+
+fn check(rc: c_int) -> io::Result<()> {
+    if rc == z::Z_OK {
+        Ok(())
+    } else {
+        Err(zlib_error("init", rc))
+    }
+}
+
+fn zlib_error(op: &str, rc: c_int) -> io::Error {
+    let kind = match rc {
+        z::Z_DATA_ERROR => io::ErrorKind::InvalidData,
+        z::Z_MEM_ERROR => io::ErrorKind::OutOfMemory,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("zlib {op} failed ({rc})"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trips_including_empty_bodies() {
+        let text = b"hello hello hello hello hello, tap".repeat(100);
+        let packed = compress(&text).unwrap();
+        assert!(packed.len() < text.len());
+        assert_eq!(&packed[..2], [0x1f, 0x8b]);
+        assert_eq!(decompress(&packed).unwrap(), text);
+
+        let empty = compress(b"").unwrap();
+        assert!(!empty.is_empty());
+        assert_eq!(decompress(&empty).unwrap(), b"");
+    }
+
+    #[test]
+    fn gzip_rejects_truncated_corrupt_and_empty_input() {
+        let packed = compress(&b"abcdefgh".repeat(1000)).unwrap();
+
+        let truncated = decompress(&packed[..packed.len() / 2]).unwrap_err();
+        assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut corrupt = packed.clone();
+        corrupt[12] ^= 0xff;
+        corrupt[13] ^= 0xff;
+        assert!(decompress(&corrupt).is_err());
+
+        let not_gzip = decompress(b"plain text, not compressed").unwrap_err();
+        assert_eq!(not_gzip.kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(
+            decompress(b"").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn gzip_accepts_honours_q_values_and_aliases() {
+        let value = |v| HeaderValue::from_static(v);
+        assert!(accepts(Some(&value("gzip, br")), "gzip"));
+        assert!(accepts(Some(&value("x-gzip")), "gzip"));
+        assert!(accepts(Some(&value("*;q=0.5")), "deflate"));
+        assert!(!accepts(Some(&value("gzip;q=0")), "gzip"));
+        assert!(!accepts(Some(&value("br")), "gzip"));
+        assert!(!accepts(None, "gzip"));
+    }
+}
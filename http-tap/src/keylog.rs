@@ -0,0 +1,46 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use rustls::KeyLog;
+
+/// Key log for both the listen-side and upstream TLS configs.
+///
+/// An explicit `--keylog` path wins; otherwise rustls' `KeyLogFile` honours
+/// `SSLKEYLOGFILE` and does nothing when it is unset.
+pub fn key_log(path: Option<&Path>) -> anyhow::Result<Arc<dyn KeyLog>> {
+    match path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("open --keylog {}", path.display()))?;
+            Ok(Arc::new(KeyLogPath {
+                file: Mutex::new(file),
+            }))
+        }
+        None => Ok(Arc::new(rustls::KeyLogFile::new())),
+    }
+}
+
+/// Writes secrets in the NSS key log format understood by Wireshark.
+#[derive(Debug)]
+struct KeyLogPath {
+    file: Mutex<File>,
+}
+
+impl KeyLog for KeyLogPath {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!("{label} {} {}\n", hex(client_random), hex(secret));
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
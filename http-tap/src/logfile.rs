@@ -0,0 +1,180 @@
+//! `--log-file` and `--log-max-size`: the log sink, with size-based rotation.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context as _;
+
+/// Number of rotated files kept next to the live log (tap.log.1 … tap.log.N).
+const MAX_ROTATED: usize = 5;
+
+/// Destination for request/response log entries.
+#[derive(Clone)]
+pub enum LogSink {
+    Stdout,
+    File(Arc<Mutex<RotatingFile>>),
+}
+
+impl LogSink {
+    pub fn file(path: &Path, max_size: u64) -> anyhow::Result<Self> {
+        let file = RotatingFile::open(path, max_size)
+            .with_context(|| format!("open log file {}", path.display()))?;
+        Ok(LogSink::File(Arc::new(Mutex::new(file))))
+    }
+
+    /// Write one log entry; a trailing newline is added.
+    pub fn write_entry(&self, entry: &str) {
+        match self {
+            LogSink::Stdout => {
+                let mut out = io::stdout().lock();
+                let _ = writeln!(out, "{entry}");
+            }
+            LogSink::File(file) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                if let Err(e) = file.write_entry(entry) {
+                    eprintln!(
+                        "Warning: failed to write log file {}: {e}",
+                        file.path.display()
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            file,
+            written,
+        })
+    }
+
+    fn write_entry(&mut self, entry: &str) -> io::Result<()> {
+        let len = entry.len() as u64 + 1;
+        if self.written > 0 && self.written + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(entry.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for i in (1..MAX_ROTATED).rev() {
+            let from = rotated_path(&self.path, i);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, i + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test's log files, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(label: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("http-tap-logfile-{label}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn logfile_rotates_when_the_next_entry_would_pass_the_limit() {
+        let dir = TempDir::new("boundary");
+        let path = dir.0.join("tap.log");
+        let mut file = RotatingFile::open(&path, 10).unwrap();
+
+        // "1234" plus its newline is 5 bytes; two of them fill the limit exactly
+        file.write_entry("1234").unwrap();
+        file.write_entry("5678").unwrap();
+        assert_eq!(read(&path), "1234\n5678\n");
+        assert!(!rotated_path(&path, 1).exists());
+
+        file.write_entry("9").unwrap();
+        assert_eq!(read(&path), "9\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "1234\n5678\n");
+
+        // An entry larger than the limit still goes in whole, on its own
+        file.write_entry("much longer than ten bytes").unwrap();
+        assert_eq!(read(&path), "much longer than ten bytes\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "9\n");
+    }
+
+    #[test]
+    fn logfile_keeps_five_rotations_and_drops_the_oldest() {
+        let dir = TempDir::new("shift");
+        let path = dir.0.join("tap.log");
+        let mut file = RotatingFile::open(&path, 2).unwrap();
+        for entry in ["a", "b", "c", "d", "e", "f", "g"] {
+            file.write_entry(entry).unwrap();
+        }
+
+        assert_eq!(read(&path), "g\n");
+        for (index, entry) in [(1, "f"), (2, "e"), (3, "d"), (4, "c"), (5, "b")] {
+            assert_eq!(read(&rotated_path(&path, index)), format!("{entry}\n"));
+        }
+        assert!(!rotated_path(&path, 6).exists());
+    }
+
+    #[test]
+    fn logfile_reopening_counts_what_is_already_there() {
+        let dir = TempDir::new("reopen");
+        let path = dir.0.join("tap.log");
+        fs::write(&path, "12345678\n").unwrap();
+
+        let mut file = RotatingFile::open(&path, 12).unwrap();
+        file.write_entry("ab").unwrap();
+        assert_eq!(read(&path), "12345678\nab\n");
+        file.write_entry("cd").unwrap();
+        assert_eq!(read(&path), "cd\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "12345678\nab\n");
+    }
+}
@@ -1,84 +1,375 @@
+mod acl;
 mod cli;
+mod connector;
+mod cookies;
+mod delay;
+mod dump;
+mod expect;
+mod forms;
+mod glob;
+mod gzip;
+mod json;
+mod keylog;
+mod logfile;
+mod otel;
 mod proxy;
+mod regex;
+mod rewrite;
 mod stats;
+mod streaming;
+mod throttle;
+mod timefmt;
 mod tui;
+mod upstream_proxy;
+mod websocket;
 
-use anyhow::Result;
+use acl::Acl;
+use anyhow::{Context as _, Result};
 use clap::Parser;
 use cli::Cli;
-use proxy::{run_proxy, Config, TlsConfig};
-use stats::channel as stats_channel;
+use dump::HttpDump;
+use hyper::http::{HeaderValue, Uri};
+use logfile::LogSink;
+use otel::Tracer;
+use proxy::{Config, TlsConfig, run_proxy};
+use rcgen::{Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyUsagePurpose};
+use rewrite::BodyRewrite;
+use rustls::{KeyLog, ServerConfig, pki_types::CertificateDer, pki_types::PrivateKeyDer};
+use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use stats::{Aggregator, StatsReceiver, channel as stats_channel};
 use std::fs::File;
 use std::io::BufReader;
-use rustls::{pki_types::CertificateDer, pki_types::PrivateKeyDer, ServerConfig};
+use std::net::Ipv6Addr;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use timefmt::{Clock, TimeFormat};
 use tokio_rustls::TlsAcceptor;
-use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
-use rcgen::{CertificateParams, Certificate, IsCa, KeyUsagePurpose, ExtendedKeyUsagePurpose};
-
-fn normalize_target(target: &str) -> (String, &'static str) {
-    // Accept host:port or full http(s)://host[:port]
-    if let Some(rest) = target.strip_prefix("http://") {
-        (rest.to_string(), "http")
-    } else if let Some(rest) = target.strip_prefix("https://") {
-        (rest.to_string(), "https")
+use upstream_proxy::UpstreamProxy;
+
+fn normalize_target(target: &str) -> Result<(String, &'static str, Option<String>)> {
+    // Accept host:port or full http(s)://host[:port][/base/path]
+    if (target.starts_with("http://") || target.starts_with("https://"))
+        && let Ok(uri) = target.parse::<Uri>()
+    {
+        let scheme = if uri.scheme_str() == Some("https") {
+            "https"
+        } else {
+            "http"
+        };
+        let authority = uri
+            .authority()
+            .map(|a| a.as_str().to_string())
+            .unwrap_or_default();
+        // Keep any base path so requests are joined onto it, e.g. /v1 + /users.
+        let base_path = match uri.path().trim_end_matches('/') {
+            "" => None,
+            path => Some(path.to_string()),
+        };
+        Ok((authority, scheme, base_path))
+    } else if let Some(rest) = target.strip_prefix('[') {
+        // Bracketed IPv6 literal: [addr] or [addr]:port. Keep the brackets so
+        // the authority stays valid inside a URI.
+        let Some((addr, port_part)) = rest.split_once(']') else {
+            anyhow::bail!("invalid --target '{target}': missing ']' after IPv6 address");
+        };
+        if port_part.is_empty() {
+            return Ok((format!("[{}]", addr), "http", None));
+        }
+        match port_part.strip_prefix(':').map(str::parse::<u16>) {
+            Some(Ok(port)) => {
+                let scheme = if port == 443 { "https" } else { "http" };
+                Ok((format!("[{}]:{}", addr, port), scheme, None))
+            }
+            _ => anyhow::bail!(
+                "invalid --target '{target}': expected [addr]:port with a numeric port"
+            ),
+        }
+    } else if target.parse::<Ipv6Addr>().is_ok() {
+        // Bare IPv6 literal without a port; rsplit_once(':') would mangle it.
+        Ok((format!("[{}]", target), "http", None))
     } else if let Some((host, port_str)) = target.rsplit_once(':') {
         if let Ok(port) = port_str.parse::<u16>() {
             let scheme = if port == 443 { "https" } else { "http" };
-            (format!("{}:{}", host, port), scheme)
+            Ok((format!("{}:{}", host, port), scheme, None))
         } else {
-            (target.to_string(), "http")
+            Ok((target.to_string(), "http", None))
         }
     } else {
-        (target.to_string(), "http")
+        Ok((target.to_string(), "http", None))
     }
 }
 
+/// Normalise a --strip-prefix/--add-prefix value to `/segment` form; `/` alone means none.
+fn path_prefix(prefix: &str) -> Option<String> {
+    let trimmed = prefix.trim_matches('/');
+    (!trimmed.is_empty()).then(|| format!("/{trimmed}"))
+}
+
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let listen = cli.listen_addr()?;
-    let (authority, scheme) = normalize_target(&cli.target);
+    let listen = cli.listen_addr().await?;
+    let (authority, scheme, base_path) = normalize_target(&cli.target)?;
+
+    let key_log = keylog::key_log(cli.keylog.as_deref())?;
 
     let tls_acceptor = if cli.listen_self_signed {
-        Some(build_self_signed_acceptor()?)
+        Some(build_self_signed_acceptor(key_log.clone())?)
     } else if cli.listen_tls_cert.is_some() || cli.listen_tls_key.is_some() {
-        Some(build_tls_acceptor(&cli)? )
-    } else { None };
+        Some(build_tls_acceptor(&cli, key_log.clone())?)
+    } else {
+        None
+    };
+
+    let upstream_basic_auth = cli
+        .upstream_basic_auth
+        .as_deref()
+        .map(basic_auth_header)
+        .transpose()?;
+
+    let upstream_proxy = match cli.upstream_proxy.as_deref() {
+        Some(url) => {
+            let no_proxy = std::env::var("NO_PROXY")
+                .or_else(|_| std::env::var("no_proxy"))
+                .unwrap_or_default();
+            upstream_proxy(url, &authority, &no_proxy)?
+        }
+        None => None,
+    };
 
-    let (stats_tx, stats_rx) = if cli.tui { let (tx, rx) = stats_channel(); (Some(tx), Some(rx)) } else { (None, None) };
+    let clock = Clock::new(TimeFormat::parse(&cli.time_format)?, cli.local_time);
+
+    let log = match &cli.log_file {
+        Some(path) => LogSink::file(path, cli.log_max_size)?,
+        None => LogSink::Stdout,
+    };
+
+    // Stats are aggregated for the TUI, or in the background when they must be persisted
+    let want_stats =
+        cli.tui || cli.stats_db.is_some() || cli.summary || cli.periodic_summary.is_some();
+    let (stats_tx, stats_rx) = if want_stats {
+        let (tx, rx) = stats_channel();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+    let stats_db = cli.stats_db.clone();
+    let mut initial_stats = match &stats_db {
+        Some(path) if path.exists() => Aggregator::load(path)?,
+        _ => Aggregator::default(),
+    };
+    initial_stats.set_latency_window(cli.latency_window);
+    initial_stats.set_by_ip(cli.by_ip);
 
     let cfg = Config {
         listen,
         target_authority: authority,
         target_scheme: if scheme == "https" { "https" } else { "http" },
+        target_base_path: base_path,
+        log_format: cli.log_format,
+        log,
+        dump_http: cli.dump_http.as_deref().map(HttpDump::create).transpose()?,
+        clock,
         include_bodies: cli.include_bodies,
         max_body_bytes: cli.max_body_bytes,
+        force_hex: cli.force_hex,
         redact_header: cli.redact_header,
+        show_cookies: cli.show_cookies,
+        redact_cookie: cli.redact_cookie,
         tls: tls_acceptor.map(|a| TlsConfig { acceptor: a }),
         insecure_upstream: cli.insecure_upstream,
+        key_log,
         stats: stats_tx,
         upstream_ca: cli.upstream_ca,
         upstream_client_cert: cli.upstream_client_cert,
         upstream_client_key: cli.upstream_client_key,
         upstream_server_name: cli.upstream_server_name,
         upstream_host: cli.upstream_host,
+        preserve_host: cli.preserve_host,
+        keep_header: cli.keep_header,
+        upstream_basic_auth,
+        upstream_retries: cli.upstream_retries,
+        upstream_bandwidth: cli.upstream_bandwidth,
+        delay: cli.delay,
+        upstream_proxy,
+        forward_proxy: cli.forward_proxy,
+        max_connections: cli.max_connections.map(NonZeroUsize::get),
+        listen_backlog: cli.listen_backlog,
+        tcp_nodelay: cli.tcp_nodelay,
+        health_path: cli.health_path,
+        strip_prefix: cli.strip_prefix.as_deref().and_then(path_prefix),
+        acl: Acl::new(cli.allow, cli.deny),
+        add_prefix: cli.add_prefix.as_deref().and_then(path_prefix),
+        recompress: cli.recompress,
+        body_replace: body_rewrites(&cli.body_replace, &cli.body_replace_regex)?,
+        body_replace_binary: cli.body_replace_binary,
+        tracer: cli
+            .otlp_endpoint
+            .as_deref()
+            .map(Tracer::start)
+            .transpose()?,
+    };
+
+    let Some(rx) = stats_rx else {
+        run_proxy(cfg).await?;
+        return Ok(());
     };
 
-    if let Some(rx) = stats_rx {
+    let agg = if cli.tui {
         // Run proxy in background and TUI in foreground
-        let proxy_task = tokio::spawn(async move { let _ = run_proxy(cfg).await; });
-        tui::run_tui(rx).await?;
+        let proxy_task = tokio::spawn(async move {
+            let _ = run_proxy(cfg).await;
+        });
+        let agg = tui::run_tui(rx, initial_stats).await?;
         // TUI exited; proxy task ends when process exits
         drop(proxy_task);
-        Ok(())
+        agg
     } else {
-        run_proxy(cfg).await?;
-        Ok(())
+        collect_stats(rx, initial_stats, cfg, cli.periodic_summary).await?
+    };
+
+    if cli.tui || cli.summary {
+        eprint!("{}", agg.summary());
     }
+    if let Some(path) = &stats_db {
+        agg.save(path)?;
+        eprintln!("Saved stats to {}", path.display());
+    }
+    Ok(())
 }
 
-fn build_tls_acceptor(cli: &Cli) -> Result<TlsAcceptor> {
+/// Aggregate stats without the TUI until Ctrl-C (or the proxy stops),
+/// printing the summary to stderr every `periodic` if set.
+async fn collect_stats(
+    mut rx: StatsReceiver,
+    mut agg: Aggregator,
+    cfg: Config,
+    periodic: Option<Duration>,
+) -> Result<Aggregator> {
+    let proxy = run_proxy(cfg);
+    tokio::pin!(proxy);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    // The ticker only fires when `periodic` is set; the fallback just keeps it constructible
+    let period = periodic.unwrap_or(Duration::from_secs(3600));
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        tokio::select! {
+            Some(ev) = rx.recv() => agg.apply(ev),
+            _ = ticker.tick(), if periodic.is_some() => eprint!("\n{}", agg.summary()),
+            res = &mut proxy => {
+                res?;
+                break;
+            }
+            _ = &mut ctrl_c => break,
+        }
+    }
+    // Requests that finished just before shutdown are still queued
+    agg.drain(&mut rx);
+    Ok(agg)
+}
+
+/// Literal rules run before regex rules, each in command-line order.
+fn body_rewrites(literal: &[String], regex: &[String]) -> Result<Vec<BodyRewrite>> {
+    let literal = literal
+        .iter()
+        .map(|spec| BodyRewrite::literal(spec).context("--body-replace"));
+    let regex = regex
+        .iter()
+        .map(|spec| BodyRewrite::regex(spec).context("--body-replace-regex"));
+    literal.chain(regex).collect()
+}
+
+fn basic_auth_header(credentials: &str) -> Result<HeaderValue> {
+    if !credentials.contains(':') {
+        anyhow::bail!("--upstream-basic-auth expects user:pass");
+    }
+    let encoded = base64_encode(credentials.as_bytes());
+    let mut value = HeaderValue::from_str(&format!("Basic {encoded}"))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Parse `--upstream-proxy http://[user:pass@]host:port`. Returns None when
+/// the target host is listed in `no_proxy` (the NO_PROXY variable).
+fn upstream_proxy(
+    url: &str,
+    target_authority: &str,
+    no_proxy: &str,
+) -> Result<Option<UpstreamProxy>> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid --upstream-proxy {url}: {e}"))?;
+    if uri.scheme_str().is_some_and(|s| s != "http") {
+        anyhow::bail!("--upstream-proxy must be an http:// URL");
+    }
+    let authority = uri
+        .authority()
+        .ok_or_else(|| anyhow::anyhow!("--upstream-proxy {url} has no host"))?;
+    let (userinfo, host_port) = match authority.as_str().rsplit_once('@') {
+        Some((userinfo, host_port)) => (Some(userinfo), host_port),
+        None => (None, authority.as_str()),
+    };
+    let port = authority.port_u16().unwrap_or(80);
+    let host = host_port
+        .strip_suffix(&format!(":{port}"))
+        .unwrap_or(host_port);
+
+    let target_host = format!("http://{target_authority}/")
+        .parse::<Uri>()
+        .ok()
+        .and_then(|u| u.host().map(str::to_string))
+        .unwrap_or_else(|| target_authority.to_string());
+    if upstream_proxy::no_proxy_matches(no_proxy, &target_host) {
+        eprintln!("{target_host} matches NO_PROXY; connecting to it directly");
+        return Ok(None);
+    }
+
+    let auth = match userinfo {
+        Some(userinfo) => {
+            let decoded = percent_encoding::percent_decode_str(userinfo).decode_utf8()?;
+            let mut value =
+                HeaderValue::from_str(&format!("Basic {}", base64_encode(decoded.as_bytes())))?;
+            value.set_sensitive(true);
+            Some(value)
+        }
+        None => None,
+    };
+    Ok(Some(UpstreamProxy {
+        authority: format!("{host}:{port}"),
+        auth,
+    }))
+}
+
+fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+fn build_tls_acceptor(cli: &Cli, key_log: Arc<dyn KeyLog>) -> Result<TlsAcceptor> {
     let cert_path = cli
         .listen_tls_cert
         .as_ref()
@@ -91,11 +382,8 @@ fn build_tls_acceptor(cli: &Cli) -> Result<TlsAcceptor> {
     let mut cert_reader = BufReader::new(File::open(cert_path)?);
     let mut key_reader = BufReader::new(File::open(key_path)?);
 
-    let certs_der: Vec<CertificateDer> = certs(&mut cert_reader)
-        .collect::<std::result::Result<Vec<_>, _>>()?
-        .into_iter()
-        .map(CertificateDer::from)
-        .collect();
+    let certs_der: Vec<CertificateDer> =
+        certs(&mut cert_reader).collect::<std::result::Result<Vec<_>, _>>()?;
 
     if certs_der.is_empty() {
         anyhow::bail!("no certificates found in {}", cert_path.display());
@@ -122,14 +410,15 @@ fn build_tls_acceptor(cli: &Cli) -> Result<TlsAcceptor> {
         anyhow::bail!("no private keys found in {}", key_path.display());
     };
 
-    let server_config = ServerConfig::builder()
+    let mut server_config = ServerConfig::builder()
         .with_no_client_auth()
         .with_single_cert(certs_der, key_der)?;
+    server_config.key_log = key_log;
 
-    Ok(TlsAcceptor::from(std::sync::Arc::new(server_config)))
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
 }
 
-fn build_self_signed_acceptor() -> Result<TlsAcceptor> {
+fn build_self_signed_acceptor(key_log: Arc<dyn KeyLog>) -> Result<TlsAcceptor> {
     // SANs for typical local dev
     let mut params = CertificateParams::new(vec![
         "localhost".to_string(),
@@ -138,7 +427,10 @@ fn build_self_signed_acceptor() -> Result<TlsAcceptor> {
     ]);
     // Server usage
     params.is_ca = IsCa::ExplicitNoCa;
-    params.key_usages = vec![KeyUsagePurpose::DigitalSignature, KeyUsagePurpose::KeyEncipherment];
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
     params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
 
     let cert = Certificate::from_params(params)?;
@@ -146,9 +438,109 @@ fn build_self_signed_acceptor() -> Result<TlsAcceptor> {
     let certs_der: Vec<CertificateDer> = vec![CertificateDer::from(cert.serialize_der()?)];
     let key_der = PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into());
 
-    let server_config = ServerConfig::builder()
+    let mut server_config = ServerConfig::builder()
         .with_no_client_auth()
         .with_single_cert(certs_der, key_der)?;
+    server_config.key_log = key_log;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_target, path_prefix, upstream_proxy};
 
-    Ok(TlsAcceptor::from(std::sync::Arc::new(server_config)))
+    #[test]
+    fn main_log_max_size_must_be_positive() {
+        use clap::Parser as _;
+
+        let parse = |size: &str| {
+            crate::cli::Cli::try_parse_from([
+                "http-tap",
+                "--target",
+                "localhost:8080",
+                "--log-max-size",
+                size,
+            ])
+        };
+        assert!(parse("0").is_err());
+        assert_eq!(parse("1").unwrap().log_max_size, 1);
+    }
+
+    #[test]
+    fn main_normalize_target_bracketed_ipv6_https() {
+        let (authority, scheme, base_path) = normalize_target("[2001:db8::1]:443").unwrap();
+        assert_eq!(authority, "[2001:db8::1]:443");
+        assert_eq!(scheme, "https");
+        assert_eq!(base_path, None);
+    }
+
+    #[test]
+    fn main_normalize_target_keeps_the_base_path() {
+        let (authority, scheme, base_path) = normalize_target("http://host/api").unwrap();
+        assert_eq!((authority.as_str(), scheme), ("host", "http"));
+        assert_eq!(base_path.as_deref(), Some("/api"));
+
+        let (_, _, base_path) = normalize_target("http://host/api/").unwrap();
+        assert_eq!(base_path.as_deref(), Some("/api"));
+
+        let (authority, scheme, base_path) = normalize_target("https://host:8443/").unwrap();
+        assert_eq!((authority.as_str(), scheme), ("host:8443", "https"));
+        assert_eq!(base_path, None);
+    }
+
+    #[test]
+    fn main_path_prefix_is_one_leading_slash_and_no_trailing_one() {
+        assert_eq!(path_prefix("/api").as_deref(), Some("/api"));
+        assert_eq!(path_prefix("api").as_deref(), Some("/api"));
+        assert_eq!(path_prefix("/api/").as_deref(), Some("/api"));
+        assert_eq!(path_prefix("api/v1/").as_deref(), Some("/api/v1"));
+        assert_eq!(path_prefix("/"), None);
+        assert_eq!(path_prefix(""), None);
+    }
+
+    #[test]
+    fn main_normalize_target_rejects_bad_ipv6_port() {
+        assert!(normalize_target("[::1]:abc").is_err());
+        assert!(normalize_target("[::1]:").is_err());
+        assert!(normalize_target("[::1").is_err());
+        let (authority, scheme, _) = normalize_target("[::1]").unwrap();
+        assert_eq!((authority.as_str(), scheme), ("[::1]", "http"));
+    }
+
+    #[test]
+    fn main_upstream_proxy_parses_host_port_and_credentials() {
+        let proxy = upstream_proxy("http://proxy.internal:3128", "api:80", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!(proxy.authority, "proxy.internal:3128");
+        assert!(proxy.auth.is_none());
+
+        let proxy = upstream_proxy("http://al%40ce:p%3Ass@[::1]", "api:80", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!(proxy.authority, "[::1]:80");
+        let auth = proxy.auth.unwrap();
+        assert!(auth.is_sensitive());
+        // base64("al@ce:p:ss")
+        assert_eq!(auth, "Basic YWxAY2U6cDpzcw==");
+
+        assert!(upstream_proxy("https://proxy:3128", "api:80", "").is_err());
+        assert!(upstream_proxy("http://", "api:80", "").is_err());
+    }
+
+    #[test]
+    fn main_upstream_proxy_skipped_for_no_proxy_targets() {
+        let no_proxy = "localhost, .corp.example:8443";
+        assert!(
+            upstream_proxy("http://proxy:3128", "db.corp.example:5432", no_proxy)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            upstream_proxy("http://proxy:3128", "api.example:443", no_proxy)
+                .unwrap()
+                .is_some()
+        );
+    }
 }
@@ -0,0 +1,347 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::http::{HeaderMap, HeaderValue, Method, Request, Uri};
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use tokio::sync::mpsc;
+
+use crate::json;
+
+/// Spans are sent in batches at most this far apart.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BATCH: usize = 512;
+const SERVICE_NAME: &str = "us-http-tap";
+/// OTLP span kind for an outgoing call.
+const SPAN_KIND_CLIENT: u8 = 3;
+const STATUS_CODE_ERROR: u8 = 2;
+/// `traceparent` flags for a trace this tap starts: sampled.
+const FLAG_SAMPLED: u8 = 0x01;
+
+/// Records one span per proxied request and exports them over OTLP/HTTP
+/// (JSON encoding) from a background task.
+#[derive(Clone)]
+pub struct Tracer {
+    tx: mpsc::UnboundedSender<(Span, SystemTime)>,
+}
+
+pub struct Span {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    /// Trace flags passed on to the upstream, as the client sent them
+    flags: u8,
+    name: String,
+    start: SystemTime,
+    attributes: Vec<(&'static str, AttrValue)>,
+    error: bool,
+}
+
+enum AttrValue {
+    Str(String),
+    Int(i64),
+}
+
+impl Tracer {
+    /// `endpoint` is the collector base URL (e.g. http://localhost:4318);
+    /// spans are POSTed to its `/v1/traces`.
+    pub fn start(endpoint: &str) -> anyhow::Result<Self> {
+        let base = endpoint.trim_end_matches('/');
+        let url = if base.ends_with("/v1/traces") {
+            base.to_string()
+        } else {
+            format!("{base}/v1/traces")
+        };
+        let url: Uri = url
+            .parse()
+            .with_context(|| format!("invalid --otlp-endpoint {endpoint}"))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(export_loop(url, rx));
+        Ok(Self { tx })
+    }
+
+    /// Start a span for a request, continuing the client's `traceparent` if it sent one.
+    pub fn start_span(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        peer: &SocketAddr,
+    ) -> Span {
+        let parent = headers
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_traceparent);
+        let (trace_id, parent_span_id, flags) = match parent {
+            Some((trace_id, span_id, flags)) => (trace_id, Some(span_id), flags),
+            None => (random_id(), None, FLAG_SAMPLED),
+        };
+        Span {
+            trace_id,
+            span_id: random_id(),
+            parent_span_id,
+            flags,
+            name: format!("{method} {}", uri.path()),
+            start: SystemTime::now(),
+            attributes: vec![
+                ("http.method", AttrValue::Str(method.to_string())),
+                ("http.route", AttrValue::Str(uri.path().to_string())),
+                ("peer.ip", AttrValue::Str(peer.ip().to_string())),
+            ],
+            error: false,
+        }
+    }
+
+    /// Queue the span for export, unless the client's `traceparent` left it
+    /// unsampled; that decision is only passed on upstream.
+    pub fn finish(&self, mut span: Span, status: u16) {
+        if span.flags & FLAG_SAMPLED == 0 {
+            return;
+        }
+        span.attributes
+            .push(("http.status_code", AttrValue::Int(i64::from(status))));
+        span.error = status >= 500;
+        let _ = self.tx.send((span, SystemTime::now()));
+    }
+}
+
+impl Span {
+    /// W3C `traceparent` naming this span as the parent of the upstream request.
+    pub fn traceparent(&self) -> HeaderValue {
+        let value = format!(
+            "00-{}-{}-{:02x}",
+            hex(&self.trace_id),
+            hex(&self.span_id),
+            self.flags
+        );
+        HeaderValue::from_str(&value).expect("hex traceparent")
+    }
+
+    fn to_json(&self, end: SystemTime) -> String {
+        let attributes: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    AttrValue::Str(s) => json::Object::new().str("stringValue", s).finish(),
+                    // int64 values are strings in the OTLP JSON mapping
+                    AttrValue::Int(n) => {
+                        json::Object::new().str("intValue", &n.to_string()).finish()
+                    }
+                };
+                json::Object::new()
+                    .str("key", key)
+                    .raw("value", &value)
+                    .finish()
+            })
+            .collect();
+        let mut obj = json::Object::new()
+            .str("traceId", &hex(&self.trace_id))
+            .str("spanId", &hex(&self.span_id));
+        if let Some(parent) = &self.parent_span_id {
+            obj = obj.str("parentSpanId", &hex(parent));
+        }
+        obj = obj
+            .str("name", &self.name)
+            .num("kind", SPAN_KIND_CLIENT)
+            .str("startTimeUnixNano", &unix_nanos(self.start).to_string())
+            .str("endTimeUnixNano", &unix_nanos(end).to_string())
+            .raw("attributes", &format!("[{}]", attributes.join(",")));
+        if self.error {
+            obj = obj.raw(
+                "status",
+                &json::Object::new().num("code", STATUS_CODE_ERROR).finish(),
+            );
+        }
+        obj.finish()
+    }
+}
+
+async fn export_loop(url: Uri, mut rx: mpsc::UnboundedReceiver<(Span, SystemTime)>) {
+    let connector = match HttpsConnectorBuilder::new().with_native_roots() {
+        Ok(builder) => builder.https_or_http().enable_http1().build(),
+        Err(e) => {
+            eprintln!("OTLP export disabled: no TLS roots: {e}");
+            return;
+        }
+    };
+    let client = Client::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(connector);
+    let mut batch = Vec::new();
+    while let Some(first) = rx.recv().await {
+        batch.push(first);
+        let deadline = tokio::time::sleep(EXPORT_INTERVAL);
+        tokio::pin!(deadline);
+        while batch.len() < MAX_BATCH {
+            tokio::select! {
+                next = rx.recv() => match next {
+                    Some(span) => batch.push(span),
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+
+        let spans: Vec<String> = batch
+            .drain(..)
+            .map(|(span, end)| span.to_json(end))
+            .collect();
+        let req = Request::post(url.clone())
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from(export_request(&spans))))
+            .expect("build OTLP request");
+        match client.request(req).await {
+            Ok(resp) if resp.status().is_success() => {
+                let _ = resp.into_body().collect().await;
+            }
+            Ok(resp) => eprintln!("OTLP export to {url} failed: {}", resp.status()),
+            Err(e) => eprintln!("OTLP export to {url} failed: {e}"),
+        }
+    }
+}
+
+/// Wrap encoded spans in an ExportTraceServiceRequest.
+fn export_request(spans: &[String]) -> String {
+    let service = json::Object::new()
+        .str("key", "service.name")
+        .raw(
+            "value",
+            &json::Object::new()
+                .str("stringValue", SERVICE_NAME)
+                .finish(),
+        )
+        .finish();
+    let resource = json::Object::new()
+        .raw("attributes", &format!("[{service}]"))
+        .finish();
+    let scope_spans = json::Object::new()
+        .raw(
+            "scope",
+            &json::Object::new().str("name", SERVICE_NAME).finish(),
+        )
+        .raw("spans", &format!("[{}]", spans.join(",")))
+        .finish();
+    let resource_spans = json::Object::new()
+        .raw("resource", &resource)
+        .raw("scopeSpans", &format!("[{scope_spans}]"))
+        .finish();
+    json::Object::new()
+        .raw("resourceSpans", &format!("[{resource_spans}]"))
+        .finish()
+}
+
+/// Parse `<version>-<trace-id>-<parent-id>-<flags>` into the ids and flags.
+/// Version `ff` and all-zero ids are invalid; version `00` has exactly four
+/// fields, while later versions may append more.
+fn parse_traceparent(value: &str) -> Option<([u8; 16], [u8; 8], u8)> {
+    let mut parts = value.trim().split('-');
+    let [version] = <[u8; 1]>::try_from(unhex(parts.next()?)?).ok()?;
+    let trace_id: [u8; 16] = unhex(parts.next()?)?.try_into().ok()?;
+    let span_id: [u8; 8] = unhex(parts.next()?)?.try_into().ok()?;
+    let [flags] = <[u8; 1]>::try_from(unhex(parts.next()?)?).ok()?;
+    if version == 0xff || (version == 0 && parts.next().is_some()) {
+        return None;
+    }
+    if trace_id == [0; 16] || span_id == [0; 8] {
+        return None;
+    }
+    Some((trace_id, span_id, flags))
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    let mut id = [0u8; N];
+    while id == [0u8; N] {
+        getrandom::getrandom(&mut id).expect("system randomness");
+    }
+    id
+}
+
+fn unix_nanos(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn otel_parse_traceparent_accepts_valid_and_rejects_invalid() {
+        let (trace_id, span_id, flags) =
+            parse_traceparent(&format!("00-{TRACE}-{PARENT}-01")).unwrap();
+        assert_eq!(hex(&trace_id), TRACE);
+        assert_eq!(hex(&span_id), PARENT);
+        assert_eq!(flags, 0x01);
+        // Later versions may carry extra fields
+        assert!(parse_traceparent(&format!("01-{TRACE}-{PARENT}-00-extra")).is_some());
+
+        for bad in [
+            format!("ff-{TRACE}-{PARENT}-01"),
+            format!("0g-{TRACE}-{PARENT}-01"),
+            format!("000-{TRACE}-{PARENT}-01"),
+            format!("00-{TRACE}-{PARENT}-01-extra"),
+            format!("00-{}-{PARENT}-01", "0".repeat(32)),
+            format!("00-{TRACE}-{}-01", "0".repeat(16)),
+            format!("00-{TRACE}-{PARENT}"),
+            format!("00-{TRACE}-{PARENT}-1"),
+        ] {
+            assert_eq!(parse_traceparent(&bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn otel_traceparent_keeps_the_clients_sampled_flag() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let tracer = Tracer { tx };
+        let peer = "127.0.0.1:9".parse().unwrap();
+        let span_for = |traceparent: Option<String>| {
+            let mut headers = HeaderMap::new();
+            if let Some(value) = traceparent {
+                headers.insert("traceparent", value.parse().unwrap());
+            }
+            let uri = Uri::from_static("/api");
+            tracer.start_span(&Method::GET, &uri, &headers, &peer)
+        };
+
+        for flags in ["00", "01"] {
+            let span = span_for(Some(format!("00-{TRACE}-{PARENT}-{flags}")));
+            let sent = span.traceparent();
+            let sent = sent.to_str().unwrap();
+            assert!(sent.starts_with(&format!("00-{TRACE}-")), "{sent}");
+            assert!(sent.ends_with(&format!("-{flags}")), "{sent}");
+            assert!(!sent.contains(PARENT), "{sent}");
+
+            // Only sampled spans are exported
+            tracer.finish(span, 200);
+            assert_eq!(rx.try_recv().is_ok(), flags == "01", "flags {flags}");
+        }
+
+        // A trace the tap starts itself is sampled
+        let root = span_for(None).traceparent();
+        assert!(root.to_str().unwrap().ends_with("-01"));
+    }
+}
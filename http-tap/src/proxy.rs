@@ -1,45 +1,102 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use crate::acl::{Acl, Decision};
+use crate::cli::LogFormat;
+use crate::connector::{TlsInfoConnector, describe_tls};
+use crate::cookies::{parse_cookie_header, parse_set_cookie};
+use crate::delay::{self, PathDelay};
+use crate::dump::HttpDump;
+use crate::expect::{CONTINUE_TIMEOUT, DeferredBody, Gate, HeldBody, expects_continue};
+use crate::forms;
+use crate::gzip;
+use crate::json;
+use crate::logfile::LogSink;
+use crate::otel::{Span, Tracer};
+use crate::rewrite::{self, BodyRewrite};
+use crate::stats::{StatsEvent, StatsMessage, StatsSender, format_bytes};
+use crate::streaming::{LineSplitter, ObservedBody, StreamObserver, is_event_stream, is_streaming};
+use crate::throttle::PacedBody;
+use crate::timefmt::Clock;
+use crate::upstream_proxy::{ProxyConnector, UpstreamProxy};
+use crate::websocket;
 use anyhow::Context as _;
 use bytes::Bytes;
-use http_body_util::{BodyExt, Full};
+use http_body_util::{BodyExt, Full, combinators::BoxBody};
+use hyper::Error as HyperError;
 use hyper::body::Incoming;
 use hyper::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
 use hyper::service::service_fn;
 use hyper::upgrade;
-use hyper::Error as HyperError;
-use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
-use hyper_util::client::legacy::{connect::HttpConnector, Client};
 use hyper_rustls::FixedServerNameResolver;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
 use hyper_util::rt::{TokioExecutor, TokioIo};
-use time::{format_description::well_known::Rfc3339, OffsetDateTime};
-use crate::stats::{StatsEvent, StatsSender};
-use rustls::{ClientConfig, SignatureScheme};
-use rustls::client::danger::{ServerCertVerified, ServerCertVerifier, HandshakeSignatureValid};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::{ClientConfig, KeyLog, SignatureScheme};
+use rustls::{
+    RootCertStore, pki_types::CertificateDer, pki_types::PrivateKeyDer, pki_types::ServerName,
+};
 use rustls_native_certs::load_native_certs;
-use rustls::{RootCertStore, pki_types::CertificateDer, pki_types::PrivateKeyDer, pki_types::ServerName};
 use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
 use tokio::io::copy_bidirectional;
+use tokio::sync::{Semaphore, oneshot};
 // (imports deduped above)
 
+/// Base delay between upstream retries; grows linearly with each attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Body type returned to clients: buffered, or paced by --upstream-bandwidth.
+pub type ProxyBody = BoxBody<Bytes, HyperError>;
+
 #[derive(Clone)]
 pub struct Config {
     pub listen: SocketAddr,
-    pub target_authority: String, // host:port
-    pub target_scheme: &'static str, // "http"
+    pub target_authority: String,         // host:port
+    pub target_scheme: &'static str,      // "http"
+    pub target_base_path: Option<String>, // e.g. "/v1", joined before the request path
+    pub log_format: LogFormat,
+    pub log: LogSink,
+    pub dump_http: Option<Arc<HttpDump>>,
+    pub clock: Clock,
     pub include_bodies: bool,
     pub max_body_bytes: usize,
+    pub force_hex: bool,
     pub redact_header: Vec<String>,
+    pub show_cookies: bool,
+    pub redact_cookie: Vec<String>,
     pub tls: Option<TlsConfig>,
     pub insecure_upstream: bool,
+    pub key_log: Arc<dyn KeyLog>,
     pub stats: Option<StatsSender>,
     pub upstream_ca: Vec<std::path::PathBuf>,
     pub upstream_client_cert: Option<std::path::PathBuf>,
     pub upstream_client_key: Option<std::path::PathBuf>,
     pub upstream_server_name: Option<String>,
     pub upstream_host: Option<String>,
+    pub preserve_host: bool,
+    pub keep_header: Vec<String>,
+    pub upstream_basic_auth: Option<HeaderValue>,
+    pub upstream_retries: u32,
+    pub upstream_bandwidth: Option<u64>,
+    pub delay: Vec<PathDelay>,
+    pub upstream_proxy: Option<UpstreamProxy>,
+    pub forward_proxy: bool,
+    pub max_connections: Option<usize>,
+    pub listen_backlog: i32,
+    pub tcp_nodelay: bool,
+    pub health_path: Option<String>,
+    pub strip_prefix: Option<String>, // e.g. "/proxy", no trailing slash
+    pub acl: Acl,
+    pub add_prefix: Option<String>,
+    pub recompress: bool,
+    pub body_replace: Vec<BodyRewrite>,
+    pub body_replace_binary: bool,
+    pub tracer: Option<Tracer>,
 }
 
 #[derive(Clone)]
@@ -47,45 +104,103 @@ pub struct TlsConfig {
     pub acceptor: tokio_rustls::TlsAcceptor,
 }
 
+/// Tokio's own backlog for `TcpListener::bind`.
+pub const DEFAULT_LISTEN_BACKLOG: i32 = 1024;
+
+/// Bind like `TcpListener::bind` does, but with `--listen-backlog`.
+fn bind_listener(addr: SocketAddr, backlog: i32) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        None,
+    )?;
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
 pub async fn run_proxy(cfg: Config) -> anyhow::Result<()> {
-    let listener = tokio::net::TcpListener::bind(cfg.listen)
-        .await
+    let listener = bind_listener(cfg.listen, cfg.listen_backlog)
         .with_context(|| format!("bind {}", cfg.listen))?;
 
     let client = {
-        let https = build_https_connector(&cfg)?;
-        Client::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(https)
+        let https = TlsInfoConnector::new(build_https_connector(&cfg)?, cfg.log.clone());
+        Client::builder(TokioExecutor::new()).build::<_, ProxyBody>(https)
     };
 
     let shared = Arc::new(ProxyState::new(cfg, client));
 
-    let listen_scheme = if shared.cfg.tls.is_some() { "https" } else { "http" };
+    let listen_scheme = if shared.cfg.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
     let upstream_scheme = shared.cfg.target_scheme;
     eprintln!(
-        "us-http-tap listening on {}://{} → {}://{}",
+        "us-http-tap listening on {}://{} → {}://{}{}",
         listen_scheme,
-        shared.cfg.listen,
+        listener.local_addr().unwrap_or(shared.cfg.listen),
         upstream_scheme,
-        shared.cfg.target_authority
+        shared.cfg.target_authority,
+        shared.cfg.target_base_path.as_deref().unwrap_or("")
     );
 
+    // Each connection holds a permit until it closes; accepting pauses while none are left
+    let limit = shared
+        .cfg
+        .max_connections
+        .map(|n| (n, Arc::new(Semaphore::new(n))));
+
     loop {
+        let permit = match &limit {
+            Some((max, sem)) => {
+                if sem.available_permits() == 0 {
+                    eprintln!(
+                        "Connection limit ({max}) reached; waiting for a connection to close"
+                    );
+                }
+                Some(sem.clone().acquire_owned().await?)
+            }
+            None => None,
+        };
         let (stream, addr) = listener.accept().await?;
+        if shared.cfg.tcp_nodelay
+            && let Err(e) = stream.set_nodelay(true)
+        {
+            eprintln!("TCP_NODELAY on connection from {addr}: {e}");
+        }
         let state = shared.clone();
         if let Some(tls) = &shared.cfg.tls {
             let acceptor = tls.acceptor.clone();
             tokio::spawn(async move {
+                let _permit = permit;
                 match acceptor.accept(stream).await {
                     Ok(tls_stream) => {
-                        let io = TokioIo::new(tls_stream);
                         let conn_id = state.next_conn_id();
-            let svc = service_fn(move |req| handle(state.clone(), conn_id, addr, req));
+                        let (_, conn) = tls_stream.get_ref();
+                        state.cfg.log.write_entry(&format!(
+                            "[conn#{conn_id}] TLS from {addr}: {}",
+                            describe_tls(
+                                conn.protocol_version(),
+                                conn.negotiated_cipher_suite(),
+                                conn.alpn_protocol()
+                            )
+                        ));
+                        let io = TokioIo::new(tls_stream);
+                        state.open_conn(conn_id);
+                        let conn_state = state.clone();
+                        let svc = service_fn(move |req| handle(state.clone(), conn_id, addr, req));
                         if let Err(err) = hyper::server::conn::http1::Builder::new()
                             .serve_connection(io, svc)
+                            .with_upgrades()
                             .await
                         {
                             eprintln!("[conn#{conn_id}] connection error: {err}");
                         }
+                        conn_state.close_conn(conn_id);
                     }
                     Err(err) => {
                         eprintln!("TLS accept error from {}: {}", addr, err);
@@ -94,15 +209,20 @@ pub async fn run_proxy(cfg: Config) -> anyhow::Result<()> {
             });
         } else {
             tokio::spawn(async move {
+                let _permit = permit;
                 let io = TokioIo::new(stream);
                 let conn_id = state.next_conn_id();
+                state.open_conn(conn_id);
+                let conn_state = state.clone();
                 let svc = service_fn(move |req| handle(state.clone(), conn_id, addr, req));
                 if let Err(err) = hyper::server::conn::http1::Builder::new()
                     .serve_connection(io, svc)
+                    .with_upgrades()
                     .await
                 {
                     eprintln!("[conn#{conn_id}] connection error: {err}");
                 }
+                conn_state.close_conn(conn_id);
             });
         }
     }
@@ -111,21 +231,91 @@ pub async fn run_proxy(cfg: Config) -> anyhow::Result<()> {
 #[derive(Clone)]
 struct ProxyState {
     cfg: Config,
-    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    client: Client<TlsInfoConnector, ProxyBody>,
     conn_seq: Arc<AtomicU64>,
+    /// Open client connections by conn id, for the summary logged on close
+    connections: Arc<Mutex<HashMap<u64, ConnTotals>>>,
+}
+
+/// What one client connection has carried so far.
+struct ConnTotals {
+    opened: std::time::Instant,
+    requests: u64,
+    /// Request and response body bytes, both directions together
+    bytes: u64,
 }
 
 impl ProxyState {
-    fn new(cfg: Config, client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>) -> Self {
+    fn new(cfg: Config, client: Client<TlsInfoConnector, ProxyBody>) -> Self {
         Self {
             cfg,
             client,
             conn_seq: Arc::new(AtomicU64::new(1)),
+            connections: Arc::default(),
         }
     }
     fn next_conn_id(&self) -> u64 {
         self.conn_seq.fetch_add(1, Ordering::Relaxed)
     }
+
+    fn open_conn(&self, conn_id: u64) {
+        let totals = ConnTotals {
+            opened: std::time::Instant::now(),
+            requests: 0,
+            bytes: 0,
+        };
+        self.conn_totals().insert(conn_id, totals);
+    }
+
+    fn count_request(&self, conn_id: u64) {
+        if let Some(totals) = self.conn_totals().get_mut(&conn_id) {
+            totals.requests += 1;
+        }
+    }
+
+    /// Bytes of a finished exchange; a streamed response may finish after
+    /// its connection has closed, in which case they are dropped.
+    fn count_bytes(&self, conn_id: u64, bytes: usize) {
+        if let Some(totals) = self.conn_totals().get_mut(&conn_id) {
+            totals.bytes += bytes as u64;
+        }
+    }
+
+    /// Forget the connection and log what it carried.
+    fn close_conn(&self, conn_id: u64) {
+        let Some(totals) = self.conn_totals().remove(&conn_id) else {
+            return;
+        };
+        let now = self.cfg.clock.now();
+        let duration = totals.opened.elapsed();
+        if self.cfg.log_format == LogFormat::Json {
+            let obj = json::Object::new()
+                .num("conn", conn_id)
+                .str("ts", &now)
+                .str("direction", "closed")
+                .num("requests", totals.requests)
+                .num("bytes", totals.bytes)
+                .num(
+                    "duration_ms",
+                    format!("{:.3}", duration.as_secs_f64() * 1000.0),
+                );
+            self.cfg.log.write_entry(&obj.finish());
+            return;
+        }
+        let requests = match totals.requests {
+            1 => "1 request".to_string(),
+            n => format!("{n} requests"),
+        };
+        self.cfg.log.write_entry(&format!(
+            "[conn#{conn_id}] {now} closed: {requests}, {}, {:.1}s",
+            format_bytes(totals.bytes),
+            duration.as_secs_f64()
+        ));
+    }
+
+    fn conn_totals(&self) -> std::sync::MutexGuard<'_, HashMap<u64, ConnTotals>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 async fn handle(
@@ -133,58 +323,108 @@ async fn handle(
     conn_id: u64,
     peer: SocketAddr,
     req: Request<Incoming>,
-) -> Result<Response<Full<Bytes>>, HyperError> {
-    let now = now_iso();
+) -> Result<Response<ProxyBody>, HyperError> {
+    let now = state.cfg.clock.now();
+
+    // The tap answers its own health checks; these are not proxied, logged, or counted
+    if state.cfg.health_path.as_deref() == Some(req.uri().path()) {
+        return Ok(health_response(&state.cfg));
+    }
+    state.count_request(conn_id);
+
+    // Forward-proxy path: CONNECT host:port opens a raw TCP tunnel
+    if state.cfg.forward_proxy && req.method() == Method::CONNECT {
+        return Ok(handle_connect(state, conn_id, peer, req, &now).await);
+    }
+
+    if let Some(decision) = state.cfg.acl.check(req.uri().path()) {
+        log_acl(&state.cfg, conn_id, &req, &decision, &now);
+        let denied = matches!(decision, Decision::Denied { .. });
+        if let Some(tx) = &state.cfg.stats {
+            let _ = tx.send(if denied {
+                StatsMessage::AclDenied
+            } else {
+                StatsMessage::AclAllowed
+            });
+        }
+        if denied {
+            return Ok(simple_response(StatusCode::FORBIDDEN, "forbidden"));
+        }
+    }
+
+    if let Some(prefix) = &state.cfg.strip_prefix
+        && strip_path_prefix(req.uri(), prefix).is_none()
+    {
+        eprintln!(
+            "[conn#{conn_id}] {now} {} {} is outside --strip-prefix {prefix}; returning 404",
+            req.method(),
+            req.uri()
+        );
+        return Ok(simple_response(StatusCode::NOT_FOUND, "not found"));
+    }
+
+    if let Some(rule) = delay::for_path(&state.cfg.delay, req.uri().path()) {
+        log_delay(&state.cfg, conn_id, &req, rule, &now);
+        tokio::time::sleep(rule.delay).await;
+    }
 
     // WebSocket upgrade path: tunnel bytes after 101 handshake
     if is_websocket_upgrade(req.headers()) {
         // Preserve required WS hop-by-hop headers for the upstream handshake.
-        let conn_hdr = req
-            .headers()
-            .get(hyper::http::header::CONNECTION)
-            .cloned();
-        let upgr_hdr = req
-            .headers()
-            .get(hyper::http::header::UPGRADE)
-            .cloned();
-        let ws_key = req
-            .headers()
-            .get("sec-websocket-key")
-            .cloned();
-        let ws_ver = req
-            .headers()
-            .get("sec-websocket-version")
-            .cloned();
-        let ws_proto = req
-            .headers()
-            .get("sec-websocket-protocol")
-            .cloned();
-        let ws_ext = req
-            .headers()
-            .get("sec-websocket-extensions")
-            .cloned();
+        let conn_hdr = req.headers().get(hyper::http::header::CONNECTION).cloned();
+        let upgr_hdr = req.headers().get(hyper::http::header::UPGRADE).cloned();
+        let ws_key = req.headers().get("sec-websocket-key").cloned();
+        let ws_ver = req.headers().get("sec-websocket-version").cloned();
+        let ws_proto = req.headers().get("sec-websocket-protocol").cloned();
+        let ws_ext = req.headers().get("sec-websocket-extensions").cloned();
 
         // Rebuild forwarded request without body
         let mut forwarded = Request::builder()
             .method(req.method().clone())
             .version(req.version())
-            .uri(remap_uri(&req.method(), req.uri(), &state.cfg))
-            .body(Full::new(Bytes::new()))
+            .uri(remap_uri(req.uri(), &state.cfg))
+            .body(full(Bytes::new()))
             .expect("build ws request");
-        copy_headers_forward(req.headers().clone(), forwarded.headers_mut(), &state.cfg);
-        if let Some(v) = conn_hdr { forwarded.headers_mut().insert(hyper::http::header::CONNECTION, v); }
-        if let Some(v) = upgr_hdr { forwarded.headers_mut().insert(hyper::http::header::UPGRADE, v); }
-        if let Some(v) = ws_key { forwarded.headers_mut().insert("sec-websocket-key", v); }
-        if let Some(v) = ws_ver { forwarded.headers_mut().insert("sec-websocket-version", v); }
-        if let Some(v) = ws_proto { forwarded.headers_mut().insert("sec-websocket-protocol", v); }
-        if let Some(v) = ws_ext { forwarded.headers_mut().insert("sec-websocket-extensions", v); }
+        copy_headers_forward(
+            req.headers().clone(),
+            forwarded.headers_mut(),
+            &state.cfg,
+            None,
+        );
+        if let Some(v) = conn_hdr {
+            forwarded
+                .headers_mut()
+                .insert(hyper::http::header::CONNECTION, v);
+        }
+        if let Some(v) = upgr_hdr {
+            forwarded
+                .headers_mut()
+                .insert(hyper::http::header::UPGRADE, v);
+        }
+        if let Some(v) = ws_key {
+            forwarded.headers_mut().insert("sec-websocket-key", v);
+        }
+        if let Some(v) = ws_ver {
+            forwarded.headers_mut().insert("sec-websocket-version", v);
+        }
+        if let Some(v) = ws_proto {
+            forwarded.headers_mut().insert("sec-websocket-protocol", v);
+        }
+        if let Some(v) = ws_ext {
+            forwarded
+                .headers_mut()
+                .insert("sec-websocket-extensions", v);
+        }
 
         // Perform upstream handshake
-        let mut upstream_resp = match state.client.request(forwarded).await {
+        let upstream_resp = match state.client.request(forwarded).await {
             Ok(r) => r,
             Err(e) => {
                 eprintln!("[conn#{conn_id}] {now} upstream WS handshake error: {e}");
-                return Ok(simple_response(StatusCode::BAD_GATEWAY, "upstream WS handshake failed"));
+                return Ok(simple_response(
+                    StatusCode::BAD_GATEWAY,
+                    "upstream WS handshake failed",
+                ));
             }
         };
 
@@ -193,7 +433,10 @@ async fn handle(
                 "[conn#{conn_id}] {now} upstream WS expected 101, got {}",
                 upstream_resp.status()
             );
-            return Ok(simple_response(StatusCode::BAD_GATEWAY, "upstream did not switch protocols"));
+            return Ok(simple_response(
+                StatusCode::BAD_GATEWAY,
+                "upstream did not switch protocols",
+            ));
         }
 
         // Build response to client with upstream headers
@@ -204,18 +447,23 @@ async fn handle(
             *h = upstream_headers;
         }
         let client_resp = client_resp_builder
-            .body(Full::new(Bytes::new()))
+            .body(full(Bytes::new()))
             .expect("ws 101 resp");
 
         // Spawn tunnel task after connection upgrades
         let state_clone = state.clone();
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string());
         tokio::spawn(async move {
-            let now = now_iso();
+            let now = state.cfg.clock.now();
             match (upgrade::on(req).await, upgrade::on(upstream_resp).await) {
                 (Ok(down), Ok(up)) => {
-                    let mut down = TokioIo::new(down);
-                    let mut up = TokioIo::new(up);
-                    let _ = copy_bidirectional(&mut down, &mut up).await;
+                    let down = TokioIo::new(down);
+                    let up = TokioIo::new(up);
+                    let _ = websocket::tunnel(down, up, path, state.cfg.stats.clone()).await;
                 }
                 (Err(e), _) | (_, Err(e)) => {
                     eprintln!("[conn#{conn_id}] {now} WS upgrade tunnel error: {e}");
@@ -227,6 +475,10 @@ async fn handle(
         return Ok(client_resp);
     }
 
+    if expects_continue(req.headers()) {
+        return Ok(handle_expect_continue(state, conn_id, peer, req, &now).await);
+    }
+
     let (req_parts, req_body_incoming) = req.into_parts();
     let req_bytes = match req_body_incoming.collect().await {
         Ok(collected) => collected.to_bytes(),
@@ -236,33 +488,83 @@ async fn handle(
         }
     };
 
+    let rewritten = rewrite_body(&state.cfg, &req_parts.headers, &req_bytes);
     let mut forwarded = Request::builder()
         .method(req_parts.method.clone())
         .version(req_parts.version)
-        .uri(remap_uri(&req_parts.method, &req_parts.uri, &state.cfg))
-        .body(Full::new(req_bytes.clone()))
+        .uri(remap_uri(&req_parts.uri, &state.cfg))
+        .body(Full::new(
+            rewritten
+                .as_ref()
+                .map_or(req_bytes.clone(), |(bytes, _)| bytes.clone()),
+        ))
         .expect("build request");
 
-    copy_headers_forward(req_parts.headers, forwarded.headers_mut(), &state.cfg);
-
-    log_request(&state.cfg, conn_id, &peer, &forwarded, &req_bytes, &now);
-    if let Some(tx) = &state.cfg.stats {
-        let path = req_parts
-            .uri
-            .path_and_query()
-            .map(|pq| pq.as_str().to_string())
-            .unwrap_or_else(|| "/".to_string());
-        let _ = tx.send(StatsEvent {
-            method: req_parts.method.clone(),
-            path,
-            at: std::time::SystemTime::now(),
-        });
+    let client_auth = req_parts
+        .headers
+        .get(hyper::http::header::AUTHORIZATION)
+        .cloned();
+    let accept_encoding = req_parts
+        .headers
+        .get(hyper::http::header::ACCEPT_ENCODING)
+        .cloned();
+    let span = state
+        .cfg
+        .tracer
+        .as_ref()
+        .map(|t| t.start_span(&req_parts.method, &req_parts.uri, &req_parts.headers, &peer));
+    copy_headers_forward(
+        req_parts.headers,
+        forwarded.headers_mut(),
+        &state.cfg,
+        span.as_ref(),
+    );
+    if let Some((bytes, _)) = &rewritten {
+        forwarded.headers_mut().insert(
+            hyper::http::header::CONTENT_LENGTH,
+            HeaderValue::from(bytes.len()),
+        );
     }
+    let logged = log_forwarded_request(
+        &state.cfg,
+        conn_id,
+        &peer,
+        &forwarded,
+        client_auth,
+        &req_bytes,
+        &now,
+    );
+    let sent = rewritten.as_ref().map_or(&req_bytes, |(bytes, _)| bytes);
+    dump_request(&state.cfg, conn_id, &logged, sent).await;
+    if let Some((bytes, count)) = &rewritten {
+        log_body_rewrite(
+            &state.cfg,
+            conn_id,
+            *count,
+            req_bytes.len(),
+            bytes.len(),
+            &now,
+        );
+    }
+    let record = outcome_recorder(
+        &state,
+        conn_id,
+        peer,
+        &req_parts.method,
+        &req_parts.uri,
+        req_bytes.len(),
+        span,
+    );
 
-    let resp = match state.client.request(forwarded).await {
+    let started = std::time::Instant::now();
+    let sent = send_upstream(&state, conn_id, &now, &req_parts.method, || {
+        clone_request(&forwarded).map(|b| b.map_err(|never| match never {}).boxed())
+    });
+    let resp = match sent.await {
         Ok(r) => r,
         Err(e) => {
             eprintln!("[conn#{conn_id}] {now} upstream error: {e}");
+            record(StatusCode::BAD_GATEWAY, 0);
             return Ok(simple_response(
                 StatusCode::BAD_GATEWAY,
                 "upstream connection failed",
@@ -270,48 +572,746 @@ async fn handle(
         }
     };
 
+    Ok(relay_response(
+        &state,
+        conn_id,
+        resp,
+        accept_encoding,
+        &now,
+        started,
+        record,
+    )
+    .await)
+}
+
+/// Send the request `build` makes, building and sending it again up to
+/// `--upstream-retries` times when the upstream could not be connected to.
+/// A request that reached the upstream may have had side effects, so other
+/// failures are not retried, and only idempotent methods are retried at all.
+async fn send_upstream(
+    state: &ProxyState,
+    conn_id: u64,
+    now: &str,
+    method: &Method,
+    mut build: impl FnMut() -> Request<ProxyBody>,
+) -> Result<Response<Incoming>, hyper_util::client::legacy::Error> {
+    let retries = if is_idempotent(method) {
+        state.cfg.upstream_retries
+    } else {
+        0
+    };
+    let mut attempt = 0;
+    loop {
+        match state.client.request(build()).await {
+            Err(e) if e.is_connect() && attempt < retries => {
+                attempt += 1;
+                eprintln!("[conn#{conn_id}] {now} upstream error: {e}; retry {attempt}/{retries}");
+                tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The request body after --body-replace rules, with the number of
+/// replacements; None when no rule applies or nothing matched.
+fn rewrite_body(cfg: &Config, headers: &HeaderMap, body: &Bytes) -> Option<(Bytes, usize)> {
+    // A compressed body's bytes are not the text the rules describe
+    if cfg.body_replace.is_empty() || body.is_empty() || rewrite::is_encoded(headers) {
+        return None;
+    }
+    if !cfg.body_replace_binary && !rewrite::is_textual(headers) {
+        return None;
+    }
+    rewrite::apply(&cfg.body_replace, body)
+}
+
+/// Requests with `Expect: 100-continue` are sent upstream before the client
+/// body is read, so the upstream can accept (100) or reject them up front.
+async fn handle_expect_continue(
+    state: Arc<ProxyState>,
+    conn_id: u64,
+    peer: SocketAddr,
+    req: Request<Incoming>,
+    now: &str,
+) -> Response<ProxyBody> {
+    let (req_parts, req_body_incoming) = req.into_parts();
+    let gate = Arc::new(Gate::new());
+    let (tee_tx, tee_rx) = oneshot::channel();
+    let held = HeldBody::new(req_body_incoming, tee_tx);
+
+    let mut head = Request::builder()
+        .method(req_parts.method.clone())
+        .version(req_parts.version)
+        .uri(remap_uri(&req_parts.uri, &state.cfg))
+        .body(())
+        .expect("build request");
+    let client_auth = req_parts
+        .headers
+        .get(hyper::http::header::AUTHORIZATION)
+        .cloned();
+    let accept_encoding = req_parts
+        .headers
+        .get(hyper::http::header::ACCEPT_ENCODING)
+        .cloned();
+    let span = state
+        .cfg
+        .tracer
+        .as_ref()
+        .map(|t| t.start_span(&req_parts.method, &req_parts.uri, &req_parts.headers, &peer));
+    copy_headers_forward(
+        req_parts.headers,
+        head.headers_mut(),
+        &state.cfg,
+        span.as_ref(),
+    );
+    // Each attempt gets its own body over the shared client body, and its
+    // own hook to open the gate when the upstream says 100 Continue
+    let build = || {
+        let mut attempt =
+            request_head(&head).map(|()| DeferredBody::new(held.clone(), gate.clone()).boxed());
+        let gate = gate.clone();
+        hyper::ext::on_informational(&mut attempt, move |res| {
+            if res.status() == StatusCode::CONTINUE {
+                gate.open();
+            }
+        });
+        attempt
+    };
+
+    let started = std::time::Instant::now();
+    let result = {
+        let pending = send_upstream(&state, conn_id, now, &req_parts.method, build);
+        tokio::pin!(pending);
+        // Upstreams that never send 100 get the body after a short wait, like curl does
+        tokio::select! {
+            res = &mut pending => res,
+            _ = tokio::time::sleep(CONTINUE_TIMEOUT) => {
+                gate.open();
+                pending.await
+            }
+        }
+    };
+    // Once no attempt still holds the client body, the tee either has its
+    // bytes or is closed, so waiting on it below cannot hang
+    drop(held);
+
+    let req_bytes = if gate.is_open() {
+        tee_rx.await.unwrap_or_default()
+    } else {
+        Bytes::new()
+    };
+    let logged = log_forwarded_request(
+        &state.cfg,
+        conn_id,
+        &peer,
+        &head,
+        client_auth,
+        &req_bytes,
+        now,
+    );
+    dump_request(&state.cfg, conn_id, &logged, &req_bytes).await;
+    let record = outcome_recorder(
+        &state,
+        conn_id,
+        peer,
+        &req_parts.method,
+        &req_parts.uri,
+        req_bytes.len(),
+        span,
+    );
+
+    match result {
+        Ok(resp) => {
+            if !gate.is_open() {
+                eprintln!(
+                    "[conn#{conn_id}] {now} upstream answered {} before 100 Continue; client body not sent",
+                    resp.status()
+                );
+            }
+            relay_response(&state, conn_id, resp, accept_encoding, now, started, record).await
+        }
+        Err(e) => {
+            eprintln!("[conn#{conn_id}] {now} upstream error: {e}");
+            record(StatusCode::BAD_GATEWAY, 0);
+            simple_response(StatusCode::BAD_GATEWAY, "upstream connection failed")
+        }
+    }
+}
+
+/// Buffer the upstream response, log it, and build the reply to the client.
+async fn relay_response(
+    state: &Arc<ProxyState>,
+    conn_id: u64,
+    resp: Response<Incoming>,
+    accept_encoding: Option<HeaderValue>,
+    now: &str,
+    started: std::time::Instant,
+    record: impl Fn(StatusCode, usize) + Send + Sync + 'static,
+) -> Response<ProxyBody> {
+    if is_streaming(resp.headers()) {
+        return stream_response(state, conn_id, resp, now, started, record);
+    }
+
+    let cfg = &state.cfg;
     let (resp_parts, resp_body_incoming) = resp.into_parts();
-    let resp_bytes = match resp_body_incoming.collect().await {
-        Ok(collected) => collected.to_bytes(),
+    let (resp_bytes, trailers) = match resp_body_incoming.collect().await {
+        Ok(collected) => {
+            let trailers = collected.trailers().cloned();
+            (collected.to_bytes(), trailers)
+        }
         Err(e) => {
             eprintln!("[conn#{conn_id}] {now} response body error: {e}");
-            return Ok(simple_response(StatusCode::BAD_GATEWAY, "upstream body error"));
+            record(StatusCode::BAD_GATEWAY, 0);
+            return simple_response(StatusCode::BAD_GATEWAY, "upstream body error");
         }
     };
 
+    let mut headers = resp_parts.headers;
+    // Re-encoding a byte range would break the offsets it was cut at
+    let partial = resp_parts.status == StatusCode::PARTIAL_CONTENT
+        || headers.contains_key(hyper::http::header::CONTENT_RANGE);
+    let (sent_bytes, resp_bytes) = if cfg.recompress && !partial {
+        recompress(
+            &mut headers,
+            resp_bytes,
+            accept_encoding.as_ref(),
+            conn_id,
+            now,
+        )
+    } else {
+        (resp_bytes.clone(), resp_bytes)
+    };
+
+    let body = match cfg.upstream_bandwidth {
+        Some(rate) => PacedBody::new(Full::new(sent_bytes.clone()), rate)
+            .map_err(|never| match never {})
+            .boxed(),
+        None => full(sent_bytes.clone()),
+    };
+    let body = match &trailers {
+        Some(trailers) => {
+            announce_trailers(&mut headers, trailers);
+            let trailers = trailers.clone();
+            body.with_trailers(std::future::ready(Some(Ok(trailers))))
+                .boxed()
+        }
+        None => body,
+    };
     let mut out = Response::builder()
         .status(resp_parts.status)
         .version(resp_parts.version)
-        .body(Full::new(resp_bytes.clone()))
+        .body(body)
         .expect("build response");
 
+    *out.headers_mut() = headers;
+
+    log_response(
+        cfg,
+        conn_id,
+        &out,
+        Some((&resp_bytes, resp_bytes.len())),
+        now,
+        started.elapsed(),
+    );
+    if let Some(trailers) = &trailers {
+        log_trailers(cfg, conn_id, trailers, now);
+    }
+    record(out.status(), sent_bytes.len());
+
+    out
+}
+
+/// Trailers only go out on a chunked body, and only the fields the response
+/// lists in `Trailer`, so a buffered response that has them is sent chunked
+/// and declares any the upstream did not.
+fn announce_trailers(headers: &mut HeaderMap, trailers: &HeaderMap) {
+    use hyper::http::header::{CONTENT_LENGTH, TRAILER, TRANSFER_ENCODING};
+
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+    let declared: Vec<String> = headers
+        .get_all(TRAILER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    for name in trailers.keys() {
+        if declared.iter().any(|d| d == name.as_str()) {
+            continue;
+        }
+        // A name that cannot be listed is left undeclared rather than failing
+        // the response
+        if let Ok(value) = HeaderValue::from_str(name.as_str()) {
+            headers.append(TRAILER, value);
+        }
+    }
+}
+
+/// Re-encode a buffered response to match the client's Accept-Encoding:
+/// gzip plain bodies the client would accept compressed, and decode gzip or
+/// deflate bodies it did not ask for. Returns the bytes to send and the
+/// decoded bytes to log.
+fn recompress(
+    headers: &mut HeaderMap,
+    body: Bytes,
+    accept_encoding: Option<&HeaderValue>,
+    conn_id: u64,
+    now: &str,
+) -> (Bytes, Bytes) {
+    use hyper::http::header::{CONTENT_ENCODING, CONTENT_LENGTH, VARY};
+
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase());
+    match encoding.as_deref() {
+        None | Some("identity") if !body.is_empty() && gzip::accepts(accept_encoding, "gzip") => {
+            match gzip::compress(&body) {
+                Ok(compressed) => {
+                    eprintln!(
+                        "[conn#{conn_id}] {now} recompress: gzipped {} → {} bytes",
+                        body.len(),
+                        compressed.len()
+                    );
+                    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                    headers.insert(CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+                    headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+                    (Bytes::from(compressed), body)
+                }
+                Err(e) => {
+                    eprintln!("[conn#{conn_id}] {now} recompress: gzip failed: {e}");
+                    (body.clone(), body)
+                }
+            }
+        }
+        Some(coding @ ("gzip" | "x-gzip" | "deflate"))
+            if !gzip::accepts(accept_encoding, coding) =>
+        {
+            match gzip::decompress(&body) {
+                Ok(plain) => {
+                    eprintln!(
+                        "[conn#{conn_id}] {now} recompress: removed {coding} the client did not accept ({} → {} bytes)",
+                        body.len(),
+                        plain.len()
+                    );
+                    headers.remove(CONTENT_ENCODING);
+                    headers.insert(CONTENT_LENGTH, HeaderValue::from(plain.len()));
+                    let plain = Bytes::from(plain);
+                    (plain.clone(), plain)
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[conn#{conn_id}] {now} recompress: could not decode {coding} body: {e}"
+                    );
+                    (body.clone(), body)
+                }
+            }
+        }
+        _ => (body.clone(), body),
+    }
+}
+
+/// Forward a response chunk by chunk. SSE events are logged line by line as
+/// they arrive; other streamed bodies are logged once they finish.
+fn stream_response(
+    state: &Arc<ProxyState>,
+    conn_id: u64,
+    resp: Response<Incoming>,
+    now: &str,
+    started: std::time::Instant,
+    record: impl Fn(StatusCode, usize) + Send + Sync + 'static,
+) -> Response<ProxyBody> {
+    let (resp_parts, resp_body_incoming) = resp.into_parts();
+    let mut head = Response::new(());
+    *head.status_mut() = resp_parts.status;
+    *head.version_mut() = resp_parts.version;
+    *head.headers_mut() = resp_parts.headers.clone();
+
+    let observer: Box<dyn StreamObserver> = if is_event_stream(&resp_parts.headers) {
+        log_response(&state.cfg, conn_id, &head, None, now, started.elapsed());
+        Box::new(EventLogger {
+            state: state.clone(),
+            conn_id,
+            status: resp_parts.status,
+            lines: LineSplitter::default(),
+            record: Box::new(record),
+        })
+    } else {
+        Box::new(ChunkCollector {
+            state: state.clone(),
+            conn_id,
+            head,
+            now: now.to_string(),
+            started,
+            collected: Vec::new(),
+            trailers: None,
+            record: Box::new(record),
+        })
+    };
+
+    let body = ObservedBody::new(resp_body_incoming, observer);
+    let body = match state.cfg.upstream_bandwidth {
+        Some(rate) => PacedBody::new(body, rate).boxed(),
+        None => body.boxed(),
+    };
+    let mut out = Response::new(body);
+    *out.status_mut() = resp_parts.status;
+    *out.version_mut() = resp_parts.version;
     *out.headers_mut() = resp_parts.headers;
+    out
+}
+
+type Recorder = Box<dyn Fn(StatusCode, usize) + Send + Sync>;
+
+/// Logs each line of an event stream as soon as it is complete.
+struct EventLogger {
+    state: Arc<ProxyState>,
+    conn_id: u64,
+    status: StatusCode,
+    lines: LineSplitter,
+    record: Recorder,
+}
+
+impl StreamObserver for EventLogger {
+    fn data(&mut self, chunk: &Bytes) {
+        let cfg = &self.state.cfg;
+        for line in self.lines.push(chunk) {
+            if line.is_empty() {
+                continue;
+            }
+            let now = cfg.clock.now();
+            if cfg.log_format == LogFormat::Json {
+                let obj = json::Object::new()
+                    .num("conn", self.conn_id)
+                    .str("ts", &now)
+                    .str("direction", "event")
+                    .str("line", &line);
+                cfg.log.write_entry(&obj.finish());
+            } else {
+                cfg.log
+                    .write_entry(&format!("[conn#{}] {now} EVENT {line}", self.conn_id));
+            }
+        }
+    }
+
+    fn trailers(&mut self, trailers: &HeaderMap) {
+        let now = self.state.cfg.clock.now();
+        log_trailers(&self.state.cfg, self.conn_id, trailers, &now);
+    }
+
+    fn close(&mut self, bytes: usize, _complete: bool) {
+        let now = self.state.cfg.clock.now();
+        eprintln!(
+            "[conn#{}] {now} event stream closed after {bytes} bytes",
+            self.conn_id
+        );
+        (self.record)(self.status, bytes);
+    }
+}
+
+/// Buffers a copy of a streamed body so it can be logged once it ends. Only
+/// the first `--max-body-bytes` are kept, and nothing when bodies are not
+/// logged, so a long download does not pile up in memory.
+struct ChunkCollector {
+    state: Arc<ProxyState>,
+    conn_id: u64,
+    head: Response<()>,
+    now: String,
+    started: std::time::Instant,
+    collected: Vec<u8>,
+    /// Held back so they are logged after the response they end.
+    trailers: Option<HeaderMap>,
+    record: Recorder,
+}
+
+impl StreamObserver for ChunkCollector {
+    fn data(&mut self, chunk: &Bytes) {
+        let cfg = &self.state.cfg;
+        if !cfg.include_bodies {
+            return;
+        }
+        let room = cfg.max_body_bytes.saturating_sub(self.collected.len());
+        self.collected
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+
+    fn trailers(&mut self, trailers: &HeaderMap) {
+        self.trailers = Some(trailers.clone());
+    }
+
+    fn close(&mut self, bytes: usize, complete: bool) {
+        let cfg = &self.state.cfg;
+        if !complete {
+            eprintln!(
+                "[conn#{}] {} streamed response ended early after {bytes} bytes",
+                self.conn_id, self.now
+            );
+        }
+        let body = Bytes::from(std::mem::take(&mut self.collected));
+        log_response(
+            cfg,
+            self.conn_id,
+            &self.head,
+            Some((&body, bytes)),
+            &self.now,
+            self.started.elapsed(),
+        );
+        if let Some(trailers) = &self.trailers {
+            log_trailers(cfg, self.conn_id, trailers, &self.now);
+        }
+        (self.record)(self.head.status(), bytes);
+    }
+}
+
+/// Build the callback that reports this request's outcome to the stats
+/// channel and ends its trace span. It is owned so a streamed response can
+/// report when it finishes.
+fn outcome_recorder(
+    state: &Arc<ProxyState>,
+    conn_id: u64,
+    peer: SocketAddr,
+    method: &Method,
+    uri: &Uri,
+    bytes_in: usize,
+    span: Option<Span>,
+) -> impl Fn(StatusCode, usize) + Send + Sync + 'static {
+    let state = state.clone();
+    let method = method.clone();
+    let path = uri
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let span = std::sync::Mutex::new(span);
+    let started = std::time::Instant::now();
+    move |status, bytes_out| {
+        let latency = started.elapsed();
+        state.count_bytes(conn_id, bytes_in + bytes_out);
+        record_stats(
+            &state.cfg,
+            peer,
+            &method,
+            &path,
+            status,
+            (bytes_in, bytes_out),
+            latency,
+        );
+        let span = span.lock().ok().and_then(|mut span| span.take());
+        if let (Some(tracer), Some(span)) = (&state.cfg.tracer, span) {
+            tracer.finish(span, status.as_u16());
+        }
+    }
+}
+
+/// `bytes` is request body bytes in, then response body bytes out.
+fn record_stats(
+    cfg: &Config,
+    peer: SocketAddr,
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    (bytes_in, bytes_out): (usize, usize),
+    latency: Duration,
+) {
+    if let Some(tx) = &cfg.stats {
+        let _ = tx.send(StatsMessage::Request(StatsEvent {
+            method: method.clone(),
+            path: path.to_string(),
+            peer: peer.ip(),
+            at: std::time::SystemTime::now(),
+            status: status.as_u16(),
+            bytes_in: bytes_in as u64,
+            bytes_out: bytes_out as u64,
+            latency,
+        }));
+    }
+}
+
+async fn handle_connect(
+    state: Arc<ProxyState>,
+    conn_id: u64,
+    peer: SocketAddr,
+    req: Request<Incoming>,
+    now: &str,
+) -> Response<ProxyBody> {
+    let Some(authority) = req.uri().authority().map(|a| a.to_string()) else {
+        eprintln!(
+            "[conn#{conn_id}] {now} CONNECT without host:port: {}",
+            req.uri()
+        );
+        return simple_response(StatusCode::BAD_REQUEST, "CONNECT requires host:port");
+    };
+
+    if state.cfg.log_format == LogFormat::Json {
+        let line = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "connect")
+            .str("authority", &authority)
+            .str("peer", &peer.to_string())
+            .finish();
+        state.cfg.log.write_entry(&line);
+    } else {
+        state.cfg.log.write_entry(&format!(
+            "\n[conn#{conn_id}] {now} CONNECT {authority} from {peer}"
+        ));
+    }
 
-    log_response(&state.cfg, conn_id, &out, &resp_bytes, &now);
+    // Dial before answering so the client sees a 502 instead of a dead tunnel
+    let dial_started = std::time::Instant::now();
+    let mut upstream = match tokio::net::TcpStream::connect(&authority).await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("[conn#{conn_id}] {now} CONNECT {authority} failed: {e}");
+            let latency = dial_started.elapsed();
+            record_stats(
+                &state.cfg,
+                peer,
+                &Method::CONNECT,
+                &authority,
+                StatusCode::BAD_GATEWAY,
+                (0, 0),
+                latency,
+            );
+            return simple_response(StatusCode::BAD_GATEWAY, "upstream connection failed");
+        }
+    };
+    let latency = dial_started.elapsed();
+    record_stats(
+        &state.cfg,
+        peer,
+        &Method::CONNECT,
+        &authority,
+        StatusCode::OK,
+        (0, 0),
+        latency,
+    );
+
+    let log = state.cfg.log.clone();
+    tokio::spawn(async move {
+        match upgrade::on(req).await {
+            Ok(down) => {
+                let mut down = TokioIo::new(down);
+                match copy_bidirectional(&mut down, &mut upstream).await {
+                    Ok((sent, received)) => log.write_entry(&format!(
+                        "[conn#{conn_id}] CONNECT {authority} closed ({sent} bytes →, {received} bytes ←)"
+                    )),
+                    Err(e) => eprintln!("[conn#{conn_id}] CONNECT {authority} tunnel error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("[conn#{conn_id}] CONNECT upgrade error: {e}"),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(full(Bytes::new()))
+        .expect("connect 200 resp")
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
 
-    Ok(out)
+fn clone_request(req: &Request<Full<Bytes>>) -> Request<Full<Bytes>> {
+    let mut out = Request::new(req.body().clone());
+    *out.method_mut() = req.method().clone();
+    *out.uri_mut() = req.uri().clone();
+    *out.version_mut() = req.version();
+    *out.headers_mut() = req.headers().clone();
+    out
 }
 
-fn simple_response(status: StatusCode, msg: &str) -> Response<Full<Bytes>> {
+/// Copy of a request's head, for logging once the body has been handed off.
+fn request_head<B>(req: &Request<B>) -> Request<()> {
+    let mut out = Request::new(());
+    *out.method_mut() = req.method().clone();
+    *out.uri_mut() = req.uri().clone();
+    *out.version_mut() = req.version();
+    *out.headers_mut() = req.headers().clone();
+    out
+}
+
+fn simple_response(status: StatusCode, msg: &str) -> Response<ProxyBody> {
     Response::builder()
         .status(status)
         .header("content-type", "text/plain; charset=utf-8")
-        .body(Full::new(Bytes::from(msg.to_string())))
+        .body(full(Bytes::from(msg.to_string())))
         .unwrap()
 }
 
-fn remap_uri(method: &Method, uri: &Uri, cfg: &Config) -> Uri {
+fn health_response(cfg: &Config) -> Response<ProxyBody> {
+    let upstream = format!(
+        "{}://{}{}",
+        cfg.target_scheme,
+        cfg.target_authority,
+        cfg.target_base_path.as_deref().unwrap_or("")
+    );
+    let body = json::Object::new()
+        .str("status", "ok")
+        .str("upstream", &upstream)
+        .finish();
+    let mut resp = simple_response(StatusCode::OK, &body);
+    resp.headers_mut().insert(
+        hyper::http::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn full(bytes: Bytes) -> ProxyBody {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
+
+fn remap_uri(uri: &Uri, cfg: &Config) -> Uri {
     // Preserve path and query, change scheme/authority to target.
-    let path_and_query = uri
-        .path_and_query()
-        .map(|pq| pq.as_str())
-        .unwrap_or("/");
-    let full = format!("{}://{}{}", cfg.target_scheme, cfg.target_authority, path_and_query);
-    full.parse::<Uri>().unwrap_or_else(|_| Uri::from_static("/"))
+    let path_and_query = cfg
+        .strip_prefix
+        .as_deref()
+        .and_then(|prefix| strip_path_prefix(uri, prefix))
+        .unwrap_or_else(|| {
+            uri.path_and_query()
+                .map(|pq| pq.as_str())
+                .unwrap_or("/")
+                .to_string()
+        });
+    let base_path = cfg.target_base_path.as_deref().unwrap_or("");
+    let add_prefix = cfg.add_prefix.as_deref().unwrap_or("");
+    let full = format!(
+        "{}://{}{}{}{}",
+        cfg.target_scheme, cfg.target_authority, base_path, add_prefix, path_and_query
+    );
+    full.parse::<Uri>()
+        .unwrap_or_else(|_| Uri::from_static("/"))
+}
+
+/// Remove `prefix` (e.g. `/proxy`) from the request path on a segment
+/// boundary, keeping the query: `/proxy/users?id=1` → `/users?id=1`.
+/// Returns None when the path is outside the prefix.
+fn strip_path_prefix(uri: &Uri, prefix: &str) -> Option<String> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    let path = if rest.is_empty() { "/" } else { rest };
+    Some(match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    })
 }
 
-fn copy_headers_forward(mut in_headers: HeaderMap, out_headers: &mut HeaderMap, cfg: &Config) {
-    // Remove hop-by-hop headers per RFC 7230
+fn copy_headers_forward(
+    mut in_headers: HeaderMap,
+    out_headers: &mut HeaderMap,
+    cfg: &Config,
+    span: Option<&Span>,
+) {
+    // Remove hop-by-hop headers per RFC 7230, plus any the client named in
+    // Connection, except those --keep-header asks to pass through
     static HOP: &[&str] = &[
         "connection",
         "proxy-connection",
@@ -319,94 +1319,507 @@ fn copy_headers_forward(mut in_headers: HeaderMap, out_headers: &mut HeaderMap,
         "transfer-encoding",
         "upgrade",
         "te",
-        "trailer",
     ];
-    for name in HOP {
-        in_headers.remove(*name);
+    let listed: Vec<String> = in_headers
+        .get_all(hyper::http::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect();
+    let kept = |name: &str| cfg.keep_header.iter().any(|k| k.eq_ignore_ascii_case(name));
+    let wants_trailers = in_headers
+        .get_all(hyper::http::header::TE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("trailers"));
+    for name in HOP.iter().copied().chain(listed.iter().map(String::as_str)) {
+        if !kept(name) {
+            in_headers.remove(name);
+        }
+    }
+    // Trailers are relayed, so the upstream may send them if the client
+    // takes them; without `TE: trailers` servers such as hyper drop them.
+    if wants_trailers && !in_headers.contains_key(hyper::http::header::TE) {
+        in_headers.insert(
+            hyper::http::header::TE,
+            HeaderValue::from_static("trailers"),
+        );
     }
 
-    // Overwrite Host to target authority, unless explicitly overridden
-    let host_value = cfg
-        .upstream_host
-        .as_deref()
-        .unwrap_or(&cfg.target_authority);
-    in_headers.insert(
-        "host",
-        HeaderValue::from_str(host_value).unwrap_or(HeaderValue::from_static("localhost")),
-    );
+    // Overwrite Host to target authority, unless explicitly overridden or
+    // the client's own Host is to be preserved
+    if !(cfg.preserve_host && in_headers.contains_key("host")) {
+        let host_value = cfg
+            .upstream_host
+            .as_deref()
+            .unwrap_or(&cfg.target_authority);
+        in_headers.insert(
+            "host",
+            HeaderValue::from_str(host_value).unwrap_or(HeaderValue::from_static("localhost")),
+        );
+    }
+
+    // Injected credentials win over whatever the client sent
+    if let Some(auth) = &cfg.upstream_basic_auth {
+        in_headers.insert(hyper::http::header::AUTHORIZATION, auth.clone());
+    }
+
+    // Plain HTTP goes to the upstream proxy as an absolute-form request
+    if cfg.target_scheme == "http"
+        && let Some(auth) = cfg.upstream_proxy.as_ref().and_then(|p| p.auth.as_ref())
+    {
+        in_headers.insert(hyper::http::header::PROXY_AUTHORIZATION, auth.clone());
+    }
+
+    // The upstream call becomes a child of this request's span
+    if let Some(span) = span {
+        in_headers.insert("traceparent", span.traceparent());
+    }
 
     *out_headers = in_headers;
 }
 
-fn log_request(
+/// Log a forwarded request, showing the client's own Authorization header
+/// rather than credentials injected by --upstream-basic-auth or --upstream-proxy.
+/// Returns the head as logged, for --dump-http.
+fn log_forwarded_request<B>(
     cfg: &Config,
     conn_id: u64,
     peer: &SocketAddr,
-    req: &Request<Full<Bytes>>,
+    forwarded: &Request<B>,
+    client_auth: Option<HeaderValue>,
+    body: &Bytes,
+    now: &str,
+) -> Request<()> {
+    let proxy_auth = cfg
+        .upstream_proxy
+        .as_ref()
+        .is_some_and(|p| p.auth.is_some());
+    if cfg.upstream_basic_auth.is_some() || proxy_auth {
+        let mut logged = request_head(forwarded);
+        if cfg.upstream_basic_auth.is_some() {
+            match client_auth {
+                Some(v) => logged
+                    .headers_mut()
+                    .insert(hyper::http::header::AUTHORIZATION, v),
+                None => logged
+                    .headers_mut()
+                    .remove(hyper::http::header::AUTHORIZATION),
+            };
+        }
+        logged
+            .headers_mut()
+            .remove(hyper::http::header::PROXY_AUTHORIZATION);
+        log_request(cfg, conn_id, peer, &logged, body, now);
+        logged
+    } else {
+        log_request(cfg, conn_id, peer, forwarded, body, now);
+        request_head(forwarded)
+    }
+}
+
+/// --dump-http, with the body as it was sent upstream; like the log, the
+/// file never holds the tap's own upstream credentials.
+async fn dump_request(cfg: &Config, conn_id: u64, logged: &Request<()>, sent: &Bytes) {
+    if let Some(dump) = &cfg.dump_http
+        && let Err(e) = dump.write(logged, sent, &cfg.redact_header).await
+    {
+        eprintln!("[conn#{conn_id}] --dump-http: {e:#}");
+    }
+}
+
+fn log_request<B>(
+    cfg: &Config,
+    conn_id: u64,
+    peer: &SocketAddr,
+    req: &Request<B>,
     body: &Bytes,
     now: &str,
 ) {
-    println!(
+    if cfg.log_format == LogFormat::Json {
+        let mut obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "request")
+            .str("method", req.method().as_str())
+            .str("url", &req.uri().to_string())
+            .str("peer", &peer.to_string())
+            .raw("headers", &headers_json(req.headers(), &cfg.redact_header))
+            .bool("upstream_basic_auth", cfg.upstream_basic_auth.is_some());
+        if cfg.include_bodies {
+            obj = body_json(obj, body, body.len(), cfg.max_body_bytes);
+        }
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+
+    let mut out = format!(
         "\n[conn#{conn_id}] {now} REQUEST {} {} from {}",
         req.method(),
         req.uri(),
         peer
     );
-    print_headers("→", req.headers(), &cfg.redact_header);
+    write_headers(
+        &mut out,
+        "→",
+        req.headers(),
+        &cfg.redact_header,
+        cookie_redaction(cfg),
+    );
+    if cfg.upstream_basic_auth.is_some() {
+        out.push_str("\n→ (upstream authorization replaced by --upstream-basic-auth)");
+    }
     if cfg.include_bodies {
-        print_body("→", body, cfg.max_body_bytes);
+        let form = req
+            .headers()
+            .get(hyper::http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(forms::detect);
+        match form {
+            Some(kind) if !cfg.force_hex && !body.is_empty() => {
+                let _ = write!(
+                    out,
+                    "\n{}",
+                    forms::summarize(&kind, body, "→", cfg.max_body_bytes)
+                );
+            }
+            _ => write_body(&mut out, "→", body, body.len(), cfg),
+        }
     }
+    cfg.log.write_entry(&out);
 }
 
-fn log_response(
+/// `body` is the response body, or the start of it, with its full length.
+fn log_response<B>(
     cfg: &Config,
     conn_id: u64,
-    resp: &Response<Full<Bytes>>,
-    body: &Bytes,
+    resp: &Response<B>,
+    body: Option<(&Bytes, usize)>,
     now: &str,
+    latency: Duration,
 ) {
-    println!("[conn#{conn_id}] {now} RESPONSE {}", resp.status());
-    print_headers("←", resp.headers(), &cfg.redact_header);
-    if cfg.include_bodies {
-        print_body("←", body, cfg.max_body_bytes);
+    if cfg.log_format == LogFormat::Json {
+        let mut obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "response")
+            .num("status", resp.status().as_u16())
+            .num(
+                "latency_ms",
+                format!("{:.3}", latency.as_secs_f64() * 1000.0),
+            )
+            .raw("headers", &headers_json(resp.headers(), &cfg.redact_header));
+        if cfg.include_bodies
+            && let Some((body, total)) = body
+        {
+            obj = body_json(obj, body, total, cfg.max_body_bytes);
+        }
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+
+    let mut out = format!("[conn#{conn_id}] {now} RESPONSE {}", resp.status());
+    write_headers(
+        &mut out,
+        "←",
+        resp.headers(),
+        &cfg.redact_header,
+        cookie_redaction(cfg),
+    );
+    if cfg.include_bodies
+        && let Some((body, total)) = body
+    {
+        write_body(&mut out, "←", body, total, cfg);
     }
+    cfg.log.write_entry(&out);
 }
 
-fn print_headers(prefix: &str, headers: &HeaderMap, redact: &[String]) {
+/// An --allow/--deny decision, naming the glob that made it.
+fn log_acl<B>(cfg: &Config, conn_id: u64, req: &Request<B>, decision: &Decision, now: &str) {
+    let (verdict, pattern) = match decision {
+        Decision::Allowed { pattern } => ("allow", Some(pattern.as_str())),
+        Decision::Denied { pattern } => ("deny", pattern.as_deref()),
+    };
+    if cfg.log_format == LogFormat::Json {
+        let mut obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "acl")
+            .str("decision", verdict)
+            .str("method", req.method().as_str())
+            .str("path", req.uri().path());
+        if let Some(pattern) = pattern {
+            obj = obj.str("pattern", pattern);
+        }
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+    let reason = match (decision, pattern) {
+        (Decision::Allowed { .. }, Some(p)) => format!("matches --allow {p}"),
+        (_, Some(p)) => format!("matches --deny {p}; returning 403"),
+        (_, None) => "matches no --allow; returning 403".to_string(),
+    };
+    cfg.log.write_entry(&format!(
+        "[conn#{conn_id}] {now} {} {} {} ({reason})",
+        verdict.to_ascii_uppercase(),
+        req.method(),
+        req.uri().path()
+    ));
+}
+
+/// A --delay about to be slept, so synthetic latency is not mistaken for
+/// the upstream's.
+fn log_delay<B>(cfg: &Config, conn_id: u64, req: &Request<B>, rule: &PathDelay, now: &str) {
+    let ms = rule.delay.as_millis();
+    if cfg.log_format == LogFormat::Json {
+        let obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "delay")
+            .str("method", req.method().as_str())
+            .str("path", req.uri().path())
+            .str("pattern", &rule.pattern)
+            .num("delay_ms", ms);
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+    cfg.log.write_entry(&format!(
+        "[conn#{conn_id}] {now} DELAY {} {} by {ms}ms (matches --delay {})",
+        req.method(),
+        req.uri().path(),
+        rule.pattern
+    ));
+}
+
+/// Sizes before and after --body-replace; the REQUEST entry above already
+/// shows the original body.
+fn log_body_rewrite(
+    cfg: &Config,
+    conn_id: u64,
+    count: usize,
+    original: usize,
+    rewritten: usize,
+    now: &str,
+) {
+    if cfg.log_format == LogFormat::Json {
+        let obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "body-replace")
+            .num("replacements", count)
+            .num("original_bytes", original)
+            .num("rewritten_bytes", rewritten);
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+    let replacements = match count {
+        1 => "1 replacement".to_string(),
+        n => format!("{n} replacements"),
+    };
+    cfg.log.write_entry(&format!(
+        "[conn#{conn_id}] {now} body-replace: {replacements}, {original} → {rewritten} bytes"
+    ));
+}
+
+/// Trailing headers that ended a response body, e.g. gRPC's `grpc-status`.
+fn log_trailers(cfg: &Config, conn_id: u64, trailers: &HeaderMap, now: &str) {
+    if cfg.log_format == LogFormat::Json {
+        let obj = json::Object::new()
+            .num("conn", conn_id)
+            .str("ts", now)
+            .str("direction", "trailers")
+            .raw("headers", &headers_json(trailers, &cfg.redact_header));
+        cfg.log.write_entry(&obj.finish());
+        return;
+    }
+
+    let mut out = format!("[conn#{conn_id}] {now} TRAILERS");
+    write_headers(
+        &mut out,
+        "←",
+        trailers,
+        &cfg.redact_header,
+        cookie_redaction(cfg),
+    );
+    cfg.log.write_entry(&out);
+}
+
+const REDACTED: HeaderValue = HeaderValue::from_static("<redacted>");
+
+/// Copy of `headers` with every header named in `redact` (case-insensitive)
+/// replaced by `<redacted>`. Every log sink goes through this so a secret
+/// hidden on the console is hidden everywhere.
+pub fn redact_headers(headers: &HeaderMap, redact: &[String]) -> HeaderMap {
+    let mut out = headers.clone();
+    for name in headers.keys() {
+        if redact.iter().any(|r| r.eq_ignore_ascii_case(name.as_str())) {
+            out.insert(name, REDACTED);
+        }
+    }
+    out
+}
+
+fn header_display(val: &HeaderValue) -> String {
+    match val.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => format!("<{} bytes>", val.as_bytes().len()),
+    }
+}
+
+/// Cookie names to redact when --show-cookies expands cookie headers.
+fn cookie_redaction(cfg: &Config) -> Option<&[String]> {
+    cfg.show_cookies.then_some(cfg.redact_cookie.as_slice())
+}
+
+/// `cookies` is Some when Cookie/Set-Cookie are expanded per cookie, in which
+/// case it replaces header-level redaction for those two headers.
+fn write_headers(
+    out: &mut String,
+    prefix: &str,
+    headers: &HeaderMap,
+    redact: &[String],
+    cookies: Option<&[String]>,
+) {
+    let redacted = redact_headers(headers, redact);
     let mut names: Vec<_> = headers.keys().map(|k| k.as_str()).collect();
     names.sort_unstable();
     for name in names {
-        if let Some(val) = headers.get(name) {
-            let display = if redact.iter().any(|r| r.eq_ignore_ascii_case(name)) {
-                "<redacted>".to_string()
-            } else {
+        if let Some(redact_cookie) = cookies
+            && (name == "cookie" || name == "set-cookie")
+        {
+            for val in headers.get_all(name) {
                 match val.to_str() {
-                    Ok(s) => s.to_string(),
-                    Err(_) => format!("<{} bytes>", val.as_bytes().len()),
+                    Ok(s) => write_cookies(out, prefix, name, s, redact_cookie),
+                    Err(_) => {
+                        let _ = write!(out, "\n{prefix} {name}: <{} bytes>", val.as_bytes().len());
+                    }
                 }
-            };
-            println!("{prefix} {name}: {display}");
+            }
+            continue;
         }
+        if let Some(val) = redacted.get(name) {
+            let _ = write!(out, "\n{prefix} {name}: {}", header_display(val));
+        }
+    }
+}
+
+fn write_cookies(out: &mut String, prefix: &str, name: &str, value: &str, redact: &[String]) {
+    let shown = |cookie: &str, value: &str| {
+        if redact.iter().any(|r| r == cookie) {
+            "<redacted>".to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    if name == "set-cookie" {
+        let cookie = parse_set_cookie(value);
+        let _ = write!(
+            out,
+            "\n{prefix} set-cookie: {} = {}",
+            cookie.name,
+            shown(cookie.name, cookie.value)
+        );
+        for (attr, attr_value) in cookie.attributes {
+            match attr_value {
+                Some(v) => {
+                    let _ = write!(out, "\n{prefix}     {attr}: {v}");
+                }
+                None => {
+                    let _ = write!(out, "\n{prefix}     {attr}");
+                }
+            }
+        }
+    } else {
+        let _ = write!(out, "\n{prefix} cookie:");
+        for (cookie, cookie_value) in parse_cookie_header(value) {
+            let _ = write!(
+                out,
+                "\n{prefix}     {cookie} = {}",
+                shown(cookie, cookie_value)
+            );
+        }
+    }
+}
+
+fn headers_json(headers: &HeaderMap, redact: &[String]) -> String {
+    let headers = redact_headers(headers, redact);
+    let mut names: Vec<_> = headers.keys().map(|k| k.as_str()).collect();
+    names.sort_unstable();
+    let mut obj = json::Object::new();
+    for name in names {
+        // Repeated headers are joined the same way they'd fold on the wire.
+        let joined = headers
+            .get_all(name)
+            .iter()
+            .map(header_display)
+            .collect::<Vec<_>>()
+            .join(", ");
+        obj = obj.str(name, &joined);
     }
+    obj.finish()
 }
 
-fn print_body(prefix: &str, body: &Bytes, max: usize) {
+/// `body` may be only the start of a body `total` bytes long, as collected
+/// from a streamed response.
+fn body_json(obj: json::Object, body: &Bytes, total: usize, max: usize) -> json::Object {
     let take = body.len().min(max);
+    obj.str("body", &String::from_utf8_lossy(&body[..take]))
+        .num("body_bytes", total)
+        .bool("body_truncated", total > take)
+}
+
+fn write_body(out: &mut String, prefix: &str, body: &Bytes, total: usize, cfg: &Config) {
+    let take = body.len().min(cfg.max_body_bytes);
     if take == 0 {
-        println!("{prefix} <no body>");
+        let _ = write!(out, "\n{prefix} <no body>");
         return;
     }
     let slice = &body[..take];
-    let printable = String::from_utf8_lossy(slice);
-    if body.len() > take {
-        println!("{prefix} body ({} / {} bytes, truncated):\n{}\n…", take, body.len(), printable);
+    let printable = if cfg.force_hex {
+        hex_dump(slice)
     } else {
-        println!("{prefix} body ({} bytes):\n{}", body.len(), printable);
+        String::from_utf8_lossy(slice).into_owned()
+    };
+    if total > take {
+        let _ = write!(
+            out,
+            "\n{prefix} body ({} / {} bytes, truncated):\n{}\n…",
+            take, total, printable
+        );
+    } else {
+        let _ = write!(
+            out,
+            "\n{prefix} body ({} bytes):\n{}",
+            body.len(),
+            printable
+        );
     }
 }
 
-fn now_iso() -> String {
-    OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_else(|_| "now".into())
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "{:08x}  ", i * 16);
+        for b in chunk {
+            let _ = write!(out, "{b:02x} ");
+        }
+        out.push_str(&"   ".repeat(16 - chunk.len()));
+        out.push(' ');
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+    }
+    out
 }
 
 fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
@@ -422,7 +1835,10 @@ fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
     headers
         .get(hyper::http::header::CONNECTION)
         .and_then(|v| v.to_str().ok())
-        .map(|s| s.split(',').any(|t| t.trim().eq_ignore_ascii_case("upgrade")))
+        .map(|s| {
+            s.split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case("upgrade"))
+        })
         .unwrap_or(false)
 }
 
@@ -475,19 +1891,23 @@ impl ServerCertVerifier for NoVerifier {
     }
 }
 
-fn build_https_connector(cfg: &Config) -> anyhow::Result<HttpsConnector<HttpConnector>> {
+fn build_https_connector(cfg: &Config) -> anyhow::Result<HttpsConnector<ProxyConnector>> {
     if cfg.insecure_upstream {
         let no_verify = Arc::new(NoVerifier);
-        let tls_cfg = ClientConfig::builder()
+        let mut tls_cfg = ClientConfig::builder()
             .dangerous()
             .with_custom_certificate_verifier(no_verify)
             .with_no_client_auth();
-        let mut b = HttpsConnectorBuilder::new().with_tls_config(tls_cfg).https_or_http();
+        tls_cfg.key_log = cfg.key_log.clone();
+        let mut b = HttpsConnectorBuilder::new()
+            .with_tls_config(tls_cfg)
+            .https_or_http();
         if let Some(name) = &cfg.upstream_server_name {
             let sn = ServerName::try_from(name.clone())?;
             b = b.with_server_name_resolver(FixedServerNameResolver::new(sn));
         }
-        Ok(b.enable_http1().build())
+        Ok(b.enable_http1()
+            .wrap_connector(ProxyConnector::new(cfg.upstream_proxy.clone())))
     } else {
         // Build root store from native + optional extra CAs
         let mut roots = RootCertStore::empty();
@@ -499,29 +1919,26 @@ fn build_https_connector(cfg: &Config) -> anyhow::Result<HttpsConnector<HttpConn
         for path in &cfg.upstream_ca {
             if let Ok(file) = std::fs::File::open(path) {
                 let mut reader = std::io::BufReader::new(file);
-                for c in certs(&mut reader) {
-                    match c {
-                        Ok(der) => {
-                            let _ = roots.add(CertificateDer::from(der));
-                        }
-                        Err(_) => {}
-                    }
+                for der in certs(&mut reader).flatten() {
+                    let _ = roots.add(der);
                 }
             } else {
-                eprintln!("Warning: unable to open upstream CA file: {}", path.display());
+                eprintln!(
+                    "Warning: unable to open upstream CA file: {}",
+                    path.display()
+                );
             }
         }
 
         let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
-        let tls_cfg = if let (Some(cert_path), Some(key_path)) = (&cfg.upstream_client_cert, &cfg.upstream_client_key) {
+        let mut tls_cfg = if let (Some(cert_path), Some(key_path)) =
+            (&cfg.upstream_client_cert, &cfg.upstream_client_key)
+        {
             // Load client cert chain
             let chain: Vec<CertificateDer<'static>> = match std::fs::File::open(cert_path) {
                 Ok(f) => {
                     let mut r = std::io::BufReader::new(f);
-                    certs(&mut r)
-                        .filter_map(|c| c.ok())
-                        .map(|d| CertificateDer::from(d))
-                        .collect()
+                    certs(&mut r).filter_map(|c| c.ok()).collect()
                 }
                 Err(_) => Vec::new(),
             };
@@ -533,14 +1950,14 @@ fn build_https_connector(cfg: &Config) -> anyhow::Result<HttpsConnector<HttpConn
                         .filter_map(|k| k.ok())
                         .map(PrivateKeyDer::from)
                         .collect();
-                    if keys.is_empty() {
-                        if let Ok(f2) = std::fs::File::open(key_path) {
-                            let mut r2 = std::io::BufReader::new(f2);
-                            keys = rsa_private_keys(&mut r2)
-                                .filter_map(|k| k.ok())
-                                .map(PrivateKeyDer::from)
-                                .collect();
-                        }
+                    if keys.is_empty()
+                        && let Ok(f2) = std::fs::File::open(key_path)
+                    {
+                        let mut r2 = std::io::BufReader::new(f2);
+                        keys = rsa_private_keys(&mut r2)
+                            .filter_map(|k| k.ok())
+                            .map(PrivateKeyDer::from)
+                            .collect();
                     }
                     keys.into_iter().next()
                 }
@@ -556,22 +1973,480 @@ fn build_https_connector(cfg: &Config) -> anyhow::Result<HttpsConnector<HttpConn
                         }
                     }
                 } else {
-                    eprintln!("Warning: upstream client key not found or invalid; proceeding without client auth");
+                    eprintln!(
+                        "Warning: upstream client key not found or invalid; proceeding without client auth"
+                    );
                     builder.clone().with_no_client_auth()
                 }
             } else {
-                eprintln!("Warning: upstream client cert chain empty; proceeding without client auth");
+                eprintln!(
+                    "Warning: upstream client cert chain empty; proceeding without client auth"
+                );
                 builder.clone().with_no_client_auth()
             }
         } else {
             builder.with_no_client_auth()
         };
+        tls_cfg.key_log = cfg.key_log.clone();
 
-        let mut b = HttpsConnectorBuilder::new().with_tls_config(tls_cfg).https_or_http();
+        let mut b = HttpsConnectorBuilder::new()
+            .with_tls_config(tls_cfg)
+            .https_or_http();
         if let Some(name) = &cfg.upstream_server_name {
             let sn = ServerName::try_from(name.clone())?;
             b = b.with_server_name_resolver(FixedServerNameResolver::new(sn));
         }
-        Ok(b.enable_http1().build())
+        Ok(b.enable_http1()
+            .wrap_connector(ProxyConnector::new(cfg.upstream_proxy.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timefmt::TimeFormat;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// A config forwarding plain HTTP to `target` with every option off.
+    fn test_config(target: SocketAddr) -> Config {
+        Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            target_authority: target.to_string(),
+            target_scheme: "http",
+            target_base_path: None,
+            log_format: LogFormat::Text,
+            log: LogSink::Stdout,
+            dump_http: None,
+            clock: Clock::new(TimeFormat::Rfc3339, false),
+            include_bodies: false,
+            max_body_bytes: 4096,
+            force_hex: false,
+            redact_header: vec![],
+            show_cookies: false,
+            redact_cookie: vec![],
+            tls: None,
+            insecure_upstream: false,
+            key_log: Arc::new(rustls::KeyLogFile::new()),
+            stats: None,
+            upstream_ca: vec![],
+            upstream_client_cert: None,
+            upstream_client_key: None,
+            upstream_server_name: None,
+            upstream_host: None,
+            preserve_host: false,
+            keep_header: vec![],
+            upstream_basic_auth: None,
+            upstream_retries: 0,
+            upstream_bandwidth: None,
+            delay: vec![],
+            upstream_proxy: None,
+            forward_proxy: false,
+            max_connections: None,
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            tcp_nodelay: false,
+            health_path: None,
+            strip_prefix: None,
+            acl: Acl::default(),
+            add_prefix: None,
+            recompress: false,
+            body_replace: vec![],
+            body_replace_binary: false,
+            tracer: None,
+        }
+    }
+
+    /// Serve `cfg` on an ephemeral port, returning the address to connect to.
+    async fn start_proxy(cfg: Config) -> SocketAddr {
+        let listener = TcpListener::bind(cfg.listen).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = Client::builder(TokioExecutor::new()).build::<_, ProxyBody>(
+            TlsInfoConnector::new(build_https_connector(&cfg).unwrap(), cfg.log.clone()),
+        );
+        let state = Arc::new(ProxyState::new(cfg, client));
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = listener.accept().await.unwrap();
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let conn_id = state.next_conn_id();
+                    let svc = service_fn(move |req| handle(state.clone(), conn_id, peer, req));
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), svc)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Read from `stream` into `buf` until `marker` has appeared.
+    async fn read_until(stream: &mut TcpStream, buf: &mut Vec<u8>, marker: &str) {
+        let mut chunk = [0; 1024];
+        while !String::from_utf8_lossy(buf).contains(marker) {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(
+                n > 0,
+                "closed before {marker:?}: {}",
+                String::from_utf8_lossy(buf)
+            );
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// An upstream that answers `Expect: 100-continue` uploads to `/ok` with
+    /// a 100 and then echoes the body, and rejects anything else with 417.
+    async fn continue_upstream() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    read_until(&mut stream, &mut buf, "\r\n\r\n").await;
+                    let head = String::from_utf8_lossy(&buf).into_owned();
+                    if !head.starts_with("PUT /ok ") {
+                        let reply = "HTTP/1.1 417 Expectation Failed\r\ncontent-length: 0\r\n\r\n";
+                        stream.write_all(reply.as_bytes()).await.unwrap();
+                        return;
+                    }
+                    stream
+                        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let mut body = buf.split_off(head.find("\r\n\r\n").unwrap() + 4);
+                    while body.len() < 5 {
+                        let mut chunk = [0; 64];
+                        let n = stream.read(&mut chunk).await.unwrap();
+                        body.extend_from_slice(&chunk[..n]);
+                    }
+                    let reply = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n{}",
+                        String::from_utf8_lossy(&body)
+                    );
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn proxy_expect_continue_relays_100_and_rejection() {
+        let proxy = start_proxy(test_config(continue_upstream().await)).await;
+        let request = |path| {
+            format!(
+                "PUT {path} HTTP/1.1\r\nhost: tap\r\nexpect: 100-continue\r\ncontent-length: 5\r\n\r\n"
+            )
+        };
+
+        // Accepted: the client only sends its body after the upstream's 100
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(request("/ok").as_bytes()).await.unwrap();
+        let mut buf = Vec::new();
+        read_until(&mut client, &mut buf, "100 Continue\r\n\r\n").await;
+        client.write_all(b"hello").await.unwrap();
+        read_until(&mut client, &mut buf, "hello").await;
+        let reply = String::from_utf8_lossy(&buf);
+        assert!(reply.contains("HTTP/1.1 200 OK"), "{reply}");
+
+        // Rejected: the 417 arrives without the client ever sending its body
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(request("/no").as_bytes()).await.unwrap();
+        let mut buf = Vec::new();
+        read_until(&mut client, &mut buf, "\r\n\r\n").await;
+        let reply = String::from_utf8_lossy(&buf);
+        assert!(reply.starts_with("HTTP/1.1 417"), "{reply}");
+        assert!(!reply.contains("100 Continue"), "{reply}");
+    }
+
+    #[test]
+    fn proxy_announce_trailers_declares_missing_names_once() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", HeaderValue::from_static("5"));
+        headers.insert("trailer", HeaderValue::from_static("X-Checksum"));
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        trailers.insert("server-timing", HeaderValue::from_static("db;dur=3"));
+
+        announce_trailers(&mut headers, &trailers);
+        assert!(!headers.contains_key("content-length"));
+        assert_eq!(headers["transfer-encoding"], "chunked");
+        let declared: Vec<_> = headers.get_all("trailer").iter().collect();
+        assert_eq!(declared, ["X-Checksum", "server-timing"]);
+    }
+
+    #[tokio::test]
+    async fn proxy_relays_upstream_trailers_to_the_client() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = Vec::new();
+            read_until(&mut stream, &mut buf, "\r\n\r\n").await;
+            let head = String::from_utf8_lossy(&buf).to_ascii_lowercase();
+            // The client's `TE: trailers` is passed on so the upstream sends them
+            assert!(head.contains("te: trailers"), "{head}");
+            let reply = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ntrailer: x-checksum\r\n\r\n\
+                         5\r\nhello\r\n0\r\nx-checksum: abc\r\n\r\n";
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        let proxy = start_proxy(test_config(target)).await;
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nhost: tap\r\nte: trailers\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        read_until(&mut client, &mut buf, "x-checksum: abc\r\n\r\n").await;
+        let reply = String::from_utf8_lossy(&buf);
+        assert!(reply.starts_with("HTTP/1.1 200 OK"), "{reply}");
+        assert!(reply.contains("hello\r\n0\r\n"), "{reply}");
+    }
+
+    #[tokio::test]
+    async fn proxy_dump_http_holds_the_rewritten_body() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = Vec::new();
+            read_until(&mut stream, &mut buf, "to prod").await;
+            let reply = "HTTP/1.1 204 No Content\r\n\r\n";
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        let dir = std::env::temp_dir().join(format!("http-tap-dump-rw-{}", std::process::id()));
+        let mut cfg = test_config(target);
+        cfg.dump_http = Some(HttpDump::create(&dir).unwrap());
+        cfg.body_replace = vec![BodyRewrite::literal("staging=>prod").unwrap()];
+        let proxy = start_proxy(cfg).await;
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(
+                b"POST /deploy HTTP/1.1\r\nhost: tap\r\ncontent-type: text/plain\r\n\
+                  content-length: 10\r\n\r\nto staging",
+            )
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        read_until(&mut client, &mut buf, "\r\n\r\n").await;
+
+        let dump = std::fs::read_to_string(dir.join("00001-POST-deploy.http")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(dump.ends_with("\n\nto prod\n"), "{dump}");
+    }
+
+    fn secret_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer s3cret"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        headers
+    }
+
+    #[tokio::test]
+    async fn proxy_streamed_response_is_paced_and_logged_truncated() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = Vec::new();
+            read_until(&mut stream, &mut buf, "\r\n\r\n").await;
+            let chunk = "x".repeat(1000);
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3e8\r\n{chunk}\r\n3e8\r\n{chunk}\r\n0\r\n\r\n"
+            );
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        let log_path =
+            std::env::temp_dir().join(format!("http-tap-stream-{}.log", std::process::id()));
+        let mut cfg = test_config(target);
+        cfg.log = LogSink::file(&log_path, 1024 * 1024).unwrap();
+        cfg.include_bodies = true;
+        cfg.max_body_bytes = 16;
+        cfg.upstream_bandwidth = Some(10_000);
+        let proxy = start_proxy(cfg).await;
+
+        let started = std::time::Instant::now();
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(b"GET /download HTTP/1.1\r\nhost: tap\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        read_until(&mut client, &mut buf, "\r\n0\r\n\r\n").await;
+        // 2000 bytes at 10 KB/s, in 1000-byte slices with a wait after each
+        assert!(started.elapsed() >= Duration::from_millis(190));
+        assert_eq!(String::from_utf8_lossy(&buf).matches('x').count(), 2000);
+
+        // The collector logs once the body has been fully polled
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        assert!(log.contains("body (16 / 2000 bytes, truncated)"), "{log}");
+        assert!(!log.contains(&"x".repeat(17)), "{log}");
+    }
+
+    #[test]
+    fn proxy_copy_headers_forward_strips_hop_by_hop_headers() {
+        let target: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let client_headers = || {
+            let mut headers = HeaderMap::new();
+            headers.insert("host", HeaderValue::from_static("tap.local:8888"));
+            headers.insert("connection", HeaderValue::from_static("keep-alive, X-Hop"));
+            headers.append("connection", HeaderValue::from_static("x-debug"));
+            headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+            headers.insert("upgrade", HeaderValue::from_static("websocket"));
+            headers.insert("x-hop", HeaderValue::from_static("1"));
+            headers.insert("x-debug", HeaderValue::from_static("1"));
+            headers.insert("te", HeaderValue::from_static("gzip, trailers"));
+            headers.insert("accept", HeaderValue::from_static("*/*"));
+            headers
+        };
+        let forward = |cfg: &Config| {
+            let mut out = HeaderMap::new();
+            copy_headers_forward(client_headers(), &mut out, cfg, None);
+            out
+        };
+
+        let mut cfg = test_config(target);
+        let out = forward(&cfg);
+        for name in ["connection", "keep-alive", "upgrade", "x-hop", "x-debug"] {
+            assert!(!out.contains_key(name), "{name} was forwarded");
+        }
+        assert_eq!(out["host"], "127.0.0.1:8080");
+        assert_eq!(out["accept"], "*/*");
+        // Only `TE: trailers` is meaningful past this hop
+        assert_eq!(out["te"], "trailers");
+
+        cfg.keep_header = vec!["X-Debug".into(), "upgrade".into()];
+        cfg.preserve_host = true;
+        let out = forward(&cfg);
+        assert_eq!(out["x-debug"], "1");
+        assert_eq!(out["upgrade"], "websocket");
+        assert!(!out.contains_key("x-hop"));
+        assert_eq!(out["host"], "tap.local:8888");
+
+        cfg.preserve_host = false;
+        cfg.upstream_host = Some("api.example".into());
+        assert_eq!(forward(&cfg)["host"], "api.example");
+    }
+
+    #[tokio::test]
+    async fn proxy_authorization_redacted_in_every_sink() {
+        let redact = vec![String::from("Authorization")];
+        let headers = secret_headers();
+
+        let redacted = redact_headers(&headers, &redact);
+        assert_eq!(redacted["authorization"], "<redacted>");
+        assert_eq!(redacted["accept"], "*/*");
+
+        let mut console = String::new();
+        write_headers(&mut console, "→", &headers, &redact, None);
+        assert!(console.contains("authorization: <redacted>"));
+
+        let json = headers_json(&headers, &redact);
+        assert!(json.contains(r#""authorization":"<redacted>""#));
+
+        let dir = std::env::temp_dir().join(format!("http-tap-redact-{}", std::process::id()));
+        let mut req = Request::new(());
+        *req.headers_mut() = headers.clone();
+        let path = HttpDump::create(&dir)
+            .unwrap()
+            .write(&req, b"", &redact)
+            .await
+            .unwrap();
+        let dump = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(dump.contains("authorization: <redacted>"));
+
+        for out in [console, json, dump] {
+            assert!(!out.contains("s3cret"), "secret leaked: {out}");
+        }
+    }
+
+    #[test]
+    fn proxy_remap_uri_joins_the_base_path() {
+        let mut cfg = test_config("127.0.0.1:9".parse().unwrap());
+        cfg.target_authority = "host".to_string();
+        let remap = |cfg: &Config, uri: &str| remap_uri(&uri.parse().unwrap(), cfg).to_string();
+
+        cfg.target_base_path = Some("/api".to_string());
+        assert_eq!(remap(&cfg, "/v1/x"), "http://host/api/v1/x");
+        // main trims the trailing slash of http://host/api/, so "/" stays single
+        assert_eq!(remap(&cfg, "/"), "http://host/api/");
+        assert_eq!(remap(&cfg, "/v1/x?q=1&r"), "http://host/api/v1/x?q=1&r");
+
+        // An absolute-form request (forward proxying) keeps only its path
+        assert_eq!(
+            remap(&cfg, "http://elsewhere:8080/v1/x?q=1"),
+            "http://host/api/v1/x?q=1"
+        );
+
+        cfg.target_base_path = None;
+        assert_eq!(remap(&cfg, "/?q=1"), "http://host/?q=1");
+    }
+
+    #[test]
+    fn proxy_strip_prefix_stops_at_a_segment_boundary() {
+        let strip = |uri: &str| strip_path_prefix(&uri.parse().unwrap(), "/api");
+        assert_eq!(strip("/api/users?id=1").as_deref(), Some("/users?id=1"));
+        assert_eq!(strip("/api").as_deref(), Some("/"));
+        assert_eq!(strip("/api?id=1").as_deref(), Some("/?id=1"));
+        assert_eq!(strip("/apiary"), None);
+        assert_eq!(strip("/v1/api"), None);
+    }
+
+    /// An upstream that answers every request with its request target.
+    async fn path_echo_upstream() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    read_until(&mut stream, &mut buf, "\r\n\r\n").await;
+                    let head = String::from_utf8_lossy(&buf).into_owned();
+                    let target = head.split(' ').nth(1).unwrap_or_default();
+                    let reply = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{target}",
+                        target.len()
+                    );
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn proxy_strip_prefix_forwards_inside_and_404s_outside() {
+        let mut cfg = test_config(path_echo_upstream().await);
+        cfg.strip_prefix = Some("/api".to_string());
+        cfg.add_prefix = Some("/v2".to_string());
+        let proxy = start_proxy(cfg).await;
+        let get = |path: &'static str| async move {
+            let mut client = TcpStream::connect(proxy).await.unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\nhost: tap\r\nconnection: close\r\n\r\n");
+            client.write_all(request.as_bytes()).await.unwrap();
+            let mut reply = Vec::new();
+            client.read_to_end(&mut reply).await.unwrap();
+            String::from_utf8_lossy(&reply).into_owned()
+        };
+
+        let reply = get("/api/users?id=1").await;
+        assert!(reply.starts_with("HTTP/1.1 200"), "{reply}");
+        assert!(reply.ends_with("\r\n\r\n/v2/users?id=1"), "{reply}");
+
+        for outside in ["/apiary", "/users"] {
+            let reply = get(outside).await;
+            assert!(reply.starts_with("HTTP/1.1 404"), "{reply}");
+            assert!(reply.ends_with("not found"), "{reply}");
+        }
     }
 }
@@ -0,0 +1,657 @@
+//! A small backtracking regular expression engine over bytes, enough for
+//! `--body-replace-regex`: literals, `.`, classes (`[a-z]`, `[^"]`, `\d`,
+//! `\w`, `\s` and their negations), anchors `^` `$`, groups `(…)` and
+//! `(?:…)`, alternation, and the quantifiers `* + ? {n} {n,} {n,m}` with a
+//! trailing `?` for lazy matching.
+//!
+//! Matching is leftmost-first like Perl's. States already tried are
+//! remembered, so a search never takes more than pattern size × input
+//! length steps.
+
+use anyhow::{Result, bail};
+
+/// Larger `{n,m}` bounds would blow up the compiled program.
+const MAX_REPEAT: usize = 1000;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Byte(u8),
+    Any,
+    Class(Vec<(u8, u8)>, bool),
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Byte(u8),
+    Any,
+    /// Byte ranges, and whether the class is negated
+    Class(Vec<(u8, u8)>, bool),
+    Start,
+    End,
+    /// Try the first branch, then the second
+    Split(usize, usize),
+    Jmp(usize),
+    /// Record the position in a capture slot
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    /// Capture groups, not counting the whole match
+    groups: usize,
+}
+
+/// Byte offsets of a match and its groups; group 0 is the whole match.
+struct Captures {
+    slots: Vec<Option<usize>>,
+}
+
+impl Captures {
+    fn get(&self, group: usize) -> Option<(usize, usize)> {
+        Some((
+            self.slots.get(group * 2).copied()??,
+            self.slots.get(group * 2 + 1).copied()??,
+        ))
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut parser = Parser {
+            pattern: pattern.as_bytes(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.pos < parser.pattern.len() {
+            bail!("unmatched ')' at offset {}", parser.pos);
+        }
+        let mut prog = vec![Inst::Save(0)];
+        emit(&node, &mut prog);
+        prog.push(Inst::Save(1));
+        prog.push(Inst::Match);
+        Ok(Self {
+            prog,
+            groups: parser.groups,
+        })
+    }
+
+    /// The leftmost match starting at or after `from`. `visited` may carry
+    /// failures over from earlier searches of the same text: a state that
+    /// cannot reach a match never can.
+    fn search(&self, text: &[u8], from: usize, visited: &mut Visited) -> Option<Captures> {
+        let mut slots = vec![None; (self.groups + 1) * 2];
+        (from..=text.len()).find_map(|start| {
+            visited.touched.clear();
+            if !self.run(text, start, visited, &mut slots) {
+                return None;
+            }
+            // The states on the way to this match did not fail
+            visited.forget_touched();
+            Some(Captures {
+                slots: slots.clone(),
+            })
+        })
+    }
+
+    fn run(
+        &self,
+        text: &[u8],
+        start: usize,
+        visited: &mut Visited,
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        enum Job {
+            Run(usize, usize),
+            Restore(usize, Option<usize>),
+        }
+        let mut stack = vec![Job::Run(0, start)];
+        while let Some(job) = stack.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Run(pc, pos) => (pc, pos),
+                Job::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+            };
+            loop {
+                // A state that failed once fails again, whatever the captures
+                if !visited.insert(pc, pos) {
+                    break;
+                }
+                match &self.prog[pc] {
+                    Inst::Byte(b) if text.get(pos) == Some(b) => pos += 1,
+                    Inst::Any if text.get(pos).is_some_and(|&c| c != b'\n') => pos += 1,
+                    Inst::Class(ranges, negated)
+                        if text.get(pos).is_some_and(|&c| {
+                            ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)) != *negated
+                        }) =>
+                    {
+                        pos += 1
+                    }
+                    Inst::Start if pos == 0 => {}
+                    Inst::End if pos == text.len() => {}
+                    Inst::Split(first, second) => {
+                        stack.push(Job::Run(*second, pos));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jmp(target) => {
+                        pc = *target;
+                        continue;
+                    }
+                    Inst::Save(slot) => {
+                        stack.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(pos);
+                    }
+                    Inst::Match => return true,
+                    _ => break,
+                }
+                pc += 1;
+            }
+        }
+        false
+    }
+
+    /// Replace every non-overlapping match; `$0`–`$9` in `replacement` insert
+    /// groups (nothing for a group that did not take part or does not exist)
+    /// and `$$` a literal `$`. Returns the new text and the count.
+    pub fn replace_all(&self, text: &[u8], replacement: &str) -> (Vec<u8>, usize) {
+        let (out, count, _) = self.replace_all_counting_steps(text, replacement);
+        (out, count)
+    }
+
+    /// `replace_all`, also returning how many states the searches stepped
+    /// through.
+    fn replace_all_counting_steps(
+        &self,
+        text: &[u8],
+        replacement: &str,
+    ) -> (Vec<u8>, usize, usize) {
+        let mut out = Vec::with_capacity(text.len());
+        let mut last = 0;
+        let mut from = 0;
+        let mut count = 0;
+        let mut visited = Visited::new(self.prog.len(), text.len() + 1);
+        while let Some(caps) = self.search(text, from, &mut visited) {
+            let Some((start, end)) = caps.get(0) else {
+                break;
+            };
+            out.extend_from_slice(&text[last..start]);
+            expand(replacement, &caps, text, &mut out);
+            count += 1;
+            last = end;
+            // Step past empty matches so the search always advances
+            from = if end == start { end + 1 } else { end };
+            if from > text.len() {
+                break;
+            }
+        }
+        out.extend_from_slice(&text[last..]);
+        (out, count, visited.steps)
+    }
+}
+
+fn expand(replacement: &str, caps: &Captures, text: &[u8], out: &mut Vec<u8>) {
+    let mut bytes = replacement.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        match (b, bytes.peek()) {
+            (b'$', Some(b'$')) => {
+                bytes.next();
+                out.push(b'$');
+            }
+            (b'$', Some(d)) if d.is_ascii_digit() => {
+                let group = usize::from(d - b'0');
+                bytes.next();
+                if let Some((start, end)) = caps.get(group) {
+                    out.extend_from_slice(&text[start..end]);
+                }
+            }
+            _ => out.push(b),
+        }
+    }
+}
+
+/// (instruction, position) pairs already explored.
+struct Visited {
+    bits: Vec<u64>,
+    width: usize,
+    /// States added by the current run
+    touched: Vec<usize>,
+    /// Every state entered, fresh or not
+    steps: usize,
+}
+
+impl Visited {
+    fn new(prog_len: usize, positions: usize) -> Self {
+        Self {
+            bits: vec![0; (prog_len * positions).div_ceil(64)],
+            width: positions,
+            touched: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    /// False if the state was already there.
+    fn insert(&mut self, pc: usize, pos: usize) -> bool {
+        self.steps += 1;
+        let i = pc * self.width + pos;
+        let (word, bit) = (i / 64, 1u64 << (i % 64));
+        let fresh = self.bits[word] & bit == 0;
+        if fresh {
+            self.bits[word] |= bit;
+            self.touched.push(i);
+        }
+        fresh
+    }
+
+    fn forget_touched(&mut self) {
+        for i in self.touched.drain(..) {
+            self.bits[i / 64] &= !(1u64 << (i % 64));
+        }
+    }
+}
+
+fn emit(node: &Node, prog: &mut Vec<Inst>) {
+    match node {
+        Node::Byte(b) => prog.push(Inst::Byte(*b)),
+        Node::Any => prog.push(Inst::Any),
+        Node::Class(ranges, negated) => prog.push(Inst::Class(ranges.clone(), *negated)),
+        Node::Start => prog.push(Inst::Start),
+        Node::End => prog.push(Inst::End),
+        Node::Group(inner, index) => {
+            if let Some(i) = index {
+                prog.push(Inst::Save(i * 2));
+            }
+            emit(inner, prog);
+            if let Some(i) = index {
+                prog.push(Inst::Save(i * 2 + 1));
+            }
+        }
+        Node::Concat(nodes) => nodes.iter().for_each(|n| emit(n, prog)),
+        Node::Alt(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                let split = prog.len();
+                let last = i + 1 == branches.len();
+                if !last {
+                    prog.push(Inst::Split(split + 1, 0));
+                }
+                emit(branch, prog);
+                if !last {
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    let next = prog.len();
+                    prog[split] = Inst::Split(split + 1, next);
+                }
+            }
+            let end = prog.len();
+            for j in jumps {
+                prog[j] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                emit(node, prog);
+            }
+            let split = |body: usize, out: usize| {
+                if *greedy {
+                    Inst::Split(body, out)
+                } else {
+                    Inst::Split(out, body)
+                }
+            };
+            match max {
+                None => {
+                    let start = prog.len();
+                    prog.push(Inst::Jmp(0));
+                    emit(node, prog);
+                    prog.push(Inst::Jmp(start));
+                    let end = prog.len();
+                    prog[start] = split(start + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Jmp(0));
+                        emit(node, prog);
+                    }
+                    let end = prog.len();
+                    for s in splits {
+                        prog[s] = split(s + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    pattern: &'a [u8],
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let b = self
+            .peek()
+            .ok_or_else(|| anyhow::anyhow!("pattern ends early"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn alternation(&mut self) -> Result<Node> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.remove(0)
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(b) = self.peek() {
+            if b == b'|' || b == b')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some(b'*') => (0, None),
+            Some(b'+') => (1, None),
+            Some(b'?') => (0, Some(1)),
+            Some(b'{') if self.counted_ahead() => {
+                self.pos += 1;
+                let min = self.number()?;
+                let max = if self.peek() == Some(b',') {
+                    self.pos += 1;
+                    if self.peek() == Some(b'}') {
+                        None
+                    } else {
+                        Some(self.number()?)
+                    }
+                } else {
+                    Some(min)
+                };
+                if self.next()? != b'}' {
+                    bail!("malformed repetition at offset {}", self.pos);
+                }
+                if max.is_some_and(|max| max < min) || min.max(max.unwrap_or(0)) > MAX_REPEAT {
+                    bail!(
+                        "bad repetition bounds {{{min},{}}}",
+                        max.map_or(String::new(), |m| m.to_string())
+                    );
+                }
+                self.pos -= 1;
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            bail!("nothing to repeat at offset {}", self.pos - 1);
+        }
+        let greedy = if self.peek() == Some(b'?') {
+            self.pos += 1;
+            false
+        } else {
+            true
+        };
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// `{` starts a repetition only when it looks like one; otherwise it is
+    /// a literal brace, as in most engines.
+    fn counted_ahead(&self) -> bool {
+        let rest = &self.pattern[self.pos + 1..];
+        let end = rest.iter().position(|&b| b == b'}');
+        end.is_some_and(|end| {
+            let inner = &rest[..end];
+            !inner.is_empty()
+                && inner[0].is_ascii_digit()
+                && inner.iter().all(|&b| b.is_ascii_digit() || b == b',')
+                && inner.iter().filter(|&&b| b == b',').count() <= 1
+        })
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.pattern[start..self.pos])?
+            .parse()
+            .map_err(|_| anyhow::anyhow!("expected a number at offset {start}"))
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let b = self.next()?;
+        Ok(match b {
+            b'.' => Node::Any,
+            b'^' => Node::Start,
+            b'$' => Node::End,
+            b'(' => {
+                let index = if self.pattern[self.pos..].starts_with(b"?:") {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.alternation()?;
+                if self.peek() != Some(b')') {
+                    bail!("unclosed '('");
+                }
+                self.pos += 1;
+                Node::Group(Box::new(inner), index)
+            }
+            b'[' => self.class()?,
+            b'\\' => self.escape()?,
+            b'*' | b'+' | b'?' => bail!("nothing to repeat at offset {}", self.pos - 1),
+            b => Node::Byte(b),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node> {
+        let b = self.next()?;
+        Ok(match class_escape(b) {
+            Some((ranges, negated)) => Node::Class(ranges, negated),
+            None => Node::Byte(literal_escape(b)),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let b = self.next().map_err(|_| anyhow::anyhow!("unclosed '['"))?;
+            if b == b']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if b == b'\\' {
+                let e = self.next()?;
+                if let Some((mut more, false)) = class_escape(e) {
+                    ranges.append(&mut more);
+                    continue;
+                }
+                if class_escape(e).is_some() {
+                    bail!(
+                        "negated class escape \\{} inside [...] is not supported",
+                        e as char
+                    );
+                }
+                literal_escape(e)
+            } else {
+                b
+            };
+            if self.peek() == Some(b'-')
+                && self.pattern.get(self.pos + 1).is_some_and(|&n| n != b']')
+            {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    b'\\' => literal_escape(self.next()?),
+                    hi => hi,
+                };
+                if hi < lo {
+                    bail!("bad class range {}-{}", lo as char, hi as char);
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+}
+
+/// `\d \w \s` and their upper-case negations.
+fn class_escape(b: u8) -> Option<(Vec<(u8, u8)>, bool)> {
+    let ranges = match b.to_ascii_lowercase() {
+        b'd' => vec![(b'0', b'9')],
+        b'w' => vec![(b'0', b'9'), (b'A', b'Z'), (b'a', b'z'), (b'_', b'_')],
+        b's' => vec![(b' ', b' '), (b'\t', b'\r')],
+        _ => return None,
+    };
+    Some((ranges, b.is_ascii_uppercase()))
+}
+
+fn literal_escape(b: u8) -> u8 {
+    match b {
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'0' => 0,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, text: &str, replacement: &str) -> String {
+        let (out, _) = Regex::new(pattern)
+            .unwrap()
+            .replace_all(text.as_bytes(), replacement);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn regex_replaces_with_groups_classes_and_quantifiers() {
+        assert_eq!(
+            replace(
+                r#""version":\s*"v(\d+)""#,
+                r#"{"version": "v1"}"#,
+                r#""version":"v$1-beta""#
+            ),
+            r#"{"version":"v1-beta"}"#
+        );
+        assert_eq!(replace("a+?", "aaa", "b"), "bbb");
+        assert_eq!(
+            replace("(cat|dog)s?", "cats and dog", "[$1]"),
+            "[cat] and [dog]"
+        );
+        assert_eq!(replace("^x|y$", "xaxy", "_"), "_ax_");
+        assert_eq!(replace("[^,]{2,3}", "abcd,e", "#"), "#d,e");
+        assert_eq!(replace("x*", "ab", "-"), "-a-b-");
+        assert!(Regex::new("(ab").is_err());
+        assert!(Regex::new("*a").is_err());
+    }
+
+    #[test]
+    fn regex_anchors_match_only_at_the_ends() {
+        assert_eq!(replace("^a", "aa", "X"), "Xa");
+        assert_eq!(replace("a$", "aa", "X"), "aX");
+        assert_eq!(replace("^$", "", "X"), "X");
+        assert_eq!(replace("^$", "a", "X"), "a");
+        // No multi-line mode: a newline is not an end
+        assert_eq!(replace("a$", "a\na", "X"), "a\nX");
+        assert!(Regex::new("^*").is_err());
+    }
+
+    #[test]
+    fn regex_alternation_is_leftmost_first() {
+        assert_eq!(replace("a|ab", "ab", "X"), "Xb");
+        assert_eq!(replace("ab|a", "ab", "X"), "X");
+        assert_eq!(replace("x(?:a|b|c)+y", "xcaby xdy", "#"), "# xdy");
+        // The empty branch fails on `b`, so the later one is tried
+        assert_eq!(replace("(|a)b", "ab", "[$1]"), "[a]");
+    }
+
+    #[test]
+    fn regex_nested_quantifiers() {
+        assert_eq!(replace("(a{2})+", "aaaaa", "X"), "Xa");
+        assert_eq!(replace("(ab*)*c", "abbac", "[$1]"), "[a]");
+        assert_eq!(replace("(?:a+)+?", "aaa", "X"), "X");
+        assert_eq!(replace("((a)|b)+", "ab", "$1$2"), "ba");
+        assert_eq!(replace("(a*)*", "b", "-"), "-b-");
+    }
+
+    #[test]
+    fn regex_missing_groups_expand_to_nothing() {
+        assert_eq!(replace("(a)", "a", "<$1$2$9>"), "<a>");
+        assert_eq!(replace("(a)|b", "b", "<$1>"), "<>");
+        assert_eq!(replace("a", "a", "$$1 $"), "$1 $");
+    }
+
+    #[test]
+    fn regex_pathological_patterns_stay_within_the_step_bound() {
+        for (pattern, text) in [
+            ("(a*)*b", "a".repeat(5000)),
+            ("(a|aa)*c", "a".repeat(5000)),
+            ("(a+)+$", format!("{}b", "a".repeat(5000))),
+            ("(?:a?){30}a{30}", "a".repeat(30)),
+        ] {
+            let regex = Regex::new(pattern).unwrap();
+            let (_, _, steps) = regex.replace_all_counting_steps(text.as_bytes(), "");
+            // Each (instruction, position) state is entered at most twice:
+            // once fresh and once when a second path finds it explored
+            let bound = 2 * regex.prog.len() * (text.len() + 1);
+            assert!(steps <= bound, "{pattern}: {steps} steps > {bound}");
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! `--body-replace` and `--body-replace-regex`: find/replace on request
+//! bodies before they are forwarded.
+
+use anyhow::{Context as _, Result, bail};
+use bytes::Bytes;
+use hyper::http::HeaderMap;
+use hyper::http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+
+use crate::regex::Regex;
+
+#[derive(Clone, Debug)]
+pub enum BodyRewrite {
+    Literal { from: Vec<u8>, to: Vec<u8> },
+    Regex { pattern: Regex, replacement: String },
+}
+
+impl BodyRewrite {
+    /// `from=>to`, replacing every occurrence of `from`.
+    pub fn literal(spec: &str) -> Result<Self> {
+        let (from, to) = split_spec(spec)?;
+        Ok(BodyRewrite::Literal {
+            from: from.as_bytes().to_vec(),
+            to: to.as_bytes().to_vec(),
+        })
+    }
+
+    /// `pattern=>replacement`, where the replacement may use `$1`…`$9`.
+    pub fn regex(spec: &str) -> Result<Self> {
+        let (pattern, replacement) = split_spec(spec)?;
+        let pattern = Regex::new(pattern)
+            .with_context(|| format!("invalid --body-replace-regex pattern '{pattern}'"))?;
+        Ok(BodyRewrite::Regex {
+            pattern,
+            replacement: replacement.to_string(),
+        })
+    }
+
+    fn apply(&self, body: &[u8]) -> (Vec<u8>, usize) {
+        match self {
+            BodyRewrite::Literal { from, to } => replace_literal(body, from, to),
+            BodyRewrite::Regex {
+                pattern,
+                replacement,
+            } => pattern.replace_all(body, replacement),
+        }
+    }
+}
+
+fn split_spec(spec: &str) -> Result<(&str, &str)> {
+    match spec.split_once("=>") {
+        Some((from, to)) if !from.is_empty() => Ok((from, to)),
+        _ => bail!("expected 'from=>to', got '{spec}'"),
+    }
+}
+
+fn replace_literal(body: &[u8], from: &[u8], to: &[u8]) -> (Vec<u8>, usize) {
+    let mut out = Vec::with_capacity(body.len());
+    let mut count = 0;
+    let mut rest = body;
+    while let Some(i) = rest.windows(from.len()).position(|w| w == from) {
+        out.extend_from_slice(&rest[..i]);
+        out.extend_from_slice(to);
+        rest = &rest[i + from.len()..];
+        count += 1;
+    }
+    out.extend_from_slice(rest);
+    (out, count)
+}
+
+/// Run every rule over `body` in turn. None when nothing matched.
+pub fn apply(rules: &[BodyRewrite], body: &[u8]) -> Option<(Bytes, usize)> {
+    let mut current = body.to_vec();
+    let mut total = 0;
+    for rule in rules {
+        let (next, count) = rule.apply(&current);
+        if count > 0 {
+            current = next;
+            total += count;
+        }
+    }
+    (total > 0).then(|| (Bytes::from(current), total))
+}
+
+/// Bodies safe to edit as text: `text/*`, JSON, XML, forms, JavaScript and
+/// GraphQL, including `+json`/`+xml` suffixes.
+pub fn is_textual(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/x-www-form-urlencoded"
+                | "application/javascript"
+                | "application/graphql"
+        )
+}
+
+/// The body is compressed or otherwise encoded (any `Content-Encoding` but
+/// `identity`), so its bytes are not the text a rule would match.
+pub fn is_encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .flat_map(|v| v.to_str().unwrap_or("?").split(','))
+        .map(str::trim)
+        .any(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn rewrite_split_spec_needs_a_non_empty_from() {
+        assert_eq!(split_spec("a=>b").unwrap(), ("a", "b"));
+        assert_eq!(split_spec("a=>").unwrap(), ("a", ""));
+        // Only the first `=>` separates; the rest belongs to the replacement
+        assert_eq!(split_spec("x=>y=>z").unwrap(), ("x", "y=>z"));
+        assert!(split_spec("=>b").is_err());
+        assert!(split_spec("no arrow").is_err());
+    }
+
+    #[test]
+    fn rewrite_replace_literal_counts_non_overlapping_matches() {
+        assert_eq!(replace_literal(b"aaaa", b"aa", b"b"), (b"bb".to_vec(), 2));
+        assert_eq!(
+            replace_literal(b"staging.example", b"staging", b"prod"),
+            (b"prod.example".to_vec(), 1)
+        );
+        assert_eq!(replace_literal(b"abc", b"x", b"y"), (b"abc".to_vec(), 0));
+        assert_eq!(replace_literal(b"a", b"abc", b"y"), (b"a".to_vec(), 0));
+    }
+
+    #[test]
+    fn rewrite_is_textual_follows_the_mime_type() {
+        for textual in [
+            "text/plain",
+            "application/json; charset=utf-8",
+            "Application/JSON",
+            "application/problem+json",
+            "image/svg+xml",
+            "application/x-www-form-urlencoded",
+        ] {
+            assert!(
+                is_textual(&headers(&[("content-type", textual)])),
+                "{textual}"
+            );
+        }
+        for binary in [
+            "application/octet-stream",
+            "image/png",
+            "multipart/form-data",
+        ] {
+            assert!(
+                !is_textual(&headers(&[("content-type", binary)])),
+                "{binary}"
+            );
+        }
+        assert!(!is_textual(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn rewrite_is_encoded_ignores_identity_only() {
+        assert!(!is_encoded(&HeaderMap::new()));
+        assert!(!is_encoded(&headers(&[("content-encoding", "identity")])));
+        assert!(is_encoded(&headers(&[("content-encoding", "gzip")])));
+        assert!(is_encoded(&headers(&[(
+            "content-encoding",
+            "identity, br"
+        )])));
+        assert!(is_encoded(&headers(&[
+            ("content-encoding", "identity"),
+            ("content-encoding", "deflate")
+        ])));
+    }
+}
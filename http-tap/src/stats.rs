@@ -1,14 +1,67 @@
-use std::collections::HashMap;
-use std::time::{SystemTime, Duration};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context as _;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 
 use hyper::http::Method;
 use tokio::sync::mpsc;
 
+use crate::json;
+
 #[derive(Clone, Debug)]
 pub struct StatsEvent {
     pub method: Method,
     pub path: String,
+    /// Client address the request came from
+    pub peer: IpAddr,
     pub at: SystemTime,
+    pub status: u16,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Time from forwarding the request to its outcome
+    pub latency: Duration,
+}
+
+/// Traffic on a tunnelled WebSocket, keyed by the path of its upgrade request.
+/// "In" is client to upstream and "out" upstream to client, as for requests.
+#[derive(Clone, Debug)]
+pub enum WsEvent {
+    Opened {
+        path: String,
+    },
+    Traffic {
+        path: String,
+        direction: WsDirection,
+        /// Data messages that ended in this chunk
+        messages: u64,
+        bytes: u64,
+        at: SystemTime,
+    },
+    Closed {
+        path: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WsDirection {
+    In,
+    Out,
+}
+
+/// What the proxy reports to the aggregator.
+#[derive(Clone, Debug)]
+pub enum StatsMessage {
+    Request(StatsEvent),
+    WebSocket(WsEvent),
+    /// A request let through by `--allow`
+    AclAllowed,
+    /// A request refused by `--deny`, or for matching no `--allow`
+    AclDenied,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -18,6 +71,9 @@ pub struct MethodCounts {
     pub put: u64,
     pub patch: u64,
     pub delete_: u64,
+    pub head: u64,
+    pub options: u64,
+    pub connect: u64,
     pub other: u64,
 }
 
@@ -26,20 +82,237 @@ pub struct Record {
     pub path: String,
     pub counts: MethodCounts,
     pub last_seen: SystemTime,
+    /// Responses with a 5xx status (including proxy-generated 502s)
+    pub errors: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Responses per status class, 1xx through 5xx
+    pub statuses: [u64; 5],
+    /// Percentiles over the most recent latency samples, as of the snapshot
+    pub latency: Option<Percentiles>,
+}
+
+/// Per-path WebSocket totals. Not persisted to the stats db.
+#[derive(Clone, Debug)]
+pub struct WsRecord {
+    pub path: String,
+    /// Tunnels currently open
+    pub active: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub last_seen: SystemTime,
+    /// Messages per traffic event over the last `WS_RATE_WINDOW`
+    recent: VecDeque<(SystemTime, u64)>,
+}
+
+/// Per-client totals, kept only with `--by-ip`. Not persisted to the stats db.
+#[derive(Clone, Debug)]
+pub struct IpRecord {
+    pub ip: IpAddr,
+    pub requests: u64,
+    /// Responses with a 5xx status
+    pub errors: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub last_seen: SystemTime,
 }
 
-#[derive(Default)]
+/// Span the WebSocket messages/sec column averages over.
+const WS_RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// p50/p95/p99 over a set of latency samples.
+#[derive(Clone, Copy, Debug)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// Latency samples kept per path unless `--latency-window` says otherwise.
+pub const DEFAULT_LATENCY_WINDOW: usize = 1000;
+
 pub struct Aggregator {
     // key: path
     map: HashMap<String, Record>,
+    /// Most recent latency samples per path, capped at `latency_window`. Kept
+    /// apart from the records so snapshots carry only the percentiles.
+    latencies: HashMap<String, VecDeque<Duration>>,
+    ws: HashMap<String, WsRecord>,
+    /// Per-client breakdown, None unless `--by-ip` asked for it
+    by_ip: Option<HashMap<IpAddr, IpRecord>>,
+    /// `--allow`/`--deny` decisions; not persisted to the stats db
+    acl_allowed: u64,
+    acl_denied: u64,
+    latency_window: usize,
+}
+
+impl Default for Aggregator {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            latencies: HashMap::new(),
+            ws: HashMap::new(),
+            by_ip: None,
+            acl_allowed: 0,
+            acl_denied: 0,
+            latency_window: DEFAULT_LATENCY_WINDOW,
+        }
+    }
 }
 
 impl Aggregator {
-    pub fn apply(&mut self, ev: StatsEvent) {
+    pub fn set_latency_window(&mut self, window: usize) {
+        self.latency_window = window.max(1);
+        for samples in self.latencies.values_mut() {
+            while samples.len() > self.latency_window {
+                samples.pop_front();
+            }
+        }
+    }
+
+    /// Keep a per-client breakdown from now on (or stop keeping one).
+    pub fn set_by_ip(&mut self, enabled: bool) {
+        if enabled != self.by_ip.is_some() {
+            self.by_ip = enabled.then(HashMap::new);
+        }
+    }
+
+    pub fn by_ip_enabled(&self) -> bool {
+        self.by_ip.is_some()
+    }
+
+    /// Clients with the most requests first; empty without `--by-ip`.
+    pub fn ip_snapshot(&self) -> Vec<IpRecord> {
+        let mut v: Vec<_> = self
+            .by_ip
+            .iter()
+            .flat_map(|m| m.values().cloned())
+            .collect();
+        v.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.ip.cmp(&b.ip)));
+        v
+    }
+
+    /// Drop all records, keeping settings such as the latency window. Open
+    /// WebSocket tunnels stay counted as active.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.latencies.clear();
+        if let Some(by_ip) = &mut self.by_ip {
+            by_ip.clear();
+        }
+        self.acl_allowed = 0;
+        self.acl_denied = 0;
+        self.ws.retain(|_, rec| rec.active > 0);
+        for rec in self.ws.values_mut() {
+            let active = rec.active;
+            *rec = WsRecord::new(rec.path.clone(), rec.last_seen);
+            rec.active = active;
+        }
+    }
+
+    pub fn apply(&mut self, msg: StatsMessage) {
+        match msg {
+            StatsMessage::Request(ev) => self.apply_request(ev),
+            StatsMessage::WebSocket(ev) => self.apply_ws(ev),
+            StatsMessage::AclAllowed => self.acl_allowed += 1,
+            StatsMessage::AclDenied => self.acl_denied += 1,
+        }
+    }
+
+    /// Apply every message already queued on `rx` without waiting for more.
+    pub fn drain(&mut self, rx: &mut StatsReceiver) {
+        while let Ok(msg) = rx.try_recv() {
+            self.apply(msg);
+        }
+    }
+
+    /// Requests allowed and denied by `--allow`/`--deny`.
+    pub fn acl_counts(&self) -> (u64, u64) {
+        (self.acl_allowed, self.acl_denied)
+    }
+
+    fn apply_ws(&mut self, ev: WsEvent) {
+        let path = match &ev {
+            WsEvent::Opened { path } | WsEvent::Closed { path } | WsEvent::Traffic { path, .. } => {
+                path
+            }
+        };
+        let rec = self
+            .ws
+            .entry(path.clone())
+            .or_insert_with(|| WsRecord::new(path.clone(), SystemTime::now()));
+        match ev {
+            WsEvent::Opened { .. } => {
+                rec.active += 1;
+                rec.last_seen = SystemTime::now();
+            }
+            WsEvent::Closed { .. } => rec.active = rec.active.saturating_sub(1),
+            WsEvent::Traffic {
+                direction,
+                messages,
+                bytes,
+                at,
+                ..
+            } => {
+                match direction {
+                    WsDirection::In => {
+                        rec.messages_in += messages;
+                        rec.bytes_in += bytes;
+                    }
+                    WsDirection::Out => {
+                        rec.messages_out += messages;
+                        rec.bytes_out += bytes;
+                    }
+                }
+                rec.last_seen = at;
+                if messages > 0 {
+                    rec.recent.push_back((at, messages));
+                }
+                while rec.recent.front().is_some_and(|(t, _)| {
+                    at.duration_since(*t).unwrap_or_default() > WS_RATE_WINDOW
+                }) {
+                    rec.recent.pop_front();
+                }
+            }
+        }
+    }
+
+    /// WebSocket paths, most recently active first.
+    pub fn ws_snapshot(&self) -> Vec<WsRecord> {
+        let mut v: Vec<_> = self.ws.values().cloned().collect();
+        v.sort_by_key(|r| std::cmp::Reverse(r.last_seen));
+        v
+    }
+
+    fn apply_request(&mut self, ev: StatsEvent) {
+        if let Some(by_ip) = &mut self.by_ip {
+            let client = by_ip.entry(ev.peer).or_insert_with(|| IpRecord {
+                ip: ev.peer,
+                requests: 0,
+                errors: 0,
+                bytes_in: 0,
+                bytes_out: 0,
+                last_seen: ev.at,
+            });
+            client.requests += 1;
+            if ev.status >= 500 {
+                client.errors += 1;
+            }
+            client.bytes_in += ev.bytes_in;
+            client.bytes_out += ev.bytes_out;
+            client.last_seen = ev.at;
+        }
         let rec = self.map.entry(ev.path.clone()).or_insert_with(|| Record {
             path: ev.path.clone(),
             counts: MethodCounts::default(),
             last_seen: ev.at,
+            errors: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            statuses: [0; 5],
+            latency: None,
         });
 
         match ev.method {
@@ -48,22 +321,561 @@ impl Aggregator {
             Method::PUT => rec.counts.put += 1,
             Method::PATCH => rec.counts.patch += 1,
             Method::DELETE => rec.counts.delete_ += 1,
+            Method::HEAD => rec.counts.head += 1,
+            Method::OPTIONS => rec.counts.options += 1,
+            Method::CONNECT => rec.counts.connect += 1,
             _ => rec.counts.other += 1,
         }
         rec.last_seen = ev.at;
+        if ev.status >= 500 {
+            rec.errors += 1;
+        }
+        if let Some(n) = rec
+            .statuses
+            .get_mut((ev.status / 100).wrapping_sub(1) as usize)
+        {
+            *n += 1;
+        }
+        rec.bytes_in += ev.bytes_in;
+        rec.bytes_out += ev.bytes_out;
+        let samples = self.latencies.entry(ev.path).or_default();
+        if samples.len() == self.latency_window {
+            samples.pop_front();
+        }
+        samples.push_back(ev.latency);
     }
 
+    /// Records with the most recently seen first, each with its latency
+    /// percentiles filled in.
     pub fn snapshot(&self) -> Vec<Record> {
-        let mut v: Vec<_> = self.map.values().cloned().collect();
+        let mut v: Vec<_> = self
+            .map
+            .values()
+            .map(|rec| Record {
+                latency: self
+                    .latencies
+                    .get(&rec.path)
+                    .and_then(|samples| Percentiles::of(samples.iter().copied().collect())),
+                ..rec.clone()
+            })
+            .collect();
         v.sort_by_key(|r| std::cmp::Reverse(r.last_seen));
         v
     }
+
+    /// Bytes received from clients and sent back to them, across all paths.
+    pub fn total_bytes(&self) -> (u64, u64) {
+        self.map
+            .values()
+            .fold((0, 0), |(bytes_in, bytes_out), rec| {
+                (bytes_in + rec.bytes_in, bytes_out + rec.bytes_out)
+            })
+    }
+
+    /// Human-readable end-of-run report: totals, methods, top paths, errors, bytes.
+    pub fn summary(&self) -> String {
+        let records = self.snapshot();
+        let mut methods = MethodCounts::default();
+        let mut errors = 0;
+        for rec in &records {
+            methods.add(&rec.counts);
+            errors += rec.errors;
+        }
+        let (bytes_in, bytes_out) = self.total_bytes();
+        let total = methods.total();
+        let error_rate = if total == 0 {
+            0.0
+        } else {
+            errors as f64 * 100.0 / total as f64
+        };
+
+        let mut out = String::from("── us-http-tap summary ──\n");
+        let _ = writeln!(out, "Requests: {total} ({errors} errors, {error_rate:.1}%)");
+        let _ = writeln!(
+            out,
+            "Bytes:    {} in / {} out",
+            format_bytes(bytes_in),
+            format_bytes(bytes_out)
+        );
+        if self.acl_allowed > 0 || self.acl_denied > 0 {
+            let _ = writeln!(
+                out,
+                "ACL:      {} allowed / {} denied",
+                self.acl_allowed, self.acl_denied
+            );
+        }
+        let all_latencies: Vec<Duration> = self.latencies.values().flatten().copied().collect();
+        if let Some(p) = Percentiles::of(all_latencies) {
+            let _ = writeln!(
+                out,
+                "Latency:  p50 {}  p95 {}  p99 {}",
+                format_latency(p.p50),
+                format_latency(p.p95),
+                format_latency(p.p99)
+            );
+        }
+        let method_list: Vec<String> = methods
+            .named()
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(name, n)| format!("{name} {n}"))
+            .collect();
+        if !method_list.is_empty() {
+            let _ = writeln!(out, "Methods:  {}", method_list.join("  "));
+        }
+
+        let mut by_count = records;
+        by_count.sort_by(|a, b| {
+            b.counts
+                .total()
+                .cmp(&a.counts.total())
+                .then(a.path.cmp(&b.path))
+        });
+        if !by_count.is_empty() {
+            out.push_str("Top paths:\n");
+            for rec in by_count.iter().take(10) {
+                let latency = match rec.latency {
+                    Some(p) => format!(
+                        "p50 {:>8} p95 {:>8} p99 {:>8}",
+                        format_latency(p.p50),
+                        format_latency(p.p95),
+                        format_latency(p.p99)
+                    ),
+                    None => format!("{:38}", ""),
+                };
+                let _ = writeln!(out, "  {:>6}  {latency}  {}", rec.counts.total(), rec.path);
+            }
+        }
+        let clients = self.ip_snapshot();
+        if !clients.is_empty() {
+            out.push_str("Top clients:\n");
+            for client in clients.iter().take(10) {
+                let _ = writeln!(
+                    out,
+                    "  {:>6}  {:>10} in / {:>10} out  {}",
+                    client.requests,
+                    format_bytes(client.bytes_in),
+                    format_bytes(client.bytes_out),
+                    client.ip
+                );
+            }
+        }
+        out
+    }
+
+    /// Point-in-time report as CSV, one row per path.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("path");
+        for (name, _) in MethodCounts::default().named() {
+            let _ = write!(out, ",{}", name.to_ascii_lowercase());
+        }
+        out.push_str(
+            ",total,1xx,2xx,3xx,4xx,5xx,bytes_in,bytes_out,p50_ms,p95_ms,p99_ms,last_seen\n",
+        );
+        for rec in self.snapshot() {
+            out.push_str(&csv_field(&rec.path));
+            for (_, n) in rec.counts.named() {
+                let _ = write!(out, ",{n}");
+            }
+            let _ = write!(out, ",{}", rec.counts.total());
+            for n in rec.statuses {
+                let _ = write!(out, ",{n}");
+            }
+            let _ = write!(out, ",{},{}", rec.bytes_in, rec.bytes_out);
+            match rec.latency {
+                Some(p) => {
+                    for d in [p.p50, p.p95, p.p99] {
+                        let _ = write!(out, ",{:.3}", d.as_secs_f64() * 1000.0);
+                    }
+                }
+                None => out.push_str(",,,"),
+            }
+            let _ = writeln!(out, ",{}", rfc3339(rec.last_seen));
+        }
+        out
+    }
+
+    /// Point-in-time report as a JSON document.
+    pub fn to_export_json(&self) -> String {
+        let records: Vec<String> = self
+            .snapshot()
+            .iter()
+            .map(|rec| {
+                let statuses = json::Object::new()
+                    .num("1xx", rec.statuses[0])
+                    .num("2xx", rec.statuses[1])
+                    .num("3xx", rec.statuses[2])
+                    .num("4xx", rec.statuses[3])
+                    .num("5xx", rec.statuses[4])
+                    .finish();
+                let mut obj = json::Object::new()
+                    .str("path", &rec.path)
+                    .raw("counts", &rec.counts.to_json())
+                    .num("total", rec.counts.total())
+                    .raw("statuses", &statuses)
+                    .num("bytes_in", rec.bytes_in)
+                    .num("bytes_out", rec.bytes_out);
+                if let Some(p) = rec.latency {
+                    let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+                    obj = obj
+                        .num("p50_ms", ms(p.p50))
+                        .num("p95_ms", ms(p.p95))
+                        .num("p99_ms", ms(p.p99));
+                }
+                obj.str("last_seen", &rfc3339(rec.last_seen)).finish()
+            })
+            .collect();
+        json::Object::new()
+            .str("generated_at", &rfc3339(SystemTime::now()))
+            .raw("records", &format!("[{}]", records.join(",")))
+            .finish()
+            + "\n"
+    }
+
+    /// Load a snapshot previously written by [`Aggregator::save`].
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read stats db {}", path.display()))?;
+        let doc =
+            json::parse(&text).with_context(|| format!("parse stats db {}", path.display()))?;
+        let mut agg = Aggregator::default();
+        for item in doc
+            .get("records")
+            .and_then(json::Value::as_array)
+            .unwrap_or_default()
+        {
+            let Some(rec) = Record::from_json(item) else {
+                continue;
+            };
+            agg.map.insert(rec.path.clone(), rec);
+        }
+        Ok(agg)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let records: Vec<String> = self.snapshot().iter().map(Record::to_json).collect();
+        let doc = json::Object::new()
+            .num("version", 1)
+            .raw("records", &format!("[{}]", records.join(",")))
+            .finish();
+        // Write-then-rename so a crash mid-write never truncates the db
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, doc + "\n").with_context(|| format!("write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("write stats db {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl MethodCounts {
+    pub fn total(&self) -> u64 {
+        self.named().iter().map(|(_, n)| n).sum()
+    }
+
+    /// Counts paired with their display names, in table order.
+    pub fn named(&self) -> [(&'static str, u64); 9] {
+        [
+            ("GET", self.get),
+            ("POST", self.post),
+            ("PUT", self.put),
+            ("PATCH", self.patch),
+            ("DELETE", self.delete_),
+            ("HEAD", self.head),
+            ("OPTIONS", self.options),
+            ("CONNECT", self.connect),
+            ("OTHER", self.other),
+        ]
+    }
+
+    fn add(&mut self, other: &MethodCounts) {
+        self.get += other.get;
+        self.post += other.post;
+        self.put += other.put;
+        self.patch += other.patch;
+        self.delete_ += other.delete_;
+        self.head += other.head;
+        self.options += other.options;
+        self.connect += other.connect;
+        self.other += other.other;
+    }
+
+    fn to_json(&self) -> String {
+        json::Object::new()
+            .num("get", self.get)
+            .num("post", self.post)
+            .num("put", self.put)
+            .num("patch", self.patch)
+            .num("delete", self.delete_)
+            .num("head", self.head)
+            .num("options", self.options)
+            .num("connect", self.connect)
+            .num("other", self.other)
+            .finish()
+    }
+
+    fn from_json(value: &json::Value) -> Self {
+        let n = |key: &str| value.get(key).and_then(json::Value::as_u64).unwrap_or(0);
+        MethodCounts {
+            get: n("get"),
+            post: n("post"),
+            put: n("put"),
+            patch: n("patch"),
+            delete_: n("delete"),
+            head: n("head"),
+            options: n("options"),
+            connect: n("connect"),
+            other: n("other"),
+        }
+    }
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles; None when there are no samples.
+    pub fn of(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let rank = |p: f64| {
+            let idx = (p / 100.0 * samples.len() as f64).ceil() as usize;
+            samples[idx.clamp(1, samples.len()) - 1]
+        };
+        Some(Self {
+            p50: rank(50.0),
+            p95: rank(95.0),
+            p99: rank(99.0),
+        })
+    }
 }
 
-pub type StatsSender = mpsc::UnboundedSender<StatsEvent>;
-pub type StatsReceiver = mpsc::UnboundedReceiver<StatsEvent>;
+impl WsRecord {
+    fn new(path: String, last_seen: SystemTime) -> Self {
+        WsRecord {
+            path,
+            active: 0,
+            messages_in: 0,
+            messages_out: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            last_seen,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Messages each way per second, averaged over the last few seconds.
+    pub fn messages_per_sec(&self, now: SystemTime) -> f64 {
+        let messages: u64 = self
+            .recent
+            .iter()
+            .filter(|(t, _)| now.duration_since(*t).unwrap_or_default() <= WS_RATE_WINDOW)
+            .map(|(_, n)| n)
+            .sum();
+        messages as f64 / WS_RATE_WINDOW.as_secs_f64()
+    }
+}
+
+impl Record {
+    fn to_json(&self) -> String {
+        let last_seen_ms = self
+            .last_seen
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        json::Object::new()
+            .str("path", &self.path)
+            .raw("counts", &self.counts.to_json())
+            .num("last_seen_ms", last_seen_ms)
+            .num("errors", self.errors)
+            .num("bytes_in", self.bytes_in)
+            .num("bytes_out", self.bytes_out)
+            .raw(
+                "statuses",
+                &format!("[{}]", self.statuses.map(|n| n.to_string()).join(",")),
+            )
+            .finish()
+    }
+
+    fn from_json(value: &json::Value) -> Option<Self> {
+        let path = value.get("path")?.as_str()?.to_string();
+        let counts = value
+            .get("counts")
+            .map(MethodCounts::from_json)
+            .unwrap_or_default();
+        let n = |key: &str| value.get(key).and_then(json::Value::as_u64).unwrap_or(0);
+        Some(Record {
+            path,
+            counts,
+            last_seen: UNIX_EPOCH + Duration::from_millis(n("last_seen_ms")),
+            errors: n("errors"),
+            bytes_in: n("bytes_in"),
+            bytes_out: n("bytes_out"),
+            statuses: statuses_from_json(value.get("statuses")),
+            // Samples are not persisted; percentiles restart with each run
+            latency: None,
+        })
+    }
+}
+
+fn statuses_from_json(value: Option<&json::Value>) -> [u64; 5] {
+    let mut statuses = [0; 5];
+    let items = value.and_then(json::Value::as_array).unwrap_or_default();
+    for (slot, item) in statuses.iter_mut().zip(items) {
+        *slot = item.as_u64().unwrap_or(0);
+    }
+    statuses
+}
+
+fn rfc3339(at: SystemTime) -> String {
+    OffsetDateTime::from(at)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Quote a CSV field when it contains a delimiter, quote, or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format a byte count with binary units, e.g. `1.5 KiB`.
+pub fn format_bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{n} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Format a latency compactly, e.g. `850µs`, `12.3ms`, `1.25s`.
+pub fn format_latency(d: Duration) -> String {
+    if d < Duration::from_millis(1) {
+        format!("{}µs", d.as_micros())
+    } else if d < Duration::from_secs(1) {
+        format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", d.as_secs_f64())
+    }
+}
+
+pub type StatsSender = mpsc::UnboundedSender<StatsMessage>;
+pub type StatsReceiver = mpsc::UnboundedReceiver<StatsMessage>;
 
 pub fn channel() -> (StatsSender, StatsReceiver) {
     mpsc::unbounded_channel()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str, status: u16) -> StatsMessage {
+        StatsMessage::Request(StatsEvent {
+            method: Method::POST,
+            path: path.into(),
+            peer: IpAddr::from([127, 0, 0, 1]),
+            at: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            status,
+            bytes_in: 10,
+            bytes_out: 200,
+            latency: Duration::from_millis(5),
+        })
+    }
+
+    #[test]
+    fn stats_percentiles_use_nearest_rank() {
+        let ms = Duration::from_millis;
+        assert!(Percentiles::of(vec![]).is_none());
+
+        let one = Percentiles::of(vec![ms(7)]).unwrap();
+        assert_eq!((one.p50, one.p95, one.p99), (ms(7), ms(7), ms(7)));
+
+        // Reversed input, so the samples must be sorted first
+        let hundred = Percentiles::of((1..=100).rev().map(ms).collect()).unwrap();
+        assert_eq!(
+            (hundred.p50, hundred.p95, hundred.p99),
+            (ms(50), ms(95), ms(99))
+        );
+
+        let ten = Percentiles::of((1..=10).map(ms).collect()).unwrap();
+        assert_eq!((ten.p50, ten.p95, ten.p99), (ms(5), ms(10), ms(10)));
+    }
+
+    #[test]
+    fn stats_snapshot_percentiles_cover_the_latency_window() {
+        let mut agg = Aggregator::default();
+        agg.set_latency_window(3);
+        for ms in [900, 800, 10, 20, 30] {
+            let StatsMessage::Request(mut ev) = request("/api", 200) else {
+                unreachable!()
+            };
+            ev.latency = Duration::from_millis(ms);
+            agg.apply(StatsMessage::Request(ev));
+        }
+        let p = agg.snapshot()[0].latency.unwrap();
+        assert_eq!(p.p50, Duration::from_millis(20));
+        assert_eq!(p.p99, Duration::from_millis(30));
+
+        agg.clear();
+        assert!(agg.snapshot().is_empty());
+        assert!(!agg.summary().contains("Latency:"));
+    }
+
+    #[test]
+    fn stats_drained_events_survive_save_and_load() {
+        let (tx, mut rx) = channel();
+        tx.send(request("/api", 201)).unwrap();
+        tx.send(request("/api", 503)).unwrap();
+        tx.send(request("/health", 200)).unwrap();
+        let mut agg = Aggregator::default();
+        agg.drain(&mut rx);
+
+        let path = std::env::temp_dir().join(format!("http-tap-stats-{}.json", std::process::id()));
+        agg.save(&path).unwrap();
+        let loaded = Aggregator::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Both records share a last_seen, so snapshot order is arbitrary
+        let summary = |agg: &Aggregator| {
+            let mut records = agg
+                .snapshot()
+                .into_iter()
+                .map(|r| {
+                    (
+                        r.path,
+                        r.counts.post,
+                        r.errors,
+                        r.bytes_in,
+                        r.bytes_out,
+                        r.statuses,
+                        r.last_seen,
+                    )
+                })
+                .collect::<Vec<_>>();
+            records.sort();
+            records
+        };
+        assert_eq!(summary(&loaded), summary(&agg));
+        assert_eq!(
+            summary(&loaded)[0],
+            (
+                "/api".into(),
+                2,
+                1,
+                20,
+                400,
+                [0, 1, 0, 0, 1],
+                UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)
+            )
+        );
+    }
+}
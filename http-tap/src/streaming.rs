@@ -0,0 +1,121 @@
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use bytes::Bytes;
+use hyper::Error as HyperError;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::http::HeaderMap;
+use hyper::http::header::{CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+
+pub fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.trim_start()
+                .to_ascii_lowercase()
+                .starts_with("text/event-stream")
+        })
+}
+
+/// Responses that may never finish (SSE) or whose length is unknown up front
+/// are forwarded as they arrive instead of being buffered.
+pub fn is_streaming(headers: &HeaderMap) -> bool {
+    let chunked = headers
+        .get(TRANSFER_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"));
+    is_event_stream(headers) || (chunked && !headers.contains_key(CONTENT_LENGTH))
+}
+
+/// Receives a streamed body's chunks as they pass through.
+pub trait StreamObserver: Send + Sync {
+    fn data(&mut self, chunk: &Bytes);
+    /// Trailing headers, sent after the last chunk.
+    fn trailers(&mut self, _trailers: &HeaderMap) {}
+    /// Called once when the body is dropped; `complete` is false if the
+    /// stream ended early (client went away or the upstream failed).
+    fn close(&mut self, bytes: usize, complete: bool);
+}
+
+/// Upstream body forwarded frame by frame while an observer watches.
+pub struct ObservedBody {
+    inner: Incoming,
+    observer: Box<dyn StreamObserver>,
+    bytes: usize,
+    complete: bool,
+}
+
+impl ObservedBody {
+    pub fn new(inner: Incoming, observer: Box<dyn StreamObserver>) -> Self {
+        Self {
+            inner,
+            observer,
+            bytes: 0,
+            complete: false,
+        }
+    }
+}
+
+impl Body for ObservedBody {
+    type Data = Bytes;
+    type Error = HyperError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, HyperError>>> {
+        let this = self.get_mut();
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(chunk) = frame.data_ref() {
+                    this.bytes += chunk.len();
+                    this.observer.data(chunk);
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    // Trailers are always the last frame.
+                    this.complete = true;
+                    this.observer.trailers(trailers);
+                }
+            }
+            Some(Err(_)) => {}
+            None => this.complete = true,
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for ObservedBody {
+    fn drop(&mut self) {
+        let complete = self.complete || self.inner.is_end_stream();
+        self.observer.close(self.bytes, complete);
+    }
+}
+
+/// Splits a byte stream into lines, holding back any trailing partial line.
+#[derive(Default)]
+pub struct LineSplitter {
+    partial: Vec<u8>,
+}
+
+impl LineSplitter {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=pos).collect();
+            let line = line.strip_suffix(b"\n").unwrap_or(&line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            lines.push(String::from_utf8_lossy(line).into_owned());
+        }
+        lines
+    }
+}
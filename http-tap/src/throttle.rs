@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use tokio::time::Sleep;
+
+/// Slices per second of bandwidth; smaller slices give smoother pacing.
+const TICKS_PER_SEC: u64 = 10;
+
+/// A body released to the client at a fixed byte rate.
+///
+/// Each poll hands out one slice worth 1/TICKS_PER_SEC of the budget and then
+/// waits long enough that the average throughput stays under the limit. Data
+/// frames from `inner` are split into slices as they arrive, so buffered and
+/// streamed bodies are paced alike; trailers pass straight through.
+pub struct PacedBody<B> {
+    inner: B,
+    /// The rest of the data frame currently being paced out
+    pending: Bytes,
+    bytes_per_sec: u64,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> PacedBody<B> {
+    pub fn new(inner: B, bytes_per_sec: u64) -> Self {
+        Self {
+            inner,
+            pending: Bytes::new(),
+            bytes_per_sec: bytes_per_sec.max(1),
+            delay: None,
+        }
+    }
+}
+
+impl<B> Body for PacedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let this = self.get_mut();
+        if let Some(delay) = &mut this.delay {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+        while this.pending.is_empty() {
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => this.pending = data,
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            }
+        }
+
+        let slice = ((this.bytes_per_sec / TICKS_PER_SEC).max(1) as usize).min(this.pending.len());
+        let chunk = this.pending.split_to(slice);
+        let wait = Duration::from_secs_f64(slice as f64 / this.bytes_per_sec as f64);
+        this.delay = Some(Box::pin(tokio::time::sleep(wait)));
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let inner = self.inner.size_hint();
+        let pending = self.pending.len() as u64;
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + pending);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint
+    }
+}
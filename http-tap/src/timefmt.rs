@@ -0,0 +1,163 @@
+use anyhow::anyhow;
+use time::format_description::OwnedFormatItem;
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, UtcOffset};
+
+/// How log timestamps are rendered.
+#[derive(Clone, Debug)]
+pub enum TimeFormat {
+    Rfc3339,
+    /// Seconds since the Unix epoch with millisecond precision
+    Epoch,
+    Pattern(OwnedFormatItem),
+}
+
+impl TimeFormat {
+    /// Accepts `rfc3339`, `epoch`, or a strftime-like pattern such as `%Y-%m-%d %H:%M:%S`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec {
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "epoch" => Ok(TimeFormat::Epoch),
+            pattern => {
+                let description = strftime_to_description(pattern)?;
+                let item = time::format_description::parse_owned::<1>(&description)
+                    .map_err(|e| anyhow!("invalid --time-format '{pattern}': {e}"))?;
+                Ok(TimeFormat::Pattern(item))
+            }
+        }
+    }
+}
+
+/// Produces timestamps for log lines in the configured format and zone.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    format: TimeFormat,
+    local: bool,
+}
+
+impl Clock {
+    pub fn new(format: TimeFormat, local: bool) -> Self {
+        Self { format, local }
+    }
+
+    pub fn now(&self) -> String {
+        self.format(OffsetDateTime::now_utc())
+    }
+
+    pub fn format(&self, at: OffsetDateTime) -> String {
+        let at = if self.local {
+            at.to_offset(local_offset(at))
+        } else {
+            at
+        };
+        match &self.format {
+            TimeFormat::Rfc3339 => at.format(&Rfc3339).unwrap_or_else(|_| "now".into()),
+            TimeFormat::Epoch => format!("{}.{:03}", at.unix_timestamp(), at.millisecond()),
+            TimeFormat::Pattern(item) => at.format(item).unwrap_or_else(|_| "now".into()),
+        }
+    }
+}
+
+/// Translate strftime directives into a `time` format description (version 1).
+fn strftime_to_description(pattern: &str) -> anyhow::Result<String> {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                let directive = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("--time-format ends with a bare '%'"))?;
+                out.push_str(match directive {
+                    'Y' => "[year]",
+                    'y' => "[year repr:last_two]",
+                    'm' => "[month]",
+                    'b' => "[month repr:short]",
+                    'B' => "[month repr:long]",
+                    'd' => "[day]",
+                    'e' => "[day padding:space]",
+                    'j' => "[ordinal]",
+                    'a' => "[weekday repr:short]",
+                    'A' => "[weekday repr:long]",
+                    'H' => "[hour]",
+                    'I' => "[hour repr:12]",
+                    'p' => "[period]",
+                    'M' => "[minute]",
+                    'S' => "[second]",
+                    'L' => "[subsecond digits:3]",
+                    'f' => "[subsecond digits:6]",
+                    'z' => "[offset_hour sign:mandatory][offset_minute]",
+                    'F' => "[year]-[month]-[day]",
+                    'T' => "[hour]:[minute]:[second]",
+                    '%' => "%",
+                    other => anyhow::bail!("unsupported --time-format directive '%{other}'"),
+                });
+            }
+            '[' => out.push_str("[["),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(unix)]
+fn local_offset(at: OffsetDateTime) -> UtcOffset {
+    let t = at.unix_timestamp() as libc::time_t;
+    // SAFETY: localtime_r only writes into the zeroed tm we hand it.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let ok = unsafe { !libc::localtime_r(&t, &mut tm).is_null() };
+    if ok {
+        UtcOffset::from_whole_seconds(tm.tm_gmtoff as i32).unwrap_or(UtcOffset::UTC)
+    } else {
+        UtcOffset::UTC
+    }
+}
+
+#[cfg(not(unix))]
+fn local_offset(_at: OffsetDateTime) -> UtcOffset {
+    UtcOffset::UTC
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-11-14T22:13:20.123Z
+    fn sample() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap()
+    }
+
+    fn utc(spec: &str) -> String {
+        Clock::new(TimeFormat::parse(spec).unwrap(), false).format(sample())
+    }
+
+    #[test]
+    fn timefmt_renders_each_format_in_utc() {
+        assert_eq!(utc("rfc3339"), "2023-11-14T22:13:20.123Z");
+        assert_eq!(utc("epoch"), "1700000000.123");
+        assert_eq!(utc("%F %T.%L %z"), "2023-11-14 22:13:20.123 +0000");
+        assert_eq!(utc("%a %d %b %Y %I%p"), "Tue 14 Nov 2023 10PM");
+        // Literal brackets and percent signs survive the translation
+        assert_eq!(utc("[%H:%M] 100%%"), "[22:13] 100%");
+    }
+
+    #[test]
+    fn timefmt_rejects_unknown_and_dangling_directives() {
+        assert!(TimeFormat::parse("%Q").is_err());
+        assert!(TimeFormat::parse("%Y-%").is_err());
+    }
+
+    #[test]
+    fn timefmt_local_time_keeps_the_instant_and_uses_the_local_offset() {
+        let local = |spec| Clock::new(TimeFormat::parse(spec).unwrap(), true).format(sample());
+        assert_eq!(local("epoch"), utc("epoch"));
+        let offset = local_offset(sample());
+        let expected = format!(
+            "{}{:02}{:02}",
+            if offset.is_negative() { '-' } else { '+' },
+            offset.whole_hours().abs(),
+            offset.minutes_past_hour().abs()
+        );
+        assert_eq!(local("%z"), expected);
+    }
+}
@@ -1,97 +1,229 @@
 use std::io;
-use std::time::{Duration, SystemTime};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Layout};
-use ratatui::style::{Style, Color};
-use ratatui::widgets::{Cell, Row, Table, Block, Borders};
-use ratatui::Terminal;
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, Cell, Row, Table};
+use time::OffsetDateTime;
+use time::macros::format_description;
 
-use crate::stats::{Aggregator, StatsReceiver, Record};
+use crate::stats::{
+    Aggregator, IpRecord, Record, StatsReceiver, WsRecord, format_bytes, format_latency,
+};
+
+/// How long a status message stays in the title bar.
+const STATUS_TTL: Duration = Duration::from_secs(3);
 
 pub struct App {
     agg: Aggregator,
+    status: Option<(String, Instant)>,
+    /// Show the per-client table instead of paths (`--by-ip` only)
+    show_ips: bool,
+    /// When `c` was pressed once; a second `c` within STATUS_TTL clears
+    clear_armed: Option<Instant>,
 }
 
 impl App {
-    pub fn new() -> Self { Self { agg: Aggregator::default() } }
+    pub fn new(agg: Aggregator) -> Self {
+        Self {
+            agg,
+            status: None,
+            show_ips: false,
+            clear_armed: None,
+        }
+    }
+
+    /// Act on a key; true when the TUI should quit. Raw mode delivers Ctrl-C
+    /// as a key, so it quits here like `q` rather than reaching `c`.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let armed = self
+            .clear_armed
+            .take()
+            .is_some_and(|at| at.elapsed() < STATUS_TTL);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            // Clearing also drops history loaded from --stats-db, which is
+            // written back on exit, so it takes a second press
+            KeyCode::Char('c') if armed => {
+                self.agg.clear();
+                self.set_status("stats cleared".into());
+            }
+            KeyCode::Char('c') => {
+                self.clear_armed = Some(Instant::now());
+                self.set_status("press c again to clear all stats".into());
+            }
+            KeyCode::Char('e') => self.export(ExportFormat::Csv),
+            KeyCode::Char('E') => self.export(ExportFormat::Json),
+            KeyCode::Char('i') => self.toggle_ips(),
+            _ => {}
+        }
+        false
+    }
+
+    fn toggle_ips(&mut self) {
+        if self.agg.by_ip_enabled() {
+            self.show_ips = !self.show_ips;
+        } else {
+            self.set_status("start with --by-ip to see clients".into());
+        }
+    }
+
+    fn set_status(&mut self, msg: String) {
+        self.status = Some((msg, Instant::now()));
+    }
+
+    fn export(&mut self, format: ExportFormat) {
+        let msg = match export_snapshot(&self.agg, format) {
+            Ok(path) => format!("wrote {}", path.display()),
+            Err(e) => format!("export failed: {e}"),
+        };
+        self.set_status(msg);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
 }
 
-pub async fn run_tui(mut rx: StatsReceiver) -> anyhow::Result<()> {
+/// Write the current snapshot to `tap-snapshot-<utc time>.<ext>` in the working directory.
+fn export_snapshot(agg: &Aggregator, format: ExportFormat) -> anyhow::Result<PathBuf> {
+    let stamp = OffsetDateTime::now_utc().format(format_description!(
+        "[year][month][day]T[hour][minute][second]Z"
+    ))?;
+    let (ext, contents) = match format {
+        ExportFormat::Csv => ("csv", agg.to_csv()),
+        ExportFormat::Json => ("json", agg.to_export_json()),
+    };
+    let path = PathBuf::from(format!("tap-snapshot-{stamp}.{ext}"));
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Run the live table until the user quits, returning the final stats.
+pub async fn run_tui(mut rx: StatsReceiver, agg: Aggregator) -> anyhow::Result<Aggregator> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     crossterm::execute!(stdout, crossterm::terminal::EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new();
-    let mut last_redraw = std::time::Instant::now();
+    let mut app = App::new(agg);
+    let mut last_redraw = Instant::now();
 
     loop {
         // Non-blocking drain of stats
-        while let Ok(ev) = rx.try_recv() {
-            app.agg.apply(ev);
-        }
+        app.agg.drain(&mut rx);
 
         // Draw ~30fps max
         if last_redraw.elapsed() > Duration::from_millis(1000 / 30) {
             terminal.draw(|f| {
                 let size = f.size();
+                let ws_records = app.agg.ws_snapshot();
+                // The WebSocket table appears under the paths once a tunnel has opened
+                let ws_height = if ws_records.is_empty() {
+                    0
+                } else {
+                    (ws_records.len() as u16 + 3).min(size.height / 3)
+                };
                 let layout = Layout::default()
-                    .constraints([Constraint::Percentage(100)])
+                    .constraints([Constraint::Min(0), Constraint::Length(ws_height)])
                     .split(size);
 
-                let rows = app
-                    .agg
-                    .snapshot()
-                    .into_iter()
-                    .map(|rec| row_for(&rec));
+                let (bytes_in, bytes_out) = app.agg.total_bytes();
+                let mut title = format!(
+                    "HTTP Tap - {} in / {} out - q quit, c c clear, e/E export csv/json",
+                    format_bytes(bytes_in),
+                    format_bytes(bytes_out)
+                );
+                if app.agg.by_ip_enabled() {
+                    title.push_str(if app.show_ips {
+                        ", i paths"
+                    } else {
+                        ", i clients"
+                    });
+                }
+                let (allowed, denied) = app.agg.acl_counts();
+                if allowed > 0 || denied > 0 {
+                    title.push_str(&format!(" - {allowed} allowed / {denied} denied"));
+                }
+                if let Some((msg, at)) = &app.status
+                    && at.elapsed() < STATUS_TTL
+                {
+                    title.push_str(&format!(" - {msg}"));
+                }
+
+                let rows = app.agg.snapshot().into_iter().map(|rec| row_for(&rec));
 
                 let table = Table::new(
-                        rows,
-                        [
-                            Constraint::Percentage(40),
-                            Constraint::Length(6),
-                            Constraint::Length(6),
-                            Constraint::Length(6),
-                            Constraint::Length(6),
-                            Constraint::Length(6),
-                            Constraint::Length(7),
-                            Constraint::Percentage(20),
-                        ],
-                    )
-                    .header(
-                        Row::new([
-                            Cell::from("Path"),
-                            Cell::from("GET"),
-                            Cell::from("POST"),
-                            Cell::from("PUT"),
-                            Cell::from("PATCH"),
-                            Cell::from("DEL"),
-                            Cell::from("OTHER"),
-                            Cell::from("Last Seen"),
-                        ])
-                        .style(Style::default().fg(Color::Yellow)),
-                    )
-                    .widths(&[])
-                    .block(Block::default().borders(Borders::ALL).title("HTTP Tap - q to quit"));
-
-                f.render_widget(table, layout[0]);
+                    rows,
+                    [
+                        Constraint::Percentage(40),
+                        Constraint::Length(6),
+                        Constraint::Length(6),
+                        Constraint::Length(6),
+                        Constraint::Length(6),
+                        Constraint::Length(6),
+                        Constraint::Length(6),
+                        Constraint::Length(5),
+                        Constraint::Length(5),
+                        Constraint::Length(7),
+                        Constraint::Length(10),
+                        Constraint::Length(10),
+                        Constraint::Length(8),
+                        Constraint::Length(8),
+                        Constraint::Length(8),
+                        Constraint::Percentage(20),
+                    ],
+                )
+                .header(
+                    Row::new([
+                        Cell::from("Path"),
+                        Cell::from("GET"),
+                        Cell::from("POST"),
+                        Cell::from("PUT"),
+                        Cell::from("PATCH"),
+                        Cell::from("DEL"),
+                        Cell::from("HEAD"),
+                        Cell::from("OPTS"),
+                        Cell::from("CONN"),
+                        Cell::from("OTHER"),
+                        Cell::from("In"),
+                        Cell::from("Out"),
+                        Cell::from("p50"),
+                        Cell::from("p95"),
+                        Cell::from("p99"),
+                        Cell::from("Last Seen"),
+                    ])
+                    .style(Style::default().fg(Color::Yellow)),
+                )
+                .block(Block::default().borders(Borders::ALL).title(title.clone()));
+
+                if app.show_ips {
+                    f.render_widget(ip_table(&app.agg.ip_snapshot(), title), layout[0]);
+                } else {
+                    f.render_widget(table, layout[0]);
+                }
+                if !ws_records.is_empty() {
+                    f.render_widget(ws_table(&ws_records), layout[1]);
+                }
             })?;
-            last_redraw = std::time::Instant::now();
+            last_redraw = Instant::now();
         }
 
         // Handle input with timeout to keep UI responsive
-        if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => break,
-                    KeyCode::Char('c') => app.agg = Aggregator::default(),
-                    _ => {}
-                }
-            }
+        if event::poll(Duration::from_millis(50))?
+            && let Event::Key(key) = event::read()?
+            && app.handle_key(key)
+        {
+            break;
         }
     }
 
@@ -101,11 +233,15 @@ pub async fn run_tui(mut rx: StatsReceiver) -> anyhow::Result<()> {
         crossterm::terminal::LeaveAlternateScreen
     )?;
     terminal.show_cursor()?;
-    Ok(())
+    // Pick up events that arrived after the last redraw
+    app.agg.drain(&mut rx);
+    Ok(app.agg)
 }
 
 fn row_for(rec: &Record) -> Row<'static> {
     let last = humanize(rec.last_seen);
+    let percentiles = rec.latency;
+    let latency = |d: Option<Duration>| d.map(format_latency).unwrap_or_else(|| "-".into());
     Row::new(vec![
         Cell::from(rec.path.clone()),
         Cell::from(rec.counts.get.to_string()),
@@ -113,14 +249,160 @@ fn row_for(rec: &Record) -> Row<'static> {
         Cell::from(rec.counts.put.to_string()),
         Cell::from(rec.counts.patch.to_string()),
         Cell::from(rec.counts.delete_.to_string()),
+        Cell::from(rec.counts.head.to_string()),
+        Cell::from(rec.counts.options.to_string()),
+        Cell::from(rec.counts.connect.to_string()),
         Cell::from(rec.counts.other.to_string()),
+        Cell::from(format_bytes(rec.bytes_in)),
+        Cell::from(format_bytes(rec.bytes_out)),
+        Cell::from(latency(percentiles.map(|p| p.p50))),
+        Cell::from(latency(percentiles.map(|p| p.p95))),
+        Cell::from(latency(percentiles.map(|p| p.p99))),
         Cell::from(last),
     ])
 }
 
+/// Open tunnels, message rate, and traffic each way per WebSocket path.
+fn ws_table(records: &[WsRecord]) -> Table<'static> {
+    let now = SystemTime::now();
+    let active: u64 = records.iter().map(|rec| rec.active).sum();
+    let rows = records.iter().map(|rec| {
+        Row::new(vec![
+            Cell::from(rec.path.clone()),
+            Cell::from(rec.active.to_string()),
+            Cell::from(format!("{:.1}", rec.messages_per_sec(now))),
+            Cell::from(rec.messages_in.to_string()),
+            Cell::from(rec.messages_out.to_string()),
+            Cell::from(format_bytes(rec.bytes_in)),
+            Cell::from(format_bytes(rec.bytes_out)),
+            Cell::from(humanize(rec.last_seen)),
+        ])
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Percentage(40),
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Percentage(20),
+        ],
+    )
+    .header(
+        Row::new([
+            Cell::from("WebSocket"),
+            Cell::from("Open"),
+            Cell::from("Msg/s"),
+            Cell::from("Msgs In"),
+            Cell::from("Msgs Out"),
+            Cell::from("In"),
+            Cell::from("Out"),
+            Cell::from("Last Seen"),
+        ])
+        .style(Style::default().fg(Color::Yellow)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("WebSockets - {active} open")),
+    )
+}
+
+/// Clients by request count, busiest first.
+fn ip_table(records: &[IpRecord], title: String) -> Table<'static> {
+    let rows = records.iter().map(|rec| {
+        Row::new(vec![
+            Cell::from(rec.ip.to_string()),
+            Cell::from(rec.requests.to_string()),
+            Cell::from(rec.errors.to_string()),
+            Cell::from(format_bytes(rec.bytes_in)),
+            Cell::from(format_bytes(rec.bytes_out)),
+            Cell::from(humanize(rec.last_seen)),
+        ])
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Percentage(40),
+            Constraint::Length(9),
+            Constraint::Length(7),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Percentage(20),
+        ],
+    )
+    .header(
+        Row::new([
+            Cell::from("Client"),
+            Cell::from("Requests"),
+            Cell::from("5xx"),
+            Cell::from("In"),
+            Cell::from("Out"),
+            Cell::from("Last Seen"),
+        ])
+        .style(Style::default().fg(Color::Yellow)),
+    )
+    .block(Block::default().borders(Borders::ALL).title(title))
+}
+
 fn humanize(ts: SystemTime) -> String {
     match ts.elapsed() {
         Ok(d) => humantime::format_duration(d).to_string() + " ago",
         Err(_) => "just now".into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use hyper::http::Method;
+
+    use super::*;
+    use crate::stats::{StatsEvent, StatsMessage};
+
+    fn app_with_history() -> App {
+        let mut agg = Aggregator::default();
+        agg.apply(StatsMessage::Request(StatsEvent {
+            method: Method::GET,
+            path: "/loaded".into(),
+            peer: IpAddr::from([127, 0, 0, 1]),
+            at: SystemTime::now(),
+            status: 200,
+            bytes_in: 1,
+            bytes_out: 2,
+            latency: Duration::from_millis(3),
+        }));
+        App::new(agg)
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn tui_ctrl_c_quits_without_clearing() {
+        let mut app = app_with_history();
+        assert!(app.handle_key(key(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert_eq!(app.agg.snapshot().len(), 1);
+    }
+
+    #[test]
+    fn tui_clear_needs_a_second_c() {
+        let mut app = app_with_history();
+        let c = key(KeyCode::Char('c'), KeyModifiers::NONE);
+        assert!(!app.handle_key(c));
+        assert_eq!(app.agg.snapshot().len(), 1);
+
+        // Any other key in between disarms it
+        assert!(!app.handle_key(key(KeyCode::Char('x'), KeyModifiers::NONE)));
+        assert!(!app.handle_key(c));
+        assert_eq!(app.agg.snapshot().len(), 1);
+
+        assert!(!app.handle_key(c));
+        assert!(app.agg.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::http::{HeaderValue, Uri};
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Largest CONNECT response head we will read from the proxy.
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+/// Egress proxy that upstream connections are routed through.
+#[derive(Clone, Debug)]
+pub struct UpstreamProxy {
+    /// `host:port` of the proxy itself.
+    pub authority: String,
+    /// `Proxy-Authorization` value built from the URL's credentials.
+    pub auth: Option<HeaderValue>,
+}
+
+/// True when `host` matches an entry of a NO_PROXY-style list: `*`, an exact
+/// host, or a domain suffix (`example.com` and `.example.com` both cover
+/// `api.example.com`). Ports on entries are ignored.
+pub fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            let entry = entry.trim_start_matches('[');
+            let entry = match entry.split_once(']') {
+                Some((v6, _)) => v6,
+                None if entry.matches(':').count() == 1 => entry.split(':').next().unwrap_or(entry),
+                None => entry,
+            };
+            let entry = entry.trim_start_matches('.').to_ascii_lowercase();
+            host == entry || host.ends_with(&format!(".{entry}"))
+        })
+}
+
+/// Connector that dials the upstream proxy instead of the target. HTTPS
+/// targets are tunnelled with CONNECT; plain HTTP connections are marked as
+/// proxied so requests go out in absolute form.
+#[derive(Clone)]
+pub struct ProxyConnector {
+    http: HttpConnector,
+    proxy: Option<UpstreamProxy>,
+}
+
+impl ProxyConnector {
+    pub fn new(proxy: Option<UpstreamProxy>) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Self { http, proxy }
+    }
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = ProxyStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<ProxyStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let Some(proxy) = self.proxy.clone() else {
+            let connecting = self.http.call(dst);
+            return Box::pin(async move {
+                Ok(ProxyStream {
+                    io: connecting.await?,
+                    proxied: false,
+                })
+            });
+        };
+        let proxy_uri = match format!("http://{}", proxy.authority).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(e) => return Box::pin(async move { Err(e.into()) }),
+        };
+        let connecting = self.http.call(proxy_uri);
+        Box::pin(async move {
+            let io = connecting.await.map_err(|e| {
+                io::Error::other(format!(
+                    "connect to upstream proxy {}: {e}",
+                    proxy.authority
+                ))
+            })?;
+            if dst.scheme_str() != Some("https") {
+                return Ok(ProxyStream { io, proxied: true });
+            }
+            let host = dst.host().ok_or("upstream URI has no host")?;
+            let port = dst.port_u16().unwrap_or(443);
+            let stream = tunnel(
+                io.into_inner(),
+                &format!("{host}:{port}"),
+                proxy.auth.as_ref(),
+            )
+            .await?;
+            Ok(ProxyStream {
+                io: TokioIo::new(stream),
+                proxied: false,
+            })
+        })
+    }
+}
+
+/// Ask the proxy to open a tunnel to `target` and wait for its 2xx.
+async fn tunnel(
+    mut stream: TcpStream,
+    target: &str,
+    auth: Option<&HeaderValue>,
+) -> io::Result<TcpStream> {
+    let mut head = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(auth) = auth {
+        let auth = auth.to_str().map_err(io::Error::other)?;
+        head.push_str(&format!("Proxy-Authorization: {auth}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+
+    // Read byte by byte so nothing past the response head (e.g. the start of
+    // a TLS handshake) is consumed here.
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE {
+            return Err(io::Error::other(
+                "upstream proxy CONNECT response too large",
+            ));
+        }
+        if stream.read(&mut byte).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "upstream proxy closed the connection during CONNECT",
+            ));
+        }
+        response.push(byte[0]);
+    }
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or("");
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok());
+    match status {
+        Some(200..=299) => Ok(stream),
+        _ => Err(io::Error::other(format!(
+            "upstream proxy refused CONNECT {target}: {status_line}"
+        ))),
+    }
+}
+
+/// TCP stream to the target or through the upstream proxy.
+pub struct ProxyStream {
+    io: TokioIo<TcpStream>,
+    proxied: bool,
+}
+
+impl Connection for ProxyStream {
+    fn connected(&self) -> Connected {
+        self.io.connected().proxy(self.proxied)
+    }
+}
+
+impl Read for ProxyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl Write for ProxyStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn upstream_proxy_no_proxy_matches_suffixes_and_wildcard() {
+        let list = "localhost, .internal.example, example.org:8080, [::1], 10.0.0.5";
+        for host in [
+            "localhost",
+            "LOCALHOST",
+            "api.internal.example",
+            "internal.example",
+            "example.org",
+            "www.example.org",
+            "[::1]",
+            "10.0.0.5",
+        ] {
+            assert!(no_proxy_matches(list, host), "{host} should match");
+        }
+        for host in [
+            "notexample.org",
+            "example.com",
+            "internal.example.com",
+            "10.0.0.50",
+        ] {
+            assert!(!no_proxy_matches(list, host), "{host} should not match");
+        }
+        assert!(no_proxy_matches("foo, *", "anything.at.all"));
+        assert!(!no_proxy_matches("", "localhost"));
+        assert!(!no_proxy_matches(" , ", "localhost"));
+    }
+
+    /// Run `tunnel` against a fake proxy that answers with `reply`, returning
+    /// the CONNECT request it received and what the tunnel produced.
+    async fn connect_via(reply: &'static [u8]) -> (String, io::Result<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0u8; 1];
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).await.unwrap();
+                request.push(byte[0]);
+            }
+            stream.write_all(reply).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let auth = HeaderValue::from_static("Basic dTpw");
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let result = tunnel(stream, "api.example:443", Some(&auth)).await;
+        (proxy.await.unwrap(), result)
+    }
+
+    #[tokio::test]
+    async fn upstream_proxy_tunnel_reads_only_the_connect_response() {
+        let (request, result) =
+            connect_via(b"HTTP/1.1 200 Connection established\r\n\r\nTLS").await;
+        assert_eq!(
+            request,
+            "CONNECT api.example:443 HTTP/1.1\r\nHost: api.example:443\r\nProxy-Authorization: Basic dTpw\r\n\r\n"
+        );
+        // Bytes after the head belong to the tunnel, not the CONNECT response
+        let mut rest = [0u8; 3];
+        result.unwrap().read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"TLS");
+    }
+
+    #[tokio::test]
+    async fn upstream_proxy_tunnel_reports_refusals_and_early_close() {
+        let (_, refused) = connect_via(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await;
+        let err = refused.unwrap_err().to_string();
+        assert!(err.contains("refused CONNECT api.example:443"), "{err}");
+        assert!(err.contains("407"), "{err}");
+
+        let (_, garbled) = connect_via(b"nonsense\r\n\r\n").await;
+        assert!(garbled.is_err());
+
+        let (_, closed) = connect_via(b"HTTP/1.1 200 OK\r\n").await;
+        assert_eq!(closed.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,150 @@
+//! WebSocket tunnels: bytes are copied through untouched while frame headers
+//! are read to count messages for the stats.
+
+use std::time::SystemTime;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::stats::{StatsMessage, StatsSender, WsDirection, WsEvent};
+
+/// Copy both directions until either side closes or fails, reporting the
+/// traffic on `path` to `stats` as it passes.
+pub async fn tunnel<D, U>(
+    down: D,
+    up: U,
+    path: String,
+    stats: Option<StatsSender>,
+) -> std::io::Result<()>
+where
+    D: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let report = |event: WsEvent| {
+        if let Some(tx) = &stats {
+            let _ = tx.send(StatsMessage::WebSocket(event));
+        }
+    };
+    report(WsEvent::Opened { path: path.clone() });
+    let (down_read, down_write) = tokio::io::split(down);
+    let (up_read, up_write) = tokio::io::split(up);
+    let result = tokio::try_join!(
+        pump(down_read, up_write, &path, WsDirection::In, &stats),
+        pump(up_read, down_write, &path, WsDirection::Out, &stats),
+    );
+    report(WsEvent::Closed { path });
+    result.map(|_| ())
+}
+
+/// One direction of a tunnel; shuts the writer down at end of stream.
+async fn pump<R, W>(
+    mut from: R,
+    mut to: W,
+    path: &str,
+    direction: WsDirection,
+    stats: &Option<StatsSender>,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut frames = FrameCounter::default();
+    let mut buf = vec![0; 16 * 1024];
+    loop {
+        let n = from.read(&mut buf).await?;
+        if n == 0 {
+            return to.shutdown().await;
+        }
+        to.write_all(&buf[..n]).await?;
+        if let Some(tx) = stats {
+            let _ = tx.send(StatsMessage::WebSocket(WsEvent::Traffic {
+                path: path.to_string(),
+                direction,
+                messages: frames.push(&buf[..n]),
+                bytes: n as u64,
+                at: SystemTime::now(),
+            }));
+        }
+    }
+}
+
+/// Follows frame boundaries in one direction of a WebSocket stream.
+#[derive(Default)]
+struct FrameCounter {
+    /// Header bytes of the next frame seen so far
+    header: Vec<u8>,
+    /// Payload bytes of the current frame not yet seen
+    remaining: u64,
+}
+
+impl FrameCounter {
+    /// Feed the next bytes of the stream; returns how many data messages
+    /// ended in them. Control frames (ping, pong, close) are not counted.
+    fn push(&mut self, mut chunk: &[u8]) -> u64 {
+        let mut messages = 0;
+        while !chunk.is_empty() {
+            if self.remaining > 0 {
+                let skip = self.remaining.min(chunk.len() as u64) as usize;
+                self.remaining -= skip as u64;
+                chunk = &chunk[skip..];
+                continue;
+            }
+            self.header.push(chunk[0]);
+            chunk = &chunk[1..];
+            if let Some((len, ends_message)) = parse_header(&self.header) {
+                self.header.clear();
+                self.remaining = len;
+                if ends_message {
+                    messages += 1;
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// Payload length and whether the frame ends a data message, once `header`
+/// holds a complete frame header (RFC 6455 §5.2).
+fn parse_header(header: &[u8]) -> Option<(u64, bool)> {
+    let [first, second, ..] = *header else {
+        return None;
+    };
+    let len_bytes = match second & 0x7f {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+    let mask_bytes = if second & 0x80 != 0 { 4 } else { 0 };
+    if header.len() < 2 + len_bytes + mask_bytes {
+        return None;
+    }
+    let len = match len_bytes {
+        0 => u64::from(second & 0x7f),
+        n => header[2..2 + n]
+            .iter()
+            .fold(0, |len, &b| len << 8 | u64::from(b)),
+    };
+    let fin = first & 0x80 != 0;
+    let control = first & 0x08 != 0;
+    Some((len, fin && !control))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn websocket_counts_messages_split_across_chunks() {
+        let mut frames = FrameCounter::default();
+        // Masked "hi" text frame, then a ping, then a 300-byte binary frame
+        // in two fragments (the first not final).
+        let mut stream = vec![0x81, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2, 0x89, 0x00];
+        stream.extend([0x02, 0x7e, 0x01, 0x00]);
+        stream.extend([0u8; 256]);
+        stream.extend([0x80, 44]);
+        stream.extend([0u8; 44]);
+        let counts: Vec<u64> = stream.chunks(7).map(|chunk| frames.push(chunk)).collect();
+        assert_eq!(counts.iter().sum::<u64>(), 2);
+        assert_eq!(frames.remaining, 0);
+        assert!(frames.header.is_empty());
+    }
+}
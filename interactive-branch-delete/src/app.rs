@@ -1,75 +1,601 @@
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime};
 
-use crate::git::BranchInfo;
+use clap::ValueEnum;
+use git2::{Oid, Repository};
+use ratatui::layout::Rect;
+
+use crate::filter::matches_any;
+use crate::git::{BranchInfo, MergeStatus, UniqueCommits, unique_commits};
+use crate::keys::Keymap;
+use crate::listing::AgeFormat;
+use crate::theme::Theme;
+
+/// Order of the selector's rows; `s` cycles through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SortOrder {
+    /// Oldest last commit first; undated branches at the end.
+    #[default]
+    Age,
+    Name,
+    /// Merged, then squash-merged, then unmerged.
+    Status,
+    /// By commit author, then name.
+    Committer,
+}
+
+impl SortOrder {
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Age => "age",
+            SortOrder::Name => "name",
+            SortOrder::Status => "status",
+            SortOrder::Committer => "committer",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SortOrder::Age => SortOrder::Name,
+            SortOrder::Name => SortOrder::Status,
+            SortOrder::Status => SortOrder::Committer,
+            SortOrder::Committer => SortOrder::Age,
+        }
+    }
+
+    fn compare(self, a: &BranchItem, b: &BranchItem) -> Ordering {
+        let by_name = || a.info.name.cmp(&b.info.name);
+        match self {
+            SortOrder::Age => match (&a.age, &b.age) {
+                (Some(a_age), Some(b_age)) => b_age.cmp(a_age).then_with(by_name),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => by_name(),
+            },
+            SortOrder::Name => by_name(),
+            SortOrder::Status => status_rank(a.info.merged)
+                .cmp(&status_rank(b.info.merged))
+                .then_with(by_name),
+            SortOrder::Committer => match (&a.info.committer, &b.info.committer) {
+                (Some(a_name), Some(b_name)) => a_name
+                    .to_lowercase()
+                    .cmp(&b_name.to_lowercase())
+                    .then_with(by_name),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => by_name(),
+            },
+        }
+    }
+}
+
+fn status_rank(status: MergeStatus) -> u8 {
+    match status {
+        MergeStatus::Merged => 0,
+        MergeStatus::SquashMerged => 1,
+        MergeStatus::Unmerged => 2,
+    }
+}
 
 pub struct BranchItem {
     pub info: BranchInfo,
     pub selected: bool,
     pub age: Option<Duration>,
+    /// Matches a --protect glob, is checked out in a worktree or is the
+    /// current branch; can never be selected.
+    pub protected: bool,
+    /// The branch checked out here, listed so it does not seem to vanish.
+    pub current: bool,
 }
 
 impl BranchItem {
-    fn new(info: BranchInfo, now: SystemTime) -> Self {
+    fn new(info: BranchInfo, now: SystemTime, current_branch: &str) -> Self {
         let age = info.age(now);
+        let current = !info.is_remote_tracking && info.name == current_branch;
+        let checked_out = current || info.worktree.is_some();
         Self {
             info,
             selected: false,
             age,
+            protected: checked_out,
+            current,
         }
     }
 }
 
+/// A line of the list: a branch, or a group header under --group-by-prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+    Group(String),
+    Branch(usize),
+}
+
+/// What the confirmation screen waits for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Confirmation {
+    /// `y` or `n`.
+    YesNo,
+    /// The number of selected branches, typed out; used above
+    /// --confirm-threshold so a stray `a` and enter cannot delete dozens.
+    ConfirmCount(String),
+}
+
+pub const DEFAULT_CONFIRM_THRESHOLD: usize = 10;
+
+/// How a selection has to be confirmed before it is deleted; decided the
+/// same way by the selector and the numbered prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmStep {
+    /// `y` or `n`.
+    YesNo,
+    /// The number of selected branches, typed out.
+    TypeCount,
+}
+
+/// The confirmation `selection` needs, or why it cannot be deleted at all:
+/// nothing selected, or unmerged branches without --force.
+pub fn confirm_step(
+    selection: &[&BranchInfo],
+    force: bool,
+    threshold: usize,
+) -> Result<ConfirmStep, String> {
+    if selection.is_empty() {
+        return Err("Select at least one branch before confirming.".to_string());
+    }
+    let unmerged = unmerged_names(selection);
+    if !unmerged.is_empty() && !force {
+        return Err(format!(
+            "Unmerged branches selected: {}. Deselect them or re-run with --force.",
+            unmerged.join(", ")
+        ));
+    }
+    Ok(if selection.len() > threshold {
+        ConfirmStep::TypeCount
+    } else {
+        ConfirmStep::YesNo
+    })
+}
+
+/// What would be lost by deleting `selection`, one line per kind of risk:
+/// unmerged commits, branches no remote has and stashes made on them.
+pub fn risk_warnings(selection: &[&BranchInfo]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let unmerged = unmerged_names(selection);
+    if !unmerged.is_empty() {
+        warnings.push(format!(
+            "Unmerged commits will be lost on: {}",
+            unmerged.join(", ")
+        ));
+    }
+    let unpushed = unpushed_names(selection);
+    if !unpushed.is_empty() {
+        warnings.push(format!(
+            "Not on any remote, no other copy exists: {}",
+            unpushed.join(", ")
+        ));
+    }
+    let stashed = stashed_names(selection);
+    if !stashed.is_empty() {
+        warnings.push(format!(
+            "Stashes were made on these; they will no longer match a branch: {}",
+            stashed.join(", ")
+        ));
+    }
+    warnings
+}
+
+/// Branches in `selection` whose commits are not merged into a base.
+pub fn unmerged_names<'a>(selection: &[&'a BranchInfo]) -> Vec<&'a str> {
+    names_where(selection, |branch| !branch.merged.is_merged())
+}
+
+/// Branches in `selection` whose tips no remote-tracking ref contains.
+pub fn unpushed_names<'a>(selection: &[&'a BranchInfo]) -> Vec<&'a str> {
+    names_where(selection, |branch| !branch.pushed)
+}
+
+/// Branches in `selection` that stashes were made on.
+pub fn stashed_names<'a>(selection: &[&'a BranchInfo]) -> Vec<&'a str> {
+    names_where(selection, |branch| branch.stashes > 0)
+}
+
+fn names_where<'a>(
+    selection: &[&'a BranchInfo],
+    keep: impl Fn(&BranchInfo) -> bool,
+) -> Vec<&'a str> {
+    selection
+        .iter()
+        .filter(|branch| keep(branch))
+        .map(|branch| branch.name.as_str())
+        .collect()
+}
+
+/// What `draw` renders for each row.
+pub enum RowView<'a> {
+    Group {
+        prefix: &'a str,
+        count: usize,
+        selected: usize,
+        collapsed: bool,
+    },
+    Branch(&'a BranchItem),
+}
+
+/// Group key for --group-by-prefix: the first path segment, or "" for
+/// names without a `/`.
+fn prefix_of(name: &str) -> &str {
+    name.split_once('/').map_or("", |(prefix, _)| prefix)
+}
+
 pub struct App {
     branches: Vec<BranchItem>,
+    /// Indices into `branches` matching the search query, in display order.
+    visible: Vec<usize>,
+    /// Lines of the list: `visible`, plus group headers when grouping, minus
+    /// the branches of collapsed groups.
+    rows: Vec<Row>,
+    /// Position within `rows`.
     cursor: usize,
+    /// Other end of the visual range started with `V`, within `rows`.
+    visual_anchor: Option<usize>,
+    /// First visible row of the list and the number of rows it shows, as of
+    /// the last draw.
+    scroll_offset: usize,
+    page_size: usize,
+    /// Where the list was last drawn, borders included, for mouse clicks.
+    list_area: Rect,
     should_quit: bool,
     confirmed: bool,
     message: Option<String>,
-    base_branch: String,
+    bases: Vec<String>,
     current_branch: String,
+    force: bool,
+    /// --protect globs, kept for branches that arrive while loading.
+    protect: Vec<String>,
+    /// Branches analyzed and in all while they are still streaming in.
+    loading: Option<(usize, usize)>,
+    /// Showing the list of branches about to be deleted, waiting for an answer.
+    confirmation: Option<Confirmation>,
+    /// Selections larger than this need their count typed to be deleted.
+    confirm_threshold: usize,
+    filter_label: Option<String>,
+    query: String,
+    searching: bool,
+    /// View filters from `M` and `U`; hidden branches keep their selection.
+    hide_merged: bool,
+    hide_unmerged: bool,
+    sort: SortOrder,
+    /// Flip `sort`.
+    reverse: bool,
+    grouped: bool,
+    collapsed: HashSet<String>,
+    /// Show commit dates instead of ages.
+    absolute_dates: bool,
+    full_hash: bool,
+    /// --wide: each branch takes its summary's lines below its own.
+    wide: bool,
+    /// Lines each row took in the last draw; empty when every row is one line.
+    row_heights: Vec<usize>,
+    age_format: AgeFormat,
+    help_lines: [String; 2],
+    /// Keys and what they do, listed by the help overlay.
+    key_table: Vec<(String, &'static str)>,
+    show_help: bool,
+    theme: Theme,
+    /// Commit pane contents by tip, loaded as branches are highlighted.
+    unique_commits: HashMap<Oid, Result<UniqueCommits, String>>,
 }
 
 impl App {
-    pub fn new(branches: Vec<BranchInfo>, base_branch: String, current_branch: String) -> Self {
+    pub fn new(branches: Vec<BranchInfo>, bases: Vec<String>, current_branch: String) -> Self {
         let now = SystemTime::now();
         let mut items: Vec<BranchItem> = branches
             .into_iter()
-            .map(|info| BranchItem::new(info, now))
+            .map(|info| BranchItem::new(info, now, &current_branch))
             .collect();
 
-        items.sort_by(|a, b| match (&a.age, &b.age) {
-            (Some(a_age), Some(b_age)) => b_age.cmp(a_age),
-            (Some(_), None) => Ordering::Less,
-            (None, Some(_)) => Ordering::Greater,
-            (None, None) => a.info.name.cmp(&b.info.name),
-        });
+        let sort = SortOrder::default();
+        items.sort_by(|a, b| sort.compare(a, b));
 
         Self {
+            visible: (0..items.len()).collect(),
+            rows: (0..items.len()).map(Row::Branch).collect(),
             branches: items,
             cursor: 0,
+            visual_anchor: None,
+            scroll_offset: 0,
+            page_size: 1,
+            list_area: Rect::default(),
             should_quit: false,
             confirmed: false,
             message: None,
-            base_branch,
+            bases,
             current_branch,
+            force: false,
+            protect: Vec::new(),
+            loading: None,
+            confirmation: None,
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+            filter_label: None,
+            query: String::new(),
+            searching: false,
+            hide_merged: false,
+            hide_unmerged: false,
+            sort,
+            reverse: false,
+            grouped: false,
+            collapsed: HashSet::new(),
+            absolute_dates: false,
+            full_hash: false,
+            wide: false,
+            row_heights: Vec::new(),
+            age_format: AgeFormat::default(),
+            help_lines: Keymap::default().help_lines(),
+            key_table: Keymap::default().key_table(),
+            show_help: false,
+            theme: Theme::default(),
+            unique_commits: HashMap::new(),
+        }
+    }
+
+    /// Show branches under collapsible headers by their first path segment.
+    pub fn set_grouped(&mut self, grouped: bool) {
+        self.grouped = grouped;
+        let current = self.cursor_branch();
+        self.refresh_visible(current);
+    }
+
+    /// Collapse or expand the group under the cursor (header or member).
+    pub fn toggle_group(&mut self) {
+        if !self.grouped {
+            return;
+        }
+        let prefix = match self.rows.get(self.cursor) {
+            Some(Row::Group(prefix)) => prefix.clone(),
+            Some(Row::Branch(index)) => prefix_of(&self.branches[*index].info.name).to_string(),
+            None => return,
+        };
+        if !self.collapsed.remove(&prefix) {
+            self.collapsed.insert(prefix.clone());
+        }
+        self.refresh_visible(None);
+        self.cursor = self
+            .rows
+            .iter()
+            .position(|row| *row == Row::Group(prefix.clone()))
+            .unwrap_or(0);
+    }
+
+    fn cursor_branch(&self) -> Option<usize> {
+        match self.rows.get(self.cursor) {
+            Some(Row::Branch(index)) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Visible branches in the group named `prefix`.
+    fn group_members(&self, prefix: &str) -> impl Iterator<Item = usize> + '_ {
+        let prefix = prefix.to_string();
+        self.visible
+            .iter()
+            .copied()
+            .filter(move |&index| prefix_of(&self.branches[index].info.name) == prefix)
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Describe the bindings actually in use, under the list and in the
+    /// help overlay.
+    pub fn set_key_help(&mut self, keymap: &Keymap) {
+        self.help_lines = keymap.help_lines();
+        self.key_table = keymap.key_table();
+    }
+
+    pub fn help_lines(&self) -> &[String; 2] {
+        &self.help_lines
+    }
+
+    pub fn key_table(&self) -> &[(String, &'static str)] {
+        &self.key_table
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
+
+    pub fn is_showing_help(&self) -> bool {
+        self.show_help
+    }
+
+    pub fn set_age_format(&mut self, age_format: AgeFormat) {
+        self.age_format = age_format;
+    }
+
+    pub fn age_format(&self) -> AgeFormat {
+        self.age_format
+    }
+
+    pub fn toggle_absolute_dates(&mut self) {
+        self.absolute_dates = !self.absolute_dates;
+    }
+
+    pub fn absolute_dates(&self) -> bool {
+        self.absolute_dates
+    }
+
+    pub fn set_full_hash(&mut self, full_hash: bool) {
+        self.full_hash = full_hash;
+    }
+
+    pub fn full_hash(&self) -> bool {
+        self.full_hash
+    }
+
+    pub fn set_wide(&mut self, wide: bool) {
+        self.wide = wide;
+    }
+
+    pub fn wide(&self) -> bool {
+        self.wide
+    }
+
+    /// Look up the highlighted branch's commits that are not in any base,
+    /// once per tip.
+    pub fn load_unique_commits(&mut self, repo: &Repository, limit: usize) {
+        let Some(tip) = self.highlighted().map(|branch| branch.info.tip) else {
+            return;
+        };
+        if !self.unique_commits.contains_key(&tip) {
+            let commits =
+                unique_commits(repo, tip, &self.bases, limit).map_err(|err| format!("{err:#}"));
+            self.unique_commits.insert(tip, commits);
         }
     }
 
+    pub fn highlighted_commits(&self) -> Option<&Result<UniqueCommits, String>> {
+        self.unique_commits.get(&self.highlighted()?.info.tip)
+    }
+
+    /// Branch under the cursor; None on a group header or an empty list.
+    pub fn highlighted(&self) -> Option<&BranchItem> {
+        self.cursor_branch().map(|index| &self.branches[index])
+    }
+
+    /// Re-sort the rows, keeping the cursor on the same branch.
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        self.sort = sort;
+        let current = self.cursor_branch();
+        let mut order: Vec<usize> = (0..self.branches.len()).collect();
+        order.sort_by(|&a, &b| {
+            let ordering = sort.compare(&self.branches[a], &self.branches[b]);
+            if self.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        let mut slots: Vec<Option<BranchItem>> = std::mem::take(&mut self.branches)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.branches = order
+            .iter()
+            .filter_map(|&index| slots[index].take())
+            .collect();
+        let current = current.and_then(|old| order.iter().position(|&index| index == old));
+        self.refresh_visible(current);
+    }
+
+    /// Flip every sort order from now on, re-sorting the rows.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+        self.set_sort(self.sort);
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reverse
+    }
+
+    /// Hide or show merged (and squash-merged) branches.
+    pub fn toggle_hide_merged(&mut self) {
+        self.hide_merged = !self.hide_merged;
+        let current = self.cursor_branch();
+        self.refresh_visible(current);
+    }
+
+    /// Hide or show unmerged branches.
+    pub fn toggle_hide_unmerged(&mut self) {
+        self.hide_unmerged = !self.hide_unmerged;
+        let current = self.cursor_branch();
+        self.refresh_visible(current);
+    }
+
+    /// The hide filters in effect, for the title.
+    pub fn hidden_label(&self) -> Option<&'static str> {
+        match (self.hide_merged, self.hide_unmerged) {
+            (false, false) => None,
+            (true, false) => Some("merged"),
+            (false, true) => Some("unmerged"),
+            (true, true) => Some("merged and unmerged"),
+        }
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.set_sort(self.sort.next());
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn protect(&mut self, patterns: &[String]) {
+        self.protect = patterns.to_vec();
+        for branch in &mut self.branches {
+            branch.protected = branch.current
+                || branch.info.worktree.is_some()
+                || matches_any(patterns, &branch.info.name);
+            branch.selected &= !branch.protected;
+        }
+    }
+
+    /// Add branches that finished loading. Each batch is sorted and appended
+    /// as one run, so re-sorting only merges it into the rows already there.
+    pub fn add_branches(&mut self, branches: Vec<BranchInfo>) {
+        if branches.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut items: Vec<BranchItem> = branches
+            .into_iter()
+            .map(|info| {
+                let mut item = BranchItem::new(info, now, &self.current_branch);
+                item.protected |= matches_any(&self.protect, &item.info.name);
+                item
+            })
+            .collect();
+        items.sort_by(|a, b| self.sort.compare(a, b));
+        if self.reverse {
+            items.reverse();
+        }
+        self.branches.extend(items);
+        self.set_sort(self.sort);
+    }
+
+    /// Show `analyzed` of `total` while branches stream in; None once done.
+    pub fn set_loading(&mut self, loading: Option<(usize, usize)>) {
+        self.loading = loading;
+    }
+
+    pub fn loading(&self) -> Option<(usize, usize)> {
+        self.loading
+    }
+
     pub fn is_empty(&self) -> bool {
         self.branches.is_empty()
     }
 
     pub fn move_down(&mut self) {
-        if self.branches.is_empty() {
+        if self.rows.is_empty() {
             return;
         }
         self.clear_message();
-        self.cursor = (self.cursor + 1).min(self.branches.len() - 1);
+        self.cursor = (self.cursor + 1).min(self.rows.len() - 1);
     }
 
     pub fn move_up(&mut self) {
-        if self.branches.is_empty() {
+        if self.rows.is_empty() {
             return;
         }
         self.clear_message();
@@ -78,17 +604,343 @@ impl App {
         }
     }
 
+    pub fn page_down(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        self.clear_message();
+        self.cursor = (self.cursor + self.page_size).min(self.rows.len() - 1);
+    }
+
+    pub fn page_up(&mut self) {
+        self.clear_message();
+        self.cursor = self.cursor.saturating_sub(self.page_size);
+    }
+
+    pub fn move_to_top(&mut self) {
+        self.clear_message();
+        self.cursor = 0;
+    }
+
+    pub fn move_to_bottom(&mut self) {
+        self.clear_message();
+        self.cursor = self.rows.len().saturating_sub(1);
+    }
+
+    /// Record the list height and how many lines each row takes (empty for
+    /// one line apiece), and scroll just enough to keep the cursor on screen.
+    /// Called from `draw`; returns the offset to render from.
+    pub fn scroll_into_view(&mut self, height: usize, row_heights: Vec<usize>) -> usize {
+        let height = height.max(1);
+        self.row_heights = row_heights;
+        if self.cursor < self.scroll_offset {
+            self.scroll_offset = self.cursor;
+        }
+        while self.scroll_offset < self.cursor
+            && !self.fits(self.scroll_offset..self.cursor + 1, height)
+        {
+            self.scroll_offset += 1;
+        }
+        // Don't leave blank lines below the last row.
+        while self.scroll_offset > 0 && self.fits(self.scroll_offset - 1..self.rows.len(), height) {
+            self.scroll_offset -= 1;
+        }
+        let mut used = 0;
+        self.page_size = (self.scroll_offset..self.rows.len())
+            .take_while(|&index| {
+                used += self.row_height(index);
+                used <= height
+            })
+            .count()
+            .max(1);
+        self.scroll_offset
+    }
+
+    fn row_height(&self, index: usize) -> usize {
+        self.row_heights.get(index).copied().unwrap_or(1)
+    }
+
+    /// Whether `rows` take at most `height` lines together.
+    fn fits(&self, rows: std::ops::Range<usize>, height: usize) -> bool {
+        let mut used = 0;
+        rows.into_iter().all(|index| {
+            used += self.row_height(index);
+            used <= height
+        })
+    }
+
+    pub fn set_list_area(&mut self, area: Rect) {
+        self.list_area = area;
+    }
+
+    /// The row drawn at a terminal cell, with the cell's column inside the
+    /// list's borders when it is on the row's first line; None outside the
+    /// list or below its last row.
+    pub fn row_at(&self, column: u16, row: u16) -> Option<(usize, Option<u16>)> {
+        let area = self.list_area;
+        let inside =
+            column > area.x && column + 1 < area.right() && row > area.y && row + 1 < area.bottom();
+        if !inside {
+            return None;
+        }
+        let mut line = usize::from(row - area.y - 1);
+        for index in self.scroll_offset..self.rows.len() {
+            let height = self.row_height(index);
+            if line < height {
+                return Some((index, (line == 0).then_some(column - area.x - 1)));
+            }
+            line -= height;
+        }
+        None
+    }
+
+    /// Put the cursor on row `index`, e.g. one that was clicked.
+    pub fn move_to(&mut self, index: usize) {
+        if index < self.rows.len() {
+            self.clear_message();
+            self.cursor = index;
+        }
+    }
+
+    /// Start a visual range at the cursor, or drop the one in progress.
+    pub fn toggle_visual(&mut self) {
+        self.visual_anchor = match self.visual_anchor {
+            Some(_) => None,
+            None => Some(self.cursor),
+        };
+    }
+
+    pub fn is_visual(&self) -> bool {
+        self.visual_anchor.is_some()
+    }
+
+    /// Whether row `row` lies between the visual anchor and the cursor.
+    pub fn in_visual_range(&self, row: usize) -> bool {
+        self.visual_anchor.is_some_and(|anchor| {
+            (anchor.min(self.cursor)..=anchor.max(self.cursor)).contains(&row)
+        })
+    }
+
+    /// Toggle every branch in the visual range at once, including those in
+    /// collapsed groups it spans, and end the range. Like a group, the range
+    /// is selected unless all of it already is.
+    pub fn toggle_visual_range(&mut self) {
+        let Some(anchor) = self.visual_anchor.take() else {
+            return;
+        };
+        let range = anchor.min(self.cursor)..=anchor.max(self.cursor);
+        let mut indices = Vec::new();
+        for row in &self.rows[range] {
+            match row {
+                Row::Branch(index) => indices.push(*index),
+                Row::Group(prefix) if self.collapsed.contains(prefix) => {
+                    indices.extend(self.group_members(prefix));
+                }
+                Row::Group(_) => {}
+            }
+        }
+        self.toggle_selection_of(&indices);
+    }
+
     pub fn toggle_current(&mut self) {
-        if let Some(current) = self.branches.get_mut(self.cursor) {
-            current.selected = !current.selected;
+        let index = match self.rows.get(self.cursor) {
+            Some(Row::Branch(index)) => *index,
+            Some(Row::Group(prefix)) => {
+                let prefix = prefix.clone();
+                self.toggle_group_selection(&prefix);
+                return;
+            }
+            None => return,
+        };
+        let current = &mut self.branches[index];
+        if current.protected {
+            let message = match &current.info.worktree {
+                _ if current.current => format!(
+                    "'{}' is the current branch and cannot be deleted.",
+                    current.info.name
+                ),
+                Some(dir) => format!(
+                    "'{}' is checked out in the worktree at {} and cannot be selected.",
+                    current.info.name,
+                    dir.display()
+                ),
+                None => format!(
+                    "'{}' is protected and cannot be selected.",
+                    current.info.name
+                ),
+            };
+            self.set_message(message);
+            return;
+        }
+        current.selected = !current.selected;
+        if current.selected && !current.info.pushed {
+            let message = format!(
+                "'{}' is not on any remote; deleting it loses its only copy.",
+                current.info.name
+            );
+            self.set_message(message);
         }
     }
 
+    /// Toggle every visible, unprotected branch.
     pub fn toggle_all(&mut self) {
-        let all_selected = self.branches.iter().all(|branch| branch.selected);
+        let visible = self.visible.clone();
+        self.toggle_selection_of(&visible);
+    }
+
+    /// Select the whole group, or deselect it when it is already selected.
+    fn toggle_group_selection(&mut self, prefix: &str) {
+        let members: Vec<usize> = self.group_members(prefix).collect();
+        self.toggle_selection_of(&members);
+    }
+
+    fn toggle_selection_of(&mut self, indices: &[usize]) {
+        let all_selected = indices
+            .iter()
+            .map(|&index| &self.branches[index])
+            .filter(|branch| !branch.protected)
+            .all(|branch| branch.selected);
+        for &index in indices {
+            let branch = &mut self.branches[index];
+            if !branch.protected {
+                branch.selected = !all_selected;
+            }
+        }
+    }
+
+    /// Select every unprotected branch whose name is not in `keep`.
+    pub fn select_all_except(&mut self, keep: &HashSet<String>) {
         for branch in &mut self.branches {
-            branch.selected = !all_selected;
+            branch.selected = !branch.protected && !keep.contains(&branch.info.name);
+        }
+    }
+
+    /// Select every visible merged (or squash-merged) branch.
+    pub fn select_merged(&mut self) {
+        self.select_visible_where(|branch| branch.info.merged.is_merged());
+    }
+
+    /// Select every visible unmerged branch.
+    pub fn select_unmerged(&mut self) {
+        self.select_visible_where(|branch| !branch.info.merged.is_merged());
+    }
+
+    /// Flip the selection of every visible, unprotected branch.
+    pub fn invert_selection(&mut self) {
+        for &index in &self.visible {
+            let branch = &mut self.branches[index];
+            if !branch.protected {
+                branch.selected = !branch.selected;
+            }
+        }
+    }
+
+    fn select_visible_where(&mut self, predicate: impl Fn(&BranchItem) -> bool) {
+        for &index in &self.visible {
+            let branch = &mut self.branches[index];
+            if !branch.protected && predicate(branch) {
+                branch.selected = true;
+            }
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.clear_message();
+        self.searching = true;
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn search_push(&mut self, c: char) {
+        self.query.push(c);
+        self.apply_search();
+    }
+
+    pub fn search_pop(&mut self) {
+        self.query.pop();
+        self.apply_search();
+    }
+
+    /// Leave input mode, keeping the list narrowed to the query.
+    pub fn finish_search(&mut self) {
+        self.searching = false;
+    }
+
+    pub fn clear_search(&mut self) {
+        self.searching = false;
+        self.query.clear();
+        self.apply_search();
+    }
+
+    /// Recompute the visible rows, keeping the cursor on the same branch
+    /// when it still matches.
+    fn apply_search(&mut self) {
+        let current = self.cursor_branch();
+        self.refresh_visible(current);
+    }
+
+    /// Recompute `visible` and `rows` from the query and grouping, and put
+    /// the cursor on the row for `branches[current]` (its group header when
+    /// collapsed), or the first row when it is filtered out.
+    fn refresh_visible(&mut self, current: Option<usize>) {
+        // The range is in rows, which are about to change.
+        self.visual_anchor = None;
+        let needle = self.query.to_lowercase();
+        let (hide_merged, hide_unmerged) = (self.hide_merged, self.hide_unmerged);
+        self.visible = self
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(_, branch)| branch.info.name.to_lowercase().contains(&needle))
+            .filter(|(_, branch)| {
+                if branch.info.merged.is_merged() {
+                    !hide_merged
+                } else {
+                    !hide_unmerged
+                }
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        if !self.grouped {
+            self.rows = self.visible.iter().copied().map(Row::Branch).collect();
+        } else {
+            // Stable sort keeps the sort order within each group; branches
+            // without a prefix go last.
+            let branches = &self.branches;
+            self.visible.sort_by_key(|&index| {
+                let prefix = prefix_of(&branches[index].info.name);
+                (prefix.is_empty(), prefix.to_string())
+            });
+            self.rows.clear();
+            let mut last_prefix = None;
+            for &index in &self.visible {
+                let prefix = prefix_of(&self.branches[index].info.name);
+                if last_prefix != Some(prefix) {
+                    self.rows.push(Row::Group(prefix.to_string()));
+                    last_prefix = Some(prefix);
+                }
+                if !self.collapsed.contains(prefix) {
+                    self.rows.push(Row::Branch(index));
+                }
+            }
         }
+
+        self.cursor = current
+            .and_then(|index| {
+                let group = Row::Group(prefix_of(&self.branches[index].info.name).to_string());
+                self.rows
+                    .iter()
+                    .position(|row| *row == Row::Branch(index))
+                    .or_else(|| self.rows.iter().position(|row| *row == group))
+            })
+            .unwrap_or(0);
     }
 
     pub fn cancel(&mut self) {
@@ -96,14 +948,119 @@ impl App {
     }
 
     pub fn confirm(&mut self) {
-        if self.selected_count() == 0 {
-            self.set_message("Select at least one branch before confirming.");
+        let selection = self.selected_infos();
+        match confirm_step(&selection, self.force, self.confirm_threshold) {
+            Ok(step) => {
+                self.clear_message();
+                self.confirmation = Some(match step {
+                    ConfirmStep::TypeCount => Confirmation::ConfirmCount(String::new()),
+                    ConfirmStep::YesNo => Confirmation::YesNo,
+                });
+            }
+            Err(message) => self.set_message(message),
+        }
+    }
+
+    pub fn set_confirm_threshold(&mut self, threshold: usize) {
+        self.confirm_threshold = threshold;
+    }
+
+    pub fn is_confirming(&self) -> bool {
+        self.confirmation.is_some()
+    }
+
+    /// What has been typed so far when the selection's count must be typed.
+    pub fn typed_count(&self) -> Option<&str> {
+        match &self.confirmation {
+            Some(Confirmation::ConfirmCount(typed)) => Some(typed),
+            _ => None,
+        }
+    }
+
+    /// A key typed at the count prompt; anything but a digit is ignored.
+    pub fn type_count_digit(&mut self, ch: char) {
+        if let Some(Confirmation::ConfirmCount(typed)) = &mut self.confirmation
+            && ch.is_ascii_digit()
+            && typed.len() < 9
+        {
+            typed.push(ch);
+            self.message = None;
+        }
+    }
+
+    pub fn erase_count_digit(&mut self) {
+        if let Some(Confirmation::ConfirmCount(typed)) = &mut self.confirmation {
+            typed.pop();
+        }
+    }
+
+    /// Enter at the count prompt: delete the selection if the number typed
+    /// matches it, otherwise start over.
+    pub fn submit_count(&mut self) {
+        let count = self.selected_count();
+        let Some(Confirmation::ConfirmCount(typed)) = &mut self.confirmation else {
             return;
+        };
+        if typed.parse() == Ok(count) {
+            self.accept_confirmation();
+        } else {
+            typed.clear();
+            self.set_message(format!(
+                "That is not {count}; type the number of branches selected."
+            ));
         }
+    }
+
+    /// `y`, or the right count, on the confirmation screen: delete the
+    /// selection.
+    pub fn accept_confirmation(&mut self) {
+        self.confirmation = None;
         self.confirmed = true;
         self.should_quit = true;
     }
 
+    /// `n`/Esc on the confirmation screen: go back to the selector.
+    pub fn cancel_confirmation(&mut self) {
+        self.confirmation = None;
+        self.clear_message();
+    }
+
+    pub fn selected_items(&self) -> impl Iterator<Item = &BranchItem> {
+        self.branches.iter().filter(|branch| branch.selected)
+    }
+
+    /// `risk_warnings` for the current selection.
+    pub fn selected_risk_warnings(&self) -> Vec<String> {
+        risk_warnings(&self.selected_infos())
+    }
+
+    fn selected_infos(&self) -> Vec<&BranchInfo> {
+        self.selected_items().map(|branch| &branch.info).collect()
+    }
+
+    /// Selected branches contained in a branch that is not selected, as
+    /// `name (via other)`: their commits stay reachable.
+    pub fn selected_reachable_names(&self) -> Vec<String> {
+        self.branches
+            .iter()
+            .filter(|branch| branch.selected)
+            .filter_map(|branch| {
+                let keeper = branch.info.dependents.iter().find(|name| {
+                    !self
+                        .branches
+                        .iter()
+                        .any(|other| other.selected && other.info.name == **name)
+                })?;
+                Some(format!("{} (via {keeper})", branch.info.name))
+            })
+            .collect()
+    }
+
+    /// Selected branches that stashes were made on.
+    pub fn selected_stashed_names(&self) -> Vec<&str> {
+        stashed_names(&self.selected_infos())
+    }
+
     pub fn should_quit(&self) -> bool {
         self.should_quit
     }
@@ -116,8 +1073,29 @@ impl App {
         self.cursor
     }
 
-    pub fn items(&self) -> &[BranchItem] {
-        &self.branches
+    pub fn rows(&self) -> impl Iterator<Item = RowView<'_>> {
+        self.rows.iter().map(|row| match row {
+            Row::Branch(index) => RowView::Branch(&self.branches[*index]),
+            Row::Group(prefix) => {
+                let (count, selected) = self.group_members(prefix).fold((0, 0), |(n, s), index| {
+                    (n + 1, s + usize::from(self.branches[index].selected))
+                });
+                RowView::Group {
+                    prefix,
+                    count,
+                    selected,
+                    collapsed: self.collapsed.contains(prefix),
+                }
+            }
+        })
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.visible.len()
     }
 
     pub fn selected_count(&self) -> usize {
@@ -151,11 +1129,179 @@ impl App {
             .collect()
     }
 
-    pub fn base_branch(&self) -> &str {
-        &self.base_branch
+    pub fn bases(&self) -> &[String] {
+        &self.bases
     }
 
     pub fn current_branch(&self) -> &str {
         &self.current_branch
     }
+
+    pub fn set_filter_label(&mut self, label: String) {
+        self.filter_label = Some(label);
+    }
+
+    pub fn filter_label(&self) -> Option<&str> {
+        self.filter_label.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Oid;
+
+    use super::App;
+    use crate::git::{BranchInfo, MergeStatus};
+
+    fn branch(name: &str, merged: MergeStatus) -> BranchInfo {
+        BranchInfo {
+            name: name.to_string(),
+            tip: Oid::zero(),
+            summary: None,
+            committer: None,
+            commit_timestamp: None,
+            merged,
+            merged_into: None,
+            ahead: 0,
+            behind: 0,
+            merge_base: None,
+            remote: None,
+            is_remote_tracking: false,
+            worktree: None,
+            upstream: None,
+            pushed: true,
+            dependents: Vec::new(),
+            description: None,
+            stashes: 0,
+        }
+    }
+
+    /// Undated branches, so the default sort lists them by name.
+    fn app(names: &[(&str, MergeStatus)]) -> App {
+        let branches = names
+            .iter()
+            .map(|&(name, merged)| branch(name, merged))
+            .collect();
+        App::new(branches, vec!["main".to_string()], "main".to_string())
+    }
+
+    fn selected(app: &App) -> Vec<&str> {
+        app.selected_items()
+            .map(|branch| branch.info.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn app_visual_range_takes_in_collapsed_groups() {
+        let mut app = app(&[
+            ("feature/a", MergeStatus::Merged),
+            ("feature/b", MergeStatus::Merged),
+            ("fix/x", MergeStatus::Merged),
+            ("main", MergeStatus::Merged),
+        ]);
+        app.set_grouped(true);
+        // Rows: feature/ (collapsed), fix/, fix/x, (no prefix), main.
+        app.toggle_group();
+        assert_eq!(app.row_count(), 5);
+
+        app.toggle_visual();
+        app.move_down();
+        app.move_down();
+        assert!(app.in_visual_range(1));
+        assert!(!app.in_visual_range(3));
+        app.toggle_visual_range();
+        assert!(!app.is_visual());
+        assert_eq!(selected(&app), ["feature/a", "feature/b", "fix/x"]);
+    }
+
+    #[test]
+    fn app_visual_range_skips_protected_rows() {
+        let mut app = app(&[
+            ("alpha", MergeStatus::Merged),
+            ("beta", MergeStatus::Merged),
+            ("main", MergeStatus::Merged),
+            ("zeta", MergeStatus::Merged),
+        ]);
+        app.protect(&["beta".to_string()]);
+
+        app.toggle_visual();
+        app.move_to_bottom();
+        app.toggle_visual_range();
+        assert_eq!(selected(&app), ["alpha", "zeta"]);
+
+        // The protected rows don't count towards "all selected".
+        app.toggle_visual();
+        app.move_to_top();
+        app.toggle_visual_range();
+        assert!(selected(&app).is_empty());
+    }
+
+    #[test]
+    fn app_refresh_visible_drops_the_visual_anchor() {
+        let mut app = app(&[
+            ("alpha", MergeStatus::Merged),
+            ("beta", MergeStatus::Unmerged),
+        ]);
+        app.toggle_visual();
+        app.search_push('a');
+        assert!(!app.is_visual());
+
+        app.toggle_visual();
+        app.toggle_hide_merged();
+        assert!(!app.is_visual());
+        app.toggle_visual_range();
+        assert!(selected(&app).is_empty());
+    }
+
+    #[test]
+    fn app_hide_filters_keep_hidden_selections() {
+        let mut app = app(&[
+            ("alpha", MergeStatus::Merged),
+            ("beta", MergeStatus::SquashMerged),
+            ("gamma", MergeStatus::Unmerged),
+        ]);
+        app.toggle_current();
+        app.toggle_hide_merged();
+        assert_eq!(app.visible_count(), 1);
+        assert_eq!(app.hidden_label(), Some("merged"));
+        assert_eq!(selected(&app), ["alpha"]);
+
+        // Bulk actions only reach what is shown.
+        app.toggle_all();
+        assert_eq!(selected(&app), ["alpha", "gamma"]);
+
+        app.toggle_hide_unmerged();
+        assert_eq!(app.visible_count(), 0);
+        assert_eq!(app.hidden_label(), Some("merged and unmerged"));
+        app.toggle_hide_merged();
+        app.toggle_hide_unmerged();
+        assert_eq!(app.visible_count(), 3);
+        assert_eq!(app.hidden_label(), None);
+    }
+
+    #[test]
+    fn app_submit_count_needs_the_exact_number() {
+        let mut app = app(&[
+            ("alpha", MergeStatus::Merged),
+            ("beta", MergeStatus::Merged),
+            ("gamma", MergeStatus::Merged),
+        ]);
+        app.set_confirm_threshold(2);
+        app.toggle_all();
+        app.confirm();
+        assert_eq!(app.typed_count(), Some(""));
+
+        app.type_count_digit('x');
+        app.type_count_digit('2');
+        assert_eq!(app.typed_count(), Some("2"));
+        app.submit_count();
+        assert!(!app.confirmed());
+        assert_eq!(app.typed_count(), Some(""));
+        assert!(app.message().is_some());
+
+        app.type_count_digit('3');
+        app.submit_count();
+        assert!(app.confirmed());
+        assert!(!app.is_confirming());
+    }
 }
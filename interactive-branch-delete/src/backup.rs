@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use git2::{Oid, Repository};
+
+use crate::git::BranchInfo;
+use crate::json::{self, Value};
+
+/// Directory in the repository's Git directory that backups are written to,
+/// so they never show up as untracked files.
+pub const BACKUP_DIR: &str = "branch-delete-backups";
+
+/// Single backup file that older versions replaced on every run; still
+/// restored from when no newer backup exists.
+const LEGACY_BACKUP_FILE: &str = ".branch-delete-backup.json";
+
+/// The most recent backup: the newest file in `BACKUP_DIR`, or the legacy
+/// single file when that directory is empty.
+pub fn latest_backup(repo: &Repository) -> PathBuf {
+    let newest = fs::read_dir(repo.path().join(BACKUP_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .max();
+    newest.unwrap_or_else(|| repo.path().join(LEGACY_BACKUP_FILE))
+}
+
+/// Record the name and tip of each branch about to be deleted in a new
+/// timestamped file, keeping earlier backups, and return its path.
+pub fn write_backup(repo: &Repository, branches: &[BranchInfo]) -> Result<PathBuf> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let entries = branches.iter().map(|branch| {
+        Value::Object(BTreeMap::from([
+            ("name".to_string(), branch.name.as_str().into()),
+            ("ref".to_string(), branch.ref_name().into()),
+            ("tip".to_string(), branch.tip.to_string().into()),
+        ]))
+    });
+    let document = Value::Object(BTreeMap::from([
+        (
+            "repository".to_string(),
+            repo.path().display().to_string().into(),
+        ),
+        ("created".to_string(), (created as i64).into()),
+        ("branches".to_string(), Value::Array(entries.collect())),
+    ]));
+
+    let dir = repo.path().join(BACKUP_DIR);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create backup directory {}", dir.display()))?;
+    // Runs within the same second get increasing sequence numbers, so the
+    // names sort in the order the backups were taken
+    for seq in 1..1000 {
+        let path = dir.join(format!("backup-{created:010}-{seq:03}.json"));
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path);
+        match file {
+            Ok(mut file) => {
+                writeln!(file, "{document}").with_context(|| {
+                    format!("Failed to write branch backup to {}", path.display())
+                })?;
+                return Ok(path);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to write branch backup to {}", path.display())
+                });
+            }
+        }
+    }
+    bail!("Too many branch backups in {} this second", dir.display())
+}
+
+pub struct RestoreResult {
+    pub name: String,
+    pub tip: String,
+    pub error: Option<String>,
+}
+
+/// Recreate every branch listed in a backup file. Branches that already
+/// exist are left alone and reported as errors.
+pub fn restore(repo: &Repository, path: &Path) -> Result<Vec<RestoreResult>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read backup file {}", path.display()))?;
+    let document = json::parse(&contents)
+        .map_err(|err| anyhow!("Invalid backup file {}: {err}", path.display()))?;
+    let entries = document
+        .get("branches")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| anyhow!("Backup file {} has no \"branches\" list", path.display()))?;
+
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let (Some(name), Some(tip)) = (
+            entry.get("name").and_then(json::Value::as_str),
+            entry.get("tip").and_then(json::Value::as_str),
+        ) else {
+            continue;
+        };
+        let ref_name = entry
+            .get("ref")
+            .and_then(json::Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("refs/heads/{name}"));
+        results.push(restore_ref(repo, name, &ref_name, tip));
+    }
+    Ok(results)
+}
+
+/// Recreate branches deleted earlier in this session, at their tips as
+/// recorded before the deletion.
+pub fn restore_deleted(repo: &Repository, branches: &[&BranchInfo]) -> Vec<RestoreResult> {
+    branches
+        .iter()
+        .map(|branch| {
+            restore_ref(
+                repo,
+                &branch.name,
+                &branch.ref_name(),
+                &branch.tip.to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Point a new `ref_name` at `tip`; fails if the ref already exists.
+fn restore_ref(repo: &Repository, name: &str, ref_name: &str, tip: &str) -> RestoreResult {
+    let error = Oid::from_str(tip)
+        .and_then(|oid| repo.find_commit(oid))
+        .and_then(|commit| {
+            repo.reference(ref_name, commit.id(), false, "branch: restored from backup")
+        })
+        .err()
+        .map(|err| format!("Failed to restore '{name}' at {tip}: {}", err.message()));
+    RestoreResult {
+        name: name.to_string(),
+        tip: tip.to_string(),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::MergeStatus;
+
+    fn branch(name: &str, tip: Oid) -> BranchInfo {
+        BranchInfo {
+            name: name.to_string(),
+            tip,
+            summary: None,
+            committer: None,
+            commit_timestamp: None,
+            merged: MergeStatus::Merged,
+            merged_into: Some("main".to_string()),
+            ahead: 0,
+            behind: 0,
+            merge_base: None,
+            remote: None,
+            is_remote_tracking: false,
+            worktree: None,
+            upstream: None,
+            pushed: true,
+            dependents: Vec::new(),
+            description: None,
+            stashes: 0,
+        }
+    }
+
+    #[test]
+    fn backup_runs_keep_earlier_files_and_restore_the_newest() {
+        let dir = std::env::temp_dir().join(format!("ibd-backup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        let sig = git2::Signature::now("Tester", "t@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        let tip = repo
+            .commit(Some("refs/heads/main"), &sig, &sig, "root", &tree, &[])
+            .unwrap();
+
+        let first = write_backup(&repo, &[branch("first \"quoted\"", tip)]).unwrap();
+        let second = write_backup(&repo, &[branch("second", tip)]).unwrap();
+        assert_ne!(first, second);
+        assert!(first.exists() && second.exists());
+        assert_eq!(latest_backup(&repo), second);
+
+        let restored = restore(&repo, &first).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].name, "first \"quoted\"");
+        let restored = restore(&repo, &latest_backup(&repo)).unwrap();
+        assert_eq!(restored[0].name, "second");
+        assert!(restored[0].error.is_none());
+        assert!(repo.find_reference("refs/heads/second").is_ok());
+
+        drop(tree);
+        drop(repo);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
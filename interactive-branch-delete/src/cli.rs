@@ -1,7 +1,11 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Parser, ValueHint};
 
+use crate::app::{DEFAULT_CONFIRM_THRESHOLD, SortOrder};
+use crate::listing::{AgeFormat, ColorMode, ListFormat};
+
 #[derive(Debug, Parser)]
 #[command(
     name = "interactive-branch-delete",
@@ -14,19 +18,218 @@ pub struct Cli {
     #[arg(long, value_hint = ValueHint::DirPath)]
     pub repo: Option<PathBuf>,
 
-    /// Base branch to compare against.
+    /// Base branch to compare against; repeat or comma-separate for several
+    /// (a branch is merged when any base contains it). A remote-tracking
+    /// branch such as origin/main works too. Remembered for the repository
+    /// and used when the flag is left out.
+    #[arg(short, long = "base", value_name = "BRANCH", value_delimiter = ',')]
+    pub bases: Vec<String>,
+
+    /// Remote to inspect when resolving the default base branch (default:
+    /// origin, or the remote last given for this repository).
+    #[arg(short, long)]
+    pub remote: Option<String>,
+
+    /// Clear the base and remote remembered for this repository; --base and
+    /// --remote are remembered across runs.
+    #[arg(long)]
+    pub forget: bool,
+
+    /// Only show branches matching this glob, e.g. 'feature/*' (repeatable).
+    #[arg(long = "pattern", value_name = "GLOB")]
+    pub patterns: Vec<String>,
+
+    /// Hide branches matching this glob (repeatable).
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub excludes: Vec<String>,
+
+    /// Never allow selecting or deleting branches matching this glob (repeatable).
+    #[arg(long = "protect", value_name = "GLOB", default_values = ["main", "master", "develop"])]
+    pub protect: Vec<String>,
+
+    /// Recreate branches recorded in a backup file (defaults to the last run's backup) and exit.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, value_hint = ValueHint::FilePath)]
+    pub restore: Option<Option<PathBuf>>,
+
+    /// Only consider branches already merged into the base.
+    #[arg(long)]
+    pub merged_only: bool,
+
+    /// Delete exactly the branches named on stdin, one per line (e.g. piped from `git branch`).
+    #[arg(long, conflicts_with_all = ["yes", "list_only"])]
+    pub stdin: bool,
+
+    /// Delete every listed branch without opening the selector (for scripts and CI).
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Let --yes and --stdin delete while the working tree has uncommitted changes.
+    #[arg(long)]
+    pub allow_dirty: bool,
+
+    /// Only show branches whose last commit is older than this (e.g. 30d, 6mo, 1y).
+    #[arg(long, value_name = "DURATION", value_parser = parse_age)]
+    pub older_than: Option<Duration>,
+
+    /// Above this many selected branches, the selector asks for the count to
+    /// be typed instead of y.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CONFIRM_THRESHOLD)]
+    pub confirm_threshold: usize,
+
+    /// Select every listed branch except the N with the newest commits.
+    #[arg(long, value_name = "N", conflicts_with = "stdin")]
+    pub keep: Option<usize>,
+
+    /// With --older-than, also keep branches whose commit time is unknown.
+    #[arg(long, requires = "older_than")]
+    pub include_undated: bool,
+
+    /// Initial order of the selector's rows (press `s` to cycle).
+    #[arg(long, value_enum, default_value_t = SortOrder::Age)]
+    pub sort: SortOrder,
+
+    /// How ages are shown in the selector and the --list-only table: `short`
+    /// (`2d`, `3w`, `5mo`) or `long` (`2days 3h 4m 5s`).
+    #[arg(long, value_enum, default_value_t = AgeFormat::Long)]
+    pub age_format: AgeFormat,
+
+    /// Flip the selector's sort order, e.g. newest first for `age`; it stays
+    /// flipped as `s` cycles through the orders.
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Group the selector's rows under collapsible headers by the first
+    /// path segment of the branch name (`feature/`, `bugfix/`, ...).
+    #[arg(long)]
+    pub group_by_prefix: bool,
+
+    /// Print the --list-only table under a heading per committer, with the
+    /// number of branches (and unmerged ones) each has.
+    #[arg(long, requires = "list_only")]
+    pub group_by_author: bool,
+
+    /// In the --list-only table, add each unmerged branch's merge-base with
+    /// the base and how many commits only it has, and each branch's
+    /// description.
     #[arg(short, long)]
-    pub base: Option<String>,
+    pub verbose: bool,
 
-    /// Remote to inspect when resolving the default base branch.
-    #[arg(short, long, default_value = "origin")]
-    pub remote: String,
+    /// Key bindings file (defaults to ~/.config/interactive-branch-delete/keys.toml).
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub keys: Option<PathBuf>,
+
+    /// Selector colors: default, high-contrast, monochrome or a theme file
+    /// (defaults to ~/.config/interactive-branch-delete/theme.toml if present).
+    #[arg(long, value_name = "NAME|FILE")]
+    pub theme: Option<String>,
+
+    /// Show complete commit ids instead of 7-character abbreviations.
+    #[arg(long)]
+    pub full_hash: bool,
+
+    /// Give each branch two lines in the selector: name, status and age, then
+    /// its whole commit summary wrapped to the window.
+    #[arg(long)]
+    pub wide: bool,
 
     /// Only list merged branches without entering interactive deletion.
     #[arg(long)]
     pub list_only: bool,
 
+    /// Listing format; json and csv imply --list-only. With --yes or --stdin,
+    /// json reports the deletion results instead. With --dry-run, `script`
+    /// prints the deletion plan as git commands.
+    #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+    pub format: ListFormat,
+
+    /// Color the status column of the table listing: auto (when stdout is a
+    /// terminal and NO_COLOR is unset), always or never.
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    /// Run this shell command after deleting, with the deleted branch names
+    /// as its arguments and on stdin (skipped on --dry-run).
+    #[arg(long, value_name = "COMMAND")]
+    pub after_delete: Option<String>,
+
+    /// Show a desktop notification with the number of branches deleted and
+    /// failed when the run finishes.
+    #[arg(long)]
+    pub notify: bool,
+
     /// Show what would happen without deleting.
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Allow deleting branches that are not merged into the base (asks twice).
+    #[arg(long)]
+    pub force: bool,
+
+    /// Also delete each branch's remote-tracking ref (its upstream, or `<remote>/<name>`).
+    #[arg(long)]
+    pub include_remote: bool,
+
+    /// Tag each branch's tip as <PREFIX><name> (e.g. archive/) before deleting it.
+    #[arg(long, value_name = "PREFIX")]
+    pub archive_prefix: Option<String>,
+
+    /// Recheck every branch's merge status instead of reusing results cached
+    /// in the Git directory for unchanged branch and base tips.
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Also offer remote-tracking refs whose branch is gone from the remote.
+    #[arg(long)]
+    pub prune_remotes: bool,
+
+    /// With --include-remote, also push the deletion to the remote.
+    #[arg(long, requires = "include_remote")]
+    pub push_delete: bool,
+}
+
+/// humantime duration that also accepts `mo` for months (`6mo`).
+fn parse_age(value: &str) -> Result<Duration, String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut normalized = String::with_capacity(value.len() + 8);
+    let mut i = 0;
+    while i < chars.len() {
+        let after_digit = i > 0 && chars[i - 1].is_ascii_digit();
+        let at_unit_end = chars.get(i + 2).is_none_or(|c| !c.is_alphabetic());
+        if after_digit && chars[i] == 'm' && chars.get(i + 1) == Some(&'o') && at_unit_end {
+            normalized.push_str("months");
+            i += 2;
+        } else {
+            normalized.push(chars[i]);
+            i += 1;
+        }
+    }
+    humantime::parse_duration(&normalized).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn cli_parse_age_reads_mo_as_months_and_m_as_minutes() {
+        let month = humantime::parse_duration("1month").unwrap();
+        assert_eq!(parse_age("6mo").unwrap(), month * 6);
+        assert_eq!(
+            parse_age("1mo 2w").unwrap(),
+            month + Duration::from_secs(14 * DAY)
+        );
+        assert_eq!(parse_age("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_age("2months").unwrap(), month * 2);
+        assert_eq!(parse_age("90d").unwrap(), Duration::from_secs(90 * DAY));
+    }
+
+    #[test]
+    fn cli_parse_age_rejects_bad_input() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("mo").is_err());
+        assert!(parse_age("6moo").is_err());
+        assert!(parse_age("soon").is_err());
+    }
 }
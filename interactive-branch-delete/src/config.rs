@@ -0,0 +1,39 @@
+//! Optional config files under `~/.config/interactive-branch-delete/`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::json::Value;
+use crate::toml;
+
+const APP_DIR: &str = "interactive-branch-delete";
+
+/// `$XDG_CONFIG_HOME/interactive-branch-delete/<name>`, falling back to
+/// `~/.config`. None when neither variable is set.
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join(APP_DIR).join(name))
+}
+
+/// Read a TOML file given explicitly with a flag, or the default one when it
+/// exists. Returns None when there is nothing to load.
+pub fn load(explicit: Option<&Path>, default_name: &str) -> Result<Option<(PathBuf, Value)>> {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => match config_file(default_name) {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(None),
+        },
+    };
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let document = toml::parse(&contents)
+        .map_err(|err| anyhow!("Invalid config file {}: {err}", path.display()))?;
+    Ok(Some((path, document)))
+}
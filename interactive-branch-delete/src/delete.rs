@@ -1,12 +1,45 @@
+use std::collections::{BTreeMap, HashSet};
+
 use anyhow::Result;
-use git2::{BranchType, Repository};
+use git2::{BranchType, PushOptions, Repository};
 
-use crate::git::BranchInfo;
+use crate::filter::matches_any;
+use crate::git::{BranchInfo, RemoteBranch, remote_callbacks};
+use crate::json::Value;
 
 #[derive(Debug, Clone)]
 pub struct DeleteResult {
     pub name: String,
     pub status: DeleteStatus,
+    pub remote: Option<RemoteDeleteResult>,
+    /// Tag created (or, in a dry run, planned) at the tip by `--archive-prefix`.
+    pub archive_tag: Option<String>,
+}
+
+impl DeleteResult {
+    /// `{name, status, error, remote, archive_tag}` for `--format json`.
+    pub fn to_json(&self) -> Value {
+        let mut object = self.status.to_json();
+        object.insert("name".to_string(), self.name.as_str().into());
+        object.insert(
+            "archive_tag".to_string(),
+            self.archive_tag.as_deref().into(),
+        );
+        let remote = self.remote.as_ref().map_or(Value::Null, |remote| {
+            let mut remote_object = remote.status.to_json();
+            remote_object.insert("name".to_string(), remote.name.as_str().into());
+            remote_object.insert(
+                "push".to_string(),
+                remote
+                    .push
+                    .as_ref()
+                    .map_or(Value::Null, |push| Value::Object(push.to_json())),
+            );
+            Value::Object(remote_object)
+        });
+        object.insert("remote".to_string(), remote);
+        Value::Object(object)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -16,40 +49,443 @@ pub enum DeleteStatus {
     Error(String),
 }
 
+impl DeleteStatus {
+    /// `deleted`, `dry_run` or `error`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeleteStatus::Deleted => "deleted",
+            DeleteStatus::DryRun => "dry_run",
+            DeleteStatus::Error(_) => "error",
+        }
+    }
+
+    /// `status` plus `error`, the message when it failed.
+    fn to_json(&self) -> BTreeMap<String, Value> {
+        let error = match self {
+            DeleteStatus::Error(message) => Value::from(message.as_str()),
+            _ => Value::Null,
+        };
+        BTreeMap::from([
+            ("status".to_string(), self.as_str().into()),
+            ("error".to_string(), error),
+        ])
+    }
+}
+
+/// Outcome for a branch's remote counterpart when `--include-remote` is set.
+#[derive(Debug, Clone)]
+pub struct RemoteDeleteResult {
+    /// `<remote>/<branch>`.
+    pub name: String,
+    /// Removal of the local remote-tracking ref.
+    pub status: DeleteStatus,
+    /// Deletion pushed to the remote itself (`--push-delete` only).
+    pub push: Option<DeleteStatus>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeleteOptions {
+    pub dry_run: bool,
+    pub force: bool,
+    pub include_remote: bool,
+    pub push_delete: bool,
+    /// Globs for branches that must never be deleted.
+    pub protect: Vec<String>,
+    /// Tag each branch's tip as `<prefix><name>` before deleting it.
+    pub archive_prefix: Option<String>,
+}
+
 pub fn delete_branches(
     repo: &Repository,
     branches: &[BranchInfo],
-    dry_run: bool,
+    options: &DeleteOptions,
 ) -> Result<Vec<DeleteResult>> {
+    Ok(delete_branches_with_progress(
+        repo,
+        branches,
+        options,
+        |_| {},
+    ))
+}
+
+/// Like `delete_branches`, calling `progress` as each branch is done.
+pub fn delete_branches_with_progress(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    options: &DeleteOptions,
+    mut progress: impl FnMut(&DeleteResult),
+) -> Vec<DeleteResult> {
     let mut results = Vec::with_capacity(branches.len());
+    for branch in leaves_first(branches) {
+        let result = delete_branch(repo, branch, options);
+        progress(&result);
+        results.push(result);
+    }
+    results
+}
 
-    for branch in branches {
-        if dry_run {
-            results.push(DeleteResult {
-                name: branch.name.clone(),
-                status: DeleteStatus::DryRun,
-            });
-            continue;
+fn delete_branch(repo: &Repository, branch: &BranchInfo, options: &DeleteOptions) -> DeleteResult {
+    if matches_any(&options.protect, &branch.name) {
+        return DeleteResult {
+            name: branch.name.clone(),
+            status: DeleteStatus::Error(format!(
+                "Refused to delete protected branch '{}'",
+                branch.name
+            )),
+            remote: None,
+            archive_tag: None,
+        };
+    }
+
+    if let Some(dir) = &branch.worktree {
+        return DeleteResult {
+            name: branch.name.clone(),
+            status: DeleteStatus::Error(format!(
+                "Refused to delete '{}'; it is checked out in the worktree at {}",
+                branch.name,
+                dir.display()
+            )),
+            remote: None,
+            archive_tag: None,
+        };
+    }
+
+    if !branch.merged.is_merged() && !options.force {
+        return DeleteResult {
+            name: branch.name.clone(),
+            status: DeleteStatus::Error(format!(
+                "Skipped unmerged branch '{}'; use --force to delete it",
+                branch.name
+            )),
+            remote: None,
+            archive_tag: None,
+        };
+    }
+
+    let archive_tag = match &options.archive_prefix {
+        Some(prefix) => {
+            let tag = format!("{prefix}{}", branch.name);
+            if !options.dry_run
+                && let Err(err) = archive(repo, branch, &tag)
+            {
+                return DeleteResult {
+                    name: branch.name.clone(),
+                    status: DeleteStatus::Error(format!(
+                        "Kept '{}': failed to create archive tag '{tag}': {err}",
+                        branch.name
+                    )),
+                    remote: None,
+                    archive_tag: None,
+                };
+            }
+            Some(tag)
+        }
+        None => None,
+    };
+
+    let delete_status = if options.dry_run {
+        DeleteStatus::DryRun
+    } else if branch.is_remote_tracking {
+        delete_tracking_ref(repo, branch)
+    } else {
+        delete_local(repo, &branch.name)
+    };
+
+    // The remote copy goes only after the local branch has, so a local
+    // delete that fails does not leave a branch that exists only locally
+    let remote = match &branch.remote {
+        Some(remote)
+            if options.include_remote && !matches!(delete_status, DeleteStatus::Error(_)) =>
+        {
+            Some(delete_remote_branch(repo, remote, options))
+        }
+        _ => None,
+    };
+
+    DeleteResult {
+        name: branch.name.clone(),
+        status: delete_status,
+        remote,
+        archive_tag,
+    }
+}
+
+/// Order `branches` so each comes before any selected branch it descends
+/// from. Along a chain the number of selected dependents strictly shrinks,
+/// so sorting by it is a topological order.
+fn leaves_first(branches: &[BranchInfo]) -> Vec<&BranchInfo> {
+    let selected: HashSet<&str> = branches.iter().map(|b| b.name.as_str()).collect();
+    let mut ordered: Vec<&BranchInfo> = branches.iter().collect();
+    ordered.sort_by_key(|branch| {
+        branch
+            .dependents
+            .iter()
+            .filter(|name| selected.contains(name.as_str()))
+            .count()
+    });
+    ordered
+}
+
+/// Point a lightweight tag at the branch tip so its commits stay reachable.
+fn archive(repo: &Repository, branch: &BranchInfo, tag: &str) -> Result<(), git2::Error> {
+    let tip = repo.find_object(branch.tip, None)?;
+    repo.tag_lightweight(tag, &tip, false)?;
+    Ok(())
+}
+
+/// Delete through libgit2's branch API, which also drops the branch's
+/// `branch.<name>.*` config. Whether an unmerged branch may go is decided
+/// by `--force` before this point.
+fn delete_local(repo: &Repository, name: &str) -> DeleteStatus {
+    match repo.find_branch(name, BranchType::Local) {
+        Ok(mut local_branch) => match local_branch.delete() {
+            Ok(_) => DeleteStatus::Deleted,
+            Err(err) => DeleteStatus::Error(format!("Failed to delete branch '{name}': {err}")),
+        },
+        Err(err) => DeleteStatus::Error(format!(
+            "Failed to locate branch '{name}' before deletion: {err}"
+        )),
+    }
+}
+
+/// Remove a stale remote-tracking ref offered by `--prune-remotes`.
+fn delete_tracking_ref(repo: &Repository, branch: &BranchInfo) -> DeleteStatus {
+    let name = &branch.name;
+    match repo.find_reference(&branch.ref_name()) {
+        Ok(mut reference) => match reference.delete() {
+            Ok(()) => DeleteStatus::Deleted,
+            Err(err) => DeleteStatus::Error(format!(
+                "Failed to delete remote-tracking branch '{name}': {err}"
+            )),
+        },
+        Err(err) => DeleteStatus::Error(format!(
+            "Failed to locate remote-tracking branch '{name}': {err}"
+        )),
+    }
+}
+
+fn delete_remote_branch(
+    repo: &Repository,
+    remote: &RemoteBranch,
+    options: &DeleteOptions,
+) -> RemoteDeleteResult {
+    let name = remote.display_name();
+    if options.dry_run {
+        return RemoteDeleteResult {
+            name,
+            status: DeleteStatus::DryRun,
+            push: options.push_delete.then_some(DeleteStatus::DryRun),
+        };
+    }
+
+    // Push first: a successful push also prunes the tracking ref, and a
+    // failed one leaves the tracking ref in place since the branch still exists.
+    let push = if options.push_delete {
+        match push_delete(repo, remote) {
+            Ok(()) => Some(DeleteStatus::Deleted),
+            Err(err) => {
+                let error = format!("Failed to delete '{name}' on the remote: {err}");
+                return RemoteDeleteResult {
+                    name,
+                    status: DeleteStatus::Error(error.clone()),
+                    push: Some(DeleteStatus::Error(error)),
+                };
+            }
         }
+    } else {
+        None
+    };
 
-        let delete_status = match repo.find_branch(&branch.name, BranchType::Local) {
-            Ok(mut local_branch) => match local_branch.delete() {
-                Ok(_) => DeleteStatus::Deleted,
-                Err(err) => {
-                    DeleteStatus::Error(format!("Failed to delete branch '{}': {err}", branch.name))
-                }
-            },
+    let status = match repo.find_reference(&remote.tracking_ref()) {
+        Ok(mut reference) => match reference.delete() {
+            Ok(()) => DeleteStatus::Deleted,
             Err(err) => DeleteStatus::Error(format!(
-                "Failed to locate branch '{}' before deletion: {err}",
-                branch.name
+                "Failed to delete remote-tracking branch '{name}': {err}"
             )),
+        },
+        Err(_) if matches!(push, Some(DeleteStatus::Deleted)) => DeleteStatus::Deleted,
+        Err(err) => DeleteStatus::Error(format!(
+            "Failed to locate remote-tracking branch '{name}': {err}"
+        )),
+    };
+
+    RemoteDeleteResult { name, status, push }
+}
+
+/// Push `:refs/heads/<branch>` to delete the branch on the remote.
+fn push_delete(repo: &Repository, remote: &RemoteBranch) -> Result<(), git2::Error> {
+    let mut git_remote = repo.find_remote(&remote.remote)?;
+    let config = repo.config()?;
+    let mut rejection = None;
+
+    let mut callbacks = remote_callbacks(config);
+    callbacks.push_update_reference(|_, status| {
+        if let Some(message) = status {
+            rejection = Some(message.to_string());
+        }
+        Ok(())
+    });
+
+    let mut push_options = PushOptions::new();
+    push_options.remote_callbacks(callbacks);
+    git_remote.push(
+        &[format!(":refs/heads/{}", remote.branch)],
+        Some(&mut push_options),
+    )?;
+    drop(push_options);
+
+    match rejection {
+        Some(message) => Err(git2::Error::from_str(&message)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::MergeStatus;
+
+    /// Repository with `main` checked out and a `topic` branch, both with an
+    /// `origin/<name>` tracking ref. Removed on drop.
+    struct Scratch {
+        repo: Repository,
+        dir: std::path::PathBuf,
+    }
+
+    impl Scratch {
+        fn new(label: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("ibd-delete-{label}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let repo = Repository::init(&dir).unwrap();
+            let sig = git2::Signature::now("Tester", "t@example.com").unwrap();
+            let tree = repo
+                .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+                .unwrap();
+            let tip = repo
+                .commit(Some("refs/heads/main"), &sig, &sig, "root", &tree, &[])
+                .unwrap();
+            repo.set_head("refs/heads/main").unwrap();
+            for name in ["main", "topic"] {
+                repo.reference(&format!("refs/heads/{name}"), tip, true, "")
+                    .unwrap();
+                repo.reference(&format!("refs/remotes/origin/{name}"), tip, true, "")
+                    .unwrap();
+            }
+            drop(tree);
+            Self { repo, dir }
+        }
+
+        fn branch(&self, name: &str) -> BranchInfo {
+            BranchInfo {
+                name: name.to_string(),
+                tip: self.repo.refname_to_id("refs/heads/main").unwrap(),
+                summary: None,
+                committer: None,
+                commit_timestamp: None,
+                merged: MergeStatus::Merged,
+                merged_into: Some("main".to_string()),
+                ahead: 0,
+                behind: 0,
+                merge_base: None,
+                remote: Some(RemoteBranch {
+                    remote: "origin".to_string(),
+                    branch: name.to_string(),
+                }),
+                is_remote_tracking: false,
+                worktree: None,
+                upstream: None,
+                pushed: true,
+                dependents: Vec::new(),
+                description: None,
+                stashes: 0,
+            }
+        }
+
+        fn has_ref(&self, name: &str) -> bool {
+            self.repo.find_reference(name).is_ok()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn delete_unmerged_needs_force_and_drops_branch_config() {
+        let scratch = Scratch::new("force");
+        let mut config = scratch.repo.config().unwrap();
+        config.set_str("branch.topic.remote", "origin").unwrap();
+        let mut topic = scratch.branch("topic");
+        topic.merged = MergeStatus::Unmerged;
+
+        let results = delete_branches(
+            &scratch.repo,
+            std::slice::from_ref(&topic),
+            &DeleteOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(&results[0].status, DeleteStatus::Error(e) if e.contains("--force")));
+        assert!(scratch.has_ref("refs/heads/topic"));
+
+        let force = DeleteOptions {
+            force: true,
+            ..DeleteOptions::default()
         };
+        let results = delete_branches(&scratch.repo, &[topic], &force).unwrap();
+        assert!(matches!(results[0].status, DeleteStatus::Deleted));
+        assert!(!scratch.has_ref("refs/heads/topic"));
+        let config = scratch.repo.config().unwrap().snapshot().unwrap();
+        assert!(config.get_str("branch.topic.remote").is_err());
+    }
 
-        results.push(DeleteResult {
-            name: branch.name.clone(),
-            status: delete_status,
-        });
+    #[test]
+    fn delete_remote_is_left_alone_when_the_local_delete_fails() {
+        let scratch = Scratch::new("order");
+        let options = DeleteOptions {
+            include_remote: true,
+            ..DeleteOptions::default()
+        };
+
+        // `main` is checked out, so git refuses to delete it
+        let results = delete_branches(
+            &scratch.repo,
+            &[scratch.branch("main"), scratch.branch("topic")],
+            &options,
+        )
+        .unwrap();
+        assert!(matches!(results[0].status, DeleteStatus::Error(_)));
+        assert!(results[0].remote.is_none());
+        assert!(scratch.has_ref("refs/heads/main"));
+        assert!(scratch.has_ref("refs/remotes/origin/main"));
+
+        assert!(matches!(results[1].status, DeleteStatus::Deleted));
+        let remote = results[1].remote.as_ref().unwrap();
+        assert!(matches!(remote.status, DeleteStatus::Deleted));
+        assert!(!scratch.has_ref("refs/heads/topic"));
+        assert!(!scratch.has_ref("refs/remotes/origin/topic"));
     }
 
-    Ok(results)
+    #[test]
+    fn delete_results_serialize_status_and_error() {
+        let failed = DeleteResult {
+            name: "topic".to_string(),
+            status: DeleteStatus::Error("Skipped unmerged branch 'topic'".to_string()),
+            remote: Some(RemoteDeleteResult {
+                name: "origin/topic".to_string(),
+                status: DeleteStatus::DryRun,
+                push: None,
+            }),
+            archive_tag: None,
+        };
+        assert_eq!(
+            failed.to_json().to_string(),
+            "{\"archive_tag\":null,\"error\":\"Skipped unmerged branch 'topic'\",\"name\":\"topic\",\
+             \"remote\":{\"error\":null,\"name\":\"origin/topic\",\"push\":null,\"status\":\"dry_run\"},\
+             \"status\":\"error\"}"
+        );
+        assert_eq!(DeleteStatus::Deleted.as_str(), "deleted");
+    }
 }
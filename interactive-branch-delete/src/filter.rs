@@ -0,0 +1,164 @@
+/// Branch-name filter built from `--pattern` and `--exclude` globs.
+#[derive(Debug, Clone, Default)]
+pub struct BranchFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl BranchFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// A name passes when it matches any include pattern (or there are none)
+    /// and no exclude pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let included = self.include.is_empty() || matches_any(&self.include, name);
+        included && !matches_any(&self.exclude, name)
+    }
+
+    /// Short description for titles, e.g. `feature/* !feature/wip-*`.
+    pub fn describe(&self) -> String {
+        self.include
+            .iter()
+            .cloned()
+            .chain(self.exclude.iter().map(|p| format!("!{p}")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Shell-style glob match: `*` matches any run of characters (including `/`,
+/// as `git branch --list` does), `?` matches one character, and `[abc]` /
+/// `[a-z]` / `[!abc]` match a character class.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position to resume from after the last `*`: (pattern index, name index).
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, next)) = match_class(&pattern, p, name[n]) {
+                    if matched {
+                        p = next;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    // Unterminated class: treat `[` literally.
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((star_p, star_n)) => {
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p;
+                n = star_n + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the class starting at `pattern[start] == '['`. Returns
+/// whether it matched and the index just past the closing `]`, or None when
+/// the class is unterminated.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let &current = pattern.get(i)?;
+        if current == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-')
+            && let Some(&end) = pattern.get(i + 2)
+            && end != ']'
+        {
+            matched |= (current..=end).contains(&c);
+            i += 3;
+        } else {
+            matched |= current == c;
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_glob_star_and_question_mark() {
+        assert!(glob_match("feature/*", "feature/login"));
+        // `*` crosses `/`, as in `git branch --list`
+        assert!(glob_match("feature/*", "feature/auth/oauth"));
+        assert!(glob_match("*-wip", "fix-42-wip"));
+        assert!(glob_match("a*b*c", "a-x-b-y-c"));
+        assert!(!glob_match("a*b*c", "a-x-b-y"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("v?.?", "v1.2"));
+        assert!(!glob_match("v?.?", "v1.22"));
+        assert!(!glob_match("v?", "v"));
+        assert!(!glob_match("main", "main2"));
+    }
+
+    #[test]
+    fn filter_glob_character_classes() {
+        assert!(glob_match("release-[0-9]", "release-7"));
+        assert!(!glob_match("release-[0-9]", "release-x"));
+        assert!(glob_match("[a-z]*", "hotfix"));
+        assert!(!glob_match("[a-z]*", "Hotfix"));
+        assert!(glob_match("[!x]y", "ay"));
+        assert!(!glob_match("[!x]y", "xy"));
+        assert!(!glob_match("[^x]y", "xy"));
+        // A leading `]` is part of the class, not its end
+        assert!(glob_match("[]a]", "]"));
+        assert!(glob_match("[ab-]", "-"));
+    }
+
+    #[test]
+    fn filter_glob_unterminated_class_is_literal() {
+        assert!(glob_match("fix[1", "fix[1"));
+        assert!(!glob_match("fix[1", "fix1"));
+        assert!(glob_match("*[", "topic["));
+    }
+}
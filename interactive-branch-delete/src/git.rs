@@ -1,8 +1,16 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result, anyhow};
-use git2::{BranchType, Oid, Repository};
+use anyhow::{Context, Result, anyhow, bail};
+use git2::{
+    Branch, BranchType, Commit, Cred, CredentialType, Direction, Oid, Reference, RemoteCallbacks,
+    Repository, Status, StatusOptions,
+};
+
+use crate::merge_cache::MergeCache;
 
 #[derive(Debug, Clone)]
 pub struct BranchInfo {
@@ -11,41 +19,174 @@ pub struct BranchInfo {
     pub summary: Option<String>,
     pub committer: Option<String>,
     pub commit_timestamp: Option<i64>,
-    pub merged: bool,
+    pub merged: MergeStatus,
+    /// Base that contains the branch (or its patches) when it is merged.
+    pub merged_into: Option<String>,
+    /// Commits on the branch that are not on the base.
+    pub ahead: usize,
+    /// Commits on the base that are not on the branch.
+    pub behind: usize,
+    /// For unmerged branches, where the branch forked from the first base;
+    /// None when they share no history (or the branch is merged).
+    pub merge_base: Option<Oid>,
+    pub remote: Option<RemoteBranch>,
+    /// A remote-tracking ref (`<remote>/<branch>`) whose branch is gone from
+    /// the remote, offered by `--prune-remotes`.
+    pub is_remote_tracking: bool,
+    /// Worktree the branch is checked out in; such branches cannot be deleted.
+    pub worktree: Option<PathBuf>,
+    /// State of the configured upstream; None when there is none.
+    pub upstream: Option<UpstreamStatus>,
+    /// The tip is reachable from some remote-tracking ref, so the commits
+    /// survive the branch's deletion somewhere else.
+    pub pushed: bool,
+    /// Other local branches whose history contains this branch's tip, so its
+    /// commits stay reachable after deleting it.
+    pub dependents: Vec<String>,
+    /// `branch.<name>.description`, as set by `git branch --edit-description`.
+    pub description: Option<String>,
+    /// Stashes made while the branch was checked out, going by their
+    /// messages; deleting the branch loses track of what they belonged to.
+    pub stashes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamStatus {
+    /// Configured, but the remote-tracking ref no longer exists: usually the
+    /// branch was merged and deleted on the server.
+    Gone,
+    /// Commits ahead of and behind the upstream.
+    Tracking { ahead: usize, behind: usize },
 }
 
+impl UpstreamStatus {
+    /// Compact indicator, e.g. `[gone]` or `[ahead 2, behind 1]`.
+    pub fn label(self) -> String {
+        match self {
+            UpstreamStatus::Gone => "[gone]".to_string(),
+            UpstreamStatus::Tracking {
+                ahead: 0,
+                behind: 0,
+            } => "[up to date]".to_string(),
+            UpstreamStatus::Tracking { ahead, behind: 0 } => format!("[ahead {ahead}]"),
+            UpstreamStatus::Tracking { ahead: 0, behind } => format!("[behind {behind}]"),
+            UpstreamStatus::Tracking { ahead, behind } => {
+                format!("[ahead {ahead}, behind {behind}]")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStatus {
+    /// The tip is reachable from the base.
+    Merged,
+    /// Not reachable, but the branch's changes already landed in the base as
+    /// an equivalent patch (squash merge, rebase or cherry-pick).
+    SquashMerged,
+    Unmerged,
+}
+
+impl MergeStatus {
+    /// Whether the branch's work is already in the base either way.
+    pub fn is_merged(self) -> bool {
+        self != MergeStatus::Unmerged
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MergeStatus::Merged => "merged",
+            MergeStatus::SquashMerged => "squash-merged",
+            MergeStatus::Unmerged => "unmerged",
+        }
+    }
+}
+
+/// Remote-tracking counterpart of a local branch.
+#[derive(Debug, Clone)]
+pub struct RemoteBranch {
+    pub remote: String,
+    /// Branch name on the remote side (without `refs/heads/`).
+    pub branch: String,
+}
+
+impl RemoteBranch {
+    pub fn tracking_ref(&self) -> String {
+        format!("refs/remotes/{}/{}", self.remote, self.branch)
+    }
+
+    pub fn display_name(&self) -> String {
+        format!("{}/{}", self.remote, self.branch)
+    }
+}
+
+/// Open the repository at or above `path`. Without a path, `GIT_DIR` and
+/// `GIT_WORK_TREE` are honored the way git does, falling back to searching
+/// up from the current directory.
 pub fn open_repository(path: Option<&Path>) -> Result<Repository> {
     match path {
         Some(dir) => Repository::discover(dir)
             .with_context(|| format!("Failed to discover a Git repository from {}", dir.display())),
-        None => Repository::discover(".")
-            .context("Failed to discover a Git repository from current directory"),
+        None => Repository::open_from_env()
+            .context("Failed to discover a Git repository from current directory (or GIT_DIR)"),
     }
 }
 
+/// The checked-out branch. Errors describe why there is none: a bare
+/// repository, a detached HEAD, or a branch without commits yet.
 pub fn current_branch_name(repo: &Repository) -> Result<String> {
-    let head = repo.head().context("Failed to resolve HEAD")?;
+    if repo.is_bare() {
+        bail!("bare repository, so no branch is checked out");
+    }
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(err) if err.code() == git2::ErrorCode::UnbornBranch => {
+            bail!("HEAD names a branch with no commits yet")
+        }
+        Err(err) => return Err(err).context("Failed to resolve HEAD"),
+    };
     if head.is_branch() {
         head.shorthand()
             .map(|s| s.to_string())
             .context("HEAD does not have a branch shorthand")
     } else {
-        Err(anyhow!("Detached HEAD state; please checkout a branch"))
+        Err(anyhow!("HEAD is detached, so no branch is checked out"))
     }
 }
 
+/// Stand-in for the current branch name in titles when there is none.
+pub fn head_label(repo: &Repository) -> &'static str {
+    if repo.is_bare() {
+        "(bare)"
+    } else if repo.head_detached().unwrap_or(false) {
+        "(detached)"
+    } else {
+        "(none)"
+    }
+}
+
+/// Pick the base branch: the default branch of the current branch's upstream
+/// remote, then that of `remote`, then a local `main` or `master`, then the
+/// current branch itself.
 pub fn resolve_base_branch(
     repo: &Repository,
     remote: &str,
     current_branch: Option<&str>,
 ) -> Result<String> {
-    let remote_head = format!("refs/remotes/{remote}/HEAD");
-    if let Ok(reference) = repo.find_reference(&remote_head) {
-        if let Some(target) = reference.symbolic_target() {
-            if let Some(stripped) = target.strip_prefix(&format!("refs/remotes/{remote}/")) {
-                return Ok(stripped.to_string());
-            }
-        }
+    // On a fork the current branch often tracks `upstream` rather than `origin`.
+    let tracked_remote = current_branch.and_then(|name| {
+        repo.branch_upstream_remote(&format!("refs/heads/{name}"))
+            .ok()
+            .and_then(|buf| buf.as_str().map(str::to_string))
+    });
+    if let Some(tracked) = tracked_remote.as_deref()
+        && let Some(branch) = remote_default_branch(repo, tracked)
+    {
+        return Ok(branch);
+    }
+
+    if let Some(branch) = remote_default_branch(repo, remote) {
+        return Ok(branch);
     }
 
     for candidate in ["refs/heads/main", "refs/heads/master"] {
@@ -57,32 +198,181 @@ pub fn resolve_base_branch(
     if let Some(branch) = current_branch {
         Ok(branch.to_string())
     } else {
+        let tracked = tracked_remote
+            .map(|name| format!("{name}/HEAD, "))
+            .unwrap_or_default();
         Err(anyhow!(
-            "Unable to determine a base branch. Specify one with --base."
+            "Unable to determine a base branch (tried {tracked}{remote}/HEAD, main, master \
+             and the current branch). Specify one with --base."
         ))
     }
 }
 
-pub fn ensure_local_branch(repo: &Repository, name: &str) -> Result<()> {
-    let reference_name = format!("refs/heads/{name}");
-    repo.find_reference(&reference_name)
-        .with_context(|| format!("Local branch '{name}' not found"))?
+/// Branch that `refs/remotes/<remote>/HEAD` points at, if it is set.
+fn remote_default_branch(repo: &Repository, remote: &str) -> Option<String> {
+    let reference = repo
+        .find_reference(&format!("refs/remotes/{remote}/HEAD"))
+        .ok()?;
+    let target = reference.symbolic_target()?;
+    target
+        .strip_prefix(&format!("refs/remotes/{remote}/"))
+        .map(str::to_string)
+}
+
+/// The ref a base names: a local branch, or else a remote-tracking branch
+/// written `<remote>/<name>`, so `--base origin/main` works without a local
+/// `main`. A local branch wins when both exist.
+fn base_reference<'r>(repo: &'r Repository, name: &str) -> Result<Reference<'r>> {
+    repo.find_reference(&format!("refs/heads/{name}"))
+        .or_else(|_| repo.find_reference(&format!("refs/remotes/{name}")))
+        .with_context(|| {
+            format!("Base branch '{name}' not found as a local or remote-tracking branch")
+        })
+}
+
+pub fn ensure_base_branch(repo: &Repository, name: &str) -> Result<()> {
+    base_reference(repo, name)?
         .resolve()
         .context("Unable to resolve base branch reference")?;
     Ok(())
 }
 
-pub fn collect_local_branches(repo: &Repository, base_branch: &str) -> Result<Vec<BranchInfo>> {
-    let base_ref = repo
-        .find_reference(&format!("refs/heads/{base_branch}"))
-        .with_context(|| format!("Failed to find reference for base branch '{base_branch}'"))?;
-    let base_commit = base_ref
-        .peel_to_commit()
-        .context("Failed to peel base branch to commit")?;
-    let base_oid = base_commit.id();
+/// The local branch a base stands for: the base itself, or `<name>` when it
+/// is the remote-tracking `<remote>/<name>`.
+pub fn base_local_name(repo: &Repository, base: &str) -> String {
+    if repo.find_reference(&format!("refs/heads/{base}")).is_ok() {
+        return base.to_string();
+    }
+    let remotes = repo.remotes().ok();
+    remotes
+        .iter()
+        .flat_map(|remotes| remotes.iter().flatten())
+        .find_map(|remote| base.strip_prefix(&format!("{remote}/")))
+        .unwrap_or(base)
+        .to_string()
+}
+
+/// Number of files with staged or unstaged changes to tracked content.
+/// Untracked and ignored files are not scanned, which keeps this cheap on
+/// large trees. Bare repositories are never dirty.
+pub fn dirty_file_count(repo: &Repository) -> Result<usize> {
+    if repo.is_bare() {
+        return Ok(0);
+    }
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(false)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .context("Failed to read working tree status")?;
+    let changed = Status::INDEX_NEW
+        | Status::INDEX_MODIFIED
+        | Status::INDEX_DELETED
+        | Status::INDEX_RENAMED
+        | Status::INDEX_TYPECHANGE
+        | Status::WT_MODIFIED
+        | Status::WT_DELETED
+        | Status::WT_RENAMED
+        | Status::WT_TYPECHANGE;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status().intersects(changed))
+        .count())
+}
+
+/// A commit as shown in the selector's commit pane.
+#[derive(Debug, Clone)]
+pub struct CommitSummary {
+    pub id: Oid,
+    pub author: String,
+    pub subject: String,
+}
 
-    let mut merged = Vec::new();
+/// The newest commits on a branch that no base contains.
+#[derive(Debug, Clone, Default)]
+pub struct UniqueCommits {
+    /// At most the requested number, newest first.
+    pub commits: Vec<CommitSummary>,
+    /// All such commits, including those not listed.
+    pub total: usize,
+}
 
+/// Walk from `tip`, hiding everything reachable from any base, and keep the
+/// first `limit` commits.
+pub fn unique_commits(
+    repo: &Repository,
+    tip: Oid,
+    bases: &[String],
+    limit: usize,
+) -> Result<UniqueCommits> {
+    let mut walk = repo.revwalk().context("Failed to start a revision walk")?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    walk.push(tip)?;
+    for (_, base_oid) in resolve_bases(repo, bases)? {
+        walk.hide(base_oid)?;
+    }
+
+    let mut unique = UniqueCommits::default();
+    for oid in walk {
+        let oid = oid.context("Failed to walk branch history")?;
+        unique.total += 1;
+        if unique.commits.len() < limit {
+            let commit = repo.find_commit(oid)?;
+            unique.commits.push(CommitSummary {
+                id: oid,
+                author: commit.author().name().unwrap_or("<unknown>").to_string(),
+                subject: commit
+                    .summary()
+                    .unwrap_or("<no commit message>")
+                    .to_string(),
+            });
+        }
+    }
+    Ok(unique)
+}
+
+/// Tip commit of each base branch, in the order given.
+fn resolve_bases(repo: &Repository, bases: &[String]) -> Result<Vec<(String, Oid)>> {
+    bases
+        .iter()
+        .map(|name| {
+            let commit = base_reference(repo, name)?
+                .peel_to_commit()
+                .context("Failed to peel base branch to commit")?;
+            Ok((name.clone(), commit.id()))
+        })
+        .collect()
+}
+
+/// Local branches with their merge status against `bases`: a branch counts
+/// as merged when any base contains it.
+pub fn collect_local_branches(
+    repo: &Repository,
+    bases: &[String],
+    remote: &str,
+    cache: &MergeCache,
+) -> Result<Vec<BranchInfo>> {
+    let bases = resolve_bases(repo, bases)?;
+
+    let worktrees = worktree_branches(repo);
+    let stashes = stash_counts(repo);
+    let tips = local_tips(repo)?;
+    let remote_tips = remote_tips(repo)?;
+    let mut merged = analyze_branches(repo, tips, &bases, cache)?;
+    for info in &mut merged {
+        add_local_details(repo, info, remote, &worktrees, &stashes, &remote_tips)?;
+    }
+    link_dependents(repo, &mut merged, &bases)?;
+
+    merged.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(merged)
+}
+
+/// Name and tip of every local branch.
+fn local_tips(repo: &Repository) -> Result<Vec<(String, Oid)>> {
+    let mut tips = Vec::new();
     let branches = repo
         .branches(Some(BranchType::Local))
         .context("Failed to enumerate local branches")?;
@@ -96,39 +386,1302 @@ pub fn collect_local_branches(repo: &Repository, base_branch: &str) -> Result<Ve
                 continue;
             }
         };
-
-        let reference = branch.into_reference();
-        let target = match reference.target() {
-            Some(oid) => oid,
-            None => continue,
+        let Some(target) = branch.into_reference().target() else {
+            continue;
         };
-        let commit = repo
-            .find_commit(target)
-            .with_context(|| format!("Failed to resolve commit for branch '{name}'"))?;
-
-        let commit_time = commit.time();
-        let timestamp = commit_time.seconds() - i64::from(commit_time.offset_minutes()) * 60;
-
-        let merged_into_base = repo.graph_descendant_of(base_oid, commit.id())?;
-
-        merged.push(BranchInfo {
-            name,
-            tip: commit.id(),
-            summary: commit.summary().map(|s| s.trim().to_string()),
-            committer: commit.author().name().map(|s| s.to_string()),
-            commit_timestamp: (timestamp >= 0).then_some(timestamp),
-            merged: merged_into_base,
+        tips.push((name, target));
+    }
+    Ok(tips)
+}
+
+/// Remote counterpart, worktree, stashes, upstream, pushed state and
+/// description of a local branch.
+fn add_local_details(
+    repo: &Repository,
+    info: &mut BranchInfo,
+    remote: &str,
+    worktrees: &HashMap<String, PathBuf>,
+    stashes: &HashMap<String, usize>,
+    remote_tips: &[(String, Oid)],
+) -> Result<()> {
+    info.remote = find_remote_branch(repo, &info.name, remote);
+    info.worktree = worktrees.get(&info.name).cloned();
+    info.stashes = stashes.get(&info.name).copied().unwrap_or(0);
+    info.upstream = repo
+        .find_branch(&info.name, BranchType::Local)
+        .ok()
+        .and_then(|branch| upstream_status(repo, &branch, &info.name));
+    info.pushed = is_pushed(repo, info.tip, remote_tips)?;
+    info.description = repo
+        .config()
+        .and_then(|config| config.get_string(&format!("branch.{}.description", info.name)))
+        .ok()
+        .filter(|description| !description.trim().is_empty());
+    Ok(())
+}
+
+/// Local branch count from which the selector opens before the branches are
+/// analyzed and fills in as they arrive; see `stream_local_branches`.
+pub const STREAM_THRESHOLD: usize = 1000;
+
+pub fn local_branch_count(repo: &Repository) -> Result<usize> {
+    Ok(repo
+        .branches(Some(BranchType::Local))
+        .context("Failed to enumerate local branches")?
+        .count())
+}
+
+/// Local branches being analyzed on background threads, delivered as each
+/// one is done rather than all at once.
+pub struct BranchStream {
+    receiver: Receiver<Result<Option<BranchInfo>>>,
+    /// Saved once every branch is in.
+    cache: Arc<MergeCache>,
+    total: usize,
+    analyzed: usize,
+    done: bool,
+}
+
+impl BranchStream {
+    /// Branches analyzed since the last call that were kept. Fails with the
+    /// first error any thread ran into.
+    pub fn poll(&mut self) -> Result<Vec<BranchInfo>> {
+        let mut batch = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(loaded) => {
+                    self.analyzed += 1;
+                    batch.extend(loaded?);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    // Only a speedup, and there is no terminal to warn on.
+                    let _ = self.cache.save();
+                    break;
+                }
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Branches analyzed so far and in all.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.analyzed, self.total)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// `collect_local_branches` for very large repositories: the branches are
+/// analyzed across one thread per CPU and sent back one at a time, keeping
+/// only those `keep` accepts. They arrive in no particular order and without
+/// `dependents`, which are skipped at this size anyway. Dropping the stream
+/// stops the threads.
+pub fn stream_local_branches(
+    repo: &Repository,
+    bases: &[String],
+    remote: &str,
+    cache: Arc<MergeCache>,
+    keep: impl Fn(&BranchInfo) -> bool + Send + Sync + 'static,
+) -> Result<BranchStream> {
+    let bases = Arc::new(resolve_bases(repo, bases)?);
+    let worktrees = Arc::new(worktree_branches(repo));
+    let stashes = Arc::new(stash_counts(repo));
+    let remote_tips = Arc::new(remote_tips(repo)?);
+    let keep = Arc::new(keep);
+    let tips = local_tips(repo)?;
+    let total = tips.len();
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = total.div_ceil(threads).max(1);
+    let (sender, receiver) = mpsc::channel();
+    let mut tips = tips.into_iter();
+    loop {
+        let chunk: Vec<_> = tips.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let path = repo.path().to_path_buf();
+        let remote = remote.to_string();
+        let (bases, worktrees, stashes, remote_tips, keep, cache) = (
+            Arc::clone(&bases),
+            Arc::clone(&worktrees),
+            Arc::clone(&stashes),
+            Arc::clone(&remote_tips),
+            Arc::clone(&keep),
+            Arc::clone(&cache),
+        );
+        let sender: Sender<Result<Option<BranchInfo>>> = sender.clone();
+        std::thread::spawn(move || {
+            let repo = match Repository::open(&path) {
+                Ok(repo) => repo,
+                Err(err) => {
+                    let error = anyhow!(err)
+                        .context(format!("Failed to open repository at {}", path.display()));
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            };
+            let mut patch_ids = PatchIdCache::default();
+            for (name, tip) in chunk {
+                let loaded = branch_info(&repo, name, tip, &bases, &mut patch_ids, &cache)
+                    .and_then(|mut info| {
+                        add_local_details(
+                            &repo,
+                            &mut info,
+                            &remote,
+                            &worktrees,
+                            &stashes,
+                            &remote_tips,
+                        )?;
+                        Ok(keep(&info).then_some(info))
+                    });
+                let failed = loaded.is_err();
+                // The receiver is gone once the selector has closed.
+                if sender.send(loaded).is_err() || failed {
+                    return;
+                }
+            }
         });
     }
 
-    merged.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(merged)
+    Ok(BranchStream {
+        receiver,
+        cache,
+        total,
+        analyzed: 0,
+        done: false,
+    })
+}
+
+/// Remote-tracking branches under `refs/remotes/<remote>/` whose branch no
+/// longer exists on the remote. Contacts the remote to list its branches.
+pub fn collect_gone_remote_branches(
+    repo: &Repository,
+    bases: &[String],
+    remote: &str,
+    cache: &MergeCache,
+) -> Result<Vec<BranchInfo>> {
+    let bases = resolve_bases(repo, bases)?;
+
+    let mut git_remote = repo
+        .find_remote(remote)
+        .with_context(|| format!("Remote '{remote}' not found"))?;
+    let config = repo.config().context("Failed to open repository config")?;
+    git_remote
+        .connect_auth(Direction::Fetch, Some(remote_callbacks(config)), None)
+        .with_context(|| format!("Failed to connect to remote '{remote}'"))?;
+    let live: HashSet<String> = git_remote
+        .list()
+        .with_context(|| format!("Failed to list branches on remote '{remote}'"))?
+        .iter()
+        .filter_map(|head| head.name().strip_prefix("refs/heads/"))
+        .map(str::to_string)
+        .collect();
+    git_remote.disconnect().ok();
+
+    let prefix = format!("{remote}/");
+    let mut tips = Vec::new();
+    let branches = repo
+        .branches(Some(BranchType::Remote))
+        .context("Failed to enumerate remote-tracking branches")?;
+    for branch_result in branches {
+        let (branch, _) = branch_result.context("Encountered an error while iterating branches")?;
+        let Ok(Some(name)) = branch.name() else {
+            continue;
+        };
+        let Some(remote_name) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        if remote_name == "HEAD" || live.contains(remote_name) {
+            continue;
+        }
+        let name = name.to_string();
+        let Some(target) = branch.into_reference().target() else {
+            continue;
+        };
+        tips.push((name, target));
+    }
+
+    let remote_tips = remote_tips(repo)?;
+    let mut gone = analyze_branches(repo, tips, &bases, cache)?;
+    for info in &mut gone {
+        info.is_remote_tracking = true;
+        // Another remote-tracking ref must still hold the commits.
+        let others: Vec<(String, Oid)> = remote_tips
+            .iter()
+            .filter(|(name, _)| *name != info.ref_name())
+            .cloned()
+            .collect();
+        info.pushed = is_pushed(repo, info.tip, &others)?;
+    }
+
+    gone.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(gone)
+}
+
+/// Branch count above which `link_dependents` is skipped: it checks every
+/// pair of branches.
+const DEPENDENT_CHECK_LIMIT: usize = 200;
+
+/// Fill in `dependents` for each branch from pairwise ancestry checks. Base
+/// branches are left out since every merged branch would list them.
+fn link_dependents(
+    repo: &Repository,
+    branches: &mut [BranchInfo],
+    bases: &[(String, Oid)],
+) -> Result<()> {
+    if branches.len() > DEPENDENT_CHECK_LIMIT {
+        return Ok(());
+    }
+    let tips: Vec<(String, Oid)> = branches
+        .iter()
+        .filter(|branch| !bases.iter().any(|(base, _)| *base == branch.name))
+        .map(|branch| (branch.name.clone(), branch.tip))
+        .collect();
+    for branch in branches.iter_mut() {
+        for (name, tip) in &tips {
+            if *name != branch.name
+                && (*tip == branch.tip || repo.graph_descendant_of(*tip, branch.tip)?)
+            {
+                branch.dependents.push(name.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every remote-tracking ref with its target; symbolic refs such as
+/// `origin/HEAD` are skipped.
+fn remote_tips(repo: &Repository) -> Result<Vec<(String, Oid)>> {
+    let mut tips = Vec::new();
+    for reference in repo
+        .references_glob("refs/remotes/*")
+        .context("Failed to enumerate remote-tracking refs")?
+    {
+        let reference = reference.context("Failed to read a remote-tracking ref")?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            tips.push((name.to_string(), target));
+        }
+    }
+    Ok(tips)
+}
+
+/// Whether `tip` is reachable from any of `remote_tips`: walking from the tip
+/// with every remote tip hidden yields nothing (`git rev-list tip --not --remotes`).
+fn is_pushed(repo: &Repository, tip: Oid, remote_tips: &[(String, Oid)]) -> Result<bool> {
+    if remote_tips.is_empty() {
+        return Ok(false);
+    }
+    if remote_tips.iter().any(|(_, oid)| *oid == tip) {
+        return Ok(true);
+    }
+    let mut walk = repo.revwalk().context("Failed to start a revision walk")?;
+    walk.push(tip)?;
+    for (_, oid) in remote_tips {
+        walk.hide(*oid)?;
+    }
+    Ok(walk.next().is_none())
+}
+
+/// Callbacks that pick up credentials from ssh-agent or the configured
+/// credential helper.
+pub fn remote_callbacks<'a>(config: git2::Config) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&config, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+    callbacks
+}
+
+/// Branch count from which merge checks are spread across threads. Below
+/// it, thread and repository-open overhead outweighs the gain.
+///
+/// Only measured on a single-CPU machine so far: with the synthetic
+/// 1000-branch repository from the tests, a release build took 70-80ms
+/// sequentially and 77-116ms on 4 threads, i.e. the overhead without any
+/// gain. The speedup on multi-core machines has not been measured, and 200
+/// is an estimate rather than a tuned value.
+const PARALLEL_THRESHOLD: usize = 200;
+
+/// Build a `BranchInfo` (merge status, divergence, commit details) for each
+/// `(name, tip)`, preserving order. Large sets are split across one thread
+/// per CPU, each with its own handle on the repository since git2's
+/// `Repository` cannot be shared between threads.
+fn analyze_branches(
+    repo: &Repository,
+    tips: Vec<(String, Oid)>,
+    bases: &[(String, Oid)],
+    cache: &MergeCache,
+) -> Result<Vec<BranchInfo>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if tips.len() < PARALLEL_THRESHOLD || threads == 1 {
+        return analyze_chunk(repo, tips, bases, cache);
+    }
+
+    analyze_parallel(repo, tips, bases, threads, cache)
+}
+
+fn analyze_parallel(
+    repo: &Repository,
+    tips: Vec<(String, Oid)>,
+    bases: &[(String, Oid)],
+    threads: usize,
+    cache: &MergeCache,
+) -> Result<Vec<BranchInfo>> {
+    let chunk_size = tips.len().div_ceil(threads);
+    let mut chunks = Vec::with_capacity(threads);
+    let mut tips = tips.into_iter();
+    loop {
+        let chunk: Vec<_> = tips.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+
+    let path = repo.path();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let repo = Repository::open(path).with_context(|| {
+                        format!("Failed to open repository at {}", path.display())
+                    })?;
+                    analyze_chunk(&repo, chunk, bases, cache)
+                })
+            })
+            .collect();
+        let mut infos = Vec::new();
+        for handle in handles {
+            let chunk = handle
+                .join()
+                .map_err(|_| anyhow!("Branch analysis thread panicked"))??;
+            infos.extend(chunk);
+        }
+        Ok(infos)
+    })
+}
+
+fn analyze_chunk(
+    repo: &Repository,
+    tips: Vec<(String, Oid)>,
+    bases: &[(String, Oid)],
+    cache: &MergeCache,
+) -> Result<Vec<BranchInfo>> {
+    let mut patch_ids = PatchIdCache::default();
+    tips.into_iter()
+        .map(|(name, target)| branch_info(repo, name, target, bases, &mut patch_ids, cache))
+        .collect()
+}
+
+fn branch_info(
+    repo: &Repository,
+    name: String,
+    target: Oid,
+    bases: &[(String, Oid)],
+    patch_ids: &mut PatchIdCache,
+    cache: &MergeCache,
+) -> Result<BranchInfo> {
+    let commit = repo
+        .find_commit(target)
+        .with_context(|| format!("Failed to resolve commit for branch '{name}'"))?;
+
+    let commit_time = commit.time();
+    let timestamp = commit_time.seconds() - i64::from(commit_time.offset_minutes()) * 60;
+
+    // Plain containment in any base wins over a patch-level match. A cached
+    // entry for a base settles both questions; a fresh containment check
+    // can only be cached once it succeeds, since a miss still leaves the
+    // patch check open.
+    let tip = commit.id();
+    let mut merged = MergeStatus::Unmerged;
+    let mut merged_into = None;
+    for (base, base_oid) in bases {
+        let contained = match cache.get(tip, *base_oid) {
+            Some(status) => status == MergeStatus::Merged,
+            None => contained_in(repo, *base_oid, tip)?,
+        };
+        if contained {
+            cache.insert(tip, *base_oid, MergeStatus::Merged);
+            merged = MergeStatus::Merged;
+            merged_into = Some(base);
+            break;
+        }
+    }
+    if merged_into.is_none() {
+        for (base, base_oid) in bases {
+            let squashed = match cache.get(tip, *base_oid) {
+                Some(status) => status == MergeStatus::SquashMerged,
+                None => {
+                    let squashed = merged_by_patch(repo, *base_oid, &commit, patch_ids)?;
+                    let status = if squashed {
+                        MergeStatus::SquashMerged
+                    } else {
+                        MergeStatus::Unmerged
+                    };
+                    cache.insert(tip, *base_oid, status);
+                    squashed
+                }
+            };
+            if squashed {
+                merged = MergeStatus::SquashMerged;
+                merged_into = Some(base);
+                break;
+            }
+        }
+    }
+    // Divergence is against the containing base, or the first one.
+    let divergence_base = bases
+        .iter()
+        .find(|(base, _)| Some(base) == merged_into)
+        .or_else(|| bases.first())
+        .map(|(_, oid)| *oid)
+        .ok_or_else(|| anyhow!("No base branch given"))?;
+    let (ahead, behind) = repo.graph_ahead_behind(commit.id(), divergence_base)?;
+    let merge_base = match merged {
+        MergeStatus::Unmerged => repo.merge_base(commit.id(), divergence_base).ok(),
+        MergeStatus::Merged | MergeStatus::SquashMerged => None,
+    };
+
+    Ok(BranchInfo {
+        name,
+        tip: commit.id(),
+        summary: commit.summary().map(|s| s.trim().to_string()),
+        committer: commit.author().name().map(|s| s.to_string()),
+        commit_timestamp: (timestamp >= 0).then_some(timestamp),
+        merged,
+        merged_into: merged_into.cloned(),
+        ahead,
+        behind,
+        merge_base,
+        remote: None,
+        is_remote_tracking: false,
+        worktree: None,
+        upstream: None,
+        pushed: false,
+        dependents: Vec::new(),
+        description: None,
+        stashes: 0,
+    })
+}
+
+/// True when `tip` is fully contained in the base: their merge base is the
+/// tip itself. Covers a tip equal to the base without a separate check.
+fn contained_in(repo: &Repository, base_oid: Oid, tip: Oid) -> Result<bool> {
+    match repo.merge_base(base_oid, tip) {
+        Ok(merge_base) => Ok(merge_base == tip),
+        // Unrelated histories share no merge base.
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Compare a branch with its configured upstream, as `git branch -vv` does.
+fn upstream_status(repo: &Repository, branch: &Branch, name: &str) -> Option<UpstreamStatus> {
+    match branch.upstream() {
+        Ok(upstream) => {
+            let local = branch.get().target()?;
+            let remote = upstream.get().target()?;
+            let (ahead, behind) = repo.graph_ahead_behind(local, remote).ok()?;
+            Some(UpstreamStatus::Tracking { ahead, behind })
+        }
+        Err(_) => {
+            let config = repo.config().ok()?;
+            config.get_string(&format!("branch.{name}.merge")).ok()?;
+            Some(UpstreamStatus::Gone)
+        }
+    }
+}
+
+/// Branches checked out in any worktree of the repository, mapped to the
+/// worktree's directory. Worktrees that cannot be opened are skipped.
+fn worktree_branches(repo: &Repository) -> HashMap<String, PathBuf> {
+    fn record(checked_out: &mut HashMap<String, PathBuf>, repo: &Repository) {
+        if let Ok(head) = repo.head()
+            && head.is_branch()
+            && let Some(name) = head.shorthand()
+        {
+            let dir = repo.workdir().unwrap_or_else(|| repo.path());
+            checked_out.insert(name.to_string(), dir.to_path_buf());
+        }
+    }
+
+    let mut checked_out = HashMap::new();
+    // A bare repository's HEAD branch counts as checked out, as it does for
+    // `git branch -d`.
+    if repo.is_bare() {
+        record(&mut checked_out, repo);
+    }
+    // From a linked worktree, list the others through the main repository.
+    let main = if repo.is_worktree() {
+        std::fs::read_to_string(repo.path().join("commondir"))
+            .ok()
+            .and_then(|common| Repository::open(repo.path().join(common.trim())).ok())
+    } else {
+        None
+    };
+    if let Some(main) = &main {
+        record(&mut checked_out, main);
+    }
+    let owner = main.as_ref().unwrap_or(repo);
+    if let Ok(names) = owner.worktrees() {
+        for name in names.iter().flatten() {
+            if let Ok(worktree) = owner.find_worktree(name)
+                && let Ok(linked) = Repository::open_from_worktree(&worktree)
+            {
+                record(&mut checked_out, &linked);
+            }
+        }
+    }
+    checked_out
+}
+
+/// How many stashes were made on each branch. Git records the branch only in
+/// the stash message (`WIP on <branch>: ...` or `On <branch>: ...`), so this
+/// is a guess; a repository whose stashes cannot be read has none.
+fn stash_counts(repo: &Repository) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    // Listing stashes needs a mutable repository.
+    let Ok(mut repo) = Repository::open(repo.path()) else {
+        return counts;
+    };
+    let _ = repo.stash_foreach(|_, message, _| {
+        if let Some(branch) = stash_branch(message) {
+            *counts.entry(branch.to_string()).or_insert(0) += 1;
+        }
+        true
+    });
+    counts
+}
+
+/// The branch named in a stash message; None for a detached HEAD or a
+/// message in some other form.
+fn stash_branch(message: &str) -> Option<&str> {
+    let rest = message
+        .strip_prefix("WIP on ")
+        .or_else(|| message.strip_prefix("On "))?;
+    let (branch, _) = rest.split_once(": ")?;
+    (branch != "(no branch)").then_some(branch)
+}
+
+/// Patch ids of commits already looked at, shared across branches since
+/// they mostly walk the same stretch of base history.
+#[derive(Default)]
+struct PatchIdCache {
+    ids: HashMap<Oid, Option<Oid>>,
+}
+
+impl PatchIdCache {
+    /// Patch id of a commit's change against its first parent; None for
+    /// merges and empty commits.
+    fn commit(&mut self, repo: &Repository, oid: Oid) -> Result<Option<Oid>> {
+        if let Some(id) = self.ids.get(&oid) {
+            return Ok(*id);
+        }
+        let commit = repo.find_commit(oid)?;
+        let id = if commit.parent_count() == 1 {
+            diff_patch_id(repo, &commit.parent(0)?, &commit)?
+        } else {
+            None
+        };
+        self.ids.insert(oid, id);
+        Ok(id)
+    }
+}
+
+fn diff_patch_id(repo: &Repository, old: &Commit, new: &Commit) -> Result<Option<Oid>> {
+    let diff = repo.diff_tree_to_tree(Some(&old.tree()?), Some(&new.tree()?), None)?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(diff.patchid(None)?))
+}
+
+/// True when the branch's changes are already in the base as equivalent
+/// patches: either its whole diff since the merge base matches a single base
+/// commit (a squash merge), or every one of its commits has a patch-identical
+/// commit in the base (a rebase or cherry-pick, as `git cherry` reports).
+fn merged_by_patch(
+    repo: &Repository,
+    base_oid: Oid,
+    tip: &Commit,
+    cache: &mut PatchIdCache,
+) -> Result<bool> {
+    let Ok(merge_base) = repo.merge_base(base_oid, tip.id()) else {
+        return Ok(false);
+    };
+
+    let mut base_walk = repo.revwalk()?;
+    base_walk.push(base_oid)?;
+    base_walk.hide(merge_base)?;
+    let mut base_ids = HashSet::new();
+    for oid in base_walk {
+        if let Some(id) = cache.commit(repo, oid?)? {
+            base_ids.insert(id);
+        }
+    }
+    if base_ids.is_empty() {
+        return Ok(false);
+    }
+
+    let squashed = diff_patch_id(repo, &repo.find_commit(merge_base)?, tip)?;
+    if squashed.is_some_and(|id| base_ids.contains(&id)) {
+        return Ok(true);
+    }
+
+    let mut branch_walk = repo.revwalk()?;
+    branch_walk.push(tip.id())?;
+    branch_walk.hide(base_oid)?;
+    let mut any = false;
+    for oid in branch_walk {
+        match cache.commit(repo, oid?)? {
+            Some(id) if base_ids.contains(&id) => any = true,
+            Some(_) => return Ok(false),
+            // Merge and empty commits carry no change of their own.
+            None => {}
+        }
+    }
+    Ok(any)
+}
+
+/// The branch's configured upstream if it has one, otherwise a branch of the
+/// same name on `default_remote`. Only counterparts with a remote-tracking
+/// ref are returned.
+fn find_remote_branch(repo: &Repository, name: &str, default_remote: &str) -> Option<RemoteBranch> {
+    let local_ref = format!("refs/heads/{name}");
+    let configured = repo
+        .branch_upstream_remote(&local_ref)
+        .ok()
+        .and_then(|remote| remote.as_str().map(str::to_string))
+        .zip(
+            repo.config()
+                .and_then(|config| config.get_string(&format!("branch.{name}.merge")))
+                .ok(),
+        )
+        .and_then(|(remote, merge)| {
+            let branch = merge.strip_prefix("refs/heads/")?.to_string();
+            Some(RemoteBranch { remote, branch })
+        });
+    let candidate = configured.unwrap_or_else(|| RemoteBranch {
+        remote: default_remote.to_string(),
+        branch: name.to_string(),
+    });
+    repo.find_reference(&candidate.tracking_ref())
+        .is_ok()
+        .then_some(candidate)
 }
 
 impl BranchInfo {
+    /// Full ref name, e.g. `refs/heads/feature/x` or `refs/remotes/origin/x`.
+    pub fn ref_name(&self) -> String {
+        if self.is_remote_tracking {
+            format!("refs/remotes/{}", self.name)
+        } else {
+            format!("refs/heads/{}", self.name)
+        }
+    }
+
+    /// First non-blank line of the description.
+    pub fn description_line(&self) -> Option<&str> {
+        self.description
+            .as_deref()?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+    }
+
+    /// Divergence from the base, e.g. `↑3 ↓12`.
+    pub fn ahead_behind(&self) -> String {
+        format!("↑{} ↓{}", self.ahead, self.behind)
+    }
+
     pub fn age(&self, now: SystemTime) -> Option<Duration> {
         let timestamp = self.commit_timestamp?;
         let commit_time = UNIX_EPOCH.checked_add(Duration::from_secs(timestamp as u64))?;
         now.duration_since(commit_time).ok()
     }
+
+    /// Commit time in UTC, e.g. `2024-05-01T09:30:00Z`.
+    pub fn commit_date(&self) -> Option<String> {
+        let timestamp = u64::try_from(self.commit_timestamp?).ok()?;
+        let commit_time = UNIX_EPOCH.checked_add(Duration::from_secs(timestamp))?;
+        Some(humantime::format_rfc3339_seconds(commit_time).to_string())
+    }
+
+    /// Tip commit id, abbreviated to 7 characters unless `full`.
+    pub fn tip_id(&self, full: bool) -> String {
+        short_id(self.tip, full)
+    }
+
+    /// Why an unmerged branch is unmerged, relative to `base`: where it
+    /// forked and how many commits only it has. None for merged branches.
+    pub fn relationship(&self, base: &str, full: bool) -> Option<String> {
+        if self.merged.is_merged() {
+            return None;
+        }
+        let unique = match self.ahead {
+            1 => "1 commit".to_string(),
+            count => format!("{count} commits"),
+        };
+        Some(match self.merge_base {
+            Some(merge_base) => format!(
+                "merge-base {} with {base}, {unique} not in it",
+                short_id(merge_base, full)
+            ),
+            None => format!("no history in common with {base}, {unique}"),
+        })
+    }
+}
+
+/// Commit id, abbreviated to 7 characters unless `full`.
+fn short_id(id: Oid, full: bool) -> String {
+    let id = id.to_string();
+    if full { id } else { id[..7].to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scratch repository under the system temp dir, removed on drop.
+    struct TestRepo {
+        repo: Repository,
+        dir: PathBuf,
+    }
+
+    impl TestRepo {
+        fn new(label: &str) -> Self {
+            let dir = Self::scratch_dir(label);
+            let repo = Repository::init(&dir).unwrap();
+            Self { repo, dir }
+        }
+
+        fn bare(label: &str) -> Self {
+            let dir = Self::scratch_dir(label);
+            let repo = Repository::init_bare(&dir).unwrap();
+            Self { repo, dir }
+        }
+
+        fn scratch_dir(label: &str) -> PathBuf {
+            std::env::temp_dir().join(format!(
+                "ibd-test-{label}-{}-{}",
+                std::process::id(),
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+            ))
+        }
+
+        /// Commit a file change on top of `parent` without moving any ref.
+        fn commit(&self, parent: Option<Oid>, file: &str, contents: &str) -> Oid {
+            self.commit_files(parent, &[(file, contents)])
+        }
+
+        /// Commit several file changes at once, e.g. a squash of a branch.
+        fn commit_files(&self, parent: Option<Oid>, files: &[(&str, &str)]) -> Oid {
+            let parent = parent.map(|oid| self.repo.find_commit(oid).unwrap());
+            let base_tree = parent.as_ref().map(|c| c.tree().unwrap());
+            let mut builder = self.repo.treebuilder(base_tree.as_ref()).unwrap();
+            for (file, contents) in files {
+                let blob = self.repo.blob(contents.as_bytes()).unwrap();
+                builder.insert(file, blob, 0o100644).unwrap();
+            }
+            let tree = self.repo.find_tree(builder.write().unwrap()).unwrap();
+            let sig = git2::Signature::now("Tester", "t@example.com").unwrap();
+            let parents: Vec<&Commit> = parent.iter().collect();
+            let message = files.iter().map(|(file, _)| *file).collect::<Vec<_>>();
+            self.repo
+                .commit(None, &sig, &sig, &message.join(" "), &tree, &parents)
+                .unwrap()
+        }
+
+        /// Whether the branch at `tip` counts as landed in `base` by patch.
+        fn merged_by_patch(&self, base: Oid, tip: Oid) -> bool {
+            let tip = self.repo.find_commit(tip).unwrap();
+            merged_by_patch(&self.repo, base, &tip, &mut PatchIdCache::default()).unwrap()
+        }
+
+        fn branch(&self, name: &str, tip: Oid) {
+            let commit = self.repo.find_commit(tip).unwrap();
+            self.repo.branch(name, &commit, true).unwrap();
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn git_base_prefers_the_current_branchs_upstream_remote() {
+        let test = TestRepo::new("base");
+        let tip = test.commit(None, "a", "a");
+        for name in ["main", "trunk", "feature"] {
+            test.branch(name, tip);
+        }
+        for (remote, branch) in [("origin", "main"), ("upstream", "trunk")] {
+            test.repo
+                .reference(&format!("refs/remotes/{remote}/{branch}"), tip, true, "")
+                .unwrap();
+            test.repo
+                .reference_symbolic(
+                    &format!("refs/remotes/{remote}/HEAD"),
+                    &format!("refs/remotes/{remote}/{branch}"),
+                    true,
+                    "",
+                )
+                .unwrap();
+        }
+        let mut config = test.repo.config().unwrap();
+        config.set_str("branch.feature.remote", "upstream").unwrap();
+        config
+            .set_str("branch.feature.merge", "refs/heads/feature")
+            .unwrap();
+
+        let base = resolve_base_branch(&test.repo, "origin", Some("feature")).unwrap();
+        assert_eq!(base, "trunk");
+        let base = resolve_base_branch(&test.repo, "origin", Some("main")).unwrap();
+        assert_eq!(base, "main");
+    }
+
+    #[test]
+    fn git_tip_equal_to_base_is_contained() {
+        let test = TestRepo::new("equal");
+        let base = test.commit(None, "a", "a");
+        assert!(contained_in(&test.repo, base, base).unwrap());
+    }
+
+    #[test]
+    fn git_ancestor_of_base_is_contained() {
+        let test = TestRepo::new("ancestor");
+        let root = test.commit(None, "a", "a");
+        let base = test.commit(Some(root), "b", "b");
+        assert!(contained_in(&test.repo, base, root).unwrap());
+    }
+
+    #[test]
+    fn git_diverged_and_ahead_branches_are_not_contained() {
+        let test = TestRepo::new("diverged");
+        let root = test.commit(None, "a", "a");
+        let base = test.commit(Some(root), "b", "b");
+        let diverged = test.commit(Some(root), "c", "c");
+        let ahead = test.commit(Some(base), "d", "d");
+        assert!(!contained_in(&test.repo, base, diverged).unwrap());
+        assert!(!contained_in(&test.repo, base, ahead).unwrap());
+    }
+
+    #[test]
+    fn git_squash_merge_is_merged_by_patch() {
+        let test = TestRepo::new("squash");
+        let root = test.commit(None, "a", "a");
+        let first = test.commit(Some(root), "x", "x");
+        let tip = test.commit(Some(first), "y", "y");
+        let moved_on = test.commit(Some(root), "b", "b");
+        let squash = test.commit_files(Some(moved_on), &[("x", "x"), ("y", "y")]);
+        let base = test.commit(Some(squash), "c", "c");
+
+        assert!(!contained_in(&test.repo, base, tip).unwrap());
+        assert!(test.merged_by_patch(base, tip));
+        // Before the squash landed the branch is not merged
+        assert!(!test.merged_by_patch(moved_on, tip));
+    }
+
+    #[test]
+    fn git_cherry_picked_series_is_merged_by_patch() {
+        let test = TestRepo::new("cherry");
+        let root = test.commit(None, "a", "a");
+        let first = test.commit(Some(root), "x", "x");
+        let tip = test.commit(Some(first), "y", "y");
+        let moved_on = test.commit(Some(root), "b", "b");
+        let picked_first = test.commit(Some(moved_on), "x", "x");
+        let base = test.commit(Some(picked_first), "y", "y");
+
+        assert!(test.merged_by_patch(base, tip));
+    }
+
+    #[test]
+    fn git_partial_patch_match_stays_unmerged() {
+        let test = TestRepo::new("partial");
+        let root = test.commit(None, "a", "a");
+        let first = test.commit(Some(root), "x", "x");
+        let tip = test.commit(Some(first), "y", "y");
+        let moved_on = test.commit(Some(root), "b", "b");
+
+        // Only the first commit was picked
+        let picked_first = test.commit(Some(moved_on), "x", "x");
+        assert!(!test.merged_by_patch(picked_first, tip));
+
+        // The second change landed, but with different contents
+        let base = test.commit(Some(picked_first), "y", "y, reworked");
+        assert!(!test.merged_by_patch(base, tip));
+
+        // A squash of only part of the branch does not count either
+        let partial_squash = test.commit_files(Some(moved_on), &[("x", "x")]);
+        assert!(!test.merged_by_patch(partial_squash, tip));
+    }
+
+    #[test]
+    fn git_unrelated_history_is_not_contained() {
+        let test = TestRepo::new("unrelated");
+        let base = test.commit(None, "a", "a");
+        let orphan = test.commit(None, "z", "z");
+        assert!(!contained_in(&test.repo, base, orphan).unwrap());
+    }
+
+    #[test]
+    fn git_merged_into_any_base_counts_as_merged() {
+        let test = TestRepo::new("multibase");
+        let root = test.commit(None, "a", "a");
+        let main = test.commit(Some(root), "b", "b");
+        let hotfix = test.commit(Some(root), "h", "h");
+        let release = test.commit(Some(hotfix), "r", "r");
+        let topic = test.commit(Some(main), "t", "t");
+        test.branch("main", main);
+        test.branch("release/1.x", release);
+        test.branch("hotfix", hotfix);
+        test.branch("topic", topic);
+
+        let bases = ["main".to_string(), "release/1.x".to_string()];
+        let infos =
+            collect_local_branches(&test.repo, &bases, "origin", &MergeCache::disabled()).unwrap();
+        let find = |name: &str| infos.iter().find(|info| info.name == name).unwrap();
+
+        assert_eq!(find("hotfix").merged, MergeStatus::Merged);
+        assert_eq!(find("hotfix").merged_into.as_deref(), Some("release/1.x"));
+        assert_eq!(find("topic").merged, MergeStatus::Unmerged);
+        assert_eq!(find("topic").merged_into, None);
+        assert_eq!((find("topic").ahead, find("topic").behind), (1, 0));
+        assert_eq!(find("topic").merge_base, Some(main));
+        assert_eq!(find("hotfix").merge_base, None);
+        assert_eq!(
+            find("topic").relationship("main", false),
+            Some(format!(
+                "merge-base {} with main, 1 commit not in it",
+                &main.to_string()[..7]
+            ))
+        );
+        assert_eq!(find("hotfix").relationship("main", false), None);
+    }
+
+    #[test]
+    fn git_cache_only_counts_merged_entries_as_contained() {
+        let test = TestRepo::new("cache-rule");
+        let root = test.commit(None, "a", "a");
+        let topic = test.commit(Some(root), "t", "t");
+        let main = test.commit(Some(root), "b", "b");
+        test.branch("main", main);
+        test.branch("release", topic);
+        test.branch("topic", topic);
+        let bases = ["main".to_string(), "release".to_string()];
+        let path = test.repo.path().join(crate::merge_cache::CACHE_FILE);
+        let topic_status = |cache: &MergeCache| {
+            let info = collect_local_branches(&test.repo, &bases, "origin", cache)
+                .unwrap()
+                .into_iter()
+                .find(|info| info.name == "topic")
+                .unwrap();
+            (info.merged, info.merged_into)
+        };
+
+        // The miss against main stays uncached: the patch check against it
+        // never ran, since release contains the tip.
+        let cache = MergeCache::load(&test.repo);
+        let merged = (MergeStatus::Merged, Some("release".to_string()));
+        assert_eq!(topic_status(&cache), merged);
+        cache.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            saved.contains(&format!("{topic} {topic} merged")),
+            "{saved}"
+        );
+        assert!(!saved.contains(&format!("{topic} {main}")), "{saved}");
+
+        // A cached squash-merge into main does not make it contain the tip,
+        // so containment in release still wins.
+        std::fs::write(&path, format!("{topic} {main} squash-merged\n")).unwrap();
+        assert_eq!(topic_status(&MergeCache::load(&test.repo)), merged);
+    }
+
+    #[test]
+    fn git_cached_merge_status_is_reused_until_the_base_moves() {
+        let test = TestRepo::new("cache");
+        let root = test.commit(None, "a", "a");
+        let main = test.commit(Some(root), "b", "b");
+        let topic = test.commit(Some(root), "t", "t");
+        test.branch("main", main);
+        test.branch("topic", topic);
+        let bases = ["main".to_string()];
+        let topic_status = |cache: &MergeCache| {
+            collect_local_branches(&test.repo, &bases, "origin", cache)
+                .unwrap()
+                .into_iter()
+                .find(|info| info.name == "topic")
+                .unwrap()
+                .merged
+        };
+
+        let cache = MergeCache::load(&test.repo);
+        assert_eq!(topic_status(&cache), MergeStatus::Unmerged);
+        cache.save().unwrap();
+        let path = test.repo.path().join(crate::merge_cache::CACHE_FILE);
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains(&format!("{topic} {main} unmerged"))
+        );
+
+        // A planted entry wins while both tips are unchanged.
+        std::fs::write(&path, format!("{topic} {main} merged\n")).unwrap();
+        assert_eq!(
+            topic_status(&MergeCache::load(&test.repo)),
+            MergeStatus::Merged
+        );
+        assert_eq!(topic_status(&MergeCache::disabled()), MergeStatus::Unmerged);
+
+        test.branch("main", test.commit(Some(main), "c", "c"));
+        assert_eq!(
+            topic_status(&MergeCache::load(&test.repo)),
+            MergeStatus::Unmerged
+        );
+    }
+
+    #[test]
+    fn git_remote_tracking_branch_can_be_the_base() {
+        let test = TestRepo::new("remote-base");
+        let root = test.commit(None, "a", "a");
+        let upstream = test.commit(Some(root), "b", "b");
+        test.branch("topic", root);
+        test.repo
+            .remote("origin", "https://example.invalid/repo.git")
+            .unwrap();
+        test.repo
+            .reference("refs/remotes/origin/main", upstream, true, "")
+            .unwrap();
+
+        let bases = ["origin/main".to_string()];
+        ensure_base_branch(&test.repo, "origin/main").unwrap();
+        assert!(ensure_base_branch(&test.repo, "origin/nope").is_err());
+        assert_eq!(base_local_name(&test.repo, "origin/main"), "main");
+        let infos =
+            collect_local_branches(&test.repo, &bases, "origin", &MergeCache::disabled()).unwrap();
+        assert_eq!(infos[0].name, "topic");
+        assert_eq!(infos[0].merged, MergeStatus::Merged);
+        assert_eq!(infos[0].merged_into.as_deref(), Some("origin/main"));
+    }
+
+    #[test]
+    fn git_descriptions_are_read_from_config() {
+        let test = TestRepo::new("description");
+        let tip = test.commit(None, "a", "a");
+        test.branch("main", tip);
+        test.branch("topic", tip);
+        let mut config = test.repo.config().unwrap();
+        config
+            .set_str(
+                "branch.topic.description",
+                "\nSpike for the new parser\nsee #12\n",
+            )
+            .unwrap();
+
+        let infos = collect_local_branches(
+            &test.repo,
+            &["main".to_string()],
+            "origin",
+            &MergeCache::disabled(),
+        )
+        .unwrap();
+        let find = |name: &str| infos.iter().find(|info| info.name == name).unwrap();
+        assert_eq!(
+            find("topic").description_line(),
+            Some("Spike for the new parser")
+        );
+        assert_eq!(find("main").description_line(), None);
+    }
+
+    #[test]
+    fn git_stash_messages_name_their_branch() {
+        assert_eq!(
+            stash_branch("WIP on feature/x: 1a2b3c4 Add parser"),
+            Some("feature/x")
+        );
+        assert_eq!(stash_branch("On topic: halfway there"), Some("topic"));
+        assert_eq!(stash_branch("WIP on (no branch): 1a2b3c4 Detached"), None);
+        assert_eq!(stash_branch("autostash"), None);
+    }
+
+    #[test]
+    fn git_parallel_analysis_matches_sequential_on_1000_branches() {
+        let test = TestRepo::new("parallel");
+        let root = test.commit(None, "a", "a");
+        let base = test.commit(Some(root), "b", "b");
+        test.branch("main", base);
+
+        let mut tips = Vec::new();
+        for i in 0..1000 {
+            let name = format!("topic/{i:04}");
+            let tip = if i % 2 == 0 {
+                root
+            } else {
+                test.commit(Some(base), &format!("f{i}"), &i.to_string())
+            };
+            test.branch(&name, tip);
+            tips.push((name, tip));
+        }
+
+        let bases = [("main".to_string(), base)];
+        let sequential =
+            analyze_chunk(&test.repo, tips.clone(), &bases, &MergeCache::disabled()).unwrap();
+        let parallel =
+            analyze_parallel(&test.repo, tips, &bases, 4, &MergeCache::disabled()).unwrap();
+
+        assert_eq!(sequential.len(), parallel.len());
+        for (a, b) in sequential.iter().zip(&parallel) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.merged, b.merged);
+            assert_eq!((a.ahead, a.behind), (b.ahead, b.behind));
+        }
+        assert_eq!(parallel[0].merged, MergeStatus::Merged);
+        assert_eq!(parallel[1].merged, MergeStatus::Unmerged);
+    }
+
+    #[test]
+    fn git_dirty_count_ignores_untracked_files() {
+        let test = TestRepo::new("dirty");
+        let tip = test.commit(None, "tracked", "one");
+        test.branch("main", tip);
+        test.repo.set_head("refs/heads/main").unwrap();
+        test.repo
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        assert_eq!(dirty_file_count(&test.repo).unwrap(), 0);
+
+        std::fs::write(test.dir.join("untracked"), "new").unwrap();
+        assert_eq!(dirty_file_count(&test.repo).unwrap(), 0);
+
+        std::fs::write(test.dir.join("tracked"), "two").unwrap();
+        assert_eq!(dirty_file_count(&test.repo).unwrap(), 1);
+
+        let mut index = test.repo.index().unwrap();
+        index.add_path(Path::new("untracked")).unwrap();
+        index.write().unwrap();
+        assert_eq!(dirty_file_count(&test.repo).unwrap(), 2);
+    }
+
+    #[test]
+    fn git_unique_commits_stop_at_every_base() {
+        let test = TestRepo::new("unique");
+        let root = test.commit(None, "a", "a");
+        let release = test.commit(Some(root), "b", "b");
+        let first = test.commit(Some(release), "c", "c");
+        let second = test.commit(Some(first), "d", "d");
+        let third = test.commit(Some(second), "e", "e");
+        test.branch("main", root);
+        test.branch("release", release);
+
+        let bases = ["main".to_string(), "release".to_string()];
+        let unique = unique_commits(&test.repo, third, &bases, 2).unwrap();
+        assert_eq!(unique.total, 3);
+        let ids: Vec<Oid> = unique.commits.iter().map(|c| c.id).collect();
+        assert_eq!(ids, [third, second]);
+        assert_eq!(unique.commits[0].subject, "e");
+
+        let none = unique_commits(&test.repo, release, &bases, 2).unwrap();
+        assert_eq!(none.total, 0);
+    }
+
+    #[test]
+    fn git_pushed_means_reachable_from_a_remote_tracking_ref() {
+        let test = TestRepo::new("pushed");
+        let root = test.commit(None, "a", "a");
+        let shared = test.commit(Some(root), "b", "b");
+        let local = test.commit(Some(shared), "c", "c");
+        test.repo
+            .reference("refs/remotes/origin/feature", shared, true, "")
+            .unwrap();
+        test.repo
+            .reference_symbolic(
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/feature",
+                true,
+                "",
+            )
+            .unwrap();
+
+        let remote_tips = remote_tips(&test.repo).unwrap();
+        assert_eq!(remote_tips.len(), 1);
+        assert!(is_pushed(&test.repo, shared, &remote_tips).unwrap());
+        assert!(is_pushed(&test.repo, root, &remote_tips).unwrap());
+        assert!(!is_pushed(&test.repo, local, &remote_tips).unwrap());
+        assert!(!is_pushed(&test.repo, root, &[]).unwrap());
+    }
+
+    #[test]
+    fn git_dependents_are_branches_containing_the_tip() {
+        let test = TestRepo::new("dependents");
+        let root = test.commit(None, "a", "a");
+        let parent = test.commit(Some(root), "b", "b");
+        let child = test.commit(Some(parent), "c", "c");
+        let other = test.commit(Some(root), "d", "d");
+        test.branch("main", root);
+        for (name, tip) in [
+            ("parent", parent),
+            ("child", child),
+            ("twin", child),
+            ("other", other),
+        ] {
+            test.branch(name, tip);
+        }
+
+        let branches = collect_local_branches(
+            &test.repo,
+            &["main".to_string()],
+            "origin",
+            &MergeCache::disabled(),
+        )
+        .unwrap();
+        let dependents = |name: &str| {
+            let branch = branches.iter().find(|b| b.name == name).unwrap();
+            let mut names = branch.dependents.clone();
+            names.sort();
+            names
+        };
+        assert_eq!(dependents("parent"), ["child", "twin"]);
+        assert_eq!(dependents("child"), ["twin"]);
+        assert!(dependents("other").is_empty());
+        // Bases are never listed, though every branch contains main.
+        assert!(dependents("main").contains(&"parent".to_string()));
+        assert!(!dependents("parent").contains(&"main".to_string()));
+    }
+
+    #[test]
+    fn git_bare_repository_keeps_its_head_branch_as_checked_out() {
+        let test = TestRepo::bare("bare");
+        let tip = test.commit(None, "a", "a");
+        test.branch("main", tip);
+        test.branch("feature", tip);
+        test.repo.set_head("refs/heads/main").unwrap();
+
+        let err = current_branch_name(&test.repo).unwrap_err();
+        assert!(err.to_string().contains("bare"), "{err}");
+        assert_eq!(head_label(&test.repo), "(bare)");
+        let checked_out = worktree_branches(&test.repo);
+        assert_eq!(
+            checked_out.get("main"),
+            Some(&test.repo.path().to_path_buf())
+        );
+        assert!(!checked_out.contains_key("feature"));
+    }
 }
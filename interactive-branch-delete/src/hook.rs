@@ -0,0 +1,108 @@
+//! `--after-delete`: a user command run once the branches are gone.
+
+use std::io::Write;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+/// How the command ended; its output is passed through to stderr.
+pub struct HookResult {
+    pub command: String,
+    pub status: Result<ExitStatus, String>,
+}
+
+impl HookResult {
+    pub fn succeeded(&self) -> bool {
+        self.status.as_ref().is_ok_and(ExitStatus::success)
+    }
+
+    pub fn describe(&self) -> String {
+        let command = &self.command;
+        match &self.status {
+            Ok(status) if status.success() => {
+                format!("After-delete command `{command}` succeeded.")
+            }
+            Ok(status) => match status.code() {
+                Some(code) => {
+                    format!("After-delete command `{command}` exited with status {code}.")
+                }
+                None => format!("After-delete command `{command}` was killed ({status})."),
+            },
+            Err(err) => format!("After-delete command `{command}` could not be run: {err}"),
+        }
+    }
+}
+
+/// Run `command` through the shell with the deleted branch names as its
+/// positional parameters (`$1`, `$2`, ... or `"$@"`) and also on stdin, one
+/// per line. The command is the script itself, so names are never parsed as
+/// shell syntax.
+pub fn run_after_delete(command: &str, names: &[&str]) -> HookResult {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command).args(names);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        // `$0` is the name sh reports errors under; the names follow.
+        shell.arg("-c").arg(command).arg("after-delete").args(names);
+        shell
+    };
+    let status = run(&mut shell, names);
+    HookResult {
+        command: command.to_string(),
+        status: status.map_err(|err| err.to_string()),
+    }
+}
+
+fn run(shell: &mut Command, names: &[&str]) -> std::io::Result<ExitStatus> {
+    // Keep stdout clean for --format json; the command's output goes to stderr.
+    let mut child = shell
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Feed stdin from its own thread while wait_with_output drains stdout, so
+    // a command that writes before it has read everything cannot deadlock.
+    let writer = child.stdin.take().map(|mut stdin| {
+        let input: String = names.iter().map(|name| format!("{name}\n")).collect();
+        thread::spawn(move || {
+            // A command that ignores stdin may exit before reading it.
+            let _ = stdin.write_all(input.as_bytes());
+        })
+    });
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    let _ = std::io::stderr().write_all(&output.stdout);
+    Ok(output.status)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::run_after_delete;
+
+    #[test]
+    fn hook_names_are_positional_parameters_not_shell_syntax() {
+        let names = ["a b", "$(exit 9);", "feature/x"];
+        let result = run_after_delete(
+            r#"test "$#" = 3 && test "$1" = 'a b' && test "$2" = '$(exit 9);' && test "$3" = feature/x"#,
+            &names,
+        );
+        assert!(result.succeeded(), "{}", result.describe());
+
+        let result = run_after_delete("exit 3", &names);
+        assert_eq!(result.status.unwrap().code(), Some(3));
+    }
+
+    #[test]
+    fn hook_reads_names_on_stdin_and_may_ignore_them() {
+        let result = run_after_delete(r#"test "$(wc -l)" -eq 2"#, &["one", "two"]);
+        assert!(result.succeeded(), "{}", result.describe());
+
+        // Far more than a pipe buffer, for a command that never reads it.
+        let names: Vec<String> = (0..20_000).map(|i| format!("topic/{i:05}")).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        assert!(run_after_delete("true", &names).succeeded());
+    }
+}
@@ -0,0 +1,396 @@
+//! Key bindings for the selector, remappable through `keys.toml`.
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use crossterm::event::KeyCode;
+
+use crate::config;
+use crate::json::Value;
+
+pub const KEYS_FILE: &str = "keys.toml";
+
+/// Something a key can do in the selector's normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    Toggle,
+    Visual,
+    ToggleAll,
+    SelectMerged,
+    SelectUnmerged,
+    HideMerged,
+    HideUnmerged,
+    Invert,
+    Sort,
+    Collapse,
+    Dates,
+    Search,
+    Help,
+    Confirm,
+}
+
+impl Action {
+    const ALL: [Action; 21] = [
+        Action::Quit,
+        Action::Down,
+        Action::Up,
+        Action::PageDown,
+        Action::PageUp,
+        Action::Top,
+        Action::Bottom,
+        Action::Toggle,
+        Action::Visual,
+        Action::ToggleAll,
+        Action::SelectMerged,
+        Action::SelectUnmerged,
+        Action::HideMerged,
+        Action::HideUnmerged,
+        Action::Invert,
+        Action::Sort,
+        Action::Collapse,
+        Action::Dates,
+        Action::Search,
+        Action::Help,
+        Action::Confirm,
+    ];
+
+    /// Name used in `keys.toml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::Toggle => "toggle",
+            Action::Visual => "visual",
+            Action::ToggleAll => "toggle_all",
+            Action::SelectMerged => "select_merged",
+            Action::SelectUnmerged => "select_unmerged",
+            Action::HideMerged => "hide_merged",
+            Action::HideUnmerged => "hide_unmerged",
+            Action::Invert => "invert",
+            Action::Sort => "sort",
+            Action::Collapse => "collapse",
+            Action::Dates => "dates",
+            Action::Search => "search",
+            Action::Help => "help",
+            Action::Confirm => "confirm",
+        }
+    }
+
+    /// What the action does, for the help overlay.
+    fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "cancel without deleting anything",
+            Action::Down => "move down",
+            Action::Up => "move up",
+            Action::PageDown => "move a page down",
+            Action::PageUp => "move a page up",
+            Action::Top => "jump to the top",
+            Action::Bottom => "jump to the bottom",
+            Action::Toggle => "toggle the branch or group under the cursor",
+            Action::Visual => "start a range at the cursor; move to extend it, toggle to apply",
+            Action::ToggleAll => "toggle all visible branches",
+            Action::SelectMerged => "select all merged branches",
+            Action::SelectUnmerged => "select all unmerged branches",
+            Action::HideMerged => "hide or show merged branches (selections are kept)",
+            Action::HideUnmerged => "hide or show unmerged branches (selections are kept)",
+            Action::Invert => "invert the selection",
+            Action::Sort => "cycle the sort order",
+            Action::Collapse => "collapse or expand the group under the cursor",
+            Action::Dates => "switch between ages and commit dates",
+            Action::Search => "search branch names",
+            Action::Help => "show or hide this help",
+            Action::Confirm => "review the selection before deleting",
+        }
+    }
+
+    /// Built-in keys; the first one is shown in the help line.
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "esc"],
+            Action::Down => &["j", "down"],
+            Action::Up => &["k", "up"],
+            Action::PageDown => &["pgdn"],
+            Action::PageUp => &["pgup"],
+            Action::Top => &["g", "home"],
+            Action::Bottom => &["G", "end"],
+            Action::Toggle => &["space"],
+            Action::Visual => &["V"],
+            Action::ToggleAll => &["a"],
+            Action::SelectMerged => &["m"],
+            Action::SelectUnmerged => &["u"],
+            Action::HideMerged => &["M"],
+            Action::HideUnmerged => &["U"],
+            Action::Invert => &["i"],
+            Action::Sort => &["s"],
+            Action::Collapse => &["c"],
+            Action::Dates => &["d"],
+            Action::Search => &["/"],
+            Action::Help => &["?"],
+            Action::Confirm => &["enter"],
+        }
+    }
+}
+
+/// Key names accepted in `keys.toml` besides single characters.
+const NAMED_KEYS: [(&str, KeyCode); 13] = [
+    ("space", KeyCode::Char(' ')),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pgup", KeyCode::PageUp),
+    ("pgdn", KeyCode::PageDown),
+];
+
+/// A named key (`enter`, `pgdn`, ...), or one or more characters typed in
+/// sequence (`x`, `dd`).
+fn parse_key(spec: &str) -> Result<Vec<KeyCode>> {
+    if let Some((_, code)) = NAMED_KEYS.iter().find(|(name, _)| *name == spec) {
+        return Ok(vec![*code]);
+    }
+    if spec.is_empty() {
+        bail!("empty key");
+    }
+    Ok(spec.chars().map(KeyCode::Char).collect())
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    keys: Vec<KeyCode>,
+    spec: String,
+    action: Action,
+}
+
+/// Maps key presses to actions, including multi-key sequences such as `dd`.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    /// Keys of a sequence typed so far.
+    pending: Vec<KeyCode>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|&action| {
+                action.default_keys().iter().map(move |spec| Binding {
+                    keys: parse_key(spec).expect("built-in keys parse"),
+                    spec: spec.to_string(),
+                    action,
+                })
+            })
+            .collect();
+        Self {
+            bindings,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The defaults, with the actions named in `path` (or the user's
+    /// `keys.toml` when there is one) rebound.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut keymap = Self::default();
+        if let Some((path, document)) = config::load(path, KEYS_FILE)? {
+            keymap.apply(&document).map_err(|err| {
+                err.context(format!("Invalid key bindings in {}", path.display()))
+            })?;
+        }
+        Ok(keymap)
+    }
+
+    /// Replace the keys of every action in `document`, which maps action
+    /// names to a key or a list of keys. A key taken by another action is
+    /// removed from it.
+    fn apply(&mut self, document: &Value) -> Result<()> {
+        let Value::Object(entries) = document else {
+            bail!("expected a table of action = key(s)");
+        };
+        for (name, value) in entries {
+            let Some(action) = Action::ALL.iter().copied().find(|a| a.name() == name) else {
+                bail!("unknown action '{name}'");
+            };
+            let specs: Vec<&str> = match value {
+                Value::String(spec) => vec![spec.as_str()],
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(spec) => Ok(spec.as_str()),
+                        _ => bail!("keys for '{name}' must be strings"),
+                    })
+                    .collect::<Result<_>>()?,
+                _ => bail!("keys for '{name}' must be a string or a list of strings"),
+            };
+            let mut bindings = Vec::with_capacity(specs.len());
+            for spec in specs {
+                let keys =
+                    parse_key(spec).map_err(|err| err.context(format!("action '{name}'")))?;
+                bindings.push(Binding {
+                    keys,
+                    spec: spec.to_string(),
+                    action,
+                });
+            }
+            self.bindings.retain(|binding| {
+                binding.action != action && !bindings.iter().any(|b| b.keys == binding.keys)
+            });
+            self.bindings.extend(bindings);
+        }
+        Ok(())
+    }
+
+    /// Feed one key press. Returns the action once a whole binding has been
+    /// typed; keys that start a longer binding are held until it completes
+    /// or is broken off.
+    pub fn press(&mut self, key: KeyCode) -> Option<Action> {
+        self.pending.push(key);
+        if let Some(action) = self.resolve() {
+            return action;
+        }
+        // Not part of any binding: drop the stale prefix and try the key alone.
+        self.pending = vec![key];
+        self.resolve().unwrap_or_else(|| {
+            self.pending.clear();
+            None
+        })
+    }
+
+    /// Some(action) when `pending` is a complete binding, Some(None) while it
+    /// is still a prefix, None when nothing starts with it.
+    fn resolve(&mut self) -> Option<Option<Action>> {
+        let pending = &self.pending;
+        if self
+            .bindings
+            .iter()
+            .any(|b| b.keys.len() > pending.len() && b.keys.starts_with(pending))
+        {
+            return Some(None);
+        }
+        let action = self.bindings.iter().find(|b| b.keys == *pending)?.action;
+        self.pending.clear();
+        Some(Some(action))
+    }
+
+    /// First key bound to `action`, as written in `keys.toml`.
+    fn label(&self, action: Action) -> &str {
+        self.bindings
+            .iter()
+            .find(|binding| binding.action == action)
+            .map_or("-", |binding| binding.spec.as_str())
+    }
+
+    /// Every action with all of its keys, for the help overlay.
+    pub fn key_table(&self) -> Vec<(String, &'static str)> {
+        Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys: Vec<&str> = self
+                    .bindings
+                    .iter()
+                    .filter(|binding| binding.action == action)
+                    .map(|binding| binding.spec.as_str())
+                    .collect();
+                let keys = if keys.is_empty() {
+                    "-".to_string()
+                } else {
+                    keys.join(", ")
+                };
+                (keys, action.description())
+            })
+            .collect()
+    }
+
+    /// The selector's two help lines for these bindings.
+    pub fn help_lines(&self) -> [String; 2] {
+        let key = |action| self.label(action);
+        [
+            format!(
+                "{}/{}: move  {}/{}: page  {}/{}: top/bottom  {}: search  {}: confirm  {}: cancel  {}: help",
+                key(Action::Down),
+                key(Action::Up),
+                key(Action::PageUp),
+                key(Action::PageDown),
+                key(Action::Top),
+                key(Action::Bottom),
+                key(Action::Search),
+                key(Action::Confirm),
+                key(Action::Quit),
+                key(Action::Help),
+            ),
+            format!(
+                "{}: toggle  {}: range  {}: toggle all  {}: select merged  {}: select unmerged  {}/{}: hide merged/unmerged  {}: invert  {}: sort  {}: collapse  {}: dates",
+                key(Action::Toggle),
+                key(Action::Visual),
+                key(Action::ToggleAll),
+                key(Action::SelectMerged),
+                key(Action::SelectUnmerged),
+                key(Action::HideMerged),
+                key(Action::HideUnmerged),
+                key(Action::Invert),
+                key(Action::Sort),
+                key(Action::Collapse),
+                key(Action::Dates),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml;
+
+    #[test]
+    fn keys_defaults_match_the_built_in_help() {
+        let keymap = Keymap::default();
+        let [first, second] = keymap.help_lines();
+        assert_eq!(
+            first,
+            "j/k: move  pgup/pgdn: page  g/G: top/bottom  /: search  enter: confirm  q: cancel  ?: help"
+        );
+        assert!(second.starts_with("space: toggle  V: range  a: toggle all"));
+    }
+
+    #[test]
+    fn keys_rebinding_supports_sequences_and_steals_keys() {
+        let mut keymap = Keymap::default();
+        let document = toml::parse("toggle = [\"x\", \"space\"]\nconfirm = \"dd\"\n").unwrap();
+        keymap.apply(&document).unwrap();
+
+        assert_eq!(keymap.press(KeyCode::Char('x')), Some(Action::Toggle));
+        assert_eq!(keymap.press(KeyCode::Enter), None);
+        // `d` alone now only starts `dd`.
+        assert_eq!(keymap.press(KeyCode::Char('d')), None);
+        assert_eq!(keymap.press(KeyCode::Char('d')), Some(Action::Confirm));
+        // A broken-off sequence does not swallow the next key.
+        assert_eq!(keymap.press(KeyCode::Char('d')), None);
+        assert_eq!(keymap.press(KeyCode::Char('j')), Some(Action::Down));
+    }
+
+    #[test]
+    fn keys_rejects_unknown_actions() {
+        let mut keymap = Keymap::default();
+        let document = toml::parse("explode = \"x\"\n").unwrap();
+        assert!(keymap.apply(&document).is_err());
+    }
+}
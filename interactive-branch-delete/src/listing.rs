@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, SystemTime};
+
+use clap::ValueEnum;
+
+use crate::git::{BranchInfo, MergeStatus};
+use crate::json::{self, Value};
+
+/// Output format for `--list-only`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ListFormat {
+    /// Aligned, human-readable columns.
+    #[default]
+    Table,
+    /// A JSON array with one object per branch.
+    Json,
+    /// A header row plus one comma-separated row per branch.
+    Csv,
+    /// With --dry-run, the deletion plan as `git` commands (not a listing).
+    Script,
+}
+
+impl ListFormat {
+    /// Formats that print the branch listing instead of deleting anything.
+    /// With --yes, `json` reports the deletion results instead, as it does
+    /// for --stdin.
+    pub fn implies_list_only(self, yes: bool) -> bool {
+        match self {
+            ListFormat::Json => !yes,
+            ListFormat::Csv => true,
+            ListFormat::Table | ListFormat::Script => false,
+        }
+    }
+}
+
+/// When the table listing uses color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorMode {
+    /// Only when stdout is a terminal and NO_COLOR is unset.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// How branch ages are written in the table and the selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum AgeFormat {
+    /// Compact, like git's relative dates: `45m`, `2d`, `3w`, `5mo`, `2y`.
+    Short,
+    /// Every unit, e.g. `2days 3h 4m 5s`.
+    #[default]
+    Long,
+}
+
+impl AgeFormat {
+    pub fn format(self, age: Duration) -> String {
+        match self {
+            AgeFormat::Long => humantime::format_duration(age).to_string(),
+            AgeFormat::Short => short_age(age.as_secs()),
+        }
+    }
+}
+
+/// One unit, rounded, switching to the next unit where git's relative dates do.
+fn short_age(seconds: u64) -> String {
+    let rounded = |unit: u64| (seconds + unit / 2) / unit;
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    if seconds < 90 {
+        format!("{seconds}s")
+    } else if seconds < 90 * MINUTE {
+        format!("{}m", rounded(MINUTE))
+    } else if seconds < 36 * HOUR {
+        format!("{}h", rounded(HOUR))
+    } else if seconds < 14 * DAY {
+        format!("{}d", rounded(DAY))
+    } else if seconds < 70 * DAY {
+        format!("{}w", rounded(7 * DAY))
+    } else if seconds < 365 * DAY {
+        format!("{}mo", rounded(30 * DAY))
+    } else {
+        format!("{}y", rounded(365 * DAY))
+    }
+}
+
+/// Longer branch names are cut short with an ellipsis in the table.
+const MAX_NAME_WIDTH: usize = 48;
+
+const CSV_COLUMNS: [&str; 16] = [
+    "name",
+    "ref",
+    "tip",
+    "summary",
+    "committer",
+    "timestamp",
+    "merged",
+    "merged_into",
+    "ahead",
+    "behind",
+    "remote",
+    "upstream",
+    "pushed",
+    "worktree",
+    "remote_tracking",
+    "age_seconds",
+];
+
+/// How the table listing is laid out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableOptions {
+    pub full_hash: bool,
+    pub color: bool,
+    /// Print the branches under a heading per committer.
+    pub group_by_author: bool,
+    /// Add each unmerged branch's merge-base and unique commit count, and
+    /// each branch's description.
+    pub verbose: bool,
+    pub age_format: AgeFormat,
+}
+
+pub fn print_listing(
+    format: ListFormat,
+    branches: &[BranchInfo],
+    bases: &[String],
+    current_branch: &str,
+    options: TableOptions,
+) {
+    match format {
+        ListFormat::Table | ListFormat::Script => {
+            print_table(branches, bases, current_branch, options)
+        }
+        ListFormat::Json => print_json(branches),
+        ListFormat::Csv => print_csv(branches),
+    }
+}
+
+fn print_table(
+    branches: &[BranchInfo],
+    bases: &[String],
+    current_branch: &str,
+    options: TableOptions,
+) {
+    println!(
+        "Branches relative to '{}' (current: {current_branch}):",
+        bases.join(", ")
+    );
+    let now = SystemTime::now();
+    // name, tip, status, divergence, upstream, age
+    let rows: Vec<[String; 6]> = branches
+        .iter()
+        .map(|branch| {
+            let age = branch
+                .age(now)
+                .map(|duration| options.age_format.format(duration))
+                .unwrap_or_else(|| "n/a".to_string());
+            let status = match &branch.merged_into {
+                Some(base) if bases.len() > 1 => format!("{} ({base})", branch.merged.label()),
+                _ => branch.merged.label().to_string(),
+            };
+            [
+                truncate(&branch.name, MAX_NAME_WIDTH),
+                branch.tip_id(options.full_hash),
+                status,
+                branch.ahead_behind(),
+                branch.upstream.map(|u| u.label()).unwrap_or_default(),
+                age,
+            ]
+        })
+        .collect();
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |index: usize| {
+        let (branch, row) = (&branches[index], &rows[index]);
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(column, (cell, width))| {
+                let padded = format!("{cell:<width$}");
+                if options.color && column == 2 {
+                    format!("\x1b[{}m{padded}\x1b[0m", status_color(branch.merged))
+                } else {
+                    padded
+                }
+            })
+            .collect();
+        let mut line = format!("  {}", cells.join("  "));
+        match &branch.committer {
+            Some(committer) => line.push_str(&format!("  {committer}")),
+            None => line.truncate(line.trim_end().len()),
+        }
+        println!("{line}");
+        let summary = branch.summary.as_deref().unwrap_or("<no commit message>");
+        println!("      {summary}");
+        if options.verbose
+            && let Some(relationship) = branch.relationship(&bases[0], options.full_hash)
+        {
+            println!("      {relationship}");
+        }
+        if options.verbose
+            && let Some(description) = branch.description_line()
+        {
+            println!("      description: {description}");
+        }
+    };
+
+    if !options.group_by_author {
+        (0..branches.len()).for_each(print_row);
+        return;
+    }
+    for (committer, indices) in by_author(branches) {
+        let unmerged = indices
+            .iter()
+            .filter(|&&index| !branches[index].merged.is_merged())
+            .count();
+        let plural = if indices.len() == 1 { "" } else { "es" };
+        println!(
+            "\n{} ({} branch{plural}, {unmerged} unmerged)",
+            committer.unwrap_or("(unknown committer)"),
+            indices.len()
+        );
+        indices.into_iter().for_each(print_row);
+    }
+}
+
+/// Indices of `branches` per committer, the committers with the most
+/// branches first and unknown committers last.
+fn by_author(branches: &[BranchInfo]) -> Vec<(Option<&str>, Vec<usize>)> {
+    let mut groups: BTreeMap<Option<&str>, Vec<usize>> = BTreeMap::new();
+    for (index, branch) in branches.iter().enumerate() {
+        groups
+            .entry(branch.committer.as_deref())
+            .or_default()
+            .push(index);
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by_key(|(committer, indices)| {
+        (committer.is_none(), std::cmp::Reverse(indices.len()))
+    });
+    groups
+}
+
+/// ANSI foreground matching the selector's default colors.
+fn status_color(status: MergeStatus) -> u8 {
+    match status {
+        MergeStatus::Merged => 32,
+        MergeStatus::SquashMerged => 92,
+        MergeStatus::Unmerged => 31,
+    }
+}
+
+/// `text` cut to `width` characters, ending in `…` when shortened.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(width.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+/// Each column name with its value, Null where there is none.
+fn fields(branch: &BranchInfo, now: SystemTime) -> Vec<(&'static str, Value)> {
+    let values: [Value; 16] = [
+        branch.name.as_str().into(),
+        branch.ref_name().into(),
+        branch.tip.to_string().into(),
+        branch.summary.clone().into(),
+        branch.committer.clone().into(),
+        branch.commit_timestamp.into(),
+        branch.merged.label().into(),
+        branch.merged_into.clone().into(),
+        branch.ahead.into(),
+        branch.behind.into(),
+        branch.remote.as_ref().map(|r| r.display_name()).into(),
+        branch
+            .upstream
+            .map(|u| u.label().trim_matches(['[', ']']).to_string())
+            .into(),
+        branch.pushed.into(),
+        branch
+            .worktree
+            .as_ref()
+            .map(|dir| dir.display().to_string())
+            .into(),
+        branch.is_remote_tracking.into(),
+        branch.age(now).map(|age| age.as_secs() as i64).into(),
+    ];
+    CSV_COLUMNS.into_iter().zip(values).collect()
+}
+
+fn print_json(branches: &[BranchInfo]) {
+    let now = SystemTime::now();
+    let rows = branches.iter().map(|branch| {
+        let object: BTreeMap<String, Value> = fields(branch, now)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        Value::Object(object)
+    });
+    println!("{}", json::array_lines(rows));
+}
+
+fn print_csv(branches: &[BranchInfo]) {
+    let now = SystemTime::now();
+    println!("{}", CSV_COLUMNS.join(","));
+    for branch in branches {
+        let row: Vec<String> = fields(branch, now)
+            .into_iter()
+            .map(|(_, value)| match value {
+                Value::Null => String::new(),
+                Value::String(s) => csv_field(&s),
+                other => other.to_string(),
+            })
+            .collect();
+        println!("{}", row.join(","));
+    }
+}
+
+/// Quote a field when it contains a delimiter, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_short_ages_use_one_rounded_unit() {
+        let short = |seconds| AgeFormat::Short.format(Duration::from_secs(seconds));
+        assert_eq!(short(42), "42s");
+        assert_eq!(short(45 * 60), "45m");
+        assert_eq!(short(5 * 3600), "5h");
+        assert_eq!(short(2 * 86400 + 3600), "2d");
+        assert_eq!(short(20 * 86400), "3w");
+        assert_eq!(short(150 * 86400), "5mo");
+        assert_eq!(short(800 * 86400), "2y");
+    }
+
+    #[test]
+    fn listing_truncate_adds_an_ellipsis_only_when_needed() {
+        assert_eq!(truncate("feature/short", 20), "feature/short");
+        assert_eq!(truncate("feature/very-long-name", 12), "feature/ver…");
+        assert_eq!(truncate("feature/very-long-name", 12).chars().count(), 12);
+    }
+}
@@ -1,119 +1,706 @@
 mod app;
+mod backup;
 mod cli;
+mod config;
 mod delete;
+mod filter;
 mod git;
+mod hook;
+mod json;
+mod keys;
+mod listing;
+mod merge_cache;
+mod notify;
+mod prompt;
+mod script;
+mod state;
+mod theme;
+mod toml;
 mod tui;
 mod ui;
 
-use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::io::{BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, bail};
 use clap::Parser;
 
 use crate::app::App;
 use crate::cli::Cli;
-use crate::delete::{DeleteStatus, delete_branches};
+use crate::delete::{
+    DeleteOptions, DeleteResult, DeleteStatus, delete_branches, delete_branches_with_progress,
+};
+use crate::filter::{BranchFilter, matches_any};
 use crate::git::{
-    BranchInfo, collect_local_branches, current_branch_name, ensure_local_branch, open_repository,
-    resolve_base_branch,
+    BranchInfo, BranchStream, STREAM_THRESHOLD, base_local_name, collect_gone_remote_branches,
+    collect_local_branches, current_branch_name, dirty_file_count, ensure_base_branch, head_label,
+    local_branch_count, open_repository, resolve_base_branch, stream_local_branches,
 };
+use crate::keys::Keymap;
+use crate::listing::{ListFormat, TableOptions, print_listing};
+use crate::merge_cache::MergeCache;
+use crate::theme::Theme;
+
+/// Remote used to resolve the base when neither --remote nor a remembered
+/// one applies.
+const DEFAULT_REMOTE: &str = "origin";
+
+/// How a run ended, reported as the process exit code (fatal errors exit
+/// with 1 through anyhow as well).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Everything requested was done (or listed).
+    Success = 0,
+    /// At least one branch could not be deleted or restored.
+    Errors = 1,
+    /// The selector or prompt was cancelled.
+    Aborted = 2,
+    /// No branches to act on.
+    NothingToDo = 3,
+}
+
+impl Outcome {
+    fn from_results(results: &[DeleteResult]) -> Self {
+        let failed = |status: &DeleteStatus| matches!(status, DeleteStatus::Error(_));
+        let any_failed = results.iter().any(|result| {
+            failed(&result.status)
+                || result.remote.as_ref().is_some_and(|remote| {
+                    failed(&remote.status) || remote.push.as_ref().is_some_and(failed)
+                })
+        });
+        if results.is_empty() {
+            Outcome::NothingToDo
+        } else if any_failed {
+            Outcome::Errors
+        } else {
+            Outcome::Success
+        }
+    }
+}
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
-    run(cli)
+    let outcome = run(cli)?;
+    Ok(ExitCode::from(outcome as u8))
 }
 
-fn run(cli: Cli) -> Result<()> {
+fn run(cli: Cli) -> Result<Outcome> {
     let repo = open_repository(cli.repo.as_deref())?;
 
+    if let Some(file) = &cli.restore {
+        let path = file.clone().unwrap_or_else(|| backup::latest_backup(&repo));
+        return restore_branches(&repo, &path);
+    }
+
+    if cli.format == ListFormat::Script && !cli.dry_run {
+        bail!("--format script prints a deletion plan and requires --dry-run");
+    }
+    if cli.group_by_author && cli.format != ListFormat::Table {
+        bail!("--group-by-author only applies to the table listing");
+    }
+
+    // Deleting the wrong branch is easier to recover from than losing
+    // uncommitted work alongside it, so non-interactive runs stop here.
+    let dirty = match dirty_file_count(&repo)? {
+        0 => None,
+        1 => Some("the working tree has uncommitted changes in 1 file".to_string()),
+        count => Some(format!(
+            "the working tree has uncommitted changes in {count} files"
+        )),
+    };
+    if let Some(dirty) = &dirty
+        && (cli.yes || cli.stdin)
+        && !cli.dry_run
+        && !cli.allow_dirty
+    {
+        bail!("Refusing to delete branches: {dirty}. Commit or stash them, or pass --allow-dirty.");
+    }
+    let dirty_warning = dirty.map(|dirty| format!("Warning: {dirty}."));
+
     let current_branch_result = current_branch_name(&repo);
-    let current_branch_display = current_branch_result
-        .as_ref()
-        .map(|s| s.as_str())
-        .unwrap_or("(detached)")
-        .to_string();
+    let current_branch_display = match &current_branch_result {
+        Ok(name) => name.clone(),
+        Err(err) => {
+            // Not fatal: there is just no branch to keep out of the list.
+            eprintln!("Note: {err:#}.");
+            head_label(&repo).to_string()
+        }
+    };
 
     let current_branch_for_base = current_branch_result.as_ref().ok().map(|s| s.as_str());
 
-    let base_branch = match cli.base {
-        Some(base) => base,
-        None => resolve_base_branch(&repo, &cli.remote, current_branch_for_base)
-            .context("Unable to resolve default base branch; use --base to set explicitly")?,
+    // Without --base/--remote, fall back to what was last given here, as
+    // long as those bases still exist.
+    let remembered = if cli.forget {
+        if state::forget(&repo)? {
+            eprintln!("Forgot the base remembered for this repository.");
+        }
+        None
+    } else {
+        state::load(&repo)
+    }
+    .filter(|remembered| {
+        remembered
+            .bases
+            .iter()
+            .all(|base| ensure_base_branch(&repo, base).is_ok())
+    });
+    let remote = cli
+        .remote
+        .clone()
+        .or_else(|| remembered.as_ref().and_then(|r| r.remote.clone()))
+        .unwrap_or_else(|| DEFAULT_REMOTE.to_string());
+
+    let bases = if !cli.bases.is_empty() {
+        cli.bases.clone()
+    } else if let Some(remembered) = remembered.as_ref().filter(|r| !r.bases.is_empty()) {
+        remembered.bases.clone()
+    } else {
+        vec![
+            resolve_base_branch(&repo, &remote, current_branch_for_base)
+                .context("Unable to resolve default base branch; use --base to set explicitly")?,
+        ]
     };
+    for base in &bases {
+        ensure_base_branch(&repo, base)?;
+    }
+    // Never offered for deletion: the bases, and the local `main` behind a
+    // remote-tracking `origin/main` base.
+    let mut base_names = bases.clone();
+    base_names.extend(bases.iter().map(|base| base_local_name(&repo, base)));
+    let base_branch = bases.join(", ");
 
-    ensure_local_branch(&repo, &base_branch)?;
+    if !cli.bases.is_empty() || cli.remote.is_some() {
+        let last = state::Remembered {
+            bases: if cli.bases.is_empty() {
+                remembered.map(|r| r.bases).unwrap_or_default()
+            } else {
+                cli.bases.clone()
+            },
+            remote: Some(remote.clone()),
+        };
+        // Only a convenience; never worth failing the run over.
+        if let Err(err) = state::save(&repo, &last) {
+            eprintln!("Warning: could not remember the base for next time: {err:#}");
+        }
+    }
 
-    let mut merged = collect_local_branches(&repo, &base_branch)?;
-    if let Ok(name) = &current_branch_result {
-        merged.retain(|branch| branch.name != base_branch && branch.name != *name);
+    let filter = BranchFilter::new(cli.patterns.clone(), cli.excludes.clone());
+    let candidate = Candidate::new(&cli, &filter, &base_names);
+    // Machine-readable listings imply --list-only.
+    let list_only = cli.list_only || cli.format.implies_list_only(cli.yes);
+    let use_tui = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let cache = if cli.no_cache {
+        MergeCache::disabled()
     } else {
-        merged.retain(|branch| branch.name != base_branch);
+        MergeCache::load(&repo)
+    };
+
+    // On very large repositories, open the selector at once and let the
+    // branches stream in. --keep needs them all before it can preselect.
+    if use_tui
+        && !list_only
+        && !cli.yes
+        && !cli.stdin
+        && cli.keep.is_none()
+        && local_branch_count(&repo)? >= STREAM_THRESHOLD
+    {
+        let mut gone = if cli.prune_remotes {
+            collect_gone_remote_branches(&repo, &bases, &remote, &cache)?
+        } else {
+            Vec::new()
+        };
+        gone.retain(|branch| candidate.accepts(branch));
+        let current = current_branch_result.as_ref().ok().cloned();
+        let cache = Arc::new(cache);
+        let stream = stream_local_branches(&repo, &bases, &remote, cache, move |branch| {
+            // The current branch is listed, locked, as long as it matches.
+            if current.as_ref() == Some(&branch.name) {
+                candidate.filter.matches(&branch.name)
+            } else {
+                candidate.accepts(branch)
+            }
+        })?;
+        let deleted = select_in_tui(
+            &repo,
+            gone,
+            Some(stream),
+            &bases,
+            &current_branch_display,
+            &cli,
+            dirty_warning.as_deref(),
+        )?;
+        return Ok(finish(&cli, deleted));
+    }
+
+    let mut merged = collect_local_branches(&repo, &bases, &remote, &cache)?;
+    merged.retain(|branch| !base_names.contains(&branch.name));
+    // Never a candidate, but the selector still lists it, locked, so it does
+    // not seem to vanish.
+    let current_info = match &current_branch_result {
+        Ok(name) => merged
+            .iter()
+            .position(|branch| branch.name == *name)
+            .map(|index| merged.remove(index)),
+        Err(_) => None,
+    };
+    if cli.prune_remotes {
+        merged.extend(collect_gone_remote_branches(
+            &repo, &bases, &remote, &cache,
+        )?);
+    }
+    // Only a speedup; never worth failing the run over.
+    if let Err(err) = cache.save() {
+        eprintln!("Warning: could not save merge results for next time: {err:#}");
+    }
+
+    if cli.stdin {
+        return delete_from_stdin(&repo, merged, &base_names, &current_branch_result, &cli);
+    }
+
+    merged.retain(|branch| candidate.accepts(branch));
+
+    // Outside the selector, --keep simply leaves the newest branches out.
+    if let Some(n) = cli.keep
+        && (list_only || cli.yes || !use_tui)
+    {
+        let kept = newest_branches(&merged, n);
+        merged.retain(|branch| !kept.contains(&branch.name));
+    }
+
+    // An empty listing and an empty --yes result are both `[]`.
+    if merged.is_empty() && matches!(cli.format, ListFormat::Json | ListFormat::Csv) {
+        print_listing(
+            cli.format,
+            &merged,
+            &bases,
+            &current_branch_display,
+            table_options(&cli),
+        );
+        return Ok(Outcome::Success);
+    }
+    if merged.is_empty() && cli.format == ListFormat::Script {
+        script::print_script(&[], &[]);
+        return Ok(Outcome::NothingToDo);
     }
 
     if merged.is_empty() {
-        println!(
-            "No branches found relative to '{base_branch}' in {}.",
-            repo.path().display()
+        if filter.is_active() {
+            println!(
+                "No branches matching '{}' found relative to '{base_branch}' in {}.",
+                filter.describe(),
+                repo.path().display()
+            );
+        } else {
+            println!(
+                "No branches found relative to '{base_branch}' in {}.",
+                repo.path().display()
+            );
+        }
+        return Ok(Outcome::NothingToDo);
+    }
+
+    if list_only {
+        print_listing(
+            cli.format,
+            &merged,
+            &bases,
+            &current_branch_display,
+            table_options(&cli),
         );
-        return Ok(());
+        return Ok(Outcome::Success);
     }
 
-    if cli.list_only {
-        print_branch_listing(&merged, &base_branch, &current_branch_display);
-        return Ok(());
+    let options = delete_options(&cli);
+
+    if cli.yes {
+        merged
+            .retain(|branch| branch.worktree.is_none() && !matches_any(&cli.protect, &branch.name));
+        let backup = write_backup(&repo, &merged, cli.dry_run)?;
+        let results = delete_branches(&repo, &merged, &options)?;
+        return Ok(report(&cli, &merged, &results, backup.as_deref()));
     }
 
-    let mut app = App::new(merged, base_branch.clone(), current_branch_display.clone());
-    app.set_message(
-        "Use space to toggle branches (green = merged, red = unmerged). Press enter to confirm.",
-    );
+    let deleted = if use_tui {
+        // The selector deletes the branches itself so it can show progress.
+        let mut branches = merged;
+        branches.extend(current_info.filter(|branch| filter.matches(&branch.name)));
+        select_in_tui(
+            &repo,
+            branches,
+            None,
+            &bases,
+            &current_branch_display,
+            &cli,
+            dirty_warning.as_deref(),
+        )?
+    } else {
+        if let Some(warning) = &dirty_warning {
+            eprintln!("{warning}");
+        }
+        match prompt::select(&merged, &cli.protect, cli.force, cli.confirm_threshold)? {
+            Some(selections) => {
+                let backup = write_backup(&repo, &selections, cli.dry_run)?;
+                let results = delete_branches(&repo, &selections, &options)?;
+                Some((selections, results, backup))
+            }
+            None => None,
+        }
+    };
+    Ok(finish(&cli, deleted))
+}
 
-    tui::run(&mut app)?;
+/// What decides whether a branch is offered: --pattern/--exclude,
+/// --merged-only and --older-than. Base branches never are.
+struct Candidate {
+    filter: BranchFilter,
+    bases: Vec<String>,
+    merged_only: bool,
+    older_than: Option<Duration>,
+    include_undated: bool,
+    now: SystemTime,
+}
 
-    if !app.confirmed() {
+impl Candidate {
+    fn new(cli: &Cli, filter: &BranchFilter, bases: &[String]) -> Self {
+        Self {
+            filter: filter.clone(),
+            bases: bases.to_vec(),
+            merged_only: cli.merged_only,
+            older_than: cli.older_than,
+            include_undated: cli.include_undated,
+            now: SystemTime::now(),
+        }
+    }
+
+    fn accepts(&self, branch: &BranchInfo) -> bool {
+        let old_enough = match (self.older_than, branch.age(self.now)) {
+            (None, _) => true,
+            (Some(threshold), Some(age)) => age >= threshold,
+            (Some(_), None) => self.include_undated,
+        };
+        !self.bases.contains(&branch.name)
+            && self.filter.matches(&branch.name)
+            && (!self.merged_only || branch.merged.is_merged())
+            && old_enough
+    }
+}
+
+/// Report what the selector or prompt deleted, or that it was cancelled.
+fn finish(cli: &Cli, deleted: Option<Deleted>) -> Outcome {
+    let Some((selections, results, backup)) = deleted else {
         println!("Aborted - no branches deleted.");
-        return Ok(());
+        return Outcome::Aborted;
+    };
+    report(cli, &selections, &results, backup.as_deref())
+}
+
+fn table_options(cli: &Cli) -> TableOptions {
+    TableOptions {
+        full_hash: cli.full_hash,
+        color: cli.color.enabled(),
+        group_by_author: cli.group_by_author,
+        verbose: cli.verbose,
+        age_format: cli.age_format,
+    }
+}
+
+fn delete_options(cli: &Cli) -> DeleteOptions {
+    DeleteOptions {
+        dry_run: cli.dry_run,
+        force: cli.force,
+        include_remote: cli.include_remote,
+        push_delete: cli.push_delete,
+        protect: cli.protect.clone(),
+        archive_prefix: cli.archive_prefix.clone(),
+    }
+}
+
+/// `--stdin`: delete the branches named on stdin. Names are matched against
+/// every local branch, ignoring --pattern/--merged-only and friends; unknown
+/// names are reported as warnings.
+fn delete_from_stdin(
+    repo: &git2::Repository,
+    branches: Vec<BranchInfo>,
+    bases: &[String],
+    current_branch: &Result<String>,
+    cli: &Cli,
+) -> Result<Outcome> {
+    let mut selections = Vec::new();
+    let mut unknown = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("Failed to read branch names from stdin")?;
+        // Tolerate `git branch` output: `* current` and `+ worktree` markers.
+        let name = line.trim().trim_start_matches(['*', '+']).trim();
+        if name.is_empty() || selections.iter().any(|b: &BranchInfo| b.name == name) {
+            continue;
+        }
+        match branches.iter().find(|branch| branch.name == name) {
+            Some(branch) => selections.push(branch.clone()),
+            None => {
+                let reason = if bases.iter().any(|base| base == name) {
+                    format!("Refused to delete base branch '{name}'")
+                } else if current_branch
+                    .as_deref()
+                    .is_ok_and(|current| current == name)
+                {
+                    format!("Refused to delete the checked-out branch '{name}'")
+                } else {
+                    format!("Branch '{name}' not found")
+                };
+                unknown.push(DeleteResult {
+                    name: name.to_string(),
+                    status: DeleteStatus::Error(reason),
+                    remote: None,
+                    archive_tag: None,
+                });
+            }
+        }
+    }
+
+    let backup = write_backup(repo, &selections, cli.dry_run)?;
+    let mut results = delete_branches(repo, &selections, &delete_options(cli))?;
+    if cli.format == ListFormat::Script {
+        script::print_script(&selections, &results);
+        for result in &unknown {
+            if let DeleteStatus::Error(err) = &result.status {
+                println!("# skipped: {err}");
+            }
+        }
+        results.extend(unknown);
+        notify_done(cli, &results);
+        return Ok(Outcome::from_results(&results));
     }
+    results.extend(unknown);
+    Ok(report(cli, &selections, &results, backup.as_deref()))
+}
 
-    let selections = app.selected_branch_infos();
+/// Branches the user confirmed, their results and the backup file, if any.
+type Deleted = (Vec<BranchInfo>, Vec<DeleteResult>, Option<PathBuf>);
 
-    let results = delete_branches(&repo, &selections, cli.dry_run)?;
+fn select_in_tui(
+    repo: &git2::Repository,
+    branches: Vec<BranchInfo>,
+    stream: Option<BranchStream>,
+    bases: &[String],
+    current_branch: &str,
+    cli: &Cli,
+    dirty_warning: Option<&str>,
+) -> Result<Option<Deleted>> {
+    let filter = BranchFilter::new(cli.patterns.clone(), cli.excludes.clone());
+    let keymap = Keymap::load(cli.keys.as_deref())?;
+    let theme = Theme::load(cli.theme.as_deref())?;
+    let kept = cli.keep.map(|n| {
+        let candidates = branches.iter().filter(|b| b.name != current_branch);
+        newest_branches(candidates, n)
+    });
+    let mut app = App::new(branches, bases.to_vec(), current_branch.to_string());
+    app.set_force(cli.force);
+    app.set_confirm_threshold(cli.confirm_threshold);
+    app.protect(&cli.protect);
+    app.set_sort(cli.sort);
+    app.set_reverse(cli.reverse);
+    app.set_grouped(cli.group_by_prefix);
+    app.set_full_hash(cli.full_hash);
+    app.set_wide(cli.wide);
+    app.set_age_format(cli.age_format);
+    app.set_key_help(&keymap);
+    app.set_theme(theme);
+    if let Some(kept) = &kept {
+        app.select_all_except(kept);
+    }
+    if filter.is_active() {
+        app.set_filter_label(filter.describe());
+    }
+    match dirty_warning {
+        Some(warning) => app.set_message(format!("{warning} Deleting branches now is risky.")),
+        None => app.set_message(
+            "Use space to toggle branches (green = merged, red = unmerged). Press enter to confirm.",
+        ),
+    }
 
-    summarize_results(&results, cli.dry_run);
+    let options = delete_options(cli);
+    let deleted = tui::run(&mut app, repo, keymap, stream, |selections, progress| {
+        let backup = write_backup(repo, selections, cli.dry_run)?;
+        let results = delete_branches_with_progress(repo, selections, &options, progress);
+        Ok((results, backup))
+    })?;
+    let Some(mut deletion) = deleted else {
+        return Ok(None);
+    };
+    // Branches undone on the results screen are no longer deleted.
+    if !deletion.restored.is_empty() {
+        let restored: HashSet<String> = deletion
+            .restored
+            .iter()
+            .filter(|result| result.error.is_none())
+            .map(|result| result.name.clone())
+            .collect();
+        deletion
+            .results
+            .retain(|result| !restored.contains(&result.name));
+        print_restore_results(deletion.restored);
+        println!();
+    }
+    Ok(Some((
+        deletion.selections,
+        deletion.results,
+        deletion.output,
+    )))
+}
+
+/// Names of the `n` branches with the newest commits; undated branches count
+/// as oldest.
+fn newest_branches<'a>(
+    branches: impl IntoIterator<Item = &'a BranchInfo>,
+    n: usize,
+) -> HashSet<String> {
+    let mut by_date: Vec<&BranchInfo> = branches.into_iter().collect();
+    by_date.sort_by_key(|branch| Reverse(branch.commit_timestamp));
+    by_date
+        .into_iter()
+        .take(n)
+        .map(|branch| branch.name.clone())
+        .collect()
+}
+
+fn report(
+    cli: &Cli,
+    branches: &[BranchInfo],
+    results: &[DeleteResult],
+    backup: Option<&Path>,
+) -> Outcome {
+    let hook = after_delete(cli, results);
+    match cli.format {
+        ListFormat::Script => script::print_script(branches, results),
+        ListFormat::Json => print_results_json(results, backup, hook.as_ref()),
+        ListFormat::Table | ListFormat::Csv => {
+            summarize_results(results, cli.dry_run, backup, hook.as_ref())
+        }
+    }
+    notify_done(cli, results);
+    match Outcome::from_results(results) {
+        Outcome::Success if hook.is_some_and(|hook| !hook.succeeded()) => Outcome::Errors,
+        outcome => outcome,
+    }
+}
+
+/// `--after-delete`: run the command with the branches actually deleted.
+/// Skipped on --dry-run and when nothing was deleted.
+fn after_delete(cli: &Cli, results: &[DeleteResult]) -> Option<hook::HookResult> {
+    let command = cli.after_delete.as_deref().filter(|_| !cli.dry_run)?;
+    let deleted: Vec<&str> = results
+        .iter()
+        .filter(|result| matches!(result.status, DeleteStatus::Deleted))
+        .map(|result| result.name.as_str())
+        .collect();
+    if deleted.is_empty() {
+        return None;
+    }
+    Some(hook::run_after_delete(command, &deleted))
+}
+
+/// `--notify`: a desktop notification once the deletions are done. Failing
+/// to show one is only worth a warning.
+fn notify_done(cli: &Cli, results: &[DeleteResult]) {
+    if !cli.notify || results.is_empty() {
+        return;
+    }
+    let (title, body) = notify::summary(results, cli.dry_run);
+    if let Err(err) = notify::send(&title, &body) {
+        eprintln!("Warning: could not show a desktop notification: {err:#}");
+    }
+}
 
-    Ok(())
+fn write_backup(
+    repo: &git2::Repository,
+    branches: &[BranchInfo],
+    dry_run: bool,
+) -> Result<Option<std::path::PathBuf>> {
+    if dry_run || branches.is_empty() {
+        return Ok(None);
+    }
+    backup::write_backup(repo, branches).map(Some)
 }
 
-fn print_branch_listing(branches: &[BranchInfo], base_branch: &str, current_branch: &str) {
-    use std::time::SystemTime;
+fn restore_branches(repo: &git2::Repository, path: &Path) -> Result<Outcome> {
+    let results = backup::restore(repo, path)?;
+    if results.is_empty() {
+        println!("No branches recorded in {}.", path.display());
+        return Ok(Outcome::NothingToDo);
+    }
+    Ok(print_restore_results(results))
+}
 
-    println!("Branches relative to '{base_branch}' (current: {current_branch}):");
-    let now = SystemTime::now();
-    for branch in branches {
-        let tip_id = branch.tip.to_string();
-        let short = &tip_id[..tip_id.len().min(7)];
-        let summary = branch.summary.as_deref().unwrap_or("<no commit message>");
-        let age = branch
-            .age(now)
-            .map(|duration| humantime::format_duration(duration).to_string())
-            .unwrap_or_else(|| "n/a".to_string());
-        let status = if branch.merged { "merged" } else { "unmerged" };
-        match &branch.committer {
-            Some(committer) => println!(
-                "  {:<24} {}  {:<10} {:<8} {}",
-                branch.name, short, status, age, committer
-            ),
-            None => println!("  {:<24} {}  {:<10} {:<8}", branch.name, short, status, age),
+fn print_restore_results(results: Vec<backup::RestoreResult>) -> Outcome {
+    let (restored, failed): (Vec<_>, Vec<_>) = results
+        .into_iter()
+        .partition(|result| result.error.is_none());
+    if !restored.is_empty() {
+        println!("Restored branches:");
+        for result in restored {
+            println!(
+                "  {} -> {}",
+                result.name,
+                &result.tip[..result.tip.len().min(7)]
+            );
         }
-        println!("      {summary}");
+    }
+    let warnings: Vec<String> = failed
+        .into_iter()
+        .filter_map(|result| result.error)
+        .collect();
+    if warnings.is_empty() {
+        return Outcome::Success;
+    }
+    println!("\nWarnings:");
+    for warning in warnings {
+        println!("  {warning}");
+    }
+    Outcome::Errors
+}
+
+/// `--format json` for a deletion: one object per branch on stdout, so the
+/// backup note goes to stderr.
+fn print_results_json(
+    results: &[DeleteResult],
+    backup: Option<&Path>,
+    hook: Option<&hook::HookResult>,
+) {
+    println!(
+        "{}",
+        json::array_lines(results.iter().map(DeleteResult::to_json))
+    );
+    if let Some(path) = backup {
+        eprintln!(
+            "Branch tips were saved to {}; undo with --restore.",
+            path.display()
+        );
+    }
+    if let Some(hook) = hook {
+        eprintln!("{}", hook.describe());
     }
 }
 
-fn summarize_results(results: &[crate::delete::DeleteResult], dry_run: bool) {
+fn summarize_results(
+    results: &[DeleteResult],
+    dry_run: bool,
+    backup: Option<&Path>,
+    hook: Option<&hook::HookResult>,
+) {
     if results.is_empty() {
         println!("No branches selected - nothing to do.");
         return;
     }
 
     let mut deleted = Vec::new();
+    let mut remote_deleted = Vec::new();
+    let mut archived = Vec::new();
     let mut skipped = Vec::new();
 
     for result in results {
@@ -122,6 +709,25 @@ fn summarize_results(results: &[crate::delete::DeleteResult], dry_run: bool) {
             DeleteStatus::DryRun => deleted.push(result.name.clone()),
             DeleteStatus::Error(err) => skipped.push(err.clone()),
         }
+        if let Some(tag) = &result.archive_tag {
+            archived.push(tag.clone());
+        }
+        if let Some(remote) = &result.remote {
+            match &remote.status {
+                DeleteStatus::Deleted | DeleteStatus::DryRun => {
+                    let pushed = matches!(
+                        remote.push,
+                        Some(DeleteStatus::Deleted | DeleteStatus::DryRun)
+                    );
+                    remote_deleted.push(if pushed {
+                        format!("{} (deleted on remote)", remote.name)
+                    } else {
+                        remote.name.clone()
+                    });
+                }
+                DeleteStatus::Error(err) => skipped.push(err.clone()),
+            }
+        }
     }
 
     if dry_run {
@@ -134,10 +740,43 @@ fn summarize_results(results: &[crate::delete::DeleteResult], dry_run: bool) {
         println!("  {name}");
     }
 
+    if !remote_deleted.is_empty() {
+        if dry_run {
+            println!("\nRemote branches that would be deleted:");
+        } else {
+            println!("\nDeleted remote branches:");
+        }
+        for name in &remote_deleted {
+            println!("  {name}");
+        }
+    }
+
+    if !archived.is_empty() {
+        if dry_run {
+            println!("\nArchive tags that would be created:");
+        } else {
+            println!("\nArchive tags created:");
+        }
+        for tag in &archived {
+            println!("  {tag}");
+        }
+    }
+
     if !skipped.is_empty() {
         println!("\nWarnings:");
         for warning in skipped {
             println!("  {warning}");
         }
     }
+
+    if let Some(path) = backup {
+        println!(
+            "\nBranch tips were saved to {}; undo with --restore.",
+            path.display()
+        );
+    }
+
+    if let Some(hook) = hook {
+        println!("\n{}", hook.describe());
+    }
 }
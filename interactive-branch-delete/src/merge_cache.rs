@@ -0,0 +1,204 @@
+//! Merge checks remembered between runs. Each entry is the status of one
+//! branch tip against one base tip, so an entry simply stops matching when
+//! either moves.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+
+use crate::git::MergeStatus;
+
+/// Written to the repository's Git directory so it never shows up as an
+/// untracked file.
+pub const CACHE_FILE: &str = ".branch-delete-merge-cache";
+
+/// Shared by the threads analyzing branches; lookups and new results both go
+/// into `used`, which is what gets saved.
+#[derive(Default)]
+pub struct MergeCache {
+    /// None when disabled by --no-cache.
+    path: Option<PathBuf>,
+    stored: HashMap<(Oid, Oid), MergeStatus>,
+    used: Mutex<HashMap<(Oid, Oid), MergeStatus>>,
+}
+
+impl MergeCache {
+    /// A cache that never hits and is never saved.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// The cache file of `repo`. A missing or unreadable file, or a line
+    /// that does not parse, just means fewer hits.
+    pub fn load(repo: &Repository) -> Self {
+        let path = repo.path().join(CACHE_FILE);
+        let stored = fs::read_to_string(&path)
+            .map(|contents| contents.lines().filter_map(parse_entry).collect())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            stored,
+            used: Mutex::default(),
+        }
+    }
+
+    /// Status of `tip` against the base at `base`, if known.
+    pub fn get(&self, tip: Oid, base: Oid) -> Option<MergeStatus> {
+        self.path.as_ref()?;
+        let mut used = self.used.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(&status) = used.get(&(tip, base)) {
+            return Some(status);
+        }
+        let status = *self.stored.get(&(tip, base))?;
+        used.insert((tip, base), status);
+        Some(status)
+    }
+
+    /// Record the status of `tip` against `base`. Only `Merged` says the
+    /// base contains the tip, so a containment check that fails must not be
+    /// recorded until the patch check has settled the entry.
+    pub fn insert(&self, tip: Oid, base: Oid, status: MergeStatus) {
+        if self.path.is_some() {
+            let mut used = self.used.lock().unwrap_or_else(|err| err.into_inner());
+            used.insert((tip, base), status);
+        }
+    }
+
+    /// Write the entries this run looked up or computed; anything else
+    /// belongs to a tip or base that has since moved, and is dropped. Skipped
+    /// when nothing changed.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let used = self.used.lock().unwrap_or_else(|err| err.into_inner());
+        if *used == self.stored {
+            return Ok(());
+        }
+        let contents: String = used
+            .iter()
+            .map(|((tip, base), status)| format!("{tip} {base} {}\n", status.label()))
+            .collect();
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write merge cache {}", path.display()))
+    }
+}
+
+/// `<tip> <base> <status>`, the status as `MergeStatus::label` writes it.
+fn parse_entry(line: &str) -> Option<((Oid, Oid), MergeStatus)> {
+    let mut fields = line.split_whitespace();
+    let tip = Oid::from_str(fields.next()?).ok()?;
+    let base = Oid::from_str(fields.next()?).ok()?;
+    let status = match fields.next()? {
+        "merged" => MergeStatus::Merged,
+        "squash-merged" => MergeStatus::SquashMerged,
+        "unmerged" => MergeStatus::Unmerged,
+        _ => return None,
+    };
+    Some(((tip, base), status))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn oid(byte: u8) -> Oid {
+        Oid::from_bytes(&[byte; 20]).unwrap()
+    }
+
+    /// Git directory of a scratch repository, removed with it on drop.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(label: &str) -> (Self, Repository) {
+            let dir =
+                std::env::temp_dir().join(format!("ibd-cache-{label}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let repo = Repository::init(&dir).unwrap();
+            (Self(dir), repo)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn cache_file(repo: &Repository) -> PathBuf {
+        repo.path().join(CACHE_FILE)
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn merge_cache_parse_entry_reads_what_save_writes() {
+        let (tip, base) = (oid(1), oid(2));
+        for status in [
+            MergeStatus::Merged,
+            MergeStatus::SquashMerged,
+            MergeStatus::Unmerged,
+        ] {
+            let line = format!("{tip} {base} {}", status.label());
+            assert_eq!(parse_entry(&line), Some(((tip, base), status)));
+        }
+        assert_eq!(parse_entry(&format!("{tip} {base} rebased")), None);
+        assert_eq!(parse_entry(&format!("{tip} {base}")), None);
+        assert_eq!(parse_entry(&format!("{tip} nothex merged")), None);
+        assert_eq!(parse_entry(""), None);
+    }
+
+    #[test]
+    fn merge_cache_saves_and_reloads_what_was_used() {
+        let (_scratch, repo) = Scratch::new("roundtrip");
+        let cache = MergeCache::load(&repo);
+        assert_eq!(cache.get(oid(1), oid(9)), None);
+        cache.insert(oid(1), oid(9), MergeStatus::Merged);
+        cache.insert(oid(2), oid(9), MergeStatus::SquashMerged);
+        cache.save().unwrap();
+
+        let cache = MergeCache::load(&repo);
+        assert_eq!(cache.get(oid(1), oid(9)), Some(MergeStatus::Merged));
+        // A moved base is a different key.
+        assert_eq!(cache.get(oid(1), oid(8)), None);
+        cache.save().unwrap();
+
+        // Only the entry looked up survives the second save.
+        let cache = MergeCache::load(&repo);
+        assert_eq!(cache.get(oid(1), oid(9)), Some(MergeStatus::Merged));
+        assert_eq!(cache.get(oid(2), oid(9)), None);
+    }
+
+    #[test]
+    fn merge_cache_skips_the_write_when_nothing_changed() {
+        let (_scratch, repo) = Scratch::new("unchanged");
+        let path = cache_file(&repo);
+        fs::write(&path, format!("{} {} merged\n", oid(1), oid(9))).unwrap();
+        let cache = MergeCache::load(&repo);
+        assert_eq!(cache.get(oid(1), oid(9)), Some(MergeStatus::Merged));
+
+        fs::write(&path, "untouched\n").unwrap();
+        cache.save().unwrap();
+        assert_eq!(read(&path), "untouched\n");
+
+        cache.insert(oid(2), oid(9), MergeStatus::Unmerged);
+        cache.save().unwrap();
+        assert_ne!(read(&path), "untouched\n");
+    }
+
+    #[test]
+    fn merge_cache_disabled_never_hits() {
+        let cache = MergeCache::disabled();
+        cache.insert(oid(1), oid(9), MergeStatus::Merged);
+        assert_eq!(cache.get(oid(1), oid(9)), None);
+        cache.save().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! Desktop notification for `--notify`, sent through the platform's own
+//! command-line tool: `notify-send` on Linux and the BSDs, `osascript` on
+//! macOS.
+
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::delete::{DeleteResult, DeleteStatus};
+
+/// Title and body summarizing a run's results.
+pub fn summary(results: &[DeleteResult], dry_run: bool) -> (String, String) {
+    let failed = results
+        .iter()
+        .filter(|result| matches!(result.status, DeleteStatus::Error(_)))
+        .count();
+    let done = results.len() - failed;
+    let branches = |count: usize| match count {
+        1 => "1 branch".to_string(),
+        count => format!("{count} branches"),
+    };
+    let title = if dry_run {
+        "Branch cleanup dry run finished"
+    } else {
+        "Branch cleanup finished"
+    };
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    let body = match failed {
+        0 => format!("{verb} {}", branches(done)),
+        failed => format!("{verb} {}, {failed} failed", branches(done)),
+    };
+    (title.to_string(), body)
+}
+
+pub fn send(title: &str, body: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command
+            .arg("--app-name=interactive-branch-delete")
+            .arg(title)
+            .arg(body);
+        command
+    } else {
+        bail!("no notification backend on this platform");
+    };
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| anyhow!("{:?} is unavailable: {err}", command.get_program()))?;
+    if !status.success() {
+        bail!("{:?} failed ({status})", command.get_program());
+    }
+    Ok(())
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(status: DeleteStatus) -> DeleteResult {
+        DeleteResult {
+            name: "topic".to_string(),
+            status,
+            remote: None,
+            archive_tag: None,
+        }
+    }
+
+    #[test]
+    fn notify_summary_counts_deleted_and_failed_branches() {
+        let results = [
+            result(DeleteStatus::Deleted),
+            result(DeleteStatus::Deleted),
+            result(DeleteStatus::Error("locked".to_string())),
+        ];
+        let (title, body) = summary(&results, false);
+        assert_eq!(title, "Branch cleanup finished");
+        assert_eq!(body, "Deleted 2 branches, 1 failed");
+        let (_, body) = summary(&results[..1], true);
+        assert_eq!(body, "Would delete 1 branch");
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{Context, Result};
+
+use crate::app::{ConfirmStep, confirm_step, risk_warnings};
+use crate::filter::matches_any;
+use crate::git::BranchInfo;
+
+/// Numbered-list fallback for when there is no terminal to draw the selector
+/// on. The selection is confirmed under the same rules as in the selector.
+/// Returns the chosen branches, or None when the input is empty or `q` or
+/// the deletion is not confirmed.
+pub fn select(
+    branches: &[BranchInfo],
+    protect: &[String],
+    force: bool,
+    confirm_threshold: usize,
+) -> Result<Option<Vec<BranchInfo>>> {
+    let width = branches.len().to_string().len();
+    for (index, branch) in branches.iter().enumerate() {
+        let note = if branch.worktree.is_some() {
+            "  (worktree)"
+        } else if matches_any(protect, &branch.name) {
+            "  (protected)"
+        } else {
+            ""
+        };
+        println!(
+            "{:>width$}) {:<24} {:<13} {}{note}",
+            index + 1,
+            branch.name,
+            branch.merged.label(),
+            branch.summary.as_deref().unwrap_or("<no commit message>"),
+        );
+    }
+    print!("Branches to delete (e.g. 1,3,5-7; empty to abort): ");
+    io::stdout().flush()?;
+
+    let mut input = io::stdin().lock();
+    let line = read_answer(&mut input)?;
+    if line.is_empty() || line.eq_ignore_ascii_case("q") {
+        return Ok(None);
+    }
+
+    let indices = parse_selection(&line, branches.len()).map_err(anyhow::Error::msg)?;
+    let mut selected = Vec::with_capacity(indices.len());
+    for index in indices {
+        let branch = &branches[index];
+        if branch.worktree.is_some() || matches_any(protect, &branch.name) {
+            eprintln!("Skipping '{}': it cannot be deleted.", branch.name);
+            continue;
+        }
+        selected.push(branch.clone());
+    }
+
+    let confirmed = confirm(
+        &selected.iter().collect::<Vec<_>>(),
+        force,
+        confirm_threshold,
+        &mut input,
+    )?;
+    Ok(confirmed.then_some(selected))
+}
+
+/// Ask for the confirmation `confirm_step` calls for, after printing what
+/// deleting `selection` would lose. A refusal is reported on stderr.
+fn confirm(
+    selection: &[&BranchInfo],
+    force: bool,
+    threshold: usize,
+    input: &mut impl BufRead,
+) -> Result<bool> {
+    let step = match confirm_step(selection, force, threshold) {
+        Ok(step) => step,
+        Err(refusal) => {
+            eprintln!("{refusal}");
+            return Ok(false);
+        }
+    };
+    for warning in risk_warnings(selection) {
+        eprintln!("{warning}");
+    }
+
+    let count = selection.len();
+    let plural = if count == 1 { "" } else { "es" };
+    match step {
+        ConfirmStep::YesNo => print!("Delete {count} branch{plural}? [y/N] "),
+        ConfirmStep::TypeCount => print!("Type {count} to delete {count} branch{plural}: "),
+    }
+    io::stdout().flush()?;
+    let answer = read_answer(input)?;
+    Ok(match step {
+        ConfirmStep::YesNo => {
+            answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+        }
+        ConfirmStep::TypeCount => answer.parse() == Ok(count),
+    })
+}
+
+/// One trimmed line of input; empty at end of input.
+fn read_answer(input: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    input
+        .read_line(&mut line)
+        .context("Failed to read an answer from stdin")?;
+    if !io::stdin().is_terminal() {
+        // Piped input is not echoed; end the prompt line ourselves.
+        println!();
+    }
+    Ok(line.trim().to_string())
+}
+
+/// Parse `1,3,5-7` into sorted, de-duplicated zero-based indices below `len`.
+fn parse_selection(input: &str, len: usize) -> Result<Vec<usize>, String> {
+    let parse = |s: &str| -> Result<usize, String> {
+        let n: usize = s
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a branch number", s.trim()))?;
+        if n == 0 || n > len {
+            return Err(format!("{n} is out of range (1-{len})"));
+        }
+        Ok(n - 1)
+    };
+
+    let mut indices = Vec::new();
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("range '{part}' is backwards"));
+                }
+                indices.extend(start..=end);
+            }
+            None => indices.push(parse(part)?),
+        }
+    }
+    indices.sort_unstable();
+    indices.dedup();
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use git2::Oid;
+
+    use super::{confirm, parse_selection};
+    use crate::git::{BranchInfo, MergeStatus};
+
+    fn branch(name: &str, merged: MergeStatus) -> BranchInfo {
+        BranchInfo {
+            name: name.to_string(),
+            tip: Oid::zero(),
+            summary: None,
+            committer: None,
+            commit_timestamp: None,
+            merged,
+            merged_into: None,
+            ahead: 0,
+            behind: 0,
+            merge_base: None,
+            remote: None,
+            is_remote_tracking: false,
+            worktree: None,
+            upstream: None,
+            pushed: true,
+            dependents: Vec::new(),
+            description: None,
+            stashes: 0,
+        }
+    }
+
+    fn answer(selection: &[BranchInfo], force: bool, threshold: usize, input: &str) -> bool {
+        let selection: Vec<&BranchInfo> = selection.iter().collect();
+        confirm(&selection, force, threshold, &mut Cursor::new(input)).unwrap()
+    }
+
+    #[test]
+    fn prompt_unmerged_needs_force_before_asking() {
+        let selection = [branch("wip", MergeStatus::Unmerged)];
+        assert!(!answer(&selection, false, 10, "y\n"));
+        assert!(answer(&selection, true, 10, "y\n"));
+        assert!(!answer(&selection, true, 10, "\n"));
+    }
+
+    #[test]
+    fn prompt_above_threshold_needs_the_count_typed() {
+        let selection: Vec<_> = (0..3)
+            .map(|i| branch(&format!("b{i}"), MergeStatus::Merged))
+            .collect();
+        assert!(!answer(&selection, false, 2, "y\n"));
+        assert!(!answer(&selection, false, 2, "2\n"));
+        assert!(answer(&selection, false, 2, "3\n"));
+        assert!(answer(&selection, false, 3, "y\n"));
+        assert!(!answer(&[], false, 3, "y\n"));
+    }
+
+    #[test]
+    fn prompt_parses_lists_and_ranges() {
+        assert_eq!(parse_selection("1,3,5-7", 8).unwrap(), vec![0, 2, 4, 5, 6]);
+        assert_eq!(parse_selection(" 2 , 2-3 ,", 3).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn prompt_rejects_bad_numbers() {
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+        assert!(parse_selection("two", 3).is_err());
+    }
+}
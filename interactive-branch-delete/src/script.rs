@@ -0,0 +1,140 @@
+use std::fmt::Write as _;
+
+use crate::delete::{DeleteResult, DeleteStatus};
+use crate::git::BranchInfo;
+
+/// Print the deletion plan from a dry run as a shell script of the `git`
+/// commands that carry it out. Branches the tool would refuse become
+/// comments, so the script never does more than the tool would.
+pub fn print_script(branches: &[BranchInfo], results: &[DeleteResult]) {
+    print!("{}", script(branches, results));
+}
+
+/// The script for `print_script`, in the order of `results`, which is the
+/// order the branches are deleted in (descendants before their bases).
+fn script(branches: &[BranchInfo], results: &[DeleteResult]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "#!/bin/sh");
+    let _ = writeln!(
+        out,
+        "# Branch deletion plan from us-interactive-branch-delete --dry-run."
+    );
+    let _ = writeln!(out, "set -e");
+    for result in results {
+        match &result.status {
+            DeleteStatus::DryRun | DeleteStatus::Deleted => {}
+            DeleteStatus::Error(err) => {
+                let _ = writeln!(out, "# skipped: {}", err.replace('\n', " "));
+                continue;
+            }
+        }
+        // Results are not in selection order, so pair them up by name
+        let Some(branch) = branches.iter().find(|branch| branch.name == result.name) else {
+            continue;
+        };
+        if let Some(tag) = &result.archive_tag {
+            let _ = writeln!(out, "git tag {} {}", shell_quote(tag), branch.tip);
+        }
+        if branch.is_remote_tracking {
+            let _ = writeln!(out, "git branch -r -D {}", shell_quote(&branch.name));
+            continue;
+        }
+        let _ = writeln!(out, "git branch -D {}", shell_quote(&branch.name));
+
+        let (Some(remote), Some(remote_result)) = (&branch.remote, &result.remote) else {
+            continue;
+        };
+        if remote_result.push.is_some() {
+            let _ = writeln!(
+                out,
+                "git push {} --delete {}",
+                shell_quote(&remote.remote),
+                shell_quote(&remote.branch)
+            );
+        } else {
+            let _ = writeln!(
+                out,
+                "git branch -r -D {}",
+                shell_quote(&remote.display_name())
+            );
+        }
+    }
+    out
+}
+
+/// Leave plain ref names alone and single-quote anything else.
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-+@:,=".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{Oid, Repository};
+
+    use super::*;
+    use crate::delete::{DeleteOptions, delete_branches};
+    use crate::git::MergeStatus;
+
+    fn branch(name: &str, dependents: &[&str]) -> BranchInfo {
+        BranchInfo {
+            name: name.to_string(),
+            tip: Oid::zero(),
+            summary: None,
+            committer: None,
+            commit_timestamp: None,
+            merged: MergeStatus::Merged,
+            merged_into: Some("main".to_string()),
+            ahead: 0,
+            behind: 0,
+            merge_base: None,
+            remote: None,
+            is_remote_tracking: false,
+            worktree: None,
+            upstream: None,
+            pushed: true,
+            dependents: dependents.iter().map(|name| name.to_string()).collect(),
+            description: None,
+            stashes: 0,
+        }
+    }
+
+    #[test]
+    fn script_follows_deletion_order_and_comments_out_refused_branches() {
+        let dir = std::env::temp_dir().join(format!("ibd-script-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        // feature-b descends from feature-a, so it is deleted first
+        let selection = [
+            branch("feature-a", &["feature-b"]),
+            branch("feature-b", &[]),
+            branch("it's", &[]),
+        ];
+        let options = DeleteOptions {
+            dry_run: true,
+            protect: vec!["feature-a".to_string()],
+            ..DeleteOptions::default()
+        };
+        let results = delete_branches(&repo, &selection, &options).unwrap();
+        drop(repo);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let plan = script(&selection, &results);
+        let commands: Vec<&str> = plan.lines().skip(3).collect();
+        assert_eq!(
+            commands,
+            [
+                "git branch -D feature-b",
+                "git branch -D 'it'\\''s'",
+                "# skipped: Refused to delete protected branch 'feature-a'",
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-repository defaults remembered between runs: the last `--base` and
+//! `--remote` given, in `state.json` under the config directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow};
+use git2::Repository;
+
+use crate::config;
+use crate::json::{self, Value};
+
+pub const STATE_FILE: &str = "state.json";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Remembered {
+    pub bases: Vec<String>,
+    pub remote: Option<String>,
+}
+
+/// The absolute path of the working tree, or of the Git directory when the
+/// repository is bare.
+fn repo_key(repo: &Repository) -> String {
+    let dir = repo.workdir().unwrap_or_else(|| repo.path());
+    fs::canonicalize(dir)
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Every repository's entry. A missing or unreadable file counts as empty,
+/// since the state is only a convenience.
+fn read_all(path: &PathBuf) -> BTreeMap<String, Value> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| json::parse(&contents).ok())
+        .and_then(|document| match document {
+            Value::Object(entries) => Some(entries),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn write_all(path: &PathBuf, entries: BTreeMap<String, Value>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    }
+    fs::write(path, format!("{}\n", Value::Object(entries)))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn state_path() -> Result<PathBuf> {
+    config::config_file(STATE_FILE)
+        .ok_or_else(|| anyhow!("Neither XDG_CONFIG_HOME nor HOME is set"))
+}
+
+/// What was last used in `repo`, if anything.
+pub fn load(repo: &Repository) -> Option<Remembered> {
+    let entries = read_all(&config::config_file(STATE_FILE)?);
+    let entry = entries.get(&repo_key(repo))?;
+    let bases = entry
+        .get("bases")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect();
+    let remote = entry
+        .get("remote")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Some(Remembered { bases, remote })
+}
+
+pub fn save(repo: &Repository, remembered: &Remembered) -> Result<()> {
+    let path = state_path()?;
+    let mut entries = read_all(&path);
+    let entry = BTreeMap::from([
+        (
+            "bases".to_string(),
+            Value::Array(
+                remembered
+                    .bases
+                    .iter()
+                    .map(|base| base.as_str().into())
+                    .collect(),
+            ),
+        ),
+        ("remote".to_string(), remembered.remote.clone().into()),
+    ]);
+    entries.insert(repo_key(repo), Value::Object(entry));
+    write_all(&path, entries)
+}
+
+/// Drop `repo`'s entry; false when there was none.
+pub fn forget(repo: &Repository) -> Result<bool> {
+    let path = state_path()?;
+    let mut entries = read_all(&path);
+    if entries.remove(&repo_key(repo)).is_none() {
+        return Ok(false);
+    }
+    write_all(&path, entries)?;
+    Ok(true)
+}
@@ -0,0 +1,265 @@
+//! Colors for the selector: built-in themes and `theme.toml` overrides.
+
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail};
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::config;
+use crate::git::MergeStatus;
+use crate::json::Value;
+
+pub const THEME_FILE: &str = "theme.toml";
+
+pub const BUILT_IN: [&str; 3] = ["default", "high-contrast", "monochrome"];
+
+/// Style for each role the selector draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub merged: Style,
+    pub squash_merged: Style,
+    pub unmerged: Style,
+    /// `[x]` markers and selection counts.
+    pub selected: Style,
+    /// The highlighted row.
+    pub cursor: Style,
+    pub branch: Style,
+    /// Remote-tracking entries offered by --prune-remotes.
+    pub remote_branch: Style,
+    /// Divergence counts, commit ids and group arrows.
+    pub accent: Style,
+    /// Ages, dates and authors.
+    pub date: Style,
+    /// Remote counterparts and upstream state.
+    pub remote: Style,
+    /// An upstream that is gone from the remote.
+    pub gone: Style,
+    pub error: Style,
+    /// The checked-out branch and its `(current)` tag.
+    pub current: Style,
+    /// Rows in a visual range, behind their own colors.
+    pub visual: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            merged: fg(Color::Green),
+            squash_merged: fg(Color::LightGreen),
+            unmerged: fg(Color::Red),
+            selected: fg(Color::Cyan),
+            cursor: Style::default()
+                .fg(Color::White)
+                .bg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            branch: fg(Color::Yellow),
+            remote_branch: fg(Color::LightBlue),
+            accent: fg(Color::Cyan),
+            date: fg(Color::Magenta),
+            remote: fg(Color::Blue),
+            gone: fg(Color::LightGreen),
+            error: fg(Color::Red),
+            current: fg(Color::LightMagenta).add_modifier(Modifier::BOLD),
+            visual: Style::default().bg(Color::DarkGray),
+        }
+    }
+}
+
+impl Theme {
+    /// A built-in theme by name.
+    pub fn built_in(name: &str) -> Option<Self> {
+        let bold = |color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+        let fg = |color| Style::default().fg(color);
+        let plain = Style::default();
+        match name {
+            "default" => Some(Self::default()),
+            // Bright, bold colors that stay distinct on light and dark backgrounds.
+            "high-contrast" => Some(Self {
+                merged: bold(Color::LightGreen),
+                squash_merged: bold(Color::LightCyan),
+                unmerged: bold(Color::LightRed),
+                selected: bold(Color::LightYellow),
+                cursor: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                branch: bold(Color::White),
+                remote_branch: fg(Color::LightCyan),
+                accent: fg(Color::LightCyan),
+                date: fg(Color::LightMagenta),
+                remote: fg(Color::LightBlue),
+                gone: bold(Color::LightYellow),
+                error: bold(Color::LightRed),
+                current: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::LightMagenta)
+                    .add_modifier(Modifier::BOLD),
+                visual: Style::default().bg(Color::Gray),
+            }),
+            // No colors at all: merge state is carried by text attributes.
+            "monochrome" => Some(Self {
+                merged: plain,
+                squash_merged: plain.add_modifier(Modifier::ITALIC),
+                unmerged: plain.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                selected: plain.add_modifier(Modifier::BOLD),
+                cursor: plain.add_modifier(Modifier::REVERSED),
+                branch: plain,
+                remote_branch: plain.add_modifier(Modifier::ITALIC),
+                accent: plain,
+                date: plain,
+                remote: plain,
+                gone: plain.add_modifier(Modifier::BOLD),
+                error: plain.add_modifier(Modifier::BOLD),
+                current: plain.add_modifier(Modifier::BOLD | Modifier::ITALIC),
+                visual: plain.add_modifier(Modifier::UNDERLINED),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Resolve `--theme`: a built-in name or a TOML file. Without the flag,
+    /// `theme.toml` in the config directory is used when it exists.
+    pub fn load(spec: Option<&str>) -> Result<Self> {
+        if let Some(theme) = spec.and_then(Self::built_in) {
+            return Ok(theme);
+        }
+        if let Some(spec) = spec
+            && !Path::new(spec).is_file()
+        {
+            bail!(
+                "Unknown theme '{spec}'; use one of {} or a path to a theme file",
+                BUILT_IN.join(", ")
+            );
+        }
+        match config::load(spec.map(Path::new), THEME_FILE)? {
+            Some((path, document)) => Self::from_document(&document)
+                .map_err(|err| err.context(format!("Invalid theme file {}", path.display()))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// A theme file: an optional `base` built-in plus `role = "style"`
+    /// entries, where a style is words such as `"bold red"` or
+    /// `"black on #ffcc00"`.
+    fn from_document(document: &Value) -> Result<Self> {
+        let Value::Object(entries) = document else {
+            bail!("expected a table of role = style");
+        };
+        let mut theme = match entries.get("base") {
+            Some(Value::String(name)) => {
+                Self::built_in(name).ok_or_else(|| anyhow!("unknown base theme '{name}'"))?
+            }
+            Some(_) => bail!("'base' must be a theme name"),
+            None => Self::default(),
+        };
+        for (role, value) in entries {
+            if role == "base" {
+                continue;
+            }
+            let Value::String(spec) = value else {
+                bail!("style for '{role}' must be a string");
+            };
+            let style = parse_style(spec).map_err(|err| err.context(format!("role '{role}'")))?;
+            *theme
+                .role_mut(role)
+                .ok_or_else(|| anyhow!("unknown role '{role}'"))? = style;
+        }
+        Ok(theme)
+    }
+
+    fn role_mut(&mut self, role: &str) -> Option<&mut Style> {
+        Some(match role {
+            "merged" => &mut self.merged,
+            "squash_merged" => &mut self.squash_merged,
+            "unmerged" => &mut self.unmerged,
+            "selected" => &mut self.selected,
+            "cursor" => &mut self.cursor,
+            "branch" => &mut self.branch,
+            "remote_branch" => &mut self.remote_branch,
+            "accent" => &mut self.accent,
+            "date" => &mut self.date,
+            "remote" => &mut self.remote,
+            "gone" => &mut self.gone,
+            "error" => &mut self.error,
+            "current" => &mut self.current,
+            "visual" => &mut self.visual,
+            _ => return None,
+        })
+    }
+
+    pub fn status(&self, status: MergeStatus) -> Style {
+        match status {
+            MergeStatus::Merged => self.merged,
+            MergeStatus::SquashMerged => self.squash_merged,
+            MergeStatus::Unmerged => self.unmerged,
+        }
+    }
+}
+
+/// `[modifiers...] [color] [on <color>]`, e.g. `bold underlined red on black`.
+fn parse_style(spec: &str) -> Result<Style> {
+    let mut style = Style::default();
+    let mut words = spec.split_whitespace();
+    while let Some(word) = words.next() {
+        let modifier = match word.to_ascii_lowercase().as_str() {
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underline" | "underlined" => Modifier::UNDERLINED,
+            "reverse" | "reversed" => Modifier::REVERSED,
+            "on" => {
+                let color = words
+                    .next()
+                    .ok_or_else(|| anyhow!("'on' needs a background color"))?;
+                style = style.bg(parse_color(color)?);
+                continue;
+            }
+            _ => {
+                style = style.fg(parse_color(word)?);
+                continue;
+            }
+        };
+        style = style.add_modifier(modifier);
+    }
+    Ok(style)
+}
+
+fn parse_color(word: &str) -> Result<Color> {
+    word.parse()
+        .map_err(|_| anyhow!("unknown color or attribute '{word}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml;
+
+    #[test]
+    fn theme_file_overrides_roles_of_its_base() {
+        let document = toml::parse(
+            "base = \"monochrome\"\nunmerged = \"bold red\"\ncursor = \"black on #ffcc00\"\n",
+        )
+        .unwrap();
+        let theme = Theme::from_document(&document).unwrap();
+        assert_eq!(
+            theme.unmerged,
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(
+            theme.cursor,
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Rgb(0xff, 0xcc, 0x00))
+        );
+        assert_eq!(theme.merged, Theme::built_in("monochrome").unwrap().merged);
+    }
+
+    #[test]
+    fn theme_file_rejects_unknown_roles_and_colors() {
+        let unknown_role = toml::parse("merge = \"green\"\n").unwrap();
+        assert!(Theme::from_document(&unknown_role).is_err());
+        let unknown_color = toml::parse("merged = \"greenish\"\n").unwrap();
+        assert!(Theme::from_document(&unknown_color).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Just enough TOML for the config files this tool reads: tables, strings,
+//! booleans, numbers and arrays. Documents are returned as `json::Value`
+//! objects so both formats share one value type.
+
+use std::collections::BTreeMap;
+
+use crate::json::Value;
+
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let mut root = BTreeMap::new();
+    let mut table: Vec<String> = Vec::new();
+
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(Value::Object(root)),
+            Some('[') => {
+                parser.pos += 1;
+                table = parser.key_path(']')?;
+                parser.pos += 1;
+                parser.end_of_line()?;
+                // Create the table so empty sections still show up.
+                table_mut(&mut root, &table).map_err(|err| parser.error(&err))?;
+            }
+            Some(_) => {
+                let path = parser.key_path('=')?;
+                parser.pos += 1;
+                let value = parser.value()?;
+                parser.end_of_line()?;
+                let (key, parents) = path.split_last().expect("key paths are never empty");
+                let full: Vec<String> = table.iter().chain(parents).cloned().collect();
+                let target = table_mut(&mut root, &full).map_err(|err| parser.error(&err))?;
+                if target.insert(key.clone(), value).is_some() {
+                    return Err(parser.error(&format!("duplicate key '{key}'")));
+                }
+            }
+        }
+    }
+}
+
+/// The table at `path`, created as needed.
+fn table_mut<'a>(
+    root: &'a mut BTreeMap<String, Value>,
+    path: &[String],
+) -> Result<&'a mut BTreeMap<String, Value>, String> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(BTreeMap::new()));
+        table = match entry {
+            Value::Object(map) => map,
+            _ => return Err(format!("'{key}' is not a table")),
+        };
+    }
+    Ok(table)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.chars[..self.pos.min(self.chars.len())]
+            .iter()
+            .filter(|&&c| c == '\n')
+            .count()
+            + 1;
+        format!("line {line}: {message}")
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Whitespace, newlines and comments, e.g. between entries or inside arrays.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n' | '\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{c}' after value"))),
+        }
+    }
+
+    /// A dotted key such as `a."b c".d`, stopping before `end`.
+    fn key_path(&mut self, end: char) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(key);
+            self.skip_spaces();
+            match self.peek() {
+                Some('.') => self.pos += 1,
+                Some(c) if c == end => return Ok(path),
+                _ => return Err(self.error(&format!("expected '.' or '{end}' after key"))),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('t' | 'f') => self.boolean(),
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-') => self.number(),
+            Some(c) => Err(self.error(&format!("unexpected '{c}' where a value was expected"))),
+            None => Err(self.error("expected a value but reached end of input")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\n' => return Err(self.error("newline in string")),
+                '\\' => {
+                    let escape = self.peek();
+                    self.pos += 1;
+                    match escape {
+                        Some('"') => out.push('"'),
+                        Some('\\') => out.push('\\'),
+                        Some('n') => out.push('\n'),
+                        Some('t') => out.push('\t'),
+                        Some('r') => out.push('\r'),
+                        Some('u') => {
+                            let end = (self.pos + 4).min(self.chars.len());
+                            let hex: String = self.chars[self.pos..end].iter().collect();
+                            self.pos = end;
+                            let code = u32::from_str_radix(&hex, 16)
+                                .map_err(|_| self.error("invalid \\u escape"))?;
+                            out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(self.error("invalid escape in string")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some('\'' | '\n')) {
+            self.pos += 1;
+        }
+        if self.peek() != Some('\'') {
+            return Err(self.error("unterminated string"));
+        }
+        let text = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        Ok(text)
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn boolean(&mut self) -> Result<Value, String> {
+        for (word, value) in [("true", true), ("false", false)] {
+            let end = self.pos + word.len();
+            if self
+                .chars
+                .get(self.pos..end)
+                .is_some_and(|s| s.iter().copied().eq(word.chars()))
+            {
+                self.pos = end;
+                return Ok(Value::Bool(value));
+            }
+        }
+        Err(self.error("invalid value; strings must be quoted"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | '_' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos]
+            .iter()
+            .filter(|&&c| c != '_')
+            .collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("invalid number '{text}'")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_parses_tables_strings_and_arrays() {
+        let document = parse(
+            "# keys\n\
+             toggle = [\"space\", 'x'] # trailing comment\n\
+             confirm = \"dd\"\n\
+             \n\
+             [colors]\n\
+             merged = \"green\"\n\
+             bold = true\n\
+             width = 1_000\n\
+             list = [\n  \"a\",\n  \"b\",\n]\n",
+        )
+        .unwrap();
+        let toggle: Vec<&str> = document
+            .get("toggle")
+            .and_then(Value::as_array)
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(toggle, ["space", "x"]);
+        assert_eq!(document.get("confirm").and_then(Value::as_str), Some("dd"));
+        let colors = document.get("colors").unwrap();
+        assert_eq!(colors.get("merged").and_then(Value::as_str), Some("green"));
+        assert_eq!(colors.get("bold"), Some(&Value::Bool(true)));
+        assert_eq!(colors.get("width"), Some(&Value::Number(1000.0)));
+        assert_eq!(
+            colors.get("list").and_then(Value::as_array).unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn toml_reports_the_line_of_an_error() {
+        let err = parse("a = \"ok\"\nb = bare\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+        let err = parse("a = 1\na = 2\n").unwrap_err();
+        assert!(err.contains("duplicate key 'a'"), "{err}");
+    }
+}
@@ -1,63 +1,295 @@
-use std::{io, time::Duration};
+use std::{
+    io::{self, Stdout},
+    time::Duration,
+};
 
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseButton,
+        MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
+use git2::Repository;
 use ratatui::{Terminal, backend::CrosstermBackend};
 
 use crate::app::App;
+use crate::backup::{self, RestoreResult};
+use crate::delete::{DeleteResult, DeleteStatus};
+use crate::git::{BranchInfo, BranchStream};
+use crate::keys::{Action, Keymap};
+use crate::ui::COMMIT_PANE_ROWS;
+
+/// A confirmed selection and what became of it.
+pub struct Deletion<T> {
+    pub selections: Vec<BranchInfo>,
+    pub results: Vec<DeleteResult>,
+    /// Branches recreated with `u` on the results screen.
+    pub restored: Vec<RestoreResult>,
+    /// Whatever else `delete` returned.
+    pub output: T,
+}
 
-pub fn run(app: &mut App) -> Result<()> {
-    if app.is_empty() {
+/// Run the selector. Once a selection is confirmed, `delete` is called with
+/// it while the screen shows a progress bar fed by its callback. When
+/// anything was deleted, a results screen follows where `u` undoes it.
+/// Returns None when cancelled. With a `stream`, the selector opens right
+/// away and branches are added as they finish loading.
+pub fn run<T>(
+    app: &mut App,
+    repo: &Repository,
+    mut keymap: Keymap,
+    mut stream: Option<BranchStream>,
+    delete: impl FnOnce(&[BranchInfo], &mut dyn FnMut(&DeleteResult)) -> Result<(Vec<DeleteResult>, T)>,
+) -> Result<Option<Deletion<T>>> {
+    if app.is_empty() && stream.is_none() {
         app.cancel();
-        return Ok(());
+        return Ok(None);
     }
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let result = loop {
+    let result: Result<()> = loop {
+        if let Some(loading) = &mut stream {
+            load_streamed(app, loading);
+            if loading.is_done() || app.loading().is_none() {
+                stream = None;
+                app.set_loading(None);
+            }
+        }
+        app.load_unique_commits(repo, COMMIT_PANE_ROWS);
         terminal.draw(|frame| crate::ui::draw(frame, app))?;
 
         if app.should_quit() {
             break Ok(());
         }
 
-        if event::poll(Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                handle_key_event(app, key);
+        // Redraw more often while branches are still arriving.
+        let tick = if stream.is_some() { 50 } else { 200 };
+        if event::poll(Duration::from_millis(tick))? {
+            match event::read()? {
+                Event::Key(key) => handle_key_event(app, &mut keymap, key),
+                Event::Mouse(mouse) => handle_mouse_event(app, mouse),
+                _ => {}
             }
         }
     };
 
+    // Delete before leaving the alternate screen so the progress bar has
+    // somewhere to go.
+    let deleted = result.and_then(|()| {
+        if !app.confirmed() {
+            return Ok(None);
+        }
+        let selections = app.selected_branch_infos();
+        let total = selections.len();
+        let mut done = 0;
+        let mut progress = |result: &DeleteResult| {
+            done += 1;
+            // A failed redraw only costs the progress display.
+            let _ = terminal
+                .draw(|frame| crate::ui::draw_progress(frame, app.theme(), done, total, result));
+        };
+        let (results, output) = delete(&selections, &mut progress)?;
+        let restored = if results
+            .iter()
+            .any(|result| matches!(result.status, DeleteStatus::Deleted))
+        {
+            show_results(&mut terminal, app, repo, &selections, &results)?
+        } else {
+            Vec::new()
+        };
+        Ok(Some(Deletion {
+            selections,
+            results,
+            restored,
+            output,
+        }))
+    });
+
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
 
-    result
+    deleted
 }
 
-fn handle_key_event(app: &mut App, key: KeyEvent) {
-    match key.code {
-        KeyCode::Char('q') | KeyCode::Esc => app.cancel(),
-        KeyCode::Down | KeyCode::Char('j') => app.move_down(),
-        KeyCode::Up | KeyCode::Char('k') => app.move_up(),
-        KeyCode::Char(' ') => {
-            app.toggle_current();
+/// List what the deletion did until a key is pressed. `u` first recreates
+/// the deleted branches from the tips recorded before deleting them.
+fn show_results(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &App,
+    repo: &Repository,
+    selections: &[BranchInfo],
+    results: &[DeleteResult],
+) -> Result<Vec<RestoreResult>> {
+    let mut restored: Option<Vec<RestoreResult>> = None;
+    loop {
+        terminal.draw(|frame| {
+            crate::ui::draw_results(frame, app.theme(), results, restored.as_deref())
+        })?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        match key.code {
+            KeyCode::Char('u') if restored.is_none() => {
+                let deleted: Vec<&BranchInfo> = selections
+                    .iter()
+                    .filter(|branch| {
+                        results.iter().any(|result| {
+                            result.name == branch.name
+                                && matches!(result.status, DeleteStatus::Deleted)
+                        })
+                    })
+                    .collect();
+                restored = Some(backup::restore_deleted(repo, &deleted));
+            }
+            _ => return Ok(restored.unwrap_or_default()),
+        }
+    }
+}
+
+/// Move whatever `stream` has finished into the list. A failure stops the
+/// loading and is shown in the status line.
+fn load_streamed(app: &mut App, stream: &mut BranchStream) {
+    match stream.poll() {
+        Ok(branches) => {
+            app.add_branches(branches);
+            app.set_loading(Some(stream.progress()));
+        }
+        Err(err) => {
+            app.set_message(format!("Stopped loading branches: {err:#}"));
+            app.set_loading(None);
+        }
+    }
+}
+
+/// Click a row to move to it, click its checkbox to toggle it, and scroll
+/// to move through the list.
+fn handle_mouse_event(app: &mut App, mouse: MouseEvent) {
+    if app.is_confirming() {
+        return;
+    }
+    let click = matches!(mouse.kind, MouseEventKind::Down(MouseButton::Left));
+    if app.is_showing_help() {
+        if click {
+            app.toggle_help();
+        }
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollDown => app.move_down(),
+        MouseEventKind::ScrollUp => app.move_up(),
+        _ if click => {
+            if let Some((row, column)) = app.row_at(mouse.column, mouse.row) {
+                app.move_to(row);
+                if column.is_some_and(crate::ui::is_checkbox_column) {
+                    app.toggle_current();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_key_event(app: &mut App, keymap: &mut Keymap, key: KeyEvent) {
+    if app.typed_count().is_some() {
+        match key.code {
+            KeyCode::Char(ch) => app.type_count_digit(ch),
+            KeyCode::Backspace => app.erase_count_digit(),
+            KeyCode::Enter => app.submit_count(),
+            KeyCode::Esc => app.cancel_confirmation(),
+            _ => {}
+        }
+        return;
+    }
+    if app.is_confirming() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => app.accept_confirmation(),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.cancel_confirmation(),
+            _ => {}
+        }
+        return;
+    }
+
+    // Any key closes the help overlay.
+    if app.is_showing_help() {
+        app.toggle_help();
+        return;
+    }
+
+    if app.is_searching() {
+        match key.code {
+            KeyCode::Esc => app.clear_search(),
+            KeyCode::Enter => app.finish_search(),
+            KeyCode::Backspace => app.search_pop(),
+            KeyCode::Down => app.move_down(),
+            KeyCode::Up => app.move_up(),
+            KeyCode::Char(c) => app.search_push(c),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.is_visual() && key.code == KeyCode::Esc {
+        app.toggle_visual();
+        return;
+    }
+
+    let Some(action) = keymap.press(key.code) else {
+        return;
+    };
+    match action {
+        Action::Quit => app.cancel(),
+        Action::Down => app.move_down(),
+        Action::Up => app.move_up(),
+        Action::PageDown => app.page_down(),
+        Action::PageUp => app.page_up(),
+        Action::Top => app.move_to_top(),
+        Action::Bottom => app.move_to_bottom(),
+        Action::Toggle if app.is_visual() => {
+            app.clear_message();
+            app.toggle_visual_range();
+        }
+        Action::Toggle => {
             app.clear_message();
+            app.toggle_current();
         }
-        KeyCode::Char('a') => {
+        Action::Visual => app.toggle_visual(),
+        Action::ToggleAll => {
+            app.clear_message();
             app.toggle_all();
+        }
+        Action::SelectMerged => {
             app.clear_message();
+            app.select_merged();
         }
-        KeyCode::Enter => app.confirm(),
-        _ => {}
+        Action::SelectUnmerged => {
+            app.clear_message();
+            app.select_unmerged();
+        }
+        Action::HideMerged => app.toggle_hide_merged(),
+        Action::HideUnmerged => app.toggle_hide_unmerged(),
+        Action::Invert => {
+            app.clear_message();
+            app.invert_selection();
+        }
+        Action::Sort => app.cycle_sort(),
+        Action::Collapse => app.toggle_group(),
+        Action::Dates => app.toggle_absolute_dates(),
+        Action::Search => app.start_search(),
+        Action::Help => app.toggle_help(),
+        Action::Confirm => app.confirm(),
     }
 }
@@ -1,74 +1,144 @@
-use humantime::format_duration;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap},
 };
 
-use crate::app::App;
+use crate::app::{App, BranchItem, RowView};
+use crate::backup::RestoreResult;
+use crate::delete::{DeleteResult, DeleteStatus};
+use crate::git::{MergeStatus, UpstreamStatus};
+use crate::theme::Theme;
 
-pub fn draw(frame: &mut Frame<'_>, app: &App) {
+/// Commits listed in the pane under the branch list.
+pub const COMMIT_PANE_ROWS: usize = 5;
+
+/// Drawn before the highlighted row, and as blank space before the others.
+const HIGHLIGHT_SYMBOL: &str = "▶ ";
+
+/// Width of a row's `[x]` marker.
+const CHECKBOX_WIDTH: u16 = 3;
+
+/// Whether a click at `column` within a list row lands on its checkbox.
+pub fn is_checkbox_column(column: u16) -> bool {
+    let start = HIGHLIGHT_SYMBOL.chars().count() as u16;
+    (start..start + CHECKBOX_WIDTH).contains(&column)
+}
+
+/// --wide summary lines start under the branch name, past the marker and
+/// the unpushed flag.
+const WIDE_INDENT: &str = "    ";
+
+/// Status block height, including borders.
+const STATUS_HEIGHT: u16 = 6;
+
+/// Shortest branch list for which the commit pane is still shown.
+const MIN_LIST_HEIGHT: u16 = 8;
+
+pub fn draw(frame: &mut Frame<'_>, app: &mut App) {
     let size = frame.size();
 
     let vertical = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .constraints([Constraint::Min(3), Constraint::Length(STATUS_HEIGHT)].as_ref())
         .split(size);
 
-    let mut state = ListState::default();
-    if !app.is_empty() {
+    if app.is_confirming() {
+        draw_confirmation(frame, app, vertical[0], vertical[1]);
+        return;
+    }
+
+    // Split the commit pane off the list when the terminal is tall enough.
+    let pane_height = COMMIT_PANE_ROWS as u16 + 2;
+    let (list_area, pane_area) = if vertical[0].height >= MIN_LIST_HEIGHT + pane_height {
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(pane_height)].as_ref())
+            .split(vertical[0]);
+        (split[0], Some(split[1]))
+    } else {
+        (vertical[0], None)
+    };
+
+    app.set_list_area(list_area);
+    // Lines inside the list's borders
+    let height = usize::from(list_area.height.saturating_sub(2));
+    let summary_width = usize::from(list_area.width.saturating_sub(2))
+        .saturating_sub(HIGHLIGHT_SYMBOL.chars().count() + WIDE_INDENT.len());
+    let row_heights = if app.wide() {
+        app.rows()
+            .map(|row| match row {
+                RowView::Group { .. } => 1,
+                RowView::Branch(branch) => 1 + wrap(summary(branch), summary_width).len(),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut state = ListState::default().with_offset(app.scroll_into_view(height, row_heights));
+    if app.row_count() > 0 {
         state.select(Some(app.cursor()));
     }
 
     let list_items: Vec<ListItem> = app
-        .items()
-        .iter()
-        .map(|branch| {
-            let marker = if branch.selected { "[x]" } else { "[ ]" };
-            let status_span = if branch.info.merged {
-                Span::styled("merged", Style::default().fg(Color::Green))
-            } else {
-                Span::styled("unmerged", Style::default().fg(Color::Red))
+        .rows()
+        .enumerate()
+        .map(|(index, row)| {
+            let item = match row {
+                RowView::Group {
+                    prefix,
+                    count,
+                    selected,
+                    collapsed,
+                } => ListItem::new(group_line(app, prefix, count, selected, collapsed)),
+                RowView::Branch(branch) if app.wide() => {
+                    ListItem::new(wide_lines(app, branch, summary_width))
+                }
+                RowView::Branch(branch) => ListItem::new(branch_line(app, branch)),
             };
-            let age_span = branch.age.map(|age| {
-                Span::styled(
-                    format_duration(age).to_string(),
-                    Style::default().fg(Color::Magenta),
-                )
-            });
-            let summary = branch
-                .info
-                .summary
-                .as_deref()
-                .unwrap_or("<no commit message>");
-            let mut spans = vec![
-                Span::styled(marker, Style::default().fg(Color::Cyan)),
-                Span::raw(" "),
-                Span::styled(&branch.info.name, Style::default().fg(Color::Yellow)),
-                Span::raw("  "),
-                status_span,
-            ];
-            if let Some(age_span) = age_span {
-                spans.push(Span::raw("  "));
-                spans.push(age_span);
+            if app.in_visual_range(index) {
+                item.style(app.theme().visual)
+            } else {
+                item
             }
-            spans.push(Span::raw("  "));
-            spans.push(Span::raw(summary));
-
-            let primary = Line::from(spans);
-            ListItem::new(primary)
         })
         .collect();
 
-    let title = format!(
+    let mut title = format!(
         "Branches relative to '{}' (current: {}) - {} / {} selected",
-        app.base_branch(),
+        app.bases().join(", "),
         app.current_branch(),
         app.selected_count(),
         app.total_count()
     );
+    title.push_str(&format!(" - sort: {}", app.sort_order().label()));
+    if app.is_reversed() {
+        title.push_str(" (reversed)");
+    }
+    if let Some((analyzed, total)) = app.loading() {
+        title.push_str(&format!(" - loading… {analyzed}/{total}"));
+    }
+    if let Some(filter) = app.filter_label() {
+        title.push_str(&format!(" - filter: {filter}"));
+    }
+    if app.is_visual() {
+        title.push_str(" - range: space toggles, esc cancels");
+    }
+    if let Some(hidden) = app.hidden_label() {
+        title.push_str(&format!(
+            " - hiding {hidden} ({} shown)",
+            app.visible_count()
+        ));
+    }
+    if !app.query().is_empty() {
+        title.push_str(&format!(
+            " - search '{}' ({} shown)",
+            app.query(),
+            app.visible_count()
+        ));
+    }
 
     let list = List::new(list_items)
         .block(
@@ -79,24 +149,609 @@ pub fn draw(frame: &mut Frame<'_>, app: &App) {
                 ))
                 .borders(Borders::ALL),
         )
-        .highlight_style(
-            Style::default()
-                .fg(Color::White)
-                .bg(Color::Blue)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol("▶ ");
+        .highlight_style(app.theme().cursor)
+        .highlight_symbol(HIGHLIGHT_SYMBOL);
 
-    frame.render_stateful_widget(list, vertical[0], &mut state);
+    frame.render_stateful_widget(list, list_area, &mut state);
+    if let Some(pane_area) = pane_area {
+        draw_commit_pane(frame, app, pane_area);
+    }
 
-    let help_line = "up/down or j/k: move  space: toggle  a: toggle all  enter: confirm  q: cancel";
-    let status_line = app
-        .message()
-        .map(ToString::to_string)
-        .unwrap_or_else(|| "Select branches to delete.".to_string());
+    let status_line = if app.is_searching() {
+        format!("/{}_  (enter: keep filter  esc: clear)", app.query())
+    } else {
+        app.message()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "Select branches to delete.".to_string())
+    };
 
-    let status_block = Paragraph::new(vec![Line::from(help_line), Line::from(status_line)])
-        .block(Block::default().title("Status").borders(Borders::ALL));
+    let mut status_lines: Vec<Line> = app.help_lines().iter().map(Line::raw).collect();
+    status_lines.push(Line::from(status_line));
+    if let Some(branch) = app.highlighted() {
+        status_lines.push(detail_line(app, branch));
+    }
+    let stashed = app.selected_stashed_names();
+    if !stashed.is_empty() {
+        status_lines.push(Line::from(Span::styled(
+            format!(
+                "Stashes were made on these; they will no longer match a branch: {}",
+                stashed.join(", ")
+            ),
+            app.theme().error,
+        )));
+    }
+    let status_block =
+        Paragraph::new(status_lines).block(Block::default().title("Status").borders(Borders::ALL));
 
     frame.render_widget(status_block, vertical[1]);
+
+    if app.is_showing_help() {
+        draw_help(frame, app);
+    }
+}
+
+/// Full-screen overlay with every key, what the colors mean and which
+/// branches the list is relative to.
+fn draw_help(frame: &mut Frame<'_>, app: &App) {
+    let theme = app.theme();
+    let heading = |text| Line::styled(text, Style::default().add_modifier(Modifier::BOLD));
+    let entry = |sample: Span<'static>, text: &'static str| {
+        let padding = 16usize.saturating_sub(sample.width());
+        Line::from(vec![
+            Span::raw("  "),
+            sample,
+            Span::raw(" ".repeat(padding)),
+            Span::raw(text),
+        ])
+    };
+
+    let mut lines = vec![
+        Line::from(format!(
+            "Base: {}    Current branch: {}",
+            app.bases().join(", "),
+            app.current_branch()
+        )),
+        Line::default(),
+        heading("Keys"),
+    ];
+    for (keys, description) in app.key_table() {
+        lines.push(entry(Span::styled(keys.clone(), theme.accent), description));
+    }
+    lines.push(entry(
+        Span::styled("y / n, esc", theme.accent),
+        "delete or go back, on the confirmation screen",
+    ));
+    lines.push(entry(
+        Span::styled("0-9, enter", theme.accent),
+        "type the count to delete more than --confirm-threshold branches",
+    ));
+    lines.push(Line::default());
+    lines.push(heading("Colors"));
+    for (status, text) in [
+        (MergeStatus::Merged, "merged into the base"),
+        (MergeStatus::SquashMerged, "merged by a squash or rebase"),
+        (
+            MergeStatus::Unmerged,
+            "commits not in the base; needs --force",
+        ),
+    ] {
+        lines.push(entry(
+            Span::styled(status.label(), theme.status(status)),
+            text,
+        ));
+    }
+    lines.extend([
+        entry(Span::styled("[x]", theme.selected), "selected for deletion"),
+        entry(Span::raw("🔒"), "protected; cannot be selected"),
+        entry(
+            Span::styled("(current)", theme.current),
+            "the checked-out branch; cannot be deleted",
+        ),
+        entry(
+            Span::styled("!", theme.error.add_modifier(Modifier::BOLD)),
+            "tip is on no remote; deleting loses the only copy",
+        ),
+        entry(
+            Span::styled(UpstreamStatus::Gone.label(), theme.gone),
+            "upstream deleted from the remote",
+        ),
+        entry(
+            Span::styled("(gone from remote)", theme.remote_branch),
+            "stale remote-tracking ref (--prune-remotes)",
+        ),
+        entry(
+            Span::styled("(parent of x)", theme.accent),
+            "other local branches contain this one",
+        ),
+    ]);
+    lines.push(Line::default());
+    lines.push(Line::raw("Press any key to close."));
+
+    let area = frame.size();
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let overlay = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let help = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().title("Help").borders(Borders::ALL));
+    frame.render_widget(Clear, overlay);
+    frame.render_widget(help, overlay);
+}
+
+/// Shown while the confirmed selection is deleted: how many branches are
+/// done and the outcome of the latest one.
+pub fn draw_progress(
+    frame: &mut Frame<'_>,
+    theme: &Theme,
+    done: usize,
+    total: usize,
+    last: &DeleteResult,
+) {
+    let area = frame.size();
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let (verb, style) = match &last.status {
+        DeleteStatus::Deleted => ("deleted", theme.merged),
+        DeleteStatus::DryRun => ("would delete", theme.accent),
+        DeleteStatus::Error(_) => ("failed", theme.error),
+    };
+    let gauge = Gauge::default()
+        .block(
+            Block::default()
+                .title(format!("Deleting branches ({done}/{total})"))
+                .borders(Borders::ALL),
+        )
+        .gauge_style(theme.selected)
+        .ratio(done as f64 / total.max(1) as f64)
+        .label(Span::styled(format!("{verb} {}", last.name), style));
+    frame.render_widget(gauge, vertical[0]);
+}
+
+/// What a finished deletion did, with `u` offered to undo it; once undone,
+/// what was restored instead.
+pub fn draw_results(
+    frame: &mut Frame<'_>,
+    theme: &Theme,
+    results: &[DeleteResult],
+    restored: Option<&[RestoreResult]>,
+) {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(STATUS_HEIGHT)].as_ref())
+        .split(frame.size());
+
+    let (title, items): (String, Vec<ListItem>) = match restored {
+        None => {
+            let deleted = results
+                .iter()
+                .filter(|result| matches!(result.status, DeleteStatus::Deleted))
+                .count();
+            let items = results
+                .iter()
+                .map(|result| {
+                    let line = match &result.status {
+                        DeleteStatus::Error(err) => Line::from(vec![
+                            Span::styled(&result.name, theme.error),
+                            Span::raw("  "),
+                            Span::styled(err, theme.error),
+                        ]),
+                        _ => Line::from(vec![
+                            Span::styled(&result.name, theme.branch),
+                            Span::raw("  "),
+                            Span::styled("deleted", theme.merged),
+                        ]),
+                    };
+                    ListItem::new(line)
+                })
+                .collect();
+            let title = format!(
+                "Deleted {deleted} branch{}",
+                if deleted == 1 { "" } else { "es" }
+            );
+            (title, items)
+        }
+        Some(restored) => {
+            let ok = restored.iter().filter(|r| r.error.is_none()).count();
+            let items = restored
+                .iter()
+                .map(|result| {
+                    let line = match &result.error {
+                        Some(err) => Line::from(Span::styled(err.as_str(), theme.error)),
+                        None => Line::from(vec![
+                            Span::styled(&result.name, theme.branch),
+                            Span::raw("  restored at "),
+                            Span::styled(&result.tip[..result.tip.len().min(7)], theme.accent),
+                        ]),
+                    };
+                    ListItem::new(line)
+                })
+                .collect();
+            (
+                format!("Restored {ok} of {} branches", restored.len()),
+                items,
+            )
+        }
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .title(Span::styled(
+                title,
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(list, vertical[0]);
+
+    let mut status_lines = match restored {
+        None => vec![Line::from(
+            "u: undo, recreating the deleted refs (not their upstream config)  any other key: done",
+        )],
+        Some(_) => vec![
+            Line::from("Press any key to exit."),
+            Line::from("Only refs were recreated; set upstreams again with git branch -u."),
+        ],
+    };
+    let pushed = results.iter().any(|result| {
+        result
+            .remote
+            .as_ref()
+            .is_some_and(|remote| matches!(remote.push, Some(DeleteStatus::Deleted)))
+    });
+    if restored.is_none() && pushed {
+        status_lines.push(Line::from(Span::styled(
+            "Deletions pushed to the remote are not undone.",
+            theme.error,
+        )));
+    }
+    let status_block =
+        Paragraph::new(status_lines).block(Block::default().title("Done").borders(Borders::ALL));
+    frame.render_widget(status_block, vertical[1]);
+}
+
+/// The highlighted branch's newest commits that are not in any base.
+fn draw_commit_pane(frame: &mut Frame<'_>, app: &App, area: Rect) {
+    let (title, lines) = match (app.highlighted(), app.highlighted_commits()) {
+        (Some(branch), Some(Ok(unique))) => {
+            let title = match unique.total {
+                0 => format!("No commits on '{}' outside the base", branch.info.name),
+                total if total > unique.commits.len() => format!(
+                    "Commits on '{}' not in the base ({} of {total})",
+                    branch.info.name,
+                    unique.commits.len()
+                ),
+                total => format!(
+                    "Commits on '{}' not in the base ({total})",
+                    branch.info.name
+                ),
+            };
+            let lines = unique
+                .commits
+                .iter()
+                .map(|commit| {
+                    let id = commit.id.to_string();
+                    let id = if app.full_hash() { &id[..] } else { &id[..7] };
+                    Line::from(vec![
+                        Span::styled(id.to_string(), app.theme().accent),
+                        Span::raw("  "),
+                        Span::styled(commit.author.as_str(), app.theme().date),
+                        Span::raw("  "),
+                        Span::raw(commit.subject.as_str()),
+                    ])
+                })
+                .collect();
+            (title, lines)
+        }
+        (Some(_), Some(Err(err))) => (
+            "Commits".to_string(),
+            vec![Line::styled(err.as_str(), app.theme().error)],
+        ),
+        _ => ("Commits".to_string(), Vec::new()),
+    };
+    let pane = Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL));
+    frame.render_widget(pane, area);
+}
+
+/// Tip, commit date and committer of the highlighted branch.
+fn detail_line<'a>(app: &App, branch: &'a BranchItem) -> Line<'a> {
+    let mut spans = vec![
+        Span::styled(branch.info.tip_id(app.full_hash()), app.theme().accent),
+        Span::raw("  "),
+        Span::styled(
+            branch
+                .info
+                .commit_date()
+                .unwrap_or_else(|| "unknown date".to_string()),
+            app.theme().date,
+        ),
+    ];
+    if let Some(committer) = &branch.info.committer {
+        spans.push(Span::raw("  "));
+        spans.push(Span::raw(committer.as_str()));
+    }
+    let base = app.bases().first().map_or("the base", String::as_str);
+    if let Some(relationship) = branch.info.relationship(base, app.full_hash()) {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(relationship, app.theme().unmerged));
+    }
+    if let Some(description) = branch.info.description_line() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            description,
+            Style::default().add_modifier(Modifier::ITALIC),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// Final check before deleting: every selected branch, colored by merge status.
+fn draw_confirmation(frame: &mut Frame<'_>, app: &App, list_area: Rect, status_area: Rect) {
+    let items: Vec<ListItem> = app
+        .selected_items()
+        .map(|branch| {
+            let style = app.theme().status(branch.info.merged);
+            ListItem::new(Line::from(vec![
+                Span::styled(&branch.info.name, style),
+                Span::raw("  "),
+                Span::styled(branch.info.merged.label(), style),
+            ]))
+        })
+        .collect();
+
+    let count = app.selected_count();
+    let title = format!(
+        "Delete {count} branch{}?",
+        if count == 1 { "" } else { "es" }
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .title(Span::styled(
+                title,
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(list, list_area);
+
+    let mut status_lines = match app.typed_count() {
+        Some(typed) => vec![Line::from(vec![
+            Span::raw(format!("Type {count} and press enter to delete them: ")),
+            Span::styled(format!("{typed}_"), app.theme().selected),
+            Span::raw("  esc: back to selection"),
+        ])],
+        None => vec![Line::from(
+            "y: delete these branches  n/esc: back to selection",
+        )],
+    };
+    if let Some(message) = app.message() {
+        status_lines.push(Line::from(Span::styled(
+            message.to_string(),
+            app.theme().error,
+        )));
+    }
+    for warning in app.selected_risk_warnings() {
+        status_lines.push(Line::from(Span::styled(warning, app.theme().error)));
+    }
+    let reachable = app.selected_reachable_names();
+    if !reachable.is_empty() {
+        status_lines.push(Line::from(format!(
+            "Commits stay reachable from unselected branches: {}",
+            reachable.join(", ")
+        )));
+    }
+    let status_block =
+        Paragraph::new(status_lines).block(Block::default().title("Confirm").borders(Borders::ALL));
+    frame.render_widget(status_block, status_area);
+}
+
+fn group_line(
+    app: &App,
+    prefix: &str,
+    count: usize,
+    selected: usize,
+    collapsed: bool,
+) -> Line<'static> {
+    let arrow = if collapsed { "▸" } else { "▾" };
+    let name = if prefix.is_empty() {
+        "(no prefix)".to_string()
+    } else {
+        format!("{prefix}/")
+    };
+    let mut spans = vec![
+        Span::styled(arrow, app.theme().accent),
+        Span::raw(" "),
+        Span::styled(name, app.theme().branch.add_modifier(Modifier::BOLD)),
+        Span::raw(format!("  ({count}")),
+    ];
+    if selected > 0 {
+        spans.push(Span::raw(", "));
+        spans.push(Span::styled(
+            format!("{selected} selected"),
+            app.theme().selected,
+        ));
+    }
+    spans.push(Span::raw(")"));
+    Line::from(spans)
+}
+
+fn branch_line<'a>(app: &App, branch: &'a BranchItem) -> Line<'a> {
+    let marker = if branch.protected {
+        " 🔒"
+    } else if branch.selected {
+        "[x]"
+    } else {
+        "[ ]"
+    };
+    let theme = app.theme();
+    let status = match &branch.info.merged_into {
+        Some(base) if app.bases().len() > 1 => {
+            format!("{} ({base})", branch.info.merged.label())
+        }
+        _ => branch.info.merged.label().to_string(),
+    };
+    let status_span = Span::styled(status, theme.status(branch.info.merged));
+    let age = if app.absolute_dates() {
+        branch.info.commit_date()
+    } else {
+        branch.age.map(|age| app.age_format().format(age))
+    };
+    let age_span = age.map(|age| Span::styled(age, theme.date));
+    let name_style = if branch.current {
+        theme.current
+    } else if branch.info.is_remote_tracking {
+        theme.remote_branch
+    } else {
+        theme.branch
+    };
+    let marker_style = if branch.selected {
+        theme.selected
+    } else {
+        theme.accent
+    };
+    // `!` flags branches whose commits exist on no remote.
+    let pushed_span = if branch.info.pushed {
+        Span::raw(" ")
+    } else {
+        Span::styled("!", theme.error.add_modifier(Modifier::BOLD))
+    };
+    let mut spans = vec![
+        Span::styled(marker, marker_style),
+        pushed_span,
+        Span::styled(&branch.info.name, name_style),
+        Span::raw("  "),
+        status_span,
+        Span::raw("  "),
+        Span::styled(branch.info.ahead_behind(), theme.accent),
+    ];
+    if branch.current {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled("(current)", theme.current));
+    }
+    if branch.info.is_remote_tracking {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled("(gone from remote)", theme.remote_branch));
+    }
+    if let Some(upstream) = branch.info.upstream {
+        let style = match upstream {
+            UpstreamStatus::Gone => theme.gone,
+            UpstreamStatus::Tracking { .. } => theme.remote,
+        };
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(upstream.label(), style));
+    }
+    if branch.info.worktree.is_some() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::raw("(worktree)"));
+    }
+    if branch.info.stashes > 0 {
+        let note = match branch.info.stashes {
+            1 => "(1 stash)".to_string(),
+            count => format!("({count} stashes)"),
+        };
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(note, theme.error));
+    }
+    if let Some((first, rest)) = branch.info.dependents.split_first() {
+        let note = match rest.len() {
+            0 => format!("(parent of {first})"),
+            more => format!("(parent of {first} +{more})"),
+        };
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(note, theme.accent));
+    }
+    if let Some(remote) = &branch.info.remote {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!("⇅ {}", remote.display_name()),
+            theme.remote,
+        ));
+    }
+    if let Some(age_span) = age_span {
+        spans.push(Span::raw("  "));
+        spans.push(age_span);
+    }
+    if !app.wide() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::raw(summary(branch)));
+    }
+
+    let mut line = Line::from(spans);
+    if dimmed(branch) {
+        dim(&mut line);
+    }
+    line
+}
+
+/// The current branch keeps its colors so it stands out.
+fn dimmed(branch: &BranchItem) -> bool {
+    branch.protected && !branch.current
+}
+
+fn dim(line: &mut Line<'_>) {
+    for span in &mut line.spans {
+        span.style = span.style.add_modifier(Modifier::DIM);
+    }
+}
+
+fn summary(branch: &BranchItem) -> &str {
+    branch
+        .info
+        .summary
+        .as_deref()
+        .unwrap_or("<no commit message>")
+}
+
+/// A --wide row: the branch line, then its summary wrapped to `width`.
+fn wide_lines<'a>(app: &App, branch: &'a BranchItem, width: usize) -> Vec<Line<'a>> {
+    let mut lines = vec![branch_line(app, branch)];
+    for text in wrap(summary(branch), width) {
+        let mut line = Line::from(vec![Span::raw(WIDE_INDENT), Span::raw(text)]);
+        if dimmed(branch) {
+            dim(&mut line);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Break `text` at spaces into lines at most `width` columns wide; a word
+/// longer than a whole line is split wherever it runs out of room.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for word in text.split_whitespace() {
+        let word_width = Span::raw(word).width();
+        if line_width > 0 && line_width + 1 + word_width <= width {
+            line.push(' ');
+            line.push_str(word);
+            line_width += 1 + word_width;
+            continue;
+        }
+        if line_width > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        line_width = 0;
+        for ch in word.chars() {
+            let ch_width = Span::raw(ch.to_string()).width();
+            if line_width > 0 && line_width + ch_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push(ch);
+            line_width += ch_width;
+        }
+    }
+    if line_width > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
 }